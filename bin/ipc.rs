@@ -2,6 +2,8 @@ use std::ffi::OsStr;
 use std::io::{Read, Write};
 use std::os::unix::net::UnixStream;
 use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
 
 use clap::builder::PossibleValue;
 use clap::{ColorChoice, Parser, Subcommand, ValueEnum};
@@ -9,7 +11,15 @@ use i3stat::bail;
 use i3stat::error::Result;
 use i3stat::i3::{I3Button, I3ClickEvent, I3Modifier};
 use i3stat::ipc::get_socket_path;
-use i3stat::ipc::protocol::{encode_ipc_msg, IpcBarEvent, IpcMessage, IpcReply, IPC_HEADER_LEN};
+use i3stat::ipc::protocol::{
+    decode_ipc_msg,
+    encode_ipc_msg,
+    IpcBarEvent,
+    IpcErrorCode,
+    IpcMessage,
+    IpcReply,
+    IpcResult,
+};
 use serde_json::Value;
 
 #[derive(Debug, Parser)]
@@ -20,15 +30,48 @@ struct Cli {
     /// Path to the socket to use for ipc.
     #[clap(long)]
     socket: Option<PathBuf>,
+    /// If the bar replies with a failure, only print its error code (e.g. `item_not_found`).
+    #[clap(long)]
+    code: bool,
+    /// Print the bar's reply exactly as it was sent, as JSON (e.g. `{"value":...}`).
+    /// Either way, the exit status is non-zero if the bar replies with a failure.
+    #[clap(long, conflicts_with = "code")]
+    json: bool,
+    /// Print help from bar items (e.g. `i3stat-ipc custom pulse`) without colours, e.g. when the
+    /// output isn't going to a terminal.
+    #[clap(long)]
+    no_color: bool,
+}
+
+/// How replies from the bar are printed
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    code_only: bool,
+    json: bool,
 }
 
 #[derive(Debug, Subcommand)]
 enum CliCommand {
     /// Returns information about the currently running bar.
-    Info,
+    Info {
+        /// Return runtime metrics instead, such as uptime and item restart counts.
+        #[clap(long)]
+        stats: bool,
+    },
+    /// Returns the items in the order they're displayed in the bar (left to right), and whether
+    /// they're visible. Useful for working out which item is at a particular position.
+    Layout,
     /// Sends a signal to all events to trigger a refresh. Note that some items completely ignore all
     /// events, and thus won't receive this refresh events.
     RefreshAll,
+    /// Restart a single bar item, as if the bar had just started (this also resets its retries).
+    /// Useful if an item gets stuck, e.g.:
+    ///
+    /// `i3stat-ipc restart-item my_item`
+    RestartItem {
+        /// The target bar item: can be an index or the name of the item
+        target: String,
+    },
     /// Returns the current bar as JSON.
     GetBar,
     /// Returns the current configuration.
@@ -37,6 +80,9 @@ enum CliCommand {
         /// If not provided, the entire config will be returned.
         pointer: Option<String>,
     },
+    /// Returns the config files which were read when the bar started (the main config file, then
+    /// any files it includes) in the order they were merged. Useful when includes don't behave.
+    ConfigSources,
     /// Returns the current theme.
     GetTheme {
         /// JSON Pointer for the theme https://datatracker.ietf.org/doc/html/rfc6901
@@ -54,6 +100,39 @@ enum CliCommand {
         /// New value to set
         json_value: String,
     },
+    /// Check a theme is valid without applying it, e.g.:
+    ///
+    /// `i3stat-ipc validate-theme "$(cat theme.json)"`
+    ValidateTheme {
+        /// The theme as JSON, any values not provided will use their defaults
+        json: String,
+    },
+    /// Patch the config at runtime with a JSON merge patch
+    /// https://datatracker.ietf.org/doc/html/rfc7386
+    /// The patch is only applied if the resulting config is valid, and any items whose config
+    /// changed are restarted. Note that arrays (such as `items`) are replaced entirely, e.g.:
+    ///
+    /// `i3stat-ipc patch-config '{"theme": {"powerline_enable": true}, "separator": false}'`
+    PatchConfig {
+        /// The patch as JSON
+        json: String,
+    },
+    /// Print the most recent logs of the bar, oldest first. Which logs are kept depends on the
+    /// bar's log level (see `RUST_LOG` and the `log_level` item option).
+    Logs {
+        /// How many lines to print
+        #[clap(long, short = 'n', default_value_t = 100)]
+        lines: usize,
+    },
+    /// Set the order in which items are displayed in the bar, e.g.:
+    ///
+    /// `i3stat-ipc set-order 2 0 my_item`
+    SetOrder {
+        /// The items to display first, in order: can be an index or the name of the item. Any items
+        /// not listed follow them, in the order they're currently displayed.
+        #[clap(required = true)]
+        items: Vec<String>,
+    },
     /// Send a click event to a bar item.
     Click {
         /// The target bar item: can be an index or the name of the item.
@@ -63,6 +142,9 @@ enum CliCommand {
         /// A list of modifiers (pass multiple times) emulated in the click event.
         #[clap(long, short)]
         modifiers: Vec<Modifier>,
+        /// Send the click to all items with the target name (see `allow_duplicate_names`).
+        #[clap(long)]
+        all: bool,
         #[clap(long, short)]
         x: Option<usize>,
         #[clap(long, short)]
@@ -85,6 +167,21 @@ enum CliCommand {
     Signal {
         /// The target bar item: can be an index or the name of the item
         target: String,
+        /// Send the signal to all items with the target name (see `allow_duplicate_names`)
+        #[clap(long)]
+        all: bool,
+    },
+    /// Returns which items are listening for each signal, e.g. `{"1":[{"index":0,"name":"foo"}]}`
+    GetSignals,
+    /// Change which signal a bar item listens for, this takes effect immediately, e.g.:
+    ///
+    /// `i3stat-ipc set-signal my_item 3` (then `pkill -RTMIN+3 i3stat` refreshes it)
+    SetSignal {
+        /// The target bar item: can be an index or the name of the item
+        target: String,
+        /// The signal to listen for, as an offset from `SIGRTMIN`. If not provided, the item will
+        /// no longer listen for a signal.
+        signal: Option<u32>,
     },
     /// Send a custom event to a bar item. Only a few bar items support custom events, see the documentation for details
     Custom {
@@ -94,9 +191,31 @@ enum CliCommand {
         #[clap(trailing_var_arg = true)]
         args: Vec<String>,
     },
+    /// Block until a bar item's text contains some text, e.g.:
+    ///
+    /// `i3stat-ipc wait my_item --contains "foo" --timeout 5s`
+    ///
+    /// Prints the item once it matches and exits with status 0. If the timeout elapses first, it
+    /// exits with status 2 (any other failure exits with status 1).
+    Wait {
+        /// The target bar item: can be an index or the name of the item
+        target: String,
+        /// The text to wait for
+        #[clap(long)]
+        contains: String,
+        /// How long to wait for, e.g. `500ms` or `5s`. If not provided, waits forever.
+        #[clap(long, value_parser = parse_duration)]
+        timeout: Option<Duration>,
+    },
     Shutdown,
 }
 
+fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    serde_json::from_value::<humantime_serde::Serde<Duration>>(Value::String(s.into()))
+        .map(|duration| duration.into_inner())
+        .map_err(|e| e.to_string())
+}
+
 #[derive(Debug, Clone)]
 struct Button(I3Button);
 
@@ -163,55 +282,112 @@ fn send_message(socket_path: impl AsRef<OsStr>, msg: IpcMessage) -> Result<IpcRe
     }
 
     let mut buf = vec![];
-    let n = match stream.read_to_end(&mut buf) {
-        Ok(n) => n,
-        Err(e) => {
-            bail!("Error reading from socket: {}", e);
-        }
-    };
+    if let Err(e) = stream.read_to_end(&mut buf) {
+        bail!("Error reading from socket: {}", e);
+    }
 
-    Ok(serde_json::from_slice(&buf[IPC_HEADER_LEN..n])?)
+    match decode_ipc_msg(&buf) {
+        Ok(reply) => Ok(reply),
+        Err(e) => bail!(
+            "Failed to read reply from bar, is i3stat-ipc the same version as i3stat? {}",
+            e
+        ),
+    }
 }
 
-fn send_and_print_response(socket_path: impl AsRef<OsStr>, msg: IpcMessage) -> Result<()> {
+/// Send a message and print the reply, returning the exit status
+fn send_and_print_response(
+    socket_path: impl AsRef<OsStr>,
+    msg: IpcMessage,
+    output: Output,
+) -> Result<i32> {
     let resp = match send_message(&socket_path, msg) {
         Ok(resp) => resp,
         Err(e) => bail!("failed to send ipc message: {}", e),
     };
 
+    print_response(resp, output)
+}
+
+/// The exit status for a reply: `2` if waiting timed out, `1` for any other failure
+fn exit_status(resp: &IpcReply) -> i32 {
+    match resp {
+        IpcReply::Result(IpcResult::Failure {
+            code: IpcErrorCode::Timeout,
+            ..
+        }) => 2,
+        IpcReply::Result(IpcResult::Failure { .. }) => 1,
+        _ => 0,
+    }
+}
+
+/// Print a reply, returning the exit status
+fn print_response(resp: IpcReply, output: Output) -> Result<i32> {
+    let status = exit_status(&resp);
     println!(
         "{}",
         match resp {
+            x if output.json => serde_json::to_string(&x)?,
             IpcReply::Help(help) => help,
             IpcReply::Value(value) => value.to_string(),
+            IpcReply::Result(IpcResult::Failure { code, .. }) if output.code_only => {
+                serde_json::to_value(code)?
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string()
+            }
             x => serde_json::to_string(&x)?,
         }
     );
 
-    Ok(())
+    Ok(status)
 }
 
 fn get_json_response(socket_path: &PathBuf, msg: IpcMessage) -> Result<Value> {
-    Ok(match send_message(socket_path, msg)? {
-        IpcReply::Value(json) => json,
-        _ => unreachable!(),
-    })
+    match send_message(socket_path, msg)? {
+        IpcReply::Value(json) => Ok(json),
+        IpcReply::Result(IpcResult::Failure { detail, .. }) => bail!("{}", detail),
+        reply => bail!("unexpected reply from bar: {:?}", reply),
+    }
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
     let socket_path = get_socket_path(args.socket.as_ref())?;
+    let output = Output {
+        code_only: args.code,
+        json: args.json,
+    };
+    let no_color = args.no_color;
 
-    match args.cmd {
-        CliCommand::Shutdown => send_and_print_response(&socket_path, IpcMessage::Shutdown)?,
-        CliCommand::Info => send_and_print_response(&socket_path, IpcMessage::Info)?,
-        CliCommand::GetBar => send_and_print_response(&socket_path, IpcMessage::GetBar)?,
-        CliCommand::RefreshAll => send_and_print_response(&socket_path, IpcMessage::RefreshAll)?,
+    let status = match args.cmd {
+        CliCommand::Shutdown => {
+            send_and_print_response(&socket_path, IpcMessage::Shutdown, output)?
+        }
+        CliCommand::Info { stats: false } => {
+            send_and_print_response(&socket_path, IpcMessage::Info, output)?
+        }
+        CliCommand::Info { stats: true } => {
+            send_and_print_response(&socket_path, IpcMessage::Stats, output)?
+        }
+        CliCommand::Layout => send_and_print_response(&socket_path, IpcMessage::BarLayout, output)?,
+        CliCommand::GetBar => send_and_print_response(&socket_path, IpcMessage::GetBar, output)?,
+        CliCommand::RefreshAll => {
+            send_and_print_response(&socket_path, IpcMessage::RefreshAll, output)?
+        }
+        CliCommand::RestartItem { target } => send_and_print_response(
+            &socket_path,
+            IpcMessage::RestartItem { instance: target },
+            output,
+        )?,
         CliCommand::GetConfig { pointer: None } => {
-            send_and_print_response(&socket_path, IpcMessage::GetConfig)?
+            send_and_print_response(&socket_path, IpcMessage::GetConfig, output)?
+        }
+        CliCommand::ConfigSources => {
+            send_and_print_response(&socket_path, IpcMessage::ConfigSources, output)?
         }
         CliCommand::GetTheme { pointer: None } => {
-            send_and_print_response(&socket_path, IpcMessage::GetTheme)?
+            send_and_print_response(&socket_path, IpcMessage::GetTheme, output)?
         }
         CliCommand::GetConfig {
             pointer: Some(pointer),
@@ -221,6 +397,8 @@ fn main() -> Result<()> {
                 Some(value) => println!("{}", value),
                 None => bail!("No value found at: {}", pointer),
             }
+
+            0
         }
         CliCommand::GetTheme {
             pointer: Some(pointer),
@@ -230,6 +408,8 @@ fn main() -> Result<()> {
                 Some(value) => println!("{}", value),
                 None => bail!("No value found at: {}", pointer),
             }
+
+            0
         }
         CliCommand::SetTheme {
             pointer,
@@ -255,15 +435,54 @@ fn main() -> Result<()> {
                     *value = new_value;
 
                     // send config back via IPC
-                    send_and_print_response(&socket_path, IpcMessage::SetTheme(theme))?;
+                    send_and_print_response(&socket_path, IpcMessage::SetTheme(theme), output)?
                 }
                 None => bail!("No value found at: {}", pointer),
             }
         }
+        CliCommand::ValidateTheme { json } => send_and_print_response(
+            &socket_path,
+            IpcMessage::ValidateTheme(serde_json::from_str(&json)?),
+            output,
+        )?,
+        CliCommand::PatchConfig { json } => send_and_print_response(
+            &socket_path,
+            IpcMessage::PatchConfig(serde_json::from_str(&json)?),
+            output,
+        )?,
+        CliCommand::Logs { lines } => {
+            let logs = get_json_response(&socket_path, IpcMessage::Logs { lines })?;
+            if output.json {
+                println!("{}", logs);
+            } else {
+                for line in serde_json::from_value::<Vec<String>>(logs)? {
+                    println!("{}", line);
+                }
+            }
+
+            0
+        }
+        CliCommand::GetSignals => {
+            send_and_print_response(&socket_path, IpcMessage::GetSignals, output)?
+        }
+        CliCommand::SetSignal { target, signal } => send_and_print_response(
+            &socket_path,
+            IpcMessage::SetSignal {
+                instance: target,
+                signal,
+            },
+            output,
+        )?,
+        CliCommand::SetOrder { items } => send_and_print_response(
+            &socket_path,
+            IpcMessage::SetItemOrder(items.into_iter().map(Value::String).collect()),
+            output,
+        )?,
         CliCommand::Click {
             target,
             button,
             modifiers,
+            all,
             x,
             y,
             relative_x,
@@ -291,26 +510,61 @@ fn main() -> Result<()> {
             let event = IpcBarEvent::Click(click);
             send_and_print_response(
                 &socket_path,
-                IpcMessage::BarEvent {
-                    instance: target,
-                    event,
+                match all {
+                    true => IpcMessage::BarEventAll {
+                        name: target,
+                        event,
+                    },
+                    false => IpcMessage::BarEvent {
+                        instance: target,
+                        event,
+                    },
                 },
-            )?;
+                output,
+            )?
         }
-        CliCommand::Signal { target } => send_and_print_response(
+        CliCommand::Signal { target, all } => send_and_print_response(
             &socket_path,
-            IpcMessage::BarEvent {
-                instance: target,
-                event: IpcBarEvent::Signal,
+            match all {
+                true => IpcMessage::BarEventAll {
+                    name: target,
+                    event: IpcBarEvent::Signal,
+                },
+                false => IpcMessage::BarEvent {
+                    instance: target,
+                    event: IpcBarEvent::Signal,
+                },
             },
+            output,
         )?,
         CliCommand::Custom { target, args } => send_and_print_response(
             &socket_path,
             IpcMessage::BarEvent {
                 instance: target,
-                event: IpcBarEvent::Custom(args),
+                event: IpcBarEvent::Custom {
+                    args,
+                    plain: no_color,
+                },
+            },
+            output,
+        )?,
+        CliCommand::Wait {
+            target,
+            contains,
+            timeout,
+        } => send_and_print_response(
+            &socket_path,
+            IpcMessage::Wait {
+                target,
+                contains,
+                timeout,
             },
+            output,
         )?,
+    };
+
+    if status != 0 {
+        process::exit(status);
     }
 
     Ok(())
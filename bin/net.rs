@@ -62,7 +62,11 @@ async fn print_interfaces(interfaces: &InterfaceUpdate) -> Result<()> {
                     "index": interface.index,
                     "name": interface.name,
                     "mac": interface.mac_address.as_ref().map(|m| m.to_string()),
-                    "ips": interface.ip_addresses.iter().collect::<Vec<_>>(),
+                    "ips": interface
+                        .ip_addresses
+                        .iter()
+                        .map(|(addr, prefix_len)| format!("{}/{}", addr, prefix_len))
+                        .collect::<Vec<_>>(),
                     "wireless": interface.wireless_info().await.map(|info| json!({
                         "index": info.index,
                         "interface": info.interface,
@@ -1,4 +1,5 @@
 use clap::{ColorChoice, Parser};
+use i3stat::signals::{dump_signal, max_item_signal};
 use libc::{SIGRTMAX, SIGRTMIN};
 use serde_json::json;
 
@@ -17,9 +18,10 @@ fn main() {
     println!(
         "{}",
         json!({
-            "count": rt_max - rt_min,
+            "count": max_item_signal(),
             "sigrtmin": rt_min,
-            "sigrtmax": rt_max
+            "sigrtmax": rt_max,
+            "dump": dump_signal()
         })
     );
 }
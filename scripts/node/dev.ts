@@ -22,6 +22,8 @@ const RETURN = '\r';
 const UP_ARR = '\x1B[A';
 const DN_ARR = '\x1B[B';
 const SOCKET_PATH = '/tmp/i3stat-socket.dev';
+// keep in sync with `IPC_VERSION` in src/ipc/protocol.rs
+const IPC_VERSION = 1;
 
 let prev_commands: string[] = [];
 let prev_command_idx = 0;
@@ -169,13 +171,15 @@ function handleInput(input: string) {
     const socket = createConnection(SOCKET_PATH);
     socket.once('connect', () => {
       const message = Buffer.from('"refresh_all"');
-      const header = Buffer.alloc(8);
-      header.writeBigUInt64LE(BigInt(message.length));
+      // header is a u32 of the ipc protocol version, then a u64 of the body's length
+      const header = Buffer.alloc(12);
+      header.writeUInt32LE(IPC_VERSION);
+      header.writeBigUInt64LE(BigInt(message.length), 4);
       const payload = Buffer.concat([header, message]);
       socket.write(payload);
       socket.on('data', (data) => {
-        // first 8 bytes are the header
-        const message = data.subarray(8);
+        // first 12 bytes are the header
+        const message = data.subarray(12);
         process.stdout.clearLine(0);
         process.stdout.write(c.green(`Refreshed all items. IPC response: ${message.toString()}\n`));
       });
@@ -4,24 +4,34 @@ use std::ops::{Index, IndexMut};
 
 use hex_color::HexColor;
 use serde_json::Value;
+use tokio::sync::watch;
 
 use crate::error::Result;
 use crate::i3::{I3Item, I3Markup};
-use crate::theme::Theme;
+use crate::theme::{PowerlineDirection, Theme};
+use crate::util::merge_partial_order;
 
 type ColorAdjusters = HashMap<HexColor, Box<dyn Fn(&HexColor) -> HexColor>>;
 
 pub struct Bar {
     /// The actual bar items - represents the latest state of each individual bar item
     items: Vec<I3Item>,
+    /// The order in which items are displayed, as indices into `items`
+    order: Vec<usize>,
+    /// Whether each item is stale, see `AppConfig::staleness`
+    stale: Vec<bool>,
     /// Cache for any colour adjusters created
     color_adjusters: ColorAdjusters,
+    /// Notifies subscribers each time the bar is output
+    updates: watch::Sender<()>,
 }
 
 impl Debug for Bar {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Bar")
             .field("items", &self.items)
+            .field("order", &self.order)
+            .field("stale", &self.stale)
             .field(
                 "color_adjusters",
                 &self.color_adjusters.keys().collect::<Vec<_>>(),
@@ -49,10 +59,23 @@ impl Bar {
     pub fn new(item_count: usize) -> Bar {
         Bar {
             items: vec![I3Item::empty(); item_count],
+            order: (0..item_count).collect(),
+            stale: vec![false; item_count],
             color_adjusters: ColorAdjusters::new(),
+            updates: watch::channel(()).0,
         }
     }
 
+    /// Returns a receiver which is notified each time the bar is output
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.updates.subscribe()
+    }
+
+    /// Notify any subscribers that the bar was output
+    pub fn notify_updated(&self) {
+        self.updates.send_replace(());
+    }
+
     /// Are there any urgent items?
     pub fn any_urgent(&self) -> bool {
         self.items
@@ -60,14 +83,82 @@ impl Bar {
             .any(|item| item.get_urgent().is_some_and(|urgent| *urgent))
     }
 
+    /// Mark an item as stale (or not), returns `true` if this changed anything
+    pub fn set_stale(&mut self, idx: usize, stale: bool) -> bool {
+        let changed = self.stale[idx] != stale;
+        self.stale[idx] = stale;
+        changed
+    }
+
+    /// The item as it should be displayed, stale items are dimmed and have a marker appended
+    fn display_item(&self, idx: usize, theme: &Theme) -> I3Item {
+        let item = self.items[idx].clone();
+        if !self.stale[idx] || item.is_empty() {
+            return item;
+        }
+
+        let text = format!("{} {}", item.full_text, Self::STALE_MARKER);
+        item.full_text(text)
+            .color(theme.dim)
+            .with_data("stale", true.into())
+    }
+
+    const STALE_MARKER: &'static str = "⚠";
+
+    /// Set the order in which items are displayed. Each item index may appear at most once: the
+    /// given items are displayed first, and any others follow them in their current order.
+    /// Items keep their index (and thus their `instance`), only their position in the bar changes.
+    pub fn set_order(&mut self, order: Vec<usize>) -> Result<()> {
+        let len = self.items.len();
+        if order.len() > len {
+            bail!(
+                "expected at most {} items in order, but got {}",
+                len,
+                order.len()
+            );
+        }
+
+        let mut seen = vec![false; len];
+        for idx in &order {
+            match seen.get_mut(*idx) {
+                Some(true) => bail!("item index {} was specified more than once", idx),
+                Some(seen) => *seen = true,
+                None => bail!("no item found with index: {}", idx),
+            }
+        }
+
+        self.order = merge_partial_order(&order, &self.order);
+        Ok(())
+    }
+
+    /// The items in the order they're displayed, as `(index, is_visible)`
+    /// Empty items aren't drawn by i3, so they don't take up any space in the bar
+    pub fn layout(&self) -> Vec<(usize, bool)> {
+        self.order
+            .iter()
+            .map(|idx| (*idx, !self.items[*idx].is_empty()))
+            .collect()
+    }
+
     /// Convert the bar to json
     pub fn to_json(&mut self, theme: &Theme) -> Result<String> {
         Ok(serde_json::to_string(&self.get_items(theme))?)
     }
 
     /// Convert the bar to a `Value`
+    /// Unlike `to_json` this includes each item's `detail`, since this isn't sent to i3
     pub fn to_value(&mut self, theme: &Theme) -> Result<Value> {
-        Ok(serde_json::to_value(self.get_items(theme))?)
+        let mut values = vec![];
+        for item in self.get_items(theme) {
+            let mut value = serde_json::to_value(&item)?;
+            if let (Some(detail), Value::Object(map)) = (item.get_detail(), &mut value) {
+                map.insert("detail".into(), detail.clone().into());
+            }
+
+            values.push(value);
+        }
+
+        Ok(Value::Array(values))
     }
 
     fn get_items(&mut self, theme: &Theme) -> Vec<I3Item> {
@@ -80,9 +171,9 @@ impl Bar {
 
     /// Return a list of items representing the bar
     fn create_bar(&mut self, theme: &Theme) -> Vec<I3Item> {
-        self.items
+        self.order
             .iter()
-            .cloned()
+            .map(|idx| self.display_item(*idx, theme))
             .map(|item| {
                 if let Some(true) = item.get_urgent() {
                     item.color(theme.urgent_fg)
@@ -101,15 +192,20 @@ impl Bar {
     /// Return a list of items representing the bar formatted as a powerline
     fn create_powerline_bar(&mut self, theme: &Theme) -> Vec<I3Item> {
         let visible_items = self.items.iter().filter(|i| !i.is_empty()).count();
+        let direction = theme.powerline_direction;
 
-        // start the powerline index so the theme colours are consistent from right to left
+        // start the powerline index so the theme colours are consistent from the side the
+        // powerline starts from
         let powerline_len = theme.powerline.len();
-        let mut powerline_bar = vec![];
-        let mut powerline_idx = powerline_len - (visible_items % powerline_len);
-
-        // each time we iterate over an item, we place in a separator and then the item itself
-        for i in 0..self.items.len() {
-            let item = &self.items[i];
+        let mut powerline_idx = match direction {
+            PowerlineDirection::RightToLeft => powerline_len - (visible_items % powerline_len),
+            PowerlineDirection::LeftToRight => powerline_len - 1,
+        };
+
+        // first style each item, keeping track of its background so the separators can blend
+        let mut styled = vec![];
+        for &i in &self.order {
+            let item = &self.display_item(i, theme);
             if item.is_empty() {
                 continue;
             }
@@ -117,7 +213,6 @@ impl Bar {
             let instance = i.to_string();
             debug_assert_eq!(item.get_instance().unwrap(), &instance);
 
-            let prev_color = &theme.powerline[powerline_idx % powerline_len];
             let this_color = &theme.powerline[(powerline_idx + 1) % powerline_len];
             powerline_idx += 1;
 
@@ -136,8 +231,45 @@ impl Bar {
                 }
             };
 
-            // create the powerline separator
-            let mut sep_item = I3Item::new(theme.powerline_separator.to_span())
+            // replace `config.theme.dim` so it's easy to see
+            let adjusted_dim = self
+                .color_adjusters
+                .entry(theme.dim)
+                .or_insert_with(|| Box::new(make_color_adjuster(&theme.bg, &theme.dim)))(
+                &item_bg
+            );
+
+            let item = item
+                .clone()
+                .full_text(format!(
+                    " {} ",
+                    // replace `config.theme.dim` use in pango spans
+                    item.full_text.replace(
+                        &theme.dim.display_rgb().to_string(),
+                        &adjusted_dim.display_rgb().to_string()
+                    )
+                ))
+                .separator(false)
+                .separator_block_width_px(0)
+                .color(match item.get_color() {
+                    _ if is_urgent => item_fg,
+                    Some(color) if color == &theme.dim => adjusted_dim,
+                    Some(color) => *color,
+                    _ => item_fg,
+                })
+                .background_color(item_bg)
+                // disable urgent here, since we override it ourselves to style the powerline more nicely
+                // but we set it as additional data just in case someone wants to use it
+                .urgent(false)
+                .with_data("urgent", true.into());
+
+            styled.push((instance, item, item_bg));
+        }
+
+        // creates a separator for an item, `blend_bg` is the background of the item on the other
+        // side of the separator - or `None` if it's a cap at the end of the powerline
+        let separator = |instance: &str, item_bg: HexColor, blend_bg: Option<HexColor>| {
+            let sep_item = I3Item::new(theme.powerline_separator.to_span(direction))
                 .instance(instance)
                 .separator(false)
                 .markup(I3Markup::Pango)
@@ -145,57 +277,31 @@ impl Bar {
                 .color(item_bg)
                 .with_data("powerline_sep", true.into());
 
-            // ensure the separator meshes with the previous item's background
-            // the first separator doesn't blend with any other item (hence > 0)
-            if i > 0 {
-                // find the first previous item which isn't empty
-                let prev_item = self.items.iter().take(i).rev().find(|i| !i.is_empty());
-                if let Some(prev_item) = prev_item {
-                    if *prev_item.get_urgent().unwrap_or(&false) {
-                        sep_item = sep_item.background_color(theme.urgent_bg);
-                    } else {
-                        sep_item =
-                            sep_item.background_color(match prev_item.get_background_color() {
-                                Some(bg) => *bg,
-                                None => prev_color.bg,
-                            });
+            match blend_bg {
+                Some(bg) => sep_item.background_color(bg),
+                None => sep_item,
+            }
+        };
+
+        // then place a separator on the side of each item that the powerline flows from
+        let mut powerline_bar = vec![];
+        for (pos, (instance, item, item_bg)) in styled.iter().enumerate() {
+            match direction {
+                PowerlineDirection::RightToLeft => {
+                    let prev_bg = pos.checked_sub(1).map(|prev| styled[prev].2);
+                    if prev_bg.is_some() || theme.powerline_caps {
+                        powerline_bar.push(separator(instance, *item_bg, prev_bg));
+                    }
+                    powerline_bar.push(item.clone());
+                }
+                PowerlineDirection::LeftToRight => {
+                    let next_bg = styled.get(pos + 1).map(|next| next.2);
+                    powerline_bar.push(item.clone());
+                    if next_bg.is_some() || theme.powerline_caps {
+                        powerline_bar.push(separator(instance, *item_bg, next_bg));
                     }
                 }
             }
-
-            // replace `config.theme.dim` so it's easy to see
-            let adjusted_dim = self
-                .color_adjusters
-                .entry(theme.dim)
-                .or_insert_with(|| Box::new(make_color_adjuster(&theme.bg, &theme.dim)))(
-                &item_bg
-            );
-
-            powerline_bar.push(sep_item);
-            powerline_bar.push(
-                item.clone()
-                    .full_text(format!(
-                        " {} ",
-                        // replace `config.theme.dim` use in pango spans
-                        item.full_text.replace(
-                            &theme.dim.display_rgb().to_string(),
-                            &adjusted_dim.display_rgb().to_string()
-                        )
-                    ))
-                    .separator(false)
-                    .separator_block_width_px(0)
-                    .color(match item.get_color() {
-                        _ if is_urgent => item_fg,
-                        Some(color) if color == &theme.dim => adjusted_dim,
-                        Some(color) => *color,
-                        _ => item_fg,
-                    })
-                    .background_color(item_bg)
-                    // disable urgent here, since we override it ourselves to style the powerline more nicely
-                    // but we set it as additional data just in case someone wants to use it
-                    .urgent(false)
-                    .with_data("urgent", true.into()),
-            );
         }
 
         powerline_bar
@@ -221,6 +327,65 @@ fn make_color_adjuster(bg: &HexColor, fg: &HexColor) -> impl Fn(&HexColor) -> He
 mod tests {
     use super::*;
 
+    #[test]
+    fn set_order() {
+        let mut bar = Bar::new(3);
+        for i in 0..3 {
+            bar[i] = I3Item::new(i.to_string()).instance(i.to_string());
+        }
+
+        bar.set_order(vec![2, 0, 1]).unwrap();
+        let items = bar.create_bar(&Theme::default());
+        let text = items
+            .iter()
+            .map(|i| i.full_text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(text, vec!["2", "0", "1"]);
+
+        // invalid orders are rejected, and the previous order is kept
+        assert!(bar.set_order(vec![0, 1, 1]).is_err());
+        assert!(bar.set_order(vec![0, 1, 3]).is_err());
+        assert!(bar.set_order(vec![0, 1, 2, 0]).is_err());
+        assert_eq!(bar.order, vec![2, 0, 1]);
+        assert_eq!(bar.layout(), vec![(2, false), (0, false), (1, false)]);
+    }
+
+    #[test]
+    fn set_partial_order() {
+        let mut bar = Bar::new(4);
+        bar.set_order(vec![3, 2, 1, 0]).unwrap();
+
+        // listed items come first, the rest keep their current order
+        bar.set_order(vec![1]).unwrap();
+        assert_eq!(bar.order, vec![1, 3, 2, 0]);
+        bar.set_order(vec![0, 2]).unwrap();
+        assert_eq!(bar.order, vec![0, 2, 1, 3]);
+
+        // an empty order changes nothing
+        bar.set_order(vec![]).unwrap();
+        assert_eq!(bar.order, vec![0, 2, 1, 3]);
+    }
+
+    #[test]
+    fn detail_only_in_value() {
+        let mut bar = Bar::new(2);
+        bar[0] = I3Item::new("0").instance("0").detail("zero");
+        bar[1] = I3Item::new("1").instance("1");
+
+        let theme = Theme::default();
+        assert_eq!(
+            bar.to_json(&theme).unwrap(),
+            r#"[{"full_text":"0","instance":"0"},{"full_text":"1","instance":"1"}]"#
+        );
+        assert_eq!(
+            bar.to_value(&theme).unwrap(),
+            serde_json::json!([
+                { "full_text": "0", "instance": "0", "detail": "zero" },
+                { "full_text": "1", "instance": "1" }
+            ])
+        );
+    }
+
     #[test]
     fn properly_format_separator_with_empty() {
         let mut bar = Bar::new(3);
@@ -259,4 +424,90 @@ mod tests {
         // item itself is red
         assert_eq!(items[1].get_background_color(), Some(&HexColor::RED));
     }
+
+    #[test]
+    fn powerline_direction() {
+        let mut bar = Bar::new(2);
+        bar[0] = I3Item::new("0")
+            .instance("0")
+            .background_color(HexColor::RED);
+        bar[1] = I3Item::new("1")
+            .instance("1")
+            .background_color(HexColor::BLUE);
+
+        let is_sep = |item: &I3Item| item.full_text.trim().parse::<usize>().is_err();
+        let rtl = Theme::default();
+        let ltr = Theme {
+            powerline_direction: PowerlineDirection::LeftToRight,
+            ..Theme::default()
+        };
+
+        // right to left: each item is preceded by its separator, blending into the previous item
+        let items = bar.create_powerline_bar(&rtl);
+        assert_eq!(
+            items.iter().map(is_sep).collect::<Vec<_>>(),
+            [true, false, true, false]
+        );
+        assert_eq!(items[0].full_text, "\u{e0b2}");
+        assert_eq!(items[2].get_color(), Some(&HexColor::BLUE));
+        assert_eq!(items[2].get_background_color(), Some(&HexColor::RED));
+
+        // left to right: each item is followed by its separator, blending into the next item
+        let items = bar.create_powerline_bar(&ltr);
+        assert_eq!(
+            items.iter().map(is_sep).collect::<Vec<_>>(),
+            [false, true, false, true]
+        );
+        assert_eq!(items[1].full_text, "\u{e0b0}");
+        assert_eq!(items[1].get_color(), Some(&HexColor::RED));
+        assert_eq!(items[1].get_background_color(), Some(&HexColor::BLUE));
+        assert_eq!(items[3].get_background_color(), None);
+    }
+
+    #[test]
+    fn powerline_without_caps() {
+        let mut bar = Bar::new(2);
+        bar[0] = I3Item::new("0").instance("0");
+        bar[1] = I3Item::new("1").instance("1");
+
+        for direction in [
+            PowerlineDirection::RightToLeft,
+            PowerlineDirection::LeftToRight,
+        ] {
+            let theme = Theme {
+                powerline_direction: direction,
+                powerline_caps: false,
+                ..Theme::default()
+            };
+
+            // only the separator between the two items remains
+            let items = bar.create_powerline_bar(&theme);
+            let text = items.iter().map(|i| i.full_text.trim()).collect::<Vec<_>>();
+            assert_eq!(text.len(), 3, "{:?}", direction);
+            assert_eq!((text[0], text[2]), ("0", "1"), "{:?}", direction);
+        }
+    }
+
+    #[test]
+    fn stale_items() {
+        let theme = Theme::default();
+        let mut bar = Bar::new(2);
+        for i in 0..2 {
+            bar[i] = I3Item::new(i.to_string()).instance(i.to_string());
+        }
+
+        assert!(bar.set_stale(1, true));
+        assert!(!bar.set_stale(1, true));
+
+        let items = bar.create_bar(&theme);
+        assert_eq!(items[0], bar[0]);
+        assert_eq!(items[1].full_text, "1 ⚠");
+        assert_eq!(items[1].get_color(), Some(&theme.dim));
+
+        // the item itself is unchanged
+        assert_eq!(bar[1].full_text, "1");
+
+        assert!(bar.set_stale(1, false));
+        assert_eq!(bar.create_bar(&theme)[1], bar[1]);
+    }
 }
@@ -1,7 +1,8 @@
 use std::cell::OnceCell;
+use std::collections::VecDeque;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use futures::try_join;
@@ -12,12 +13,14 @@ use tokio::sync::mpsc::Receiver;
 
 use crate::context::{BarEvent, BarItem, Context, StopAction};
 use crate::dbus::notifications::NotificationsProxy;
+use crate::dbus::upower::{UPowerDeviceProxy, UPowerProxy};
 use crate::dbus::{dbus_connection, BusType};
 use crate::error::Result;
 use crate::i3::{I3Button, I3Item, I3Markup};
 use crate::theme::Theme;
 use crate::util::acpi::ffi::AcpiGenericNetlinkEvent;
-use crate::util::{netlink_acpi_listen, Paginator};
+use crate::util::format::PercentFormat;
+use crate::util::{exec, expand_path, netlink_acpi_listen, Paginator};
 
 #[derive(Debug)]
 enum BatState {
@@ -36,6 +39,31 @@ impl BatState {
             _ => (None, None),
         }
     }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Charging => "charging",
+            Self::Discharging => "discharging",
+            Self::NotCharging => "not charging",
+            Self::Full => "full",
+        }
+    }
+}
+
+impl BatState {
+    /// Convert a device's state as reported by UPower
+    fn from_upower(state: u32) -> BatState {
+        match state {
+            1 => Self::Charging,
+            // discharging, empty, and pending discharge
+            2 | 3 | 6 => Self::Discharging,
+            4 => Self::Full,
+            // pending charge
+            5 => Self::NotCharging,
+            _ => Self::Unknown,
+        }
+    }
 }
 
 impl FromStr for BatState {
@@ -129,11 +157,17 @@ impl Bat {
     }
 
     pub async fn watts_now(&self) -> Result<f64> {
-        let (current_pico, voltage_pico) = try_join!(
+        // `power_now` is in µW, but not all batteries provide it
+        if let Ok(power_micro) = self.read_usize("power_now").await {
+            return Ok(power_micro as f64 / 1_000_000.0);
+        }
+
+        // otherwise compute it: µA * µV = pW
+        let (current_micro, voltage_micro) = try_join!(
             self.read_usize("current_now"),
             self.read_usize("voltage_now"),
         )?;
-        Ok((current_pico as f64) * (voltage_pico as f64) / 1_000_000_000_000.0)
+        Ok((current_micro as f64) * (voltage_micro as f64) / 1_000_000_000_000.0)
     }
 
     pub async fn get_info(&self) -> Result<BatInfo> {
@@ -170,19 +204,205 @@ impl Bat {
     }
 }
 
+/// A device known to UPower, such as a UPS or a wireless mouse or keyboard
+struct UPowerBat {
+    proxy: UPowerDeviceProxy<'static>,
+}
+
+impl UPowerBat {
+    /// UPower's device type for AC adapters, which have no charge
+    const TYPE_LINE_POWER: u32 = 1;
+
+    pub async fn find_all() -> Result<Vec<UPowerBat>> {
+        let connection = dbus_connection(BusType::System).await?;
+        let upower = UPowerProxy::new(connection).await?;
+
+        let mut batteries = vec![];
+        for path in upower.enumerate_devices().await? {
+            let proxy = UPowerDeviceProxy::builder(connection)
+                .path(path)?
+                .build()
+                .await?;
+
+            if proxy.device_type().await? == Self::TYPE_LINE_POWER || !proxy.is_present().await? {
+                continue;
+            }
+
+            log::debug!("found upower device: {}", proxy.native_path().await?);
+            batteries.push(UPowerBat { proxy });
+        }
+
+        Ok(batteries)
+    }
+
+    pub async fn get_info(&self) -> Result<BatInfo> {
+        let (model, native_path, charge, state) = try_join!(
+            self.proxy.model(),
+            self.proxy.native_path(),
+            self.proxy.percentage(),
+            self.proxy.state(),
+        )?;
+
+        Ok(BatInfo {
+            // not all devices report a model
+            name: if model.is_empty() { native_path } else { model },
+            charge: charge as f32,
+            state: BatState::from_upower(state),
+        })
+    }
+
+    pub async fn watts_now(&self) -> Result<f64> {
+        Ok(self.proxy.energy_rate().await?)
+    }
+}
+
+/// A battery, read from any of the supported sources
+enum BatDevice {
+    Sysfs(Bat),
+    UPower(UPowerBat),
+}
+
+impl BatDevice {
+    async fn get_info(&self) -> Result<BatInfo> {
+        match self {
+            BatDevice::Sysfs(bat) => bat.get_info().await,
+            BatDevice::UPower(bat) => bat.get_info().await,
+        }
+    }
+
+    async fn watts_now(&self) -> Result<f64> {
+        match self {
+            BatDevice::Sysfs(bat) => bat.watts_now().await,
+            BatDevice::UPower(bat) => bat.watts_now().await,
+        }
+    }
+}
+
+/// How many samples are used to work out the charge rate, more samples means a smoother rate
+const TREND_SAMPLES: usize = 5;
+
+/// Charge rate in percent per hour between two samples of `(time, percentage)`.
+/// Positive when charging, negative when discharging.
+fn charge_rate(from: (Instant, f32), to: (Instant, f32)) -> Option<f32> {
+    let hours = to.0.checked_duration_since(from.0)?.as_secs_f32() / 3600.0;
+    if hours <= 0.0 {
+        return None;
+    }
+
+    Some((to.1 - from.1) / hours)
+}
+
+/// Recent charge samples of a battery, used to show whether it's gaining or losing charge
+#[derive(Debug, Default)]
+struct ChargeTrend {
+    samples: VecDeque<(Instant, f32)>,
+}
+
+impl ChargeTrend {
+    fn push(&mut self, at: Instant, charge: f32) {
+        if self.samples.len() == TREND_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, charge));
+    }
+
+    /// The rate over all the samples we have, which smooths out any jitter between samples
+    fn rate(&self) -> Option<f32> {
+        match (self.samples.front(), self.samples.back()) {
+            (Some(from), Some(to)) => charge_rate(*from, *to),
+            _ => None,
+        }
+    }
+}
+
+/// What to do when a battery discharges below `critical_percentage`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CriticalAction {
+    #[default]
+    None,
+    /// Send a (one-off) critical notification
+    Notify,
+    /// Run a command, e.g. `systemctl suspend`
+    Command(String),
+}
+
+/// Makes sure the critical action only happens once each time a battery discharges below the
+/// threshold, rather than on every sample while it's below it
+#[derive(Debug, Default)]
+struct CriticalTrigger {
+    fired: bool,
+}
+
+impl CriticalTrigger {
+    /// Returns true if the critical action should happen for this sample
+    fn update(&mut self, charge: f32, state: &BatState, threshold: u8) -> bool {
+        match state {
+            BatState::Discharging if charge <= threshold as f32 => {
+                !std::mem::replace(&mut self.fired, true)
+            }
+            BatState::Discharging | BatState::Unknown => false,
+            // once the battery is charging again, the next discharge may trigger the action again
+            BatState::Charging | BatState::NotCharging | BatState::Full => {
+                self.fired = false;
+                false
+            }
+        }
+    }
+}
+
+/// Where battery information is read from
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatterySource {
+    /// Batteries in `/sys/class/power_supply`
+    #[default]
+    Sysfs,
+    /// All devices known to UPower (except AC adapters)
+    Upower,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Battery {
     #[serde(with = "crate::human_time")]
     interval: Duration,
+    /// Where to read batteries from
+    #[serde(default)]
+    source: BatterySource,
+    /// Only used when `source` is `sysfs`
     batteries: Option<Vec<Bat>>,
     #[serde(default)]
     notify_on_adapter: bool,
     // TODO: option to run command(s) at certain percentage(s)
     #[serde(default)]
     notify_percentage: Option<u8>,
+    /// Percentage at or below which `critical_action` happens, while discharging
+    #[serde(default)]
+    critical_percentage: Option<u8>,
+    /// What happens (once per discharge) when the battery reaches `critical_percentage`
+    #[serde(default)]
+    critical_action: CriticalAction,
+    /// Alternate the item's colour while the battery is charging
+    #[serde(default)]
+    animate_charging: bool,
+    /// Display the current power draw in watts
+    #[serde(default)]
+    show_power: bool,
+    /// Display whether the battery is gaining or losing charge, and how fast (in %/hour)
+    #[serde(default)]
+    show_rate: bool,
+    /// When there's more than one battery, also display an icon for the state of each of them
+    #[serde(default)]
+    show_individual: bool,
+    /// Optionally show the charge as a progress bar
+    #[serde(flatten)]
+    pct_fmt: PercentFormat,
 }
 
 impl Battery {
+    /// How long each frame of the charging animation is shown for
+    const CHARGING_FRAME: Duration = Duration::from_secs(1);
+
     fn detail(theme: &Theme, info: &BatInfo) -> (&'static str, Option<HexColor>, bool) {
         let (charge_icon, charge_fg, urgent) = match info.charge as u32 {
             0..=15 => {
@@ -202,27 +422,117 @@ impl Battery {
         (icon, fg, urgent)
     }
 
+    fn format_detail(info: &BatInfo, watts: Option<f64>) -> String {
+        let mut detail = format!("{}: {:.1}% {}", info.name, info.charge, info.state.label());
+        if let Some(watts) = watts {
+            detail.push_str(&format!(" at {:.2} W", watts));
+        }
+
+        detail
+    }
+
+    /// An arrow showing the direction of the rate, followed by the rate, e.g.: `▼5.2%/h`
+    fn format_rate(rate: f32) -> String {
+        // don't flip between arrows when the rate is about zero
+        let arrow = match rate {
+            r if r >= 0.05 => "▲",
+            r if r <= -0.05 => "▼",
+            _ => "",
+        };
+
+        format!("{}{:.1}%/h", arrow, rate.abs())
+    }
+
+    /// The same colours as the charge percentage, but in reverse: higher draw is worse
+    fn power_color(theme: &Theme, watts: f64) -> Option<HexColor> {
+        match watts as u32 {
+            25..=u32::MAX => Some(theme.red),
+            15..=24 => Some(theme.orange),
+            10..=14 => Some(theme.yellow),
+            5..=9 => None,
+            0..=4 => Some(theme.green),
+        }
+    }
+
+    fn format_power(theme: &Theme, watts: f64) -> String {
+        let text = format!("{:.1}W", watts);
+        match Self::power_color(theme, watts) {
+            Some(fg) => format!(r#"<span foreground="{}">{}</span>"#, fg.display_rgb(), text),
+            None => text,
+        }
+    }
+
+    /// An icon for each battery coloured by its state, e.g. to see which one is charging while
+    /// another is selected. `selected` is the info that was just read for the selected battery.
+    async fn format_individual(
+        theme: &Theme,
+        batteries: &[BatDevice],
+        selected: (usize, &BatInfo),
+    ) -> String {
+        let mut icons = vec![];
+        for (idx, bat) in batteries.iter().enumerate() {
+            let info = match idx == selected.0 {
+                true => None,
+                false => match bat.get_info().await {
+                    Ok(info) => Some(info),
+                    Err(e) => {
+                        log::debug!("failed to read battery {}: {}", idx, e);
+                        continue;
+                    }
+                },
+            };
+
+            icons.push(Self::format_icon(theme, info.as_ref().unwrap_or(selected.1)));
+        }
+
+        icons.join("")
+    }
+
+    fn format_icon(theme: &Theme, info: &BatInfo) -> String {
+        match Self::detail(theme, info) {
+            (icon, Some(fg), _) => {
+                format!(r#"<span foreground="{}">{}</span>"#, fg.display_rgb(), icon)
+            }
+            (icon, None, _) => icon.into(),
+        }
+    }
+
     fn format_watts(_: &Theme, watts: f64) -> I3Item {
         I3Item::new(format!("{:.2} W", watts)).short_text(format!("{:.0}", watts))
     }
 
-    async fn format(_: &Theme, info: &BatInfo, icon: &str) -> I3Item {
+    async fn format(&self, _: &Theme, info: &BatInfo, icon: &str) -> I3Item {
         let name = if info.name == "BAT0" {
             icon
         } else {
             info.name.as_str()
         };
-        I3Item::new(format!("{}  {:.0}%", name, info.charge))
-            .short_text(format!("{:.0}%", info.charge))
+        let charge = self
+            .pct_fmt
+            .format(&format!("{:.0}%", info.charge), info.charge as f64);
+        I3Item::new(format!("{}  {}", name, charge)).short_text(charge)
     }
 }
 
 #[async_trait(?Send)]
 impl BarItem for Battery {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
-        let batteries = match self.batteries.clone() {
-            Some(inner) => inner,
-            None => Bat::find_all().await?,
+        let batteries = match self.source {
+            BatterySource::Sysfs => match self.batteries.clone() {
+                Some(inner) => inner
+                    .into_iter()
+                    .map(|bat| Ok(Bat::new(expand_path(bat.dir)?)))
+                    .collect::<Result<Vec<_>>>()?,
+                None => Bat::find_all().await?,
+            }
+            .into_iter()
+            .map(BatDevice::Sysfs)
+            .collect::<Vec<_>>(),
+            BatterySource::Upower => UPowerBat::find_all()
+                .await?
+                .into_iter()
+                .map(BatDevice::UPower)
+                .collect(),
         };
 
         let mut show_watts = false;
@@ -237,31 +547,97 @@ impl BarItem for Battery {
         let notifications = NotificationsProxy::new(dbus).await?;
         let mut on_acpi_event = battery_acpi_events().await?;
         let mut sent_critical_notification = false;
+        // flash between colours while charging, in time with any other animated items
+        let clock = ctx.clock();
+        let mut last_info = None;
+        // when the battery should next be read, animation frames before this reuse `last_info`
+        let mut next_read = Instant::now();
+        let mut trends = batteries
+            .iter()
+            .map(|_| ChargeTrend::default())
+            .collect::<Vec<_>>();
+        let mut critical_triggers = batteries
+            .iter()
+            .map(|_| CriticalTrigger::default())
+            .collect::<Vec<_>>();
+        let show_individual = self.show_individual && batteries.len() > 1;
+        let mut individual = String::new();
         loop {
             let theme = &ctx.config.theme;
 
-            // get info for selected battery
+            // get info for selected battery, unless this is only an animation frame
             let bat = &batteries[p.idx()];
-            let info = bat.get_info().await?;
-
-            // send critical battery notification if configured
-            if let Some(pct) = self.notify_percentage {
-                let charge = info.charge as u8;
-                if charge <= pct && matches!(info.state, BatState::Discharging) {
-                    notifications.battery_critical(charge).await;
-                    sent_critical_notification = true;
-                } else if sent_critical_notification {
-                    notifications.battery_critical_off().await;
-                    sent_critical_notification = false;
+            let fresh = last_info.is_none();
+            let (info, watts) = match last_info.take() {
+                Some(last) => last,
+                None => {
+                    let info = bat.get_info().await?;
+                    trends[p.idx()].push(Instant::now(), info.charge);
+
+                    // send critical battery notification if configured
+                    if let Some(pct) = self.notify_percentage {
+                        let charge = info.charge as u8;
+                        if charge <= pct && matches!(info.state, BatState::Discharging) {
+                            notifications.battery_critical(charge).await;
+                            sent_critical_notification = true;
+                        } else if sent_critical_notification {
+                            notifications.battery_critical_off().await;
+                            sent_critical_notification = false;
+                        }
+                    }
+
+                    // run the critical action, if configured
+                    if let Some(pct) = self.critical_percentage {
+                        if critical_triggers[p.idx()].update(info.charge, &info.state, pct) {
+                            log::warn!("{} is critically low at {:.0}%", info.name, info.charge);
+                            match &self.critical_action {
+                                CriticalAction::None => {}
+                                CriticalAction::Notify => {
+                                    notifications
+                                        .battery_critical_action(info.charge as u8)
+                                        .await
+                                }
+                                CriticalAction::Command(command) => {
+                                    exec(command, &I3Item::new(format!("{:.0}%", info.charge)))
+                                }
+                            }
+                        }
+                    }
+
+                    // the power draw is read along with the rest of the info, since it's also shown
+                    // in the item's detail
+                    let watts = match bat.watts_now().await {
+                        Ok(watts) => Some(watts),
+                        Err(e) if show_watts => return Err(e),
+                        Err(e) => {
+                            log::debug!("failed to read power draw of {}: {}", info.name, e);
+                            None
+                        }
+                    };
+
+                    (info, watts)
                 }
+            };
+
+            if fresh && show_individual && !show_watts {
+                individual = format!(
+                    " {}",
+                    Self::format_individual(theme, &batteries, (p.idx(), &info)).await
+                );
             }
 
+            let animating = self.animate_charging && matches!(info.state, BatState::Charging);
+
             // build battery item
             let (icon, fg, urgent) = Self::detail(theme, &info);
-            let item = if show_watts {
-                Self::format_watts(theme, bat.watts_now().await?)
+            let fg = if animating && clock.tick(Self::CHARGING_FRAME) % 2 == 1 {
+                Some(theme.green)
             } else {
-                Self::format(theme, &info, icon).await
+                fg
+            };
+            let item = match (show_watts, watts) {
+                (true, Some(watts)) => Self::format_watts(theme, watts),
+                _ => self.format(theme, &info, icon).await,
             };
 
             // format item
@@ -271,9 +647,31 @@ impl BarItem for Battery {
                 (None, false) => item,
             };
 
+            // optionally include the power draw, if it can be read
+            let power = match (self.show_power, show_watts, watts) {
+                (true, false, Some(watts)) => format!(" {}", Self::format_power(theme, watts)),
+                _ => "".into(),
+            };
+
+            // optionally include the charge rate, once there are enough samples to work it out
+            let rate = match (self.show_rate, show_watts, trends[p.idx()].rate()) {
+                (true, false, Some(rate)) => format!(" {}", Self::format_rate(rate)),
+                _ => "".into(),
+            };
+
             // update item
-            let full_text = format!("{}{}", item.get_full_text(), p.format(theme));
-            let item = item.full_text(full_text).markup(I3Markup::Pango);
+            let full_text = format!(
+                "{}{}{}{}{}",
+                item.get_full_text(),
+                if show_watts { "" } else { &individual },
+                power,
+                rate,
+                p.format(theme)
+            );
+            let item = item
+                .full_text(full_text)
+                .markup(I3Markup::Pango)
+                .detail(Self::format_detail(&info, watts));
             ctx.update_item(item).await?;
 
             // change delay if we're displaying watts
@@ -282,8 +680,12 @@ impl BarItem for Battery {
             } else {
                 self.interval
             };
+            if fresh {
+                next_read = Instant::now() + delay;
+            }
 
             // cycle though batteries
+            let delay = next_read.saturating_duration_since(Instant::now());
             let wait_for_click = ctx.delay_with_event_handler(delay, |event| {
                 p.update(&event);
                 if let BarEvent::Click(click) = event {
@@ -295,6 +697,10 @@ impl BarItem for Battery {
             });
 
             tokio::select! {
+                // only swap colours, the battery isn't read again until `next_read`
+                () = clock.wait_for_tick(Self::CHARGING_FRAME), if animating => {
+                    last_info = Some((info, watts));
+                },
                 // reload block on click (or timeout)
                 () = wait_for_click => {},
                 // reload block on any ACPI event
@@ -351,6 +757,7 @@ async fn battery_acpi_events() -> Result<Receiver<BatteryAcpiEvent>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::util::{local_block_on, TestDir};
 
     #[test]
     fn de() {
@@ -359,10 +766,173 @@ mod tests {
         assert_eq!(battery.dir, PathBuf::from(path));
     }
 
+    #[test]
+    fn upower_state() {
+        assert!(matches!(BatState::from_upower(1), BatState::Charging));
+        assert!(matches!(BatState::from_upower(2), BatState::Discharging));
+        assert!(matches!(BatState::from_upower(3), BatState::Discharging));
+        assert!(matches!(BatState::from_upower(4), BatState::Full));
+        assert!(matches!(BatState::from_upower(5), BatState::NotCharging));
+        assert!(matches!(BatState::from_upower(6), BatState::Discharging));
+        assert!(matches!(BatState::from_upower(0), BatState::Unknown));
+        assert!(matches!(BatState::from_upower(42), BatState::Unknown));
+    }
+
     #[test]
     fn name() {
         let battery = Bat::new(PathBuf::from("/sys/class/power_supply/BAT0"));
         assert_eq!(battery.name().unwrap(), "BAT0");
         assert_eq!(battery.name().unwrap(), "BAT0");
     }
+
+    #[test]
+    fn format_detail() {
+        let info = BatInfo {
+            name: "BAT0".into(),
+            charge: 42.0,
+            state: BatState::NotCharging,
+        };
+        assert_eq!(
+            Battery::format_detail(&info, None),
+            "BAT0: 42.0% not charging"
+        );
+        assert_eq!(
+            Battery::format_detail(&info, Some(7.5)),
+            "BAT0: 42.0% not charging at 7.50 W"
+        );
+    }
+
+    #[test]
+    fn format_icon() {
+        let theme = Theme::default();
+        let info = |charge: f32, state: BatState| BatInfo {
+            name: "BAT0".into(),
+            charge,
+            state,
+        };
+
+        assert_eq!(
+            Battery::format_icon(&theme, &info(60.0, BatState::Charging)),
+            format!(r#"<span foreground="{}">󰚥</span>"#, theme.blue.display_rgb())
+        );
+        assert_eq!(
+            Battery::format_icon(&theme, &info(60.0, BatState::Discharging)),
+            "\u{f241}"
+        );
+        assert_eq!(
+            Battery::format_icon(&theme, &info(10.0, BatState::Discharging)),
+            format!("<span foreground=\"{}\">\u{f244}</span>", theme.red.display_rgb())
+        );
+    }
+
+    #[test]
+    fn format_power() {
+        let theme = Theme::default();
+        assert_eq!(Battery::format_power(&theme, 7.0), "7.0W");
+        assert_eq!(
+            Battery::format_power(&theme, 30.0),
+            format!(r#"<span foreground="{}">30.0W</span>"#, theme.red.display_rgb())
+        );
+        assert_eq!(
+            Battery::format_power(&theme, 2.0),
+            format!(r#"<span foreground="{}">2.0W</span>"#, theme.green.display_rgb())
+        );
+    }
+
+    #[test]
+    fn rate_from_two_samples() {
+        let start = Instant::now();
+        let half_hour = start + Duration::from_secs(30 * 60);
+
+        // gained 10% in half an hour
+        assert_eq!(charge_rate((start, 50.0), (half_hour, 60.0)), Some(20.0));
+        // lost 5% in half an hour
+        assert_eq!(charge_rate((start, 50.0), (half_hour, 45.0)), Some(-10.0));
+        // no time has passed, or samples are out of order
+        assert_eq!(charge_rate((start, 50.0), (start, 60.0)), None);
+        assert_eq!(charge_rate((half_hour, 50.0), (start, 60.0)), None);
+    }
+
+    #[test]
+    fn rate_is_smoothed() {
+        let start = Instant::now();
+        let mut trend = ChargeTrend::default();
+        assert_eq!(trend.rate(), None);
+
+        trend.push(start, 50.0);
+        assert_eq!(trend.rate(), None);
+
+        // jitter between samples is ignored, only the oldest and newest samples count
+        for (mins, charge) in [(15, 49.0), (30, 49.5), (45, 48.0), (60, 47.0)] {
+            trend.push(start + Duration::from_secs(mins * 60), charge);
+        }
+        assert_eq!(trend.rate(), Some(-3.0));
+
+        // old samples are dropped
+        trend.push(start + Duration::from_secs(75 * 60), 47.0);
+        assert_eq!(trend.rate(), Some(-2.0));
+    }
+
+    #[test]
+    fn critical_action_once_per_discharge() {
+        use BatState::*;
+
+        let mut trigger = CriticalTrigger::default();
+        let samples = [
+            (10.0, Discharging),
+            (5.0, Discharging),
+            (4.0, Discharging),
+            (3.0, Discharging),
+            // jitter above the threshold doesn't count as a new discharge
+            (6.0, Discharging),
+            (2.0, Discharging),
+        ];
+        let fired = samples
+            .iter()
+            .filter(|(charge, state)| trigger.update(*charge, state, 5))
+            .count();
+        assert_eq!(fired, 1);
+
+        // an unknown state doesn't reset it
+        assert!(!trigger.update(2.0, &Unknown, 5));
+        assert!(!trigger.update(2.0, &Discharging, 5));
+
+        // but charging does
+        assert!(!trigger.update(2.0, &Charging, 5));
+        assert!(trigger.update(2.0, &Discharging, 5));
+    }
+
+    #[test]
+    fn critical_action_config() {
+        let action = |value| serde_json::from_value::<CriticalAction>(value).unwrap();
+        assert_eq!(action(serde_json::json!("notify")), CriticalAction::Notify);
+        assert_eq!(
+            action(serde_json::json!({ "command": "systemctl suspend" })),
+            CriticalAction::Command("systemctl suspend".into())
+        );
+    }
+
+    #[test]
+    fn format_rate() {
+        assert_eq!(Battery::format_rate(12.34), "▲12.3%/h");
+        assert_eq!(Battery::format_rate(-5.0), "▼5.0%/h");
+        assert_eq!(Battery::format_rate(0.01), "0.0%/h");
+    }
+
+    #[test]
+    fn watts_now() {
+        let dir = TestDir::new("bat");
+        let bat = Bat::new(dir.to_path_buf());
+
+        // 12.3 V * 1 A = 12.3 W
+        std::fs::write(dir.join("voltage_now"), "12300000\n").unwrap();
+        std::fs::write(dir.join("current_now"), "1000000\n").unwrap();
+        let (watts, _) = local_block_on(bat.watts_now()).unwrap();
+        assert_eq!(format!("{:.1}", watts.unwrap()), "12.3");
+
+        // `power_now` is preferred when present
+        std::fs::write(dir.join("power_now"), "4500000\n").unwrap();
+        let (watts, _) = local_block_on(bat.watts_now()).unwrap();
+        assert_eq!(format!("{:.1}", watts.unwrap()), "4.5");
+    }
 }
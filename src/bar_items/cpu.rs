@@ -1,15 +1,17 @@
+use std::collections::VecDeque;
 use std::time::Duration;
 
 use async_trait::async_trait;
 use hex_color::HexColor;
 use serde_derive::{Deserialize, Serialize};
 use sysinfo::CpuRefreshKind;
+use tokio::fs;
 
 use crate::context::{BarItem, Context, StopAction};
 use crate::error::Result;
 use crate::i3::{I3Item, I3Markup};
 use crate::theme::Theme;
-use crate::util::format::{float, FloatFormat};
+use crate::util::format::{float, sparkline_glyph, FloatFormat, PercentFormat};
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Cpu {
@@ -17,11 +19,109 @@ pub struct Cpu {
     interval: Duration,
     #[serde(flatten)]
     float_fmt: FloatFormat,
+    /// Optionally show the usage as a progress bar
+    #[serde(flatten)]
+    pct_fmt: PercentFormat,
+    /// Display a sparkline of the last N samples
+    #[serde(default)]
+    sparkline: Option<usize>,
+    /// Display the current cpu frequency
+    #[serde(default)]
+    show_freq: bool,
+    /// How the frequencies of each cpu are combined
+    #[serde(default)]
+    freq_mode: FreqMode,
+}
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FreqMode {
+    #[default]
+    Average,
+    Max,
+}
+
+impl FreqMode {
+    fn combine(&self, freqs: &[u64]) -> Option<u64> {
+        if freqs.is_empty() {
+            return None;
+        }
+
+        match self {
+            FreqMode::Average => Some(freqs.iter().sum::<u64>() / freqs.len() as u64),
+            FreqMode::Max => freqs.iter().max().copied(),
+        }
+    }
+}
+
+/// Reads the current frequency (in kHz) of each cpu. This is empty if cpufreq isn't available,
+/// which is common in virtual machines.
+async fn read_cpu_freqs() -> Vec<u64> {
+    let mut freqs = vec![];
+    let mut entries = match fs::read_dir("/sys/devices/system/cpu").await {
+        Ok(entries) => entries,
+        Err(_) => return freqs,
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        // only `cpuN` directories, there are others like `cpufreq` and `cpuidle`
+        let name = entry.file_name();
+        let is_cpu = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("cpu"))
+            .map_or(false, |n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        if !is_cpu {
+            continue;
+        }
+
+        let path = entry.path().join("cpufreq/scaling_cur_freq");
+        if let Ok(khz) = fs::read_to_string(path).await.map(|s| s.trim().parse::<u64>()) {
+            match khz {
+                Ok(khz) => freqs.push(khz),
+                Err(e) => log::warn!("failed to parse frequency of {:?}: {}", name, e),
+            }
+        }
+    }
+
+    freqs
+}
+
+/// Format a frequency in kHz as MHz, or GHz when it's 1000 MHz or higher
+fn format_freq(khz: u64) -> String {
+    let mhz = khz / 1000;
+    if mhz >= 1000 {
+        format!("{:.2} GHz", mhz as f64 / 1000.0)
+    } else {
+        format!("{} MHz", mhz)
+    }
 }
 
 impl Cpu {
-    fn get_full_text(&self, _: &Theme, pct: f32) -> String {
-        format!(" {}%", float(pct, &self.float_fmt))
+    const MAX_SPARKLINE_LEN: usize = 60;
+
+    fn sparkline_len(&self) -> usize {
+        self.sparkline.unwrap_or(0).min(Self::MAX_SPARKLINE_LEN)
+    }
+
+    fn get_full_text(
+        &self,
+        _: &Theme,
+        pct: f32,
+        freq: Option<u64>,
+        history: &VecDeque<f32>,
+    ) -> String {
+        let usage = format!("{}%", float(pct, &self.float_fmt));
+        let mut text = format!(" {}", self.pct_fmt.format(&usage, pct as f64));
+        if let Some(khz) = freq {
+            text.push(' ');
+            text.push_str(&format_freq(khz));
+        }
+        if !history.is_empty() {
+            text.push(' ');
+            text.extend(history.iter().map(|pct| sparkline_glyph(*pct)));
+        }
+
+        text
     }
 
     fn get_color(&self, theme: &Theme, pct: f32) -> Option<HexColor> {
@@ -37,6 +137,8 @@ impl Cpu {
 #[async_trait(?Send)]
 impl BarItem for Cpu {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
+        let sparkline_len = self.sparkline_len();
+        let mut history = VecDeque::with_capacity(sparkline_len);
         loop {
             let pct = {
                 // refresh cpu usage
@@ -47,8 +149,22 @@ impl BarItem for Cpu {
                 ctx.state.sys.global_cpu_info().cpu_usage()
             };
 
+            if sparkline_len > 0 {
+                if history.len() == sparkline_len {
+                    history.pop_front();
+                }
+                history.push_back(pct);
+            }
+
+            let freq = if self.show_freq {
+                self.freq_mode.combine(&read_cpu_freqs().await)
+            } else {
+                None
+            };
+
             let theme = &ctx.config.theme;
-            let mut item = I3Item::new(self.get_full_text(theme, pct)).markup(I3Markup::Pango);
+            let mut item = I3Item::new(self.get_full_text(theme, pct, freq, &history))
+                .markup(I3Markup::Pango);
             if let Some(fg) = self.get_color(theme, pct) {
                 item = item.color(fg);
             }
@@ -58,3 +174,48 @@ impl BarItem for Cpu {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_freq() {
+        assert_eq!(format_freq(800_000), "800 MHz");
+        assert_eq!(format_freq(999_999), "999 MHz");
+        assert_eq!(format_freq(1_000_000), "1.00 GHz");
+        assert_eq!(format_freq(2_400_000), "2.40 GHz");
+        assert_eq!(format_freq(3_456_789), "3.46 GHz");
+    }
+
+    #[test]
+    fn test_full_text() {
+        let cpu = |style: &str| {
+            serde_json::from_value::<Cpu>(serde_json::json!({ "interval": "1s", "style": style }))
+                .unwrap()
+        };
+        let theme = Theme::default();
+        let history = VecDeque::new();
+
+        assert_eq!(
+            cpu("number").get_full_text(&theme, 60.0, None, &history),
+            "  60%"
+        );
+        assert_eq!(
+            cpu("bar").get_full_text(&theme, 60.0, None, &history),
+            " [███░░]"
+        );
+        assert_eq!(
+            cpu("both").get_full_text(&theme, 60.0, Some(800_000), &history),
+            "  60% [███░░] 800 MHz"
+        );
+    }
+
+    #[test]
+    fn test_freq_mode() {
+        assert_eq!(FreqMode::Average.combine(&[]), None);
+        assert_eq!(FreqMode::Max.combine(&[]), None);
+        assert_eq!(FreqMode::Average.combine(&[1000, 2000, 3000]), Some(2000));
+        assert_eq!(FreqMode::Max.combine(&[1000, 3000, 2000]), Some(3000));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use hex_color::HexColor;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::context::{BarItem, Context, StopAction};
+use crate::error::Result;
+use crate::i3::I3Item;
+use crate::theme::Theme;
+use crate::util::expand_path;
+use crate::util::format::bytes;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DirSize {
+    #[serde(with = "crate::human_time")]
+    interval: Duration,
+    /// The directory to measure, e.g. `~/Downloads`
+    path: PathBuf,
+    /// Size in bytes at which the directory is considered full, used to colour the item
+    max: u64,
+    /// How long a single traversal may take before giving up and showing a partial size.
+    /// Defaults to 10 seconds.
+    #[serde(default, with = "crate::human_time::option")]
+    timeout: Option<Duration>,
+}
+
+/// The result of walking a directory
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct DirUsage {
+    size: u64,
+    /// Whether the traversal stopped early because it took too long
+    partial: bool,
+}
+
+/// Recursively sum the size of all files under `root`. Symlinks aren't followed, and any entries
+/// which can't be read (e.g. because of permissions) are skipped.
+fn dir_usage(root: &Path, deadline: Instant) -> DirUsage {
+    let mut usage = DirUsage::default();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => continue,
+            Err(e) => {
+                log::debug!("failed to read {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            if Instant::now() >= deadline {
+                usage.partial = true;
+                return usage;
+            }
+
+            let meta = match entry.metadata() {
+                Ok(meta) => meta,
+                Err(_) => continue,
+            };
+
+            if meta.is_dir() {
+                dirs.push(entry.path());
+            } else if meta.is_file() {
+                usage.size += meta.len();
+            }
+        }
+    }
+
+    usage
+}
+
+impl DirSize {
+    /// Walking large directories is expensive, so don't allow it to happen too often
+    const MIN_INTERVAL: Duration = Duration::from_secs(30);
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn validate(&self) -> Result<()> {
+        if self.interval < Self::MIN_INTERVAL {
+            bail!("dir_size interval must be at least {:?}", Self::MIN_INTERVAL);
+        }
+
+        if self.max == 0 {
+            bail!("dir_size max must be greater than 0");
+        }
+
+        Ok(())
+    }
+
+    fn get_color(&self, theme: &Theme, size: u64) -> Option<HexColor> {
+        match (size as f64 / self.max as f64 * 100.0) as u64 {
+            100..=u64::MAX => Some(theme.red),
+            90..=99 => Some(theme.orange),
+            75..=89 => Some(theme.yellow),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl BarItem for DirSize {
+    async fn start(&self, mut ctx: Context) -> Result<StopAction> {
+        let path = expand_path(&self.path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+        let timeout = self.timeout.unwrap_or(Self::DEFAULT_TIMEOUT);
+
+        loop {
+            // the traversal blocks, so run it off the main thread
+            let root = path.clone();
+            let usage =
+                tokio::task::spawn_blocking(move || dir_usage(&root, Instant::now() + timeout))
+                    .await?;
+
+            if usage.partial {
+                log::warn!(
+                    "reading {} took longer than {:?}, size is incomplete",
+                    path.display(),
+                    timeout
+                );
+            }
+
+            let size = bytes(usage.size, ctx.config.units);
+            let mut item = I3Item::new(format!(
+                "󰉋 {} {}{}",
+                name,
+                size,
+                if usage.partial { "+" } else { "" }
+            ))
+            .short_text(size);
+
+            if let Some(fg) = self.get_color(&ctx.config.theme, usage.size) {
+                item = item.color(fg);
+            }
+
+            ctx.update_item(item).await?;
+            ctx.delay_with_event_handler(self.interval, |_| async {}).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TestDir;
+
+    #[test]
+    fn accumulates_size() {
+        let root = TestDir::new("dir-size");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("one"), [0; 100]).unwrap();
+        fs::write(root.join("a/two"), [0; 20]).unwrap();
+        fs::write(root.join("a/b/three"), [0; 3]).unwrap();
+        // symlinks aren't followed
+        std::os::unix::fs::symlink(root.join("a"), root.join("link")).unwrap();
+
+        let usage = dir_usage(&root, Instant::now() + Duration::from_secs(60));
+        assert_eq!(
+            usage,
+            DirUsage {
+                size: 123,
+                partial: false
+            }
+        );
+    }
+
+    #[test]
+    fn missing_dir_is_empty() {
+        let usage = dir_usage(
+            Path::new("/this/path/does/not/exist"),
+            Instant::now() + Duration::from_secs(60),
+        );
+        assert_eq!(usage, DirUsage::default());
+    }
+
+    #[test]
+    fn stops_at_deadline() {
+        let root = TestDir::new("dir-timeout");
+        fs::write(root.join("file"), [0; 10]).unwrap();
+
+        let usage = dir_usage(&root, Instant::now());
+        assert_eq!(
+            usage,
+            DirUsage {
+                size: 0,
+                partial: true
+            }
+        );
+    }
+
+    #[test]
+    fn validate_interval_and_max() {
+        let dir_size = |interval, max| DirSize {
+            interval: Duration::from_secs(interval),
+            path: PathBuf::from("/tmp"),
+            max,
+            timeout: None,
+        };
+
+        assert!(dir_size(60, 1024).validate().is_ok());
+        assert!(dir_size(1, 1024).validate().is_err());
+        assert!(dir_size(60, 0).validate().is_err());
+    }
+}
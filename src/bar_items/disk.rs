@@ -1,18 +1,21 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
-use bytesize::ByteSize;
 use hex_color::HexColor;
+use nix::sys::statvfs::statvfs;
 use serde_derive::{Deserialize, Serialize};
 use sysinfo::{Disk as SysDisk, Disks};
+use tokio::process::Command;
+use tokio::time::timeout;
 
 use crate::context::{BarItem, Context, StopAction};
 use crate::error::Result;
-use crate::i3::{I3Item, I3Markup};
+use crate::i3::{pango_escape, I3Item, I3Markup};
 use crate::theme::Theme;
-use crate::util::{expand_path, Paginator};
+use crate::util::format::{bytes, PercentFormat, SizeUnits};
+use crate::util::{expand_path, output_bounded, Paginator};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiskAlias {
@@ -20,6 +23,17 @@ pub struct DiskAlias {
     name: String,
 }
 
+/// What's measured (and displayed) for each disk
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Measure {
+    /// Free space
+    #[default]
+    Bytes,
+    /// Free inodes
+    Inodes,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Disk {
     #[serde(with = "crate::human_time")]
@@ -28,28 +42,119 @@ pub struct Disk {
     mounts: HashSet<PathBuf>,
     #[serde(default)]
     aliases: Vec<DiskAlias>,
+    /// Optional command to check the health of each disk (e.g., `smartctl -H $DISK_DEVICE`).
+    /// A non-zero exit status marks the disk as unhealthy.
+    #[serde(default)]
+    health_command: Option<String>,
+    /// How often `health_command` is run. Defaults to once an hour.
+    #[serde(default, with = "crate::human_time::option")]
+    health_interval: Option<Duration>,
+    /// Whether to display free space or free inodes
+    #[serde(default)]
+    measure: Measure,
+    /// Optionally show how full each disk is as a progress bar
+    #[serde(flatten)]
+    pct_fmt: PercentFormat,
+}
+
+impl Disk {
+    const DEFAULT_HEALTH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+    /// How long `health_command` may run before it's killed, so a hung command can't stall the item
+    const HEALTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Runs `health_command` for the given disk, returning whether it's healthy
+    async fn check_health(&self, command: &str, disk: &SysDisk) -> bool {
+        // the command is killed when this is dropped, since it's spawned with `kill_on_drop`
+        let output = timeout(
+            Self::HEALTH_TIMEOUT,
+            output_bounded(
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("DISK_DEVICE", disk.name())
+                    .env("DISK_MOUNT_POINT", disk.mount_point()),
+            ),
+        )
+        .await;
+
+        match output {
+            Ok(Ok(output)) => match output.into_result() {
+                Ok(_) => true,
+                Err(e) => {
+                    log::warn!("health check failed for {}: {}", disk.mount_point().display(), e);
+                    false
+                }
+            },
+            // don't mark the disk as unhealthy if the command itself couldn't be run or finish
+            Ok(Err(e)) => {
+                log::error!("failed to run health command --> {} <-- {}", command, e);
+                true
+            }
+            Err(_) => {
+                log::error!(
+                    "health command timed out after {:?} --> {} <--",
+                    Self::HEALTH_TIMEOUT,
+                    command
+                );
+                true
+            }
+        }
+    }
+}
+
+/// Percentage of `total` which is available. Some filesystems (e.g., btrfs) report no inodes at
+/// all, which is treated as entirely available.
+fn available_pct(available: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 100.0;
+    }
+
+    (available as f64 / total as f64) * 100.0
+}
+
+/// Returns the free and total inodes (`f_ffree` and `f_files`) of the given mount point
+fn inode_usage(disk: &SysDisk) -> Option<(u64, u64)> {
+    match statvfs(disk.mount_point()) {
+        Ok(stat) => Some((stat.files_free() as u64, stat.files() as u64)),
+        Err(e) => {
+            log::warn!("failed to statvfs {}: {}", disk.mount_point().display(), e);
+            None
+        }
+    }
 }
 
 struct DiskStats {
     alias: Option<String>,
     mount_point: PathBuf,
-    available_bytes: u64,
-    total_bytes: u64,
+    measure: Measure,
+    available: u64,
+    total: u64,
+    healthy: bool,
 }
 
 impl DiskStats {
-    fn new(disk: &SysDisk, alias: Option<String>) -> DiskStats {
+    fn new(disk: &SysDisk, alias: Option<String>, healthy: bool, measure: Measure) -> DiskStats {
+        let (available, total) = match measure {
+            Measure::Bytes => (disk.available_space(), disk.total_space()),
+            Measure::Inodes => inode_usage(disk).unwrap_or_default(),
+        };
+
         DiskStats {
             alias,
             mount_point: disk.mount_point().to_path_buf(),
-            available_bytes: disk.available_space(),
-            total_bytes: disk.total_space(),
+            measure,
+            available,
+            total,
+            healthy,
         }
     }
 
     fn get_color(&self, theme: &Theme) -> Option<HexColor> {
-        let pct = (self.available_bytes as f64 / self.total_bytes as f64) * 100.0;
-        match pct as u32 {
+        if !self.healthy {
+            return Some(theme.red);
+        }
+
+        match available_pct(self.available, self.total) as u32 {
             0..=10 => Some(theme.red),
             11..=20 => Some(theme.orange),
             21..=30 => Some(theme.yellow),
@@ -57,18 +162,29 @@ impl DiskStats {
         }
     }
 
-    fn format(&self, _: &Theme) -> (String, String) {
-        let name = self
-            .alias
-            .as_ref()
-            .cloned()
-            .unwrap_or_else(|| self.mount_point.to_string_lossy().to_string());
+    fn format(&self, units: SizeUnits, pct_fmt: &PercentFormat) -> (String, String) {
+        // mount points and aliases may contain anything, so they're escaped
+        let name = pango_escape(
+            &self
+                .alias
+                .as_ref()
+                .cloned()
+                .unwrap_or_else(|| self.mount_point.to_string_lossy().to_string()),
+        );
+
+        let available = match self.measure {
+            Measure::Bytes => bytes(self.available, units),
+            Measure::Inodes => format!("{:.0}% inodes", available_pct(self.available, self.total)),
+        };
+        let used_pct = 100.0 - available_pct(self.available, self.total);
+        let available = pct_fmt.format(&available, used_pct);
 
         (
             format!(
-                "󰋊 {} {}",
+                "{} {} {}",
+                if self.healthy { "󰋊" } else { "󱁌" },
                 name,
-                ByteSize(self.available_bytes).to_string_as(true)
+                available
             ),
             name,
         )
@@ -80,39 +196,59 @@ impl BarItem for Disk {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
         let mut p = Paginator::new();
         let mut disks = Disks::new();
+        let mut health = HashMap::new();
+        let mut last_health_check: Option<Instant> = None;
+        let mounts = self
+            .mounts
+            .iter()
+            .map(expand_path)
+            .collect::<Result<HashSet<_>>>()?;
+        let health_interval = self
+            .health_interval
+            .unwrap_or(Self::DEFAULT_HEALTH_INTERVAL);
         loop {
-            let stats: Vec<DiskStats> = {
-                disks.refresh();
-                disks.refresh_list();
-                disks
-                    .iter()
-                    .filter(|d| {
-                        if self.mounts.is_empty() {
-                            true
-                        } else {
-                            self.mounts.contains(d.mount_point())
-                        }
-                    })
-                    .map(|d| {
-                        DiskStats::new(
-                            d,
-                            self.aliases
-                                .iter()
-                                .find(|a| {
-                                    expand_path(&a.path).map_or(false, |p| p == d.mount_point())
-                                })
-                                .map(|a| a.name.clone()),
-                        )
-                    })
-                    .collect()
-            };
+            disks.refresh();
+            disks.refresh_list();
+            let filtered = disks.iter().filter(|d| {
+                if mounts.is_empty() {
+                    true
+                } else {
+                    mounts.contains(d.mount_point())
+                }
+            });
+
+            // periodically check disk health, if configured
+            if let Some(command) = &self.health_command {
+                if last_health_check.map_or(true, |t| t.elapsed() >= health_interval) {
+                    for d in filtered.clone() {
+                        let healthy = self.check_health(command, d).await;
+                        health.insert(d.mount_point().to_path_buf(), healthy);
+                    }
+
+                    last_health_check = Some(Instant::now());
+                }
+            }
+
+            let stats: Vec<DiskStats> = filtered
+                .map(|d| {
+                    DiskStats::new(
+                        d,
+                        self.aliases
+                            .iter()
+                            .find(|a| expand_path(&a.path).map_or(false, |p| p == d.mount_point()))
+                            .map(|a| a.name.clone()),
+                        health.get(d.mount_point()).cloned().unwrap_or(true),
+                        self.measure,
+                    )
+                })
+                .collect();
             let len = stats.len();
             if len > 0 {
                 p.set_len(len)?;
 
                 let disk = &stats[p.idx()];
                 let theme = &ctx.config.theme;
-                let (full, short) = disk.format(theme);
+                let (full, short) = disk.format(ctx.config.units, &self.pct_fmt);
                 let full = format!("{}{}", full, p.format(theme));
 
                 let mut item = I3Item::new(full)
@@ -138,3 +274,53 @@ impl BarItem for Disk {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(available: u64, total: u64) -> DiskStats {
+        DiskStats {
+            alias: Some("root".into()),
+            mount_point: PathBuf::from("/"),
+            measure: Measure::Inodes,
+            available,
+            total,
+            healthy: true,
+        }
+    }
+
+    #[test]
+    fn inode_percentage() {
+        // synthetic `f_ffree` and `f_files` values
+        assert_eq!(available_pct(500_000, 1_000_000), 50.0);
+        assert_eq!(available_pct(0, 1_000_000), 0.0);
+        assert_eq!(available_pct(1_000_000, 1_000_000), 100.0);
+        // filesystems without a fixed number of inodes
+        assert_eq!(available_pct(0, 0), 100.0);
+    }
+
+    #[test]
+    fn inode_color_and_format() {
+        let theme = Theme::default();
+        assert_eq!(stats(50_000, 1_000_000).get_color(&theme), Some(theme.red));
+        assert_eq!(stats(150_000, 1_000_000).get_color(&theme), Some(theme.orange));
+        assert_eq!(stats(250_000, 1_000_000).get_color(&theme), Some(theme.yellow));
+        assert_eq!(stats(900_000, 1_000_000).get_color(&theme), None);
+        assert_eq!(stats(0, 0).get_color(&theme), None);
+
+        let (full, short) =
+            stats(250_000, 1_000_000).format(SizeUnits::default(), &PercentFormat::default());
+        assert_eq!(full, "󰋊 root 25% inodes");
+        assert_eq!(short, "root");
+
+        // the bar shows how much is used
+        let pct_fmt = serde_json::from_value::<PercentFormat>(serde_json::json!({
+            "style": "both",
+            "bar_width": 4,
+        }))
+        .unwrap();
+        let (full, _) = stats(250_000, 1_000_000).format(SizeUnits::default(), &pct_fmt);
+        assert_eq!(full, "󰋊 root 25% inodes [███░]");
+    }
+}
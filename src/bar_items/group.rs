@@ -0,0 +1,353 @@
+use async_trait::async_trait;
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::item::{Common, Item, Retry};
+use crate::context::{BarEvent, BarItem, Context, StopAction};
+use crate::error::Result;
+use crate::i3::{pango_escape, I3Button, I3ClickEvent, I3Item, I3Markup};
+use crate::theme::{rainbow, Theme};
+use crate::util::format::truncate;
+
+/// Groups other items into a single block, which displays a summary until it's clicked
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Group {
+    /// Text shown when the group is collapsed (and before the items when it's expanded).
+    /// This is interpreted as pango markup.
+    #[serde(default = "Group::default_summary")]
+    summary: String,
+    /// The items in this group
+    items: Vec<Item>,
+    /// Whether the group starts expanded
+    #[serde(default)]
+    expanded: bool,
+}
+
+/// Aborts the group's child items when the group stops
+struct ChildTasks(Vec<JoinHandle<()>>);
+
+impl Drop for ChildTasks {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// Roughly how many characters are displayed for the given pango markup
+fn visible_len(markup: &str) -> usize {
+    let mut in_tag = false;
+    markup
+        .chars()
+        .filter(|c| match c {
+            '<' => {
+                in_tag = true;
+                false
+            }
+            '>' => {
+                in_tag = false;
+                false
+            }
+            _ => !in_tag,
+        })
+        .count()
+}
+
+/// Given the (visible) length of each segment, find which one contains the given fraction of the
+/// total length. Segments are separated by a single space.
+fn segment_at(lengths: &[usize], fraction: f64) -> Option<usize> {
+    let total = lengths.iter().sum::<usize>() + lengths.len().saturating_sub(1);
+    if total == 0 {
+        return None;
+    }
+
+    let target = fraction.clamp(0.0, 1.0) * total as f64;
+    let mut end = 0;
+    for (idx, len) in lengths.iter().enumerate() {
+        // the trailing space counts towards the segment before it
+        end += len + 1;
+        if target < end as f64 {
+            return Some(idx);
+        }
+    }
+
+    lengths.len().checked_sub(1)
+}
+
+impl Group {
+    fn default_summary() -> String {
+        "…".into()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for item in &self.items {
+            // these are handled by the bar for each of its blocks, but a group is a single block
+            let unsupported = [
+                ("signal", item.common.signal.is_some()),
+                ("separator", item.common.separator.is_some()),
+                ("log_level", item.common.log_level.is_some()),
+                ("retry", item.common.retry != Retry::default()),
+            ];
+            if let Some((option, _)) = unsupported.iter().find(|(_, set)| *set) {
+                bail!(
+                    "`{}` isn't supported for items within a group (found on {})",
+                    option,
+                    item.name()
+                );
+            }
+
+            item.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply the child's `max_width` and `rainbow` options, like the bar does for its items
+    fn style_child(common: &Common, theme: &Theme, mut item: I3Item) -> I3Item {
+        if let Some(max_width) = common.max_width {
+            let pango = matches!(item.get_markup(), Some(I3Markup::Pango));
+            item.full_text = truncate(&item.full_text, max_width, pango);
+        }
+
+        if common.rainbow == Some(true) {
+            let pango = matches!(item.get_markup(), Some(I3Markup::Pango));
+            item.full_text = rainbow(&item.full_text, &theme.rainbow_colors(), pango);
+            item = item.markup(I3Markup::Pango);
+        }
+
+        item
+    }
+
+    /// Formats a child item as pango markup
+    fn format_child(item: &I3Item) -> String {
+        let text = match item.get_markup() {
+            Some(I3Markup::Pango) => item.get_full_text().clone(),
+            _ => pango_escape(item.get_full_text()),
+        };
+
+        match item.get_color() {
+            Some(color) => format!(
+                r#"<span foreground="{}">{}</span>"#,
+                color.display_rgb(),
+                text
+            ),
+            None => text,
+        }
+    }
+
+    /// Returns the indices of children that are displayed, along with their markup
+    fn segments(&self, children: &[I3Item]) -> Vec<(Option<usize>, String)> {
+        let mut segments = vec![(None, self.summary.clone())];
+        segments.extend(
+            children
+                .iter()
+                .enumerate()
+                .filter(|(_, item)| !item.is_empty())
+                .map(|(idx, item)| (Some(idx), Self::format_child(item))),
+        );
+
+        segments
+    }
+
+    fn render(&self, theme: &Theme, children: &[I3Item], expanded: bool) -> I3Item {
+        let urgent = children
+            .iter()
+            .any(|item| item.get_urgent().copied().unwrap_or(false));
+
+        let item = if expanded {
+            let segments = self.segments(children);
+            I3Item::new(
+                segments
+                    .into_iter()
+                    .map(|(_, text)| text)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+        } else {
+            I3Item::new(&self.summary)
+        };
+
+        let item = item.markup(I3Markup::Pango);
+        match (urgent, expanded) {
+            // there's no way to tell which child is urgent when collapsed, so mark the group
+            (true, false) => item.urgent(true),
+            _ => item.color(theme.fg),
+        }
+    }
+
+    /// Find which child was clicked, `None` means the summary was clicked
+    fn clicked_child(&self, children: &[I3Item], click: &I3ClickEvent) -> Option<usize> {
+        if click.width == 0 {
+            return None;
+        }
+
+        let segments = self.segments(children);
+        let lengths = segments
+            .iter()
+            .map(|(_, text)| visible_len(text))
+            .collect::<Vec<_>>();
+
+        let fraction = click.relative_x as f64 / click.width as f64;
+        segment_at(&lengths, fraction).and_then(|idx| segments[idx].0)
+    }
+}
+
+#[async_trait(?Send)]
+impl BarItem for Group {
+    async fn start(&self, mut ctx: Context) -> Result<StopAction> {
+        if self.items.is_empty() {
+            bail!("a group must contain at least one item");
+        }
+
+        // start each child item, just like the bar does
+        let (item_tx, mut item_rx) = mpsc::channel(self.items.len() + 1);
+        let mut event_txs = vec![];
+        let mut tasks = ChildTasks(vec![]);
+        for (idx, item) in self.items.iter().enumerate() {
+            let (event_tx, event_rx) = mpsc::channel(32);
+            event_txs.push(event_tx);
+
+            let child_ctx = Context::new(
+                ctx.config.clone(),
+                ctx.state.clone(),
+                item_tx.clone(),
+                event_rx,
+                idx,
+                &item.common,
+            );
+
+            let bar_item = item.to_bar_item();
+            let name = item.name().clone();
+            let item_tx = item_tx.clone();
+            // read the theme when the error happens, since it may have changed via ipc since then
+            let config = ctx.config.clone();
+            tasks.0.push(tokio::task::spawn_local(async move {
+                let item = match bar_item.start(child_ctx).await {
+                    Ok(StopAction::Complete) => return,
+                    Ok(StopAction::Remove) => I3Item::empty(),
+                    Ok(StopAction::Restart) => {
+                        log::warn!("group item {} requested restart, which isn't supported", name);
+                        return;
+                    }
+                    Err(e) => {
+                        log::error!("group item {} exited with error: {}", name, e);
+                        I3Item::new(format!("ERROR({})", name))
+                            .color(config.theme.bg)
+                            .background_color(config.theme.red)
+                    }
+                };
+
+                let _ = item_tx.send((item, idx)).await;
+            }));
+        }
+
+        let mut children = vec![I3Item::empty(); self.items.len()];
+        let mut expanded = self.expanded;
+        loop {
+            ctx.update_item(self.render(&ctx.config.theme, &children, expanded)).await?;
+
+            tokio::select! {
+                Some((item, idx)) = item_rx.recv() => {
+                    children[idx] = Self::style_child(&self.items[idx].common, &ctx.config.theme, item);
+                }
+                Some(event) = ctx.raw_event() => match event {
+                    BarEvent::Click(click) => {
+                        let child = if expanded {
+                            self.clicked_child(&children, &click)
+                        } else {
+                            None
+                        };
+
+                        match child {
+                            Some(idx) => {
+                                let _ = event_txs[idx].send(BarEvent::Click(click)).await;
+                            }
+                            // clicking the summary toggles the group
+                            None if click.button == I3Button::Left => expanded = !expanded,
+                            None => {}
+                        }
+                    }
+                    BarEvent::Signal => {
+                        for tx in &event_txs {
+                            let _ = tx.send(BarEvent::Signal).await;
+                        }
+                    }
+                    BarEvent::Visibility(visible) => {
+                        for tx in &event_txs {
+                            let _ = tx.send(BarEvent::Visibility(visible)).await;
+                        }
+                    }
+                    // there's no child to pass custom events to, dropping the responder lets the
+                    // sender know nothing is listening
+                    BarEvent::Custom { .. } => {}
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visible_length() {
+        assert_eq!(visible_len("abc"), 3);
+        assert_eq!(visible_len(r#"<span foreground="red">abc</span>"#), 3);
+        assert_eq!(visible_len(""), 0);
+    }
+
+    #[test]
+    fn segments() {
+        // "… ab cdef"
+        let lengths = [1, 2, 4];
+        assert_eq!(segment_at(&lengths, 0.0), Some(0));
+        assert_eq!(segment_at(&lengths, 0.15), Some(0));
+        assert_eq!(segment_at(&lengths, 0.3), Some(1));
+        assert_eq!(segment_at(&lengths, 0.6), Some(2));
+        assert_eq!(segment_at(&lengths, 1.0), Some(2));
+        assert_eq!(segment_at(&[], 0.5), None);
+    }
+
+    #[test]
+    fn unsupported_child_options() {
+        let group = |child: serde_json::Value| {
+            serde_json::from_value::<Group>(serde_json::json!({ "items": [child] })).unwrap()
+        };
+
+        assert!(group(serde_json::json!({ "type": "raw", "max_width": 4 }))
+            .validate()
+            .is_ok());
+        for (option, value) in [
+            ("signal", serde_json::json!(1)),
+            ("separator", serde_json::json!(false)),
+            ("log_level", serde_json::json!("debug")),
+            ("retry", serde_json::json!({ "max_retries": 1 })),
+        ] {
+            let err = group(serde_json::json!({ "type": "raw", option: value }))
+                .validate()
+                .unwrap_err();
+            assert!(err.to_string().contains(option), "{}", err);
+        }
+    }
+
+    #[test]
+    fn styles_children() {
+        let common = Common {
+            max_width: Some(4),
+            ..Default::default()
+        };
+        let item = Group::style_child(&common, &Theme::default(), I3Item::new("abcdefg"));
+        assert_eq!(item.get_full_text(), "abc…");
+    }
+
+    #[test]
+    fn escapes_plain_children() {
+        let item = I3Item::new("a < b & c");
+        assert_eq!(Group::format_child(&item), "a &lt; b &amp; c");
+
+        let item = I3Item::new("<b>bold</b>").markup(I3Markup::Pango);
+        assert_eq!(Group::format_child(&item), "<b>bold</b>");
+    }
+}
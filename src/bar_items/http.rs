@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::context::{BarItem, Context, StopAction};
+use crate::error::Result;
+use crate::i3::{I3Item, I3Markup};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Http {
+    /// The url to request, this should respond with JSON
+    url: String,
+    /// A JSON pointer to the value to display, if not set the whole response is displayed
+    /// See: https://datatracker.ietf.org/doc/html/rfc6901
+    #[serde(default)]
+    pointer: Option<String>,
+    /// Text to display, any `{}` is replaced with the value
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(with = "crate::human_time")]
+    interval: Duration,
+    /// How long to wait for a response before giving up
+    #[serde(default = "Http::default_timeout", with = "crate::human_time")]
+    timeout: Duration,
+    #[serde(default)]
+    markup: I3Markup,
+}
+
+impl Http {
+    /// Don't hammer remote servers, no matter what's configured
+    const MIN_INTERVAL: Duration = Duration::from_secs(5);
+
+    fn default_timeout() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    async fn fetch(&self, client: &reqwest::Client) -> Result<String> {
+        let json = client
+            .get(&self.url)
+            .timeout(self.timeout)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        let value = match &self.pointer {
+            Some(pointer) => match json.pointer(pointer) {
+                Some(value) => value,
+                None => bail!("no value found at {} in response", pointer),
+            },
+            None => &json,
+        };
+
+        Ok(match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl BarItem for Http {
+    async fn start(&self, mut ctx: Context) -> Result<StopAction> {
+        let interval = if self.interval < Self::MIN_INTERVAL {
+            log::warn!(
+                "interval {:?} is below the minimum, using {:?}",
+                self.interval,
+                Self::MIN_INTERVAL
+            );
+            Self::MIN_INTERVAL
+        } else {
+            self.interval
+        };
+
+        let client = ctx.state.http_client();
+        loop {
+            let item = match self.fetch(&client).await {
+                Ok(value) => I3Item::new(match &self.format {
+                    Some(fmt) => fmt.replace("{}", &value),
+                    None => value,
+                })
+                .markup(self.markup),
+                // network errors are usually transient, so show an error and try again later
+                Err(e) => {
+                    log::error!("failed to fetch {}: {}", self.url, e);
+                    I3Item::new("ERROR").background_color(ctx.config.theme.red)
+                }
+            };
+
+            ctx.update_item(item).await?;
+
+            // refresh on interval or any event
+            ctx.wait_for_event(Some(interval)).await;
+        }
+    }
+}
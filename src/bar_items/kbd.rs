@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -15,9 +16,15 @@ pub struct Kbd {
     show: Option<Vec<Keys>>,
     #[serde(default, with = "crate::human_time::option")]
     interval: Option<Duration>,
+    /// Override the labels used for each key
+    #[serde(default)]
+    labels: HashMap<Keys, String>,
+    /// Only show keys which are on, hiding the item entirely if all are off
+    #[serde(default)]
+    hide_inactive: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy, EnumIter, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, EnumIter, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum Keys {
     CapsLock,
@@ -72,23 +79,65 @@ impl Keys {
         }
     }
 
-    async fn format(self, theme: &Theme) -> Result<String> {
-        Ok(match self.is_on().await {
-            Ok(is_on) => format!(
+    /// Returns `None` if the key is off and inactive keys should be hidden
+    async fn format(self, label: &str, hide_inactive: bool, theme: &Theme) -> Option<String> {
+        match self.is_on().await {
+            Ok(false) if hide_inactive => None,
+            Ok(is_on) => Some(format!(
                 r#"<span foreground="{}">{}</span>"#,
                 (if is_on { theme.fg } else { theme.dim }).display_rgb(),
-                self.symbol()
-            ),
+                label
+            )),
             Err(e) => {
                 log::error!("{}", e);
-                format!(
+                Some(format!(
                     r#"<span background="{}" foreground="{}">{}</span>"#,
                     theme.red.display_rgb(),
                     theme.bg.display_rgb(),
-                    self.symbol()
-                )
+                    label
+                ))
             }
-        })
+        }
+    }
+}
+
+/// Watches a keyboard's input events for LED changes, so the item can be updated instantly
+#[cfg(feature = "evdev")]
+struct LedWatcher {
+    stream: evdev::EventStream,
+}
+
+#[cfg(feature = "evdev")]
+impl LedWatcher {
+    /// Opens the first input device which has a caps lock LED
+    fn open() -> Result<LedWatcher> {
+        let device = evdev::enumerate()
+            .map(|(_, device)| device)
+            .find(|device| {
+                device
+                    .supported_leds()
+                    .map_or(false, |leds| leds.contains(evdev::LedType::LED_CAPSL))
+            });
+
+        match device {
+            Some(device) => {
+                log::debug!("watching {:?} for led events", device.name());
+                Ok(LedWatcher {
+                    stream: device.into_event_stream()?,
+                })
+            }
+            None => bail!("no readable input device with leds found"),
+        }
+    }
+
+    /// Resolves when any LED changes
+    async fn wait_for_change(&mut self) -> Result<()> {
+        loop {
+            let event = self.stream.next_event().await?;
+            if event.event_type() == evdev::EventType::LED {
+                return Ok(());
+            }
+        }
     }
 }
 
@@ -97,21 +146,70 @@ impl BarItem for Kbd {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
         let keys = self.show.clone().unwrap_or_else(|| Keys::iter().collect());
 
-        'outer: loop {
-            let text = futures::future::join_all(keys.iter().map(|k| k.format(&ctx.config.theme)))
-                .await
-                .into_iter()
-                .collect::<Result<Vec<_>>>()?
-                .join("");
+        #[cfg(feature = "evdev")]
+        let mut watcher = match LedWatcher::open() {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                log::error!(
+                    "failed to watch input devices, falling back to polling (reading /dev/input/event* usually requires being in the `input` group): {}",
+                    e
+                );
+                None
+            }
+        };
+
+        loop {
+            let text = futures::future::join_all(keys.iter().map(|k| {
+                k.format(self.label(*k), self.hide_inactive, &ctx.config.theme)
+            }))
+            .await
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("");
 
-            let item = I3Item::new(text).markup(I3Markup::Pango);
+            let item = if text.is_empty() {
+                I3Item::empty()
+            } else {
+                I3Item::new(text).markup(I3Markup::Pango)
+            };
             ctx.update_item(item).await?;
 
-            // wait for a signal and then refresh
-            loop {
-                if let Some(BarEvent::Signal) = ctx.wait_for_event(self.interval).await {
-                    continue 'outer;
+            #[cfg(feature = "evdev")]
+            if let Some(inner) = watcher.as_mut() {
+                let result = tokio::select! {
+                    result = inner.wait_for_change() => result,
+                    _ = self.wait_for_refresh(&mut ctx) => Ok(()),
+                };
+
+                if let Err(e) = result {
+                    log::error!("failed to read input events, falling back to polling: {}", e);
+                    watcher = None;
                 }
+
+                continue;
+            }
+
+            self.wait_for_refresh(&mut ctx).await;
+        }
+    }
+}
+
+impl Kbd {
+    fn label(&self, key: Keys) -> &str {
+        self.labels
+            .get(&key)
+            .map(|s| s.as_str())
+            .unwrap_or_else(|| key.symbol())
+    }
+
+    /// Waits for a signal, or the interval to elapse (if set)
+    async fn wait_for_refresh(&self, ctx: &mut Context) {
+        loop {
+            match ctx.wait_for_event(self.interval).await {
+                // timed out, or signalled
+                None | Some(BarEvent::Signal) => return,
+                _ => {}
             }
         }
     }
@@ -13,6 +13,7 @@ use tokio::fs;
 use crate::context::{BarEvent, BarItem, Context, CustomResponse, StopAction};
 use crate::error::Result;
 use crate::i3::{I3Button, I3Item};
+use crate::util::expand_path;
 
 struct LightFile {
     /// Max brightness of this device
@@ -130,7 +131,7 @@ pub struct Light {
 impl BarItem for Light {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
         let light = match &self.path {
-            Some(path) => LightFile::new(path).await?,
+            Some(path) => LightFile::new(expand_path(path)?).await?,
             None => LightFile::detect().await?,
         };
 
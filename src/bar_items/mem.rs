@@ -1,16 +1,16 @@
 use std::time::Duration;
 
 use async_trait::async_trait;
-use bytesize::ByteSize;
 use hex_color::HexColor;
 use serde_derive::{Deserialize, Serialize};
 use strum::EnumIter;
+use tokio::fs;
 
 use crate::context::{BarEvent, BarItem, Context, StopAction};
 use crate::error::Result;
 use crate::i3::{I3Button, I3Item, I3Markup};
 use crate::theme::Theme;
-use crate::util::format::{float, FloatFormat};
+use crate::util::format::{bytes, float, FloatFormat, PercentFormat};
 use crate::util::EnumCycle;
 
 #[derive(Debug, Default, Copy, Clone, Serialize, Deserialize, PartialEq, EnumIter)]
@@ -27,8 +27,54 @@ pub struct Mem {
     interval: Duration,
     #[serde(flatten)]
     float_fmt: FloatFormat,
+    /// Optionally show how much memory is used as a progress bar
+    #[serde(flatten)]
+    pct_fmt: PercentFormat,
     #[serde(default)]
     display: MemDisplay,
+    /// If set, the item is highlighted when more than this many pages were swapped in or out
+    /// since the last interval
+    #[serde(default)]
+    swap_threshold: Option<u64>,
+}
+
+/// Swap counters from `/proc/vmstat`, measured in pages.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct SwapCounters {
+    pswpin: u64,
+    pswpout: u64,
+}
+
+impl SwapCounters {
+    fn parse(vmstat: &str) -> Option<SwapCounters> {
+        let mut pswpin = None;
+        let mut pswpout = None;
+        for line in vmstat.lines() {
+            match line.split_once(' ') {
+                Some(("pswpin", value)) => pswpin = value.trim().parse().ok(),
+                Some(("pswpout", value)) => pswpout = value.trim().parse().ok(),
+                _ => continue,
+            }
+        }
+
+        Some(SwapCounters {
+            pswpin: pswpin?,
+            pswpout: pswpout?,
+        })
+    }
+
+    async fn read() -> Result<SwapCounters> {
+        let vmstat = fs::read_to_string("/proc/vmstat").await?;
+        match Self::parse(&vmstat) {
+            Some(counters) => Ok(counters),
+            None => bail!("failed to find pswpin/pswpout in /proc/vmstat"),
+        }
+    }
+
+    /// Number of pages swapped in or out since `prev`
+    fn delta(&self, prev: &SwapCounters) -> u64 {
+        self.pswpin.saturating_sub(prev.pswpin) + self.pswpout.saturating_sub(prev.pswpout)
+    }
 }
 
 impl Mem {
@@ -46,7 +92,11 @@ impl Mem {
 impl BarItem for Mem {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
         let mut total = None;
-        let mut display = EnumCycle::new_at(self.display)?;
+        let mut display = self.display;
+        let mut swap_counters = match self.swap_threshold {
+            Some(_) => Some(SwapCounters::read().await?),
+            None => None,
+        };
         loop {
             let (available, total) = {
                 ctx.state.sys.refresh_memory();
@@ -57,21 +107,40 @@ impl BarItem for Mem {
             };
 
             let used_pct = ((total - available) as f64 / total as f64) * 100.0;
-            let s = match *display.current() {
-                MemDisplay::Bytes => ByteSize(available).to_string_as(true),
+            let s = match display {
+                MemDisplay::Bytes => bytes(available, ctx.config.units),
                 MemDisplay::Percentage => format!("{}%", float(used_pct, &self.float_fmt)),
             };
+            let s = self.pct_fmt.format(&s, used_pct);
+
+            // check if the system is actively swapping
+            let swapping = match (self.swap_threshold, swap_counters.as_mut()) {
+                (Some(threshold), Some(prev)) => {
+                    let next = SwapCounters::read().await?;
+                    let delta = next.delta(prev);
+                    *prev = next;
+                    delta > threshold
+                }
+                _ => false,
+            };
 
-            let mut item = I3Item::new(format!(" {}", s)).markup(I3Markup::Pango);
-            if let Some(fg) = Self::get_color(&ctx.config.theme, used_pct) {
-                item = item.color(fg);
+            let theme = &ctx.config.theme;
+            let item = if swapping {
+                I3Item::new(format!(" {} 󰓡", s)).color(theme.orange)
+            } else {
+                let item = I3Item::new(format!(" {}", s));
+                match Self::get_color(theme, used_pct) {
+                    Some(fg) => item.color(fg),
+                    None => item,
+                }
             }
+            .markup(I3Markup::Pango);
 
             ctx.update_item(item).await?;
             ctx.delay_with_event_handler(self.interval, |ev| {
                 if let BarEvent::Click(c) = ev {
                     if let I3Button::Left = c.button {
-                        display.next();
+                        display = display.next();
                     }
                 }
 
@@ -81,3 +150,28 @@ impl BarItem for Mem {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_counters_delta() {
+        let before = SwapCounters::parse("nr_free_pages 1000\npswpin 10\npswpout 20\nswap_ra 0\n");
+        let after = SwapCounters::parse("nr_free_pages 900\npswpin 15\npswpout 120\nswap_ra 0\n");
+
+        let before = before.unwrap();
+        let after = after.unwrap();
+        assert_eq!(before, SwapCounters { pswpin: 10, pswpout: 20 });
+        assert_eq!(after.delta(&before), 105);
+        assert_eq!(after.delta(&after), 0);
+
+        // counters going backwards (e.g., reset) don't underflow
+        assert_eq!(before.delta(&after), 0);
+    }
+
+    #[test]
+    fn swap_counters_missing() {
+        assert_eq!(SwapCounters::parse("nr_free_pages 1000\npswpin 10\n"), None);
+    }
+}
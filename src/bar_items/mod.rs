@@ -1,3 +1,7 @@
 use_and_export!(
-    battery, cpu, disk, dunst, kbd, krb, light, mem, net_usage, nic, pulse, script, sensors, time
+    battery, cpu, dir_size, disk, dunst, group, kbd, krb, light, mem, net_usage, nic, pulse, raw,
+    script, sensors, time
 );
+
+#[cfg(feature = "http")]
+use_and_export!(http);
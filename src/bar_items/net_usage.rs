@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -12,20 +14,88 @@ use crate::context::{BarEvent, BarItem, Context, StopAction};
 use crate::error::Result;
 use crate::i3::{I3Button, I3Item, I3Markup};
 use crate::theme::Theme;
-use crate::util::EnumCycle;
+use crate::util::format::{size, sparkline_glyph, SizeUnits};
+use crate::util::filter::InterfaceFilter;
+use crate::util::{expand_path, EnumCycle};
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, EnumIter)]
 #[serde(rename_all = "snake_case")]
 enum UsageDisplay {
-    // as bits: 1 Kbit == 8000 bits == 1000 B
+    // as bits, using the configured `units`: 1 kbit == 1000 bits or 1 Kibit == 1024 bits
     Bits,
-    // as bytes: 1 KB == 1000 B
+    // as bytes, using the configured `units`: 1 kB == 1000 B or 1 KiB == 1024 B
     #[default]
     Bytes,
-    // as bibytes: 1 KiB == 1024 B
+    // as bibytes, regardless of the configured `units`: 1 KiB == 1024 B
     Bibytes,
 }
 
+impl UsageDisplay {
+    /// `Bibytes` is the same as `Bytes` when the configured units are already binary, so it's
+    /// skipped when cycling through the displays
+    fn is_redundant(&self, units: SizeUnits) -> bool {
+        matches!((self, units), (UsageDisplay::Bibytes, SizeUnits::Binary))
+    }
+}
+
+/// What the item shows, toggled with a middle click
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    /// Current transfer rate
+    Rate,
+    /// A graph of recent transfer rates, if `graph` is set
+    Graph,
+    /// Bytes transferred since the item started
+    Totals,
+}
+
+impl View {
+    fn next(self, has_graph: bool) -> View {
+        match self {
+            View::Rate if has_graph => View::Graph,
+            View::Rate | View::Graph => View::Totals,
+            View::Totals => View::Rate,
+        }
+    }
+}
+
+/// Running totals of bytes transferred
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Totals {
+    down: u64,
+    up: u64,
+}
+
+impl Totals {
+    /// Add a sample of bytes transferred since the last sample
+    fn add(&mut self, down: u64, up: u64) {
+        self.down = self.down.saturating_add(down);
+        self.up = self.up.saturating_add(up);
+    }
+
+    async fn load(path: &Path) -> Totals {
+        let totals = match tokio::fs::read_to_string(path).await {
+            Ok(contents) => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            // nothing has been saved yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Totals::default()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        totals.unwrap_or_else(|e| {
+            log::warn!("failed to read totals from {}: {}", path.display(), e);
+            Totals::default()
+        })
+    }
+
+    async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        Ok(tokio::fs::write(path, serde_json::to_vec(self)?).await?)
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct NetUsage {
     #[serde(with = "crate::human_time")]
@@ -33,16 +103,64 @@ pub struct NetUsage {
     minimum: Option<ByteSize>,
     #[serde(default)]
     thresholds: Vec<ByteSize>,
+    /// If set, only interfaces matching one of these filters are counted
     #[serde(default)]
-    ignored_interfaces: Vec<String>,
+    include: Vec<InterfaceFilter>,
+    /// Interfaces matching any of these filters aren't counted
+    #[serde(default = "NetUsage::default_exclude", alias = "ignored_interfaces")]
+    exclude: Vec<InterfaceFilter>,
     #[serde(default)]
     display: UsageDisplay,
+    /// If set, cumulative totals are kept in this file so they persist across restarts
+    totals_file: Option<PathBuf>,
+    /// Keep the last N samples, so a graph of recent rates can be shown (toggled with a middle click)
+    #[serde(default)]
+    graph: Option<usize>,
     /// Currently only surfaced for testing.
     #[serde(default)]
     _always_assume_interval: bool,
 }
 
 impl NetUsage {
+    fn default_exclude() -> Vec<InterfaceFilter> {
+        vec![InterfaceFilter::new("lo", None)]
+    }
+
+    /// How often cumulative totals are written to `totals_file`
+    const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+    const MAX_GRAPH_LEN: usize = 60;
+
+    fn graph_len(&self) -> usize {
+        self.graph.unwrap_or(0).min(Self::MAX_GRAPH_LEN)
+    }
+
+    /// The rate drawn as a full block in the graph: the highest threshold if any are configured,
+    /// otherwise the highest rate in the graph
+    fn graph_max(&self, rates: impl Iterator<Item = u64>) -> u64 {
+        match self.thresholds.last() {
+            Some(max) => max.as_u64(),
+            None => rates.max().unwrap_or(0),
+        }
+    }
+
+    fn render_graph(&self, history: &VecDeque<(u64, u64)>) -> String {
+        let down = history.iter().map(|(down, _)| *down);
+        let up = history.iter().map(|(_, up)| *up);
+        format!(
+            "{}↓ {}↑",
+            sparkline(down.clone(), self.graph_max(down)),
+            sparkline(up.clone(), self.graph_max(up))
+        )
+    }
+
+    /// Whether the given interface should be counted in the totals
+    fn is_selected(&self, interface: &str) -> bool {
+        let included = self.include.is_empty() || self.include.iter().any(|f| f.matches_name(interface));
+
+        included && !self.exclude.iter().any(|f| f.matches_name(interface))
+    }
+
     fn get_color(&self, theme: &Theme, bytes: u64) -> Option<HexColor> {
         if self.thresholds.is_empty() {
             return None;
@@ -77,16 +195,40 @@ impl NetUsage {
     }
 }
 
-fn format_bytes(bytes: u64, si: bool, as_bits: bool) -> String {
-    let mut s = ByteSize(if as_bits { bytes * 8 } else { bytes }).to_string_as(si);
+/// Draw rates as a sparkline, scaled so that `max` is drawn as a full block
+fn sparkline(rates: impl Iterator<Item = u64>, max: u64) -> String {
+    rates
+        .map(|rate| match max {
+            0 => sparkline_glyph(0.0),
+            max => sparkline_glyph(rate as f32 / max as f32 * 100.0),
+        })
+        .collect()
+}
+
+fn format_bytes(bytes: u64, units: SizeUnits, as_bits: bool) -> String {
     if as_bits {
-        s.pop();
-        format!("{}bits", s)
+        size(bytes * 8, units, "bits")
     } else {
-        s
+        size(bytes, units, "B")
+    }
+}
+
+fn format_display(bytes: u64, display: UsageDisplay, units: SizeUnits) -> String {
+    match display {
+        UsageDisplay::Bits => format_bytes(bytes, units, true),
+        UsageDisplay::Bytes => format_bytes(bytes, units, false),
+        UsageDisplay::Bibytes => format_bytes(bytes, SizeUnits::Binary, false),
     }
 }
 
+fn format_totals(totals: &Totals, display: UsageDisplay, units: SizeUnits) -> String {
+    format!(
+        "{}↓ {}↑",
+        format_display(totals.down, display, units),
+        format_display(totals.up, display, units)
+    )
+}
+
 #[async_trait(?Send)]
 impl BarItem for NetUsage {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
@@ -96,23 +238,33 @@ impl BarItem for NetUsage {
                 .unwrap_or("".into())
         };
 
+        let units = ctx.config.units;
         let min = self.minimum.map_or(bytesize::KIB, |b| b.as_u64());
         let text = |bytes, display| {
             format!(
                 "{:>8}",
                 if bytes >= min {
-                    match display {
-                        UsageDisplay::Bits => format_bytes(bytes, false, true),
-                        UsageDisplay::Bytes => format_bytes(bytes, false, false),
-                        UsageDisplay::Bibytes => format_bytes(bytes, true, false),
-                    }
+                    format_display(bytes, display, units)
                 } else {
                     "-".into()
                 }
             )
         };
 
-        let mut display = EnumCycle::new_at(self.display)?;
+        let mut display = self.display;
+        let mut view = View::Rate;
+
+        let graph_len = self.graph_len();
+        let mut history = VecDeque::with_capacity(graph_len);
+
+        // totals for this session, and optionally cumulative totals which persist across restarts
+        let mut session = Totals::default();
+        let totals_file = self.totals_file.as_ref().map(expand_path).transpose()?;
+        let mut cumulative = match &totals_file {
+            Some(path) => Some(Totals::load(path).await),
+            None => None,
+        };
+        let mut last_save = Instant::now();
 
         let div_as_u64 = |u, f| (u as f64 / f) as u64;
         let mut last_check = Instant::now();
@@ -126,13 +278,18 @@ impl BarItem for NetUsage {
 
                 // this returns the number of bytes since the last refresh
                 let (down, up) = networks.iter().fold((0, 0), |(d, u), (interface, net)| {
-                    if self.ignored_interfaces.contains(interface) {
-                        (d, u)
-                    } else {
+                    if self.is_selected(interface) {
                         (d + net.received(), u + net.transmitted())
+                    } else {
+                        (d, u)
                     }
                 });
 
+                session.add(down, up);
+                if let Some(cumulative) = cumulative.as_mut() {
+                    cumulative.add(down, up);
+                }
+
                 // so we check how long it's been since the last refresh, and adjust accordingly
                 let elapsed = last_check.elapsed().as_secs_f64();
                 last_check = Instant::now();
@@ -144,24 +301,172 @@ impl BarItem for NetUsage {
                 }
             };
 
-            ctx.update_item(
-                I3Item::new(format!(
+            if graph_len > 0 {
+                if history.len() == graph_len {
+                    history.pop_front();
+                }
+                history.push_back((down, up));
+            }
+
+            if let (Some(path), Some(cumulative)) = (&totals_file, &cumulative) {
+                if last_save.elapsed() >= Self::SAVE_INTERVAL {
+                    last_save = Instant::now();
+                    if let Err(e) = cumulative.save(path).await {
+                        log::warn!("failed to save totals to {}: {}", path.display(), e);
+                    }
+                }
+            }
+
+            let full_text = match view {
+                View::Rate => format!(
                     "<span{}>{}↓</span> <span{}>{}↑</span>",
                     fg(down, &ctx.config.theme),
-                    text(down, *display.current()),
+                    text(down, display),
                     fg(up, &ctx.config.theme),
-                    text(up, *display.current())
-                ))
-                .markup(I3Markup::Pango),
+                    text(up, display)
+                ),
+                View::Graph => self.render_graph(&history),
+                View::Totals => format!("Σ {}", format_totals(&session, display, units)),
+            };
+
+            let mut detail = format!("session: {}", format_totals(&session, display, units));
+            if let Some(cumulative) = &cumulative {
+                detail.push_str(&format!(
+                    ", total: {}",
+                    format_totals(cumulative, display, units)
+                ));
+            }
+
+            ctx.update_item(
+                I3Item::new(full_text)
+                    .markup(I3Markup::Pango)
+                    .detail(detail),
             )
             .await?;
 
-            // swap between bits and bytes on click
+            // swap between bits and bytes on click, or between rates, the graph and totals
             if let Some(BarEvent::Click(click)) = ctx.wait_for_event(Some(self.interval)).await {
-                if click.button == I3Button::Left {
-                    display.next();
+                match click.button {
+                    I3Button::Left => {
+                        display = display.next();
+                        if display.is_redundant(units) {
+                            display = display.next();
+                        }
+                    }
+                    I3Button::Middle => view = view.next(graph_len > 0),
+                    _ => {}
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{local_block_on, TestDir};
+
+    fn net_usage(include: &[&str], exclude: &[&str]) -> NetUsage {
+        NetUsage {
+            include: include.iter().map(|s| s.parse().unwrap()).collect(),
+            exclude: exclude.iter().map(|s| s.parse().unwrap()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn selects_all_but_loopback_by_default() {
+        let n = net_usage(&[], &["lo"]);
+        assert!(!n.is_selected("lo"));
+        assert!(n.is_selected("lo0"));
+        assert!(n.is_selected("wlan0"));
+        assert!(n.is_selected("eth0"));
+    }
+
+    #[test]
+    fn selects_only_included() {
+        let n = net_usage(&["wlan*", "eth0"], &["lo"]);
+        assert!(n.is_selected("wlan0"));
+        assert!(n.is_selected("wlan1"));
+        assert!(n.is_selected("eth0"));
+        assert!(!n.is_selected("eth1"));
+        assert!(!n.is_selected("lo"));
+    }
+
+    #[test]
+    fn accumulates_totals() {
+        let mut totals = Totals::default();
+        for (down, up) in [(100, 10), (0, 0), (1_000, 20), (42, 0)] {
+            totals.add(down, up);
+        }
+        assert_eq!(totals, Totals { down: 1_142, up: 30 });
+        assert_eq!(
+            format_totals(&totals, UsageDisplay::Bytes, SizeUnits::Decimal),
+            "1.1 kB↓ 30 B↑"
+        );
+        assert_eq!(
+            format_totals(&totals, UsageDisplay::Bytes, SizeUnits::Binary),
+            "1.1 KiB↓ 30 B↑"
+        );
+
+        // totals never overflow
+        totals.add(u64::MAX, 0);
+        assert_eq!(totals.down, u64::MAX);
+    }
+
+    #[test]
+    fn totals_persist() {
+        // saving creates any missing parent directories
+        let dir = TestDir::new("net-usage");
+        let path = dir.join("state").join("totals.json");
+
+        let (totals, _) = local_block_on(Totals::load(&path)).unwrap();
+        assert_eq!(totals, Totals::default());
+
+        let totals = Totals { down: 5, up: 7 };
+        local_block_on(totals.save(&path)).unwrap().0.unwrap();
+        let (loaded, _) = local_block_on(Totals::load(&path)).unwrap();
+        assert_eq!(loaded, totals);
+    }
+
+    #[test]
+    fn graph_scaling() {
+        let history = [(0, 0), (250, 10), (500, 20), (1_000, 40)]
+            .into_iter()
+            .collect::<VecDeque<_>>();
+
+        // scaled to the highest rate in the graph
+        let n = NetUsage::default();
+        assert_eq!(n.render_graph(&history), "▁▃▅█↓ ▁▃▅█↑");
+
+        // or to the highest threshold
+        let n = NetUsage {
+            thresholds: vec![ByteSize::b(100), ByteSize::b(2_000)],
+            ..Default::default()
+        };
+        assert_eq!(n.render_graph(&history), "▁▂▃▅↓ ▁▁▁▁↑");
+    }
+
+    #[test]
+    fn graph_all_zero() {
+        let history = [(0, 0); 3].into_iter().collect::<VecDeque<_>>();
+        assert_eq!(NetUsage::default().render_graph(&history), "▁▁▁↓ ▁▁▁↑");
+        assert_eq!(NetUsage::default().render_graph(&VecDeque::new()), "↓ ↑");
+    }
+
+    #[test]
+    fn view_cycling() {
+        assert_eq!(View::Rate.next(false), View::Totals);
+        assert_eq!(View::Rate.next(true), View::Graph);
+        assert_eq!(View::Graph.next(true), View::Totals);
+        assert_eq!(View::Totals.next(true), View::Rate);
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let n = net_usage(&["wlan*"], &["wlan1", "vpn*"]);
+        assert!(n.is_selected("wlan0"));
+        assert!(!n.is_selected("wlan1"));
+        assert!(!n.is_selected("vpn0"));
+    }
+}
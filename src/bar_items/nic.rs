@@ -1,15 +1,21 @@
+use std::collections::{HashMap, VecDeque};
 use std::net::IpAddr;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use hex_color::HexColor;
 use serde_derive::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::context::{BarEvent, BarItem, Context, StopAction};
+use crate::dbus::network_manager::NetworkManagerProxy;
+use crate::dbus::{dbus_connection, BusType};
 use crate::error::Result;
-use crate::i3::{I3Item, I3Markup, I3Modifier};
+use crate::i3::{pango_escape, I3Item, I3Markup, I3Modifier};
 use crate::theme::Theme;
 use crate::util::filter::InterfaceFilter;
-use crate::util::nl80211::SignalStrength;
+use crate::util::format::sparkline_glyph;
+use crate::util::nl80211::{SignalStrength, WirelessInfo};
 use crate::util::{net_subscribe, Interfaces, NetlinkInterface, Paginator};
 
 #[derive(Debug, Default, Serialize, Deserialize, Copy, Clone)]
@@ -21,6 +27,53 @@ enum WirelessDisplay {
     Dbm,
 }
 
+/// The kind of connection an interface provides, used to pick an icon
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConnectionKind {
+    Wireless,
+    Ethernet,
+    Vpn,
+    Cellular,
+    Loopback,
+    Other,
+}
+
+impl ConnectionKind {
+    /// Classify an interface by its name, `is_wireless` (from nl80211) takes precedence
+    fn classify(name: &str, is_wireless: bool) -> ConnectionKind {
+        if is_wireless {
+            return ConnectionKind::Wireless;
+        }
+
+        let starts_with = |prefixes: &[&str]| prefixes.iter().any(|p| name.starts_with(p));
+        if name == "lo" {
+            ConnectionKind::Loopback
+        } else if starts_with(&["wwan", "wwp", "rmnet"]) {
+            ConnectionKind::Cellular
+        } else if starts_with(&["wl", "wifi"]) {
+            ConnectionKind::Wireless
+        } else if starts_with(&["en", "eth"]) {
+            ConnectionKind::Ethernet
+        } else if starts_with(&["wg", "tun", "tap", "ppp", "vpn", "tailscale"]) {
+            ConnectionKind::Vpn
+        } else {
+            ConnectionKind::Other
+        }
+    }
+
+    fn default_icon(&self) -> &'static str {
+        match self {
+            ConnectionKind::Wireless => "\u{f1eb}",
+            ConnectionKind::Ethernet => "\u{f0200}",
+            ConnectionKind::Vpn => "\u{f023}",
+            ConnectionKind::Cellular => "\u{f012}",
+            ConnectionKind::Loopback => "\u{f021}",
+            ConnectionKind::Other => "\u{f0ac}",
+        }
+    }
+}
+
 #[derive(Debug)]
 enum ConnectionDetail {
     None,
@@ -53,73 +106,174 @@ impl ConnectionDetail {
 struct Connection<'a> {
     /// Interface name
     name: &'a str,
+    /// What kind of connection this is
+    kind: ConnectionKind,
     /// Interface address as a string
     addr: &'a IpAddr,
+    /// Prefix length of the address
+    prefix_len: u8,
     /// Extra detail about the connection
     detail: Option<ConnectionDetail>,
     /// Connection quality expressed as a percentage value between 0 and 100
     /// Only set when connection is wireless, and expresses the signal strength
     /// This is used to infer which colour the item should be
     quality: Option<u8>,
+    /// Whether the interface is up and has a carrier
+    has_carrier: bool,
 }
 
 impl<'a> Connection<'a> {
-    async fn new(interface: &'a NetlinkInterface, addr: &'a IpAddr) -> Connection<'a> {
-        let wireless_info = interface.wireless_info().await;
+    /// `wireless_info` should be the result of `interface.wireless_info()`, it's passed in so it can
+    /// be reused while the text scrolls rather than being queried each frame
+    fn new(
+        interface: &'a NetlinkInterface,
+        addr: &'a IpAddr,
+        prefix_len: u8,
+        wireless_info: Option<&WirelessInfo>,
+    ) -> Connection<'a> {
         let quality = wireless_info
-            .as_ref()
             .and_then(|info| info.signal.as_ref())
             .map(|signal| signal.quality() as u8);
 
         Connection {
             name: &interface.name,
+            kind: ConnectionKind::classify(&interface.name, wireless_info.is_some()),
             addr,
-            detail: wireless_info.map(|info| match (info.ssid, info.signal) {
+            prefix_len,
+            detail: wireless_info.map(|info| match (&info.ssid, &info.signal) {
                 (Some(ssid), Some(signal)) => {
-                    ConnectionDetail::SsidAndSignal(ssid.to_string(), signal)
+                    ConnectionDetail::SsidAndSignal(ssid.to_string(), signal.clone())
                 }
                 (Some(ssid), None) => ConnectionDetail::Ssid(ssid.to_string()),
                 _ => ConnectionDetail::None,
             }),
             quality,
+            has_carrier: interface.has_carrier,
+        }
+    }
+
+    /// All known information about the connection, regardless of display settings
+    fn format_detail(&self) -> String {
+        let mut detail = format!("{}: {}/{}", self.name, self.addr, self.prefix_len);
+        match &self.detail {
+            Some(ConnectionDetail::SsidAndSignal(ssid, signal)) => detail.push_str(&format!(
+                " on {} ({}%, {} dBm)",
+                ssid,
+                signal.quality() as u8,
+                signal.dbm
+            )),
+            Some(ConnectionDetail::Ssid(ssid)) => detail.push_str(&format!(" on {}", ssid)),
+            Some(ConnectionDetail::None) | None => {}
+        }
+        if !self.has_carrier {
+            detail.push_str(" (down)");
         }
+
+        detail
+    }
+
+    /// The text describing the connection, without any markup
+    fn text(&self, wireless_display: WirelessDisplay, show_prefix: bool) -> String {
+        format!(
+            "{}({}{}){}{}",
+            self.name,
+            self.addr,
+            if show_prefix {
+                format!("/{}", self.prefix_len)
+            } else {
+                "".into()
+            },
+            match self
+                .detail
+                .as_ref()
+                .and_then(|cd| cd.display(wireless_display))
+            {
+                Some(detail) => format!(" {}", detail),
+                _ => "".into(),
+            },
+            if self.has_carrier { "" } else { " down" }
+        )
     }
 
-    fn format(&self, theme: &Theme, wireless_display: WirelessDisplay) -> (String, String) {
+    /// Wrap the text in markup, the text (which may include an SSID) is escaped but `icon` isn't
+    fn format(&self, theme: &Theme, text: &str, icon: Option<&str>) -> (String, String) {
         let fg = format!(
             r#" foreground="{}""#,
             (match self.quality {
-                Some(quality) => match quality {
-                    100..=u8::MAX => theme.green,
-                    80..=99 => theme.green,
-                    60..=79 => theme.yellow,
-                    40..=59 => theme.orange,
-                    _ => theme.red,
-                },
+                _ if !self.has_carrier => theme.dim,
+                Some(quality) => quality_color(theme, quality),
                 None => theme.green,
             })
             .display_rgb()
         );
+        let icon = icon.map(|icon| format!("{} ", icon)).unwrap_or_default();
         (
+            format!(r#"<span{}>{}{}</span>"#, fg, icon, pango_escape(text)),
             format!(
-                r#"<span{}>{}({}){}</span>"#,
+                r#"<span{}>{}{}</span>"#,
                 fg,
-                self.name,
-                self.addr,
-                match self
-                    .detail
-                    .as_ref()
-                    .and_then(|cd| cd.display(wireless_display))
-                {
-                    Some(detail) => format!(" {}", detail),
-                    _ => "".into(),
-                }
+                icon,
+                pango_escape(self.name)
             ),
-            format!(r#"<span{}>{}</span>"#, fg, self.name),
         )
     }
 }
 
+/// The colour used for a wireless connection of the given quality
+fn quality_color(theme: &Theme, quality: u8) -> HexColor {
+    match quality {
+        100..=u8::MAX => theme.green,
+        80..=99 => theme.green,
+        60..=79 => theme.yellow,
+        40..=59 => theme.orange,
+        _ => theme.red,
+    }
+}
+
+/// The sparkline glyph for a signal strength, a full block is a perfect signal
+fn signal_glyph(dbm: i8) -> char {
+    sparkline_glyph(SignalStrength::new(dbm).quality())
+}
+
+/// Draw recent signal strengths as a sparkline, each glyph coloured by its quality
+fn format_signal_history(theme: &Theme, history: &VecDeque<i8>) -> String {
+    history
+        .iter()
+        .map(|dbm| {
+            let quality = SignalStrength::new(*dbm).quality() as u8;
+            format!(
+                r#"<span foreground="{}">{}</span>"#,
+                quality_color(theme, quality).display_rgb(),
+                signal_glyph(*dbm)
+            )
+        })
+        .collect()
+}
+
+/// Show a `width` graphemes wide window of `text`, which has been scrolled along by `offset`
+/// graphemes. The text wraps around, with a gap between its end and its start.
+fn scroll_window(text: &str, width: usize, offset: u64) -> String {
+    const GAP: &str = " • ";
+
+    let graphemes = text.graphemes(true).collect::<Vec<_>>();
+    if graphemes.len() <= width {
+        return text.into();
+    }
+
+    let looped = graphemes
+        .into_iter()
+        .chain(GAP.graphemes(true))
+        .collect::<Vec<_>>();
+    let start = (offset % looped.len() as u64) as usize;
+    looped
+        .iter()
+        .cycle()
+        .skip(start)
+        .take(width)
+        .copied()
+        .collect()
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Nic {
     #[serde(default, with = "crate::human_time::option")]
@@ -130,6 +284,74 @@ pub struct Nic {
     wireless_display: WirelessDisplay,
     #[serde(default, with = "crate::human_time::option")]
     wireless_refresh_interval: Option<Duration>,
+    /// Show the prefix length of addresses, e.g.: `/64`
+    #[serde(default)]
+    show_prefix: bool,
+    /// Only show globally routable addresses (hides private ipv4 and unique local ipv6 addresses)
+    #[serde(default)]
+    global_only: bool,
+    /// Show an icon for the kind of connection (wireless, ethernet, vpn, etc)
+    #[serde(default)]
+    show_icon: bool,
+    /// Override the icons used for each kind of connection
+    #[serde(default)]
+    icons: HashMap<ConnectionKind, String>,
+    /// Show interfaces which are down (or have no carrier), these are dimmed
+    #[serde(default)]
+    show_down: bool,
+    /// Show whether a VPN is connected, according to NetworkManager's active connections
+    #[serde(default)]
+    show_vpn: bool,
+    /// Scroll the connection's text when it's wider than `scroll_width`, rather than showing it all
+    #[serde(default)]
+    scroll: bool,
+    /// How many characters to show at once when `scroll` is set
+    #[serde(default = "Nic::default_scroll_width")]
+    scroll_width: usize,
+    /// Show a sparkline of recent signal strengths for wireless connections
+    #[serde(default)]
+    signal_history: bool,
+}
+
+impl Nic {
+    /// How long the text stays still between each step of scrolling
+    const SCROLL_FRAME: Duration = Duration::from_millis(500);
+    /// How many signal strength samples are kept for each interface when `signal_history` is set
+    const SIGNAL_HISTORY_LEN: usize = 10;
+
+    fn default_scroll_width() -> usize {
+        20
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.scroll && self.scroll_width == 0 {
+            bail!("nic scroll_width must be greater than 0");
+        }
+
+        Ok(())
+    }
+
+    fn icon(&self, kind: ConnectionKind) -> &str {
+        self.icons
+            .get(&kind)
+            .map(|s| s.as_str())
+            .unwrap_or_else(|| kind.default_icon())
+    }
+
+    /// A lock which is dimmed unless a VPN is connected
+    fn format_vpn(&self, theme: &Theme, vpn: Option<&str>) -> String {
+        format!(
+            r#"<span foreground="{}">{}</span> "#,
+            (if vpn.is_some() { theme.fg } else { theme.dim }).display_rgb(),
+            self.icon(ConnectionKind::Vpn)
+        )
+    }
+}
+
+/// Connect to NetworkManager on the system bus
+async fn network_manager() -> Result<NetworkManagerProxy<'static>> {
+    let connection = dbus_connection(BusType::System).await?;
+    Ok(NetworkManagerProxy::new(connection).await?)
 }
 
 #[async_trait(?Send)]
@@ -145,14 +367,44 @@ impl BarItem for Nic {
         let mut net = net_subscribe().await?;
         let mut p = Paginator::new();
 
+        // if NetworkManager isn't available, only the interfaces are shown
+        let mut nm = None;
+        if self.show_vpn {
+            match network_manager().await {
+                Ok(proxy) => nm = Some(proxy),
+                Err(e) => log::warn!("failed to connect to NetworkManager, not showing vpn: {}", e),
+            }
+        }
+
+        // only tick the clock while there's text to scroll
+        let clock = ctx.clock();
+        let mut scrolling = false;
+
+        // recent signal strengths of each wireless interface, only sampled when the item updates
+        // (not each time the text is scrolled)
+        let mut signal_history = HashMap::<String, VecDeque<i8>>::new();
+
+        // queried each time the item updates, and reused while the text scrolls
+        let mut wireless_info = None;
+        // `None` if the vpn status is unknown, otherwise the name of the active vpn (if any)
+        let mut vpn = None;
+
         let mut interfaces = Interfaces::default();
         let mut total_address_count = interfaces.len_addresses();
         loop {
+            // false when only moving the scrolling text along
+            let mut refresh = true;
             tokio::select! {
                 // wait for network changes
                 Ok(new_interfaces) = net.wait_for_change() => {
                     total_address_count = new_interfaces.len_addresses();
                     interfaces = new_interfaces.filtered(&self.filter);
+                    if self.global_only {
+                        interfaces = interfaces.global_only();
+                    }
+                    if !self.show_down {
+                        interfaces = interfaces.connected_only();
+                    }
                 },
                 // on any bar event
                 Some(event) = ctx.wait_for_event(self.interval) => {
@@ -169,8 +421,23 @@ impl BarItem for Nic {
                 // if set, start a timeout to refresh the wireless details
                 // this just breaks the `select!` so the wireless details will be fetched again
                 () = wireless_refresh_trigger() => {}
+                // move the scrolling text along
+                () = clock.wait_for_tick(Self::SCROLL_FRAME), if scrolling => refresh = false,
+            }
+
+            if refresh {
+                let active = match &nm {
+                    Some(nm) => nm.active_vpn().await.map(Some),
+                    None => Ok(None),
+                };
+                vpn = active.unwrap_or_else(|e| {
+                    log::warn!("failed to query NetworkManager, not showing vpn: {}", e);
+                    nm = None;
+                    None
+                });
             }
 
+            scrolling = false;
             let item = if interfaces.is_empty() {
                 if total_address_count > 0 {
                     I3Item::new(format!("filtered: {}", total_address_count))
@@ -183,16 +450,208 @@ impl BarItem for Nic {
                 let theme = &ctx.config.theme;
                 // SAFETY(unwrap): we always set the paginator's length to `len_addresses` so it
                 // should always be within bounds
-                let (interface, ip_addr) = interfaces.get_address_at(p.idx()).unwrap();
-                let (full, short) = Connection::new(interface, ip_addr)
-                    .await
-                    .format(theme, self.wireless_display);
+                let (interface, ip_addr, prefix_len) = interfaces.get_address_at(p.idx()).unwrap();
+                if refresh {
+                    wireless_info = interface.wireless_info().await;
+                }
+                let connection =
+                    Connection::new(interface, ip_addr, prefix_len, wireless_info.as_ref());
+                let icon = self.show_icon.then(|| self.icon(connection.kind));
+                let mut text = connection.text(self.wireless_display, self.show_prefix);
+                if self.scroll && text.graphemes(true).count() > self.scroll_width {
+                    scrolling = true;
+                    text = scroll_window(&text, self.scroll_width, clock.tick(Self::SCROLL_FRAME));
+                }
+                let (full, short) = connection.format(theme, &text, icon);
+
+                let mut sparkline = String::new();
+                if let (true, Some(ConnectionDetail::SsidAndSignal(_, signal))) =
+                    (self.signal_history, &connection.detail)
+                {
+                    let history = signal_history.entry(connection.name.into()).or_default();
+                    if refresh || history.is_empty() {
+                        if history.len() == Self::SIGNAL_HISTORY_LEN {
+                            history.pop_front();
+                        }
+                        history.push_back(signal.dbm);
+                    }
+                    sparkline = format!(" {}", format_signal_history(theme, history));
+                }
 
-                let full = format!(r#"{}{}"#, full, p.format(theme));
-                I3Item::new(full).short_text(short).markup(I3Markup::Pango)
+                let full = format!(r#"{}{}{}"#, full, sparkline, p.format(theme));
+                let mut detail = connection.format_detail();
+                let (full, short) = match &vpn {
+                    Some(active) => {
+                        if let Some(name) = active {
+                            detail.push_str(&format!(" (vpn: {})", name));
+                        }
+
+                        let lock = self.format_vpn(theme, active.as_deref());
+                        (format!("{}{}", lock, full), format!("{}{}", lock, short))
+                    }
+                    None => (full, short),
+                };
+
+                I3Item::new(full)
+                    .short_text(short)
+                    .markup(I3Markup::Pango)
+                    .detail(detail)
             };
 
             ctx.update_item(item).await?;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_connection_kind() {
+        use ConnectionKind::*;
+
+        // nl80211 detection always wins
+        assert_eq!(ConnectionKind::classify("eth0", true), Wireless);
+
+        for (name, kind) in [
+            ("lo", Loopback),
+            ("wlan0", Wireless),
+            ("wlp3s0", Wireless),
+            ("eth0", Ethernet),
+            ("enp0s31f6", Ethernet),
+            ("wg0", Vpn),
+            ("tun0", Vpn),
+            ("tap1", Vpn),
+            ("wwan0", Cellular),
+            ("wwp0s20f0u6", Cellular),
+            ("docker0", Other),
+            ("", Other),
+        ] {
+            assert_eq!(ConnectionKind::classify(name, false), kind, "{}", name);
+        }
+    }
+
+    #[test]
+    fn configured_icons() {
+        let nic = Nic {
+            icons: HashMap::from([(ConnectionKind::Vpn, "V".into())]),
+            ..Default::default()
+        };
+        assert_eq!(nic.icon(ConnectionKind::Vpn), "V");
+        assert_eq!(nic.icon(ConnectionKind::Ethernet), ConnectionKind::Ethernet.default_icon());
+    }
+
+    #[test]
+    fn escapes_ssid() {
+        let theme = Theme::default();
+        let addr = IpAddr::from([10, 0, 0, 1]);
+        let connection = Connection {
+            name: "wlan0",
+            kind: ConnectionKind::Wireless,
+            addr: &addr,
+            prefix_len: 24,
+            detail: Some(ConnectionDetail::Ssid("Tom & Jerry <3".into())),
+            quality: None,
+            has_carrier: true,
+        };
+
+        let text = connection.text(WirelessDisplay::Percent, false);
+        assert_eq!(text, "wlan0(10.0.0.1) Tom & Jerry <3");
+        let (full, short) = connection.format(&theme, &text, Some("<b>W</b>"));
+        let fg = theme.green.display_rgb();
+        assert_eq!(
+            full,
+            format!(
+                r#"<span foreground="{}"><b>W</b> wlan0(10.0.0.1) Tom &amp; Jerry &lt;3</span>"#,
+                fg
+            )
+        );
+        assert_eq!(
+            short,
+            format!(r#"<span foreground="{}"><b>W</b> wlan0</span>"#, fg)
+        );
+    }
+
+    #[test]
+    fn scroll_long_text() {
+        let text = "a very long ssid";
+        assert_eq!(scroll_window(text, 20, 3), text);
+        assert_eq!(scroll_window(text, 16, 3), text);
+
+        assert_eq!(scroll_window(text, 6, 0), "a very");
+        assert_eq!(scroll_window(text, 6, 2), "very l");
+        // wraps around with a gap
+        assert_eq!(scroll_window(text, 6, 12), "ssid •");
+        assert_eq!(scroll_window(text, 6, 15), "d • a ");
+        // 16 graphemes of text + 3 of the gap is a full loop
+        assert_eq!(scroll_window(text, 6, 19), "a very");
+        assert_eq!(scroll_window(text, 6, 19 * 100 + 2), "very l");
+
+        // graphemes aren't split
+        assert_eq!(scroll_window("cafe\u{301} wifi", 3, 2), "fe\u{301} ");
+    }
+
+    #[test]
+    fn validate_scroll_width() {
+        let nic = |scroll, scroll_width| Nic {
+            scroll,
+            scroll_width,
+            ..Default::default()
+        };
+        assert!(nic(false, 0).validate().is_ok());
+        assert!(nic(true, 10).validate().is_ok());
+        assert!(nic(true, 0).validate().is_err());
+    }
+
+    #[test]
+    fn signal_glyphs() {
+        // from no signal at all up to a perfect one
+        for (dbm, glyph) in [
+            (i8::MIN, '▁'),
+            (-110, '▁'),
+            (-100, '▂'),
+            (-90, '▃'),
+            (-80, '▄'),
+            (-75, '▅'),
+            (-60, '▆'),
+            (-50, '▇'),
+            (-40, '█'),
+            (-20, '█'),
+            (i8::MAX, '█'),
+        ] {
+            assert_eq!(signal_glyph(dbm), glyph, "{} dBm", dbm);
+        }
+    }
+
+    #[test]
+    fn signal_history_colours() {
+        let theme = Theme::default();
+        let history = VecDeque::from([-40, -95]);
+        assert_eq!(
+            format_signal_history(&theme, &history),
+            format!(
+                r#"<span foreground="{}">█</span><span foreground="{}">▂</span>"#,
+                theme.green.display_rgb(),
+                theme.red.display_rgb()
+            )
+        );
+    }
+
+    #[test]
+    fn vpn_lock() {
+        let theme = Theme::default();
+        let nic = Nic {
+            icons: HashMap::from([(ConnectionKind::Vpn, "V".into())]),
+            ..Default::default()
+        };
+        assert_eq!(
+            nic.format_vpn(&theme, Some("work")),
+            format!(r#"<span foreground="{}">V</span> "#, theme.fg.display_rgb())
+        );
+        assert_eq!(
+            nic.format_vpn(&theme, None),
+            format!(r#"<span foreground="{}">V</span> "#, theme.dim.display_rgb())
+        );
+    }
+}
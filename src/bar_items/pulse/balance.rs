@@ -0,0 +1,162 @@
+//! Stereo balance, expressed as a percentage between -100 (only left) and 100 (only right).
+//! This follows the same model as pulse: the loudest channel is kept at the current volume, and
+//! the channels on the other side are reduced to achieve the balance.
+
+use libpulse_binding::channelmap::{Map as ChannelMap, Position};
+use libpulse_binding::volume::{ChannelVolumes, Volume};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Side {
+    Left,
+    Right,
+    Neither,
+}
+
+impl From<Position> for Side {
+    fn from(value: Position) -> Self {
+        match value {
+            Position::FrontLeft
+            | Position::RearLeft
+            | Position::FrontLeftOfCenter
+            | Position::SideLeft
+            | Position::TopFrontLeft
+            | Position::TopRearLeft => Side::Left,
+            Position::FrontRight
+            | Position::RearRight
+            | Position::FrontRightOfCenter
+            | Position::SideRight
+            | Position::TopFrontRight
+            | Position::TopRearRight => Side::Right,
+            _ => Side::Neither,
+        }
+    }
+}
+
+/// Average volume of all channels on the given side, if there are any
+fn side_volume(cv: &ChannelVolumes, map: &ChannelMap, side: Side) -> Option<u64> {
+    let volumes = cv
+        .get()
+        .iter()
+        .zip(map.get())
+        .filter(|(_, pos)| Side::from(**pos) == side)
+        .map(|(vol, _)| vol.0 as u64)
+        .collect::<Vec<_>>();
+
+    match volumes.len() {
+        0 => None,
+        n => Some(volumes.iter().sum::<u64>() / n as u64),
+    }
+}
+
+/// Returns the current balance, or `None` if the channels don't have a left and right side
+pub fn get_balance(cv: &ChannelVolumes, map: &ChannelMap) -> Option<i32> {
+    let left = side_volume(cv, map, Side::Left)?;
+    let right = side_volume(cv, map, Side::Right)?;
+
+    Some(match left.cmp(&right) {
+        std::cmp::Ordering::Equal => 0,
+        std::cmp::Ordering::Greater => -(100 - (right * 100 / left) as i32),
+        std::cmp::Ordering::Less => 100 - (left * 100 / right) as i32,
+    })
+}
+
+/// Returns new channel volumes with the given balance applied, values outside of -100..=100 are
+/// clamped. Channels which aren't on either side (e.g., centre or LFE) are left at the max volume.
+pub fn set_balance(cv: &ChannelVolumes, map: &ChannelMap, balance: i32) -> ChannelVolumes {
+    let balance = balance.clamp(-100, 100);
+    let max = cv.max();
+    let reduced = |pct: i32| Volume((max.0 as u64 * (100 - pct as u64) / 100) as u32);
+
+    let mut new = *cv;
+    for (vol, pos) in new.get_mut().iter_mut().zip(map.get()) {
+        *vol = match Side::from(*pos) {
+            // balance is to the right, so reduce the left
+            Side::Left if balance > 0 => reduced(balance),
+            // balance is to the left, so reduce the right
+            Side::Right if balance < 0 => reduced(-balance),
+            _ => max,
+        };
+    }
+
+    new
+}
+
+/// A short description of the balance, e.g.: `L20` or `R100`. Empty when centred.
+pub fn format_balance(balance: i32) -> String {
+    match balance {
+        0 => String::new(),
+        b if b < 0 => format!("L{}", -b),
+        b => format!("R{}", b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo(left: u32, right: u32) -> (ChannelVolumes, ChannelMap) {
+        let mut map = ChannelMap::default();
+        map.init_stereo();
+
+        let mut cv = ChannelVolumes::default();
+        cv.set(2, Volume::NORMAL);
+        cv.get_mut()[0] = Volume(left);
+        cv.get_mut()[1] = Volume(right);
+
+        (cv, map)
+    }
+
+    #[test]
+    fn balance_from_volumes() {
+        let (cv, map) = stereo(1000, 1000);
+        assert_eq!(get_balance(&cv, &map), Some(0));
+
+        let (cv, map) = stereo(1000, 800);
+        assert_eq!(get_balance(&cv, &map), Some(-20));
+
+        let (cv, map) = stereo(500, 1000);
+        assert_eq!(get_balance(&cv, &map), Some(50));
+
+        let (cv, map) = stereo(0, 1000);
+        assert_eq!(get_balance(&cv, &map), Some(100));
+
+        // no left or right channels
+        let mut map = ChannelMap::default();
+        map.init_mono();
+        let mut cv = ChannelVolumes::default();
+        cv.set(1, Volume::NORMAL);
+        assert_eq!(get_balance(&cv, &map), None);
+    }
+
+    #[test]
+    fn volumes_from_balance() {
+        let volumes = |cv: ChannelVolumes| cv.get().iter().map(|v| v.0).collect::<Vec<_>>();
+
+        let (cv, map) = stereo(1000, 800);
+        assert_eq!(volumes(set_balance(&cv, &map, 0)), vec![1000, 1000]);
+        assert_eq!(volumes(set_balance(&cv, &map, -20)), vec![1000, 800]);
+        assert_eq!(volumes(set_balance(&cv, &map, 50)), vec![500, 1000]);
+        assert_eq!(volumes(set_balance(&cv, &map, 100)), vec![0, 1000]);
+        assert_eq!(volumes(set_balance(&cv, &map, -100)), vec![1000, 0]);
+
+        // out of range values are clamped
+        assert_eq!(volumes(set_balance(&cv, &map, 150)), vec![0, 1000]);
+        assert_eq!(volumes(set_balance(&cv, &map, -150)), vec![1000, 0]);
+    }
+
+    #[test]
+    fn balance_round_trip() {
+        let (cv, map) = stereo(1000, 1000);
+        for balance in [-100, -75, -30, 0, 10, 60, 100] {
+            let cv = set_balance(&cv, &map, balance);
+            assert_eq!(get_balance(&cv, &map), Some(balance));
+        }
+    }
+
+    #[test]
+    fn balance_format() {
+        assert_eq!(format_balance(0), "");
+        assert_eq!(format_balance(-20), "L20");
+        assert_eq!(format_balance(100), "R100");
+    }
+}
@@ -4,7 +4,7 @@ use serde_derive::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::sync::oneshot;
 
-use super::structs::Port;
+use super::structs::{Card, Port, Profile};
 use super::{Dir, InOut, Object, PortAvailable, PulseState, Vol};
 use crate::context::CustomResponse;
 use crate::util::RcCell;
@@ -53,6 +53,15 @@ enum PulseCommand {
         what: Object,
         vol: u32,
     },
+    /// Set the stereo balance, from -100 (only left) to 100 (only right)
+    Balance {
+        what: Object,
+        #[arg(
+            allow_hyphen_values = true,
+            value_parser = clap::value_parser!(i32).range(-100..=100)
+        )]
+        balance: i32,
+    },
     Mute {
         what: Object,
         mute: Bool,
@@ -73,6 +82,12 @@ enum PulseCommand {
         what: Object,
         dir: Dir,
     },
+    /// Switch the profile of the card the default sink or source belongs to (e.g., to switch a
+    /// bluetooth device between A2DP and HSP/HFP). See `info` for the available profiles.
+    SwitchProfile {
+        what: Object,
+        name: String,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -99,16 +114,40 @@ impl Port {
     }
 }
 
+impl Profile {
+    fn to_value(&self) -> Value {
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "available": self.available,
+        })
+    }
+}
+
+impl Card {
+    fn to_value(&self) -> Value {
+        json!({
+            "index": self.index,
+            "name": self.name,
+            "profiles": self.profiles.iter().map(|p| p.to_value()).collect::<Vec<_>>(),
+            "active_profile": self.active_profile.as_ref().map_or(Value::Null, |p| p.to_value()),
+        })
+    }
+}
+
 impl InOut {
     fn to_value(&self) -> Value {
         json!({
             "index": self.index,
             "name": self.name,
             "volume": self.volume_pct(),
+            "balance": self.balance(),
             "mute": self.mute,
             "ports": self.ports.iter().map(|p| p.to_value()).collect::<Vec<_>>(),
             "active_port": self.active_port.as_ref().map_or(Value::Null, |p| p.to_value()),
             "is_source_monitor": self.is_source_monitor,
+            "is_remote": self.is_remote,
+            "card": self.card,
         })
     }
 }
@@ -149,6 +188,7 @@ impl RcCell<PulseState> {
                         "default_source": &*self.default_source,
                         "sinks": self.sinks.iter().map(|p| p.to_value()).collect::<Value>(),
                         "sources": self.sources.iter().map(|p| p.to_value()).collect::<Value>(),
+                        "cards": self.cards.iter().map(|c| c.to_value()).collect::<Value>(),
                     })),
                     PulseCommand::List { what } => match what {
                         Object::Sink => {
@@ -185,6 +225,15 @@ impl RcCell<PulseState> {
                             }),
                         );
                     }
+                    PulseCommand::Balance { what, balance } => {
+                        return self.set_balance(
+                            what,
+                            balance,
+                            Self::custom_responder(tx, move || {
+                                format!("failed to set {} balance", what)
+                            }),
+                        );
+                    }
                     PulseCommand::Mute { what, mute } => {
                         return self.set_mute(
                             what,
@@ -241,6 +290,15 @@ impl RcCell<PulseState> {
                             )),
                         }
                     }
+                    PulseCommand::SwitchProfile { what, name } => {
+                        return self.set_profile(
+                            what,
+                            name.clone(),
+                            Self::custom_responder(tx, move || {
+                                format!("failed to switch {what} profile to {name}, is the name right?")
+                            }),
+                        );
+                    }
                     PulseCommand::Cycle { what, dir } => {
                         return self.cycle_objects_and_ports(
                             what,
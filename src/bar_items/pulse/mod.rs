@@ -11,7 +11,9 @@
 //! * https://gitlab.gnome.org/GNOME/libgnome-volume-control/-/blob/master/gvc-mixer-control.c
 
 mod audio;
+mod balance;
 mod custom;
+mod ramp;
 mod structs;
 
 use std::fmt::Debug;
@@ -21,7 +23,7 @@ use std::rc::Rc;
 
 use async_trait::async_trait;
 use libpulse_binding::callbacks::ListResult;
-use libpulse_binding::context::introspect::{Introspector, SinkInfo, SourceInfo};
+use libpulse_binding::context::introspect::{CardInfo, Introspector, SinkInfo, SourceInfo};
 use libpulse_binding::context::subscribe::{Facility, InterestMaskSet, Operation};
 use libpulse_binding::context::{Context as PAContext, FlagSet, State};
 use libpulse_binding::def::PortAvailable;
@@ -34,15 +36,27 @@ use libpulse_tokio::TokioMain;
 use num_traits::ToPrimitive;
 use serde_derive::{Deserialize, Serialize};
 use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
 
 use crate::context::{BarEvent, BarItem, Context, StopAction};
-use crate::dbus::notifications::NotificationsProxy;
+use crate::dbus::notifications::{NotificationsProxy, NotifyOptions};
 use crate::dbus::{dbus_connection, BusType};
 use crate::error::Result;
 use crate::i3::{I3Button, I3Item, I3Markup, I3Modifier};
 use crate::util::{expand_path, RcCell};
 
-use self::structs::{Command, Dir, InOut, NotificationSetting, Object, Vol};
+use self::ramp::{ramp, RAMP_STEPS, RAMP_STEP_DELAY};
+use self::structs::{
+    Card,
+    Command,
+    Dir,
+    InOut,
+    NotificationSetting,
+    Object,
+    Port,
+    UnplugAction,
+    Vol,
+};
 
 const SAMPLE_NAME: &str = "i3stat-pulse-volume";
 
@@ -55,17 +69,66 @@ pub struct Pulse {
     increment_sound: Option<PathBuf>,
     /// The maximum allowed volume; measured in percent
     max_volume: Option<u32>,
+    /// Colour the volume red when it's above this; measured in percent. Unlike `max_volume` this
+    /// doesn't limit the volume, it's only a warning (e.g. when boosting above 100%)
+    warn_above: Option<u32>,
     /// Whether to send notifications on server state changes
     #[serde(default)]
     notify: NotificationSetting,
+    /// Urgency and icon options for any notifications sent
+    #[serde(default)]
+    notify_options: NotifyOptions,
     /// Name of the audio server to try to connect to
     server_name: Option<String>,
+    /// Sink name patterns which are preferred for display; if the default sink doesn't match any
+    /// of these, then the first sink that does is displayed (and controlled) instead
+    #[serde(default)]
+    display_filter: Vec<String>,
+    /// What to do when the active port of a sink or source is unplugged
+    #[serde(default)]
+    on_unplug: UnplugAction,
+    /// Show the stereo balance when it's not centred, e.g.: `L20`
+    #[serde(default)]
+    show_balance: bool,
+    /// Automatically make any newly added sink the default sink
+    #[serde(default)]
+    auto_switch: bool,
+    /// Ramp the volume over a few steps when changing it, rather than jumping straight to it.
+    /// Muting is still instant.
+    #[serde(default)]
+    smooth: bool,
 }
 
 impl Pulse {
     pub const fn default_increment() -> u32 {
         5
     }
+
+    /// Create a pulse main loop and a context which is connected to the server
+    async fn connect(&self) -> Result<(TokioMain, PAContext)> {
+        let mut main_loop = TokioMain::new();
+
+        let app_name = env!("CARGO_PKG_NAME");
+        let mut props = Proplist::new().ok_or("Failed to create PulseAudio Proplist")?;
+        let _ = props.set_str(APPLICATION_NAME, app_name);
+        let _ = props.set_str(APPLICATION_PROCESS_ID, &process::id().to_string());
+
+        let mut pa_ctx = PAContext::new_with_proplist(&main_loop, app_name, &props)
+            .ok_or("Failed to create PulseAudio context")?;
+
+        pa_ctx.connect(self.server_name.as_deref(), FlagSet::NOFAIL, None)?;
+        match main_loop.wait_for_ready(&pa_ctx).await {
+            Ok(State::Ready) => {}
+            Ok(state) => bail!(
+                "failed to connect: state={:?}, err={:?}",
+                state,
+                pa_ctx.errno().to_string()
+            ),
+            Err(_) => bail!("Pulse mainloop exited while waiting on context, not continuing"),
+        }
+
+        Ok((main_loop, pa_ctx))
+    }
 }
 
 pub struct PulseState {
@@ -73,11 +136,38 @@ pub struct PulseState {
     increment: u32,
     increment_sound: bool,
     max_volume: Option<u32>,
+    warn_above: Option<u32>,
+    display_filter: Rc<[String]>,
+    on_unplug: UnplugAction,
+    show_balance: bool,
+    auto_switch: bool,
+    smooth: bool,
+    /// The volume ramp that's currently running (if `smooth` is set)
+    volume_ramp: Option<VolumeRamp>,
+    /// Whether the initial server state has been fetched, anything added after this is new
+    loaded: bool,
     pa_ctx: PAContext,
     default_sink: Rc<str>,
     default_source: Rc<str>,
     sinks: Vec<InOut>,
     sources: Vec<InOut>,
+    cards: Vec<Card>,
+    /// The stream used to upload `increment_sound` to the server, until it's torn down
+    sample_upload: Option<RcCell<Stream>>,
+}
+
+/// Forget a sink or source once the server has removed it
+fn remove_by_index(objs: &mut Vec<InOut>, idx: u32) {
+    objs.retain(|obj| obj.index != idx);
+}
+
+/// A running task which is smoothly changing the volume of a sink or source
+struct VolumeRamp {
+    what: Object,
+    index: u32,
+    /// The volume it's ramping to
+    target: ChannelVolumes,
+    task: JoinHandle<()>,
 }
 
 macro_rules! impl_pa_methods {
@@ -87,7 +177,15 @@ macro_rules! impl_pa_methods {
                 match result {
                     ListResult::Item(info) => {
                         match self.[<$name s>].iter_mut().find(|s| s.index == info.index) {
-                            Some(s) => *s = info.into(),
+                            Some(s) => {
+                                let obj: InOut = info.into();
+                                let unplugged = obj.active_port_unplugged(s).map(|p| p.description.clone());
+                                *s = obj;
+
+                                if let Some(port) = unplugged {
+                                    self.handle_unplug(Object::[<$name:camel>], info.index, port);
+                                }
+                            },
                             None => {
                                 let obj = info.into();
 
@@ -98,6 +196,7 @@ macro_rules! impl_pa_methods {
                                     }
 
                                     let _ = self.tx.send(obj.notify_new(stringify!($name)));
+                                    self.auto_switch_to(Object::[<$name:camel>], &obj);
                                 }
 
                                 self.[<$name s>].push(obj);
@@ -110,7 +209,7 @@ macro_rules! impl_pa_methods {
             }
 
             fn [<remove_ $name>](&mut self, idx: u32) {
-                self.[<$name s>].retain(|s| s.index == idx);
+                remove_by_index(&mut self.[<$name s>], idx);
             }
 
             fn [<set_mute_ $name>]<F>(&self, idx: u32, mute: bool, f: F)
@@ -130,10 +229,141 @@ macro_rules! impl_pa_methods {
     };
 }
 
+/// What should be changed when cycling through sinks/sources and their ports
+#[derive(Debug)]
+enum CycleTarget<'a> {
+    /// Move to the next port on the current object
+    Port { obj: &'a InOut, port: &'a Port },
+    /// Move to another object, optionally with the port it should use
+    Object { obj: &'a InOut, port: Option<&'a Port> },
+}
+
+/// Decides where to cycle to next. This only uses the state reported by the server (the default
+/// object name and each object's `active_port`) so any updates that arrive while cycling can't
+/// cause us to lose our place.
+fn cycle_target<'a>(
+    objects: &'a [InOut],
+    curr_obj_name: &str,
+    dir: Dir,
+) -> Option<CycleTarget<'a>> {
+    let curr_obj_idx = match objects.iter().position(|s| &*s.name == curr_obj_name) {
+        Some(idx) => idx,
+        None => {
+            log::warn!("failed to find active object {curr_obj_name}");
+            return None;
+        }
+    };
+
+    // cycle next port if there's one available
+    let curr_obj = &objects[curr_obj_idx];
+    if let (Some(curr), Some(next)) = (curr_obj.active_port.as_ref(), curr_obj.next_port(dir)) {
+        if curr.name != next.name {
+            return Some(CycleTarget::Port {
+                obj: curr_obj,
+                port: next,
+            });
+        }
+    }
+
+    // get the next object (that isn't a source monitor)
+    let next_obj = dir.cycle(curr_obj_idx, objects, |o| !o.is_source_monitor)?;
+
+    // if there aren't any other objects to cycle to, then we're done
+    if curr_obj.index == next_obj.index {
+        return None;
+    }
+
+    Some(CycleTarget::Object {
+        obj: next_obj,
+        port: next_obj.first_port(),
+    })
+}
+
+impl PulseState {
+    fn new(pulse: &Pulse, tx: UnboundedSender<Command>, pa_ctx: PAContext) -> PulseState {
+        PulseState {
+            tx,
+            increment: pulse.increment,
+            increment_sound: false,
+            max_volume: pulse.max_volume,
+            warn_above: pulse.warn_above,
+            display_filter: pulse.display_filter.clone().into(),
+            on_unplug: pulse.on_unplug,
+            show_balance: pulse.show_balance,
+            auto_switch: pulse.auto_switch,
+            smooth: pulse.smooth,
+            volume_ramp: None,
+            loaded: false,
+
+            pa_ctx,
+            default_sink: "?".into(),
+            default_source: "?".into(),
+            sinks: vec![],
+            sources: vec![],
+            cards: vec![],
+            sample_upload: None,
+        }
+    }
+}
+
 impl RcCell<PulseState> {
     impl_pa_methods!(sink);
     impl_pa_methods!(source);
 
+    fn add_card(&mut self, result: ListResult<&CardInfo>) {
+        match result {
+            ListResult::Item(info) => {
+                let card = Card::from(info);
+                match self.cards.iter_mut().find(|c| c.index == info.index) {
+                    Some(c) => *c = card,
+                    None => self.cards.push(card),
+                }
+            }
+            ListResult::Error => log::warn!("add_card failed"),
+            ListResult::End => {}
+        }
+    }
+
+    fn remove_card(&mut self, idx: u32) {
+        self.cards.retain(|c| c.index != idx);
+    }
+
+    /// The card's active profile, which is only shown for bluetooth devices
+    fn active_profile_label(&self, obj: &InOut) -> Option<String> {
+        if !obj.is_bluetooth() {
+            return None;
+        }
+
+        self.cards
+            .iter()
+            .find(|c| Some(c.index) == obj.card)
+            .and_then(|c| c.active_profile.as_ref())
+            .map(|p| p.label())
+    }
+
+    fn set_profile<F>(&mut self, what: Object, profile: impl AsRef<str>, mut f: F)
+    where
+        F: FnMut(bool) + 'static,
+    {
+        let profile = profile.as_ref();
+        log::trace!("set_profile_{what} {profile}");
+        let obj = match what {
+            Object::Sink => self.default_sink(),
+            Object::Source => self.default_source(),
+        };
+
+        match obj.and_then(|obj| obj.card) {
+            Some(card) => {
+                let mut introspect = self.pa_ctx.introspect();
+                introspect.set_card_profile_by_index(card, profile, Some(Box::new(f)));
+            }
+            None => {
+                log::warn!("default {what} doesn't have a card, can't set its profile");
+                f(false);
+            }
+        }
+    }
+
     fn default_sink(&self) -> Option<InOut> {
         self.sinks
             .iter()
@@ -148,6 +378,87 @@ impl RcCell<PulseState> {
             .cloned()
     }
 
+    fn handle_unplug(&mut self, what: Object, index: u32, port: Rc<str>) {
+        log::debug!("{what} port unplugged: {port}");
+        match self.on_unplug {
+            UnplugAction::None => {}
+            UnplugAction::Notify => {
+                let _ = self.tx.send(Command::NotifyUnplugged {
+                    port,
+                    what: what.into(),
+                });
+            }
+            UnplugAction::Mute => {
+                let f = move |success| {
+                    if !success {
+                        log::warn!("failed to mute {what} after {port} was unplugged");
+                    }
+                };
+
+                match what {
+                    Object::Sink => self.set_mute_sink(index, true, f),
+                    Object::Source => self.set_mute_source(index, true, f),
+                }
+            }
+        }
+    }
+
+    /// If `auto_switch` is enabled, make the newly added sink the default
+    fn auto_switch_to(&mut self, what: Object, obj: &InOut) {
+        // the objects which exist when we first connect aren't new, so don't switch to them
+        if !self.auto_switch || !self.loaded || !matches!(what, Object::Sink) {
+            return;
+        }
+
+        if obj.is_source_monitor || obj.name == self.default_sink {
+            return;
+        }
+
+        log::info!("new {what} {} added, auto switching the default to it", obj.name);
+        let name = obj.name.clone();
+        self.set_default(what, obj.name.clone(), move |success| {
+            if !success {
+                log::warn!("failed to auto switch default {what} to {name}");
+            }
+        });
+    }
+
+    fn matches_display_filter(&self, obj: &InOut) -> bool {
+        self.display_filter
+            .iter()
+            .any(|pattern| obj.name.contains(pattern.as_str()))
+    }
+
+    /// The sink shown in the item. This is the default sink, unless a `display_filter` is set and
+    /// the default sink doesn't match it - then the first sink which matches is used instead.
+    fn display_sink(&self) -> Option<InOut> {
+        let default_sink = self.default_sink()?;
+        if self.display_filter.is_empty() || self.matches_display_filter(&default_sink) {
+            return Some(default_sink);
+        }
+
+        match self.sinks.iter().find(|s| self.matches_display_filter(s)) {
+            Some(sink) => {
+                log::debug!(
+                    "display_filter hid default sink {} from display, showing {} instead",
+                    default_sink.name,
+                    sink.name
+                );
+                Some(sink.clone())
+            }
+            None => Some(default_sink),
+        }
+    }
+
+    /// The object whose volume and mute are changed by actions: this is the one that's displayed,
+    /// so the item never shows one sink while changing another.
+    fn controlled(&self, what: Object) -> Option<InOut> {
+        match what {
+            Object::Sink => self.display_sink(),
+            Object::Source => self.default_source(),
+        }
+    }
+
     fn cycle_objects_and_ports<F>(&mut self, what: Object, dir: Dir, mut f: F)
     where
         F: FnMut(bool) + 'static,
@@ -160,36 +471,17 @@ impl RcCell<PulseState> {
             Object::Sink => self.default_sink.clone(),
             Object::Source => self.default_source.clone(),
         };
-        let curr_obj_idx = match objects.iter().position(|s| s.name == curr_obj_name) {
-            Some(idx) => idx,
-            None => {
-                log::warn!("failed to find active {what}");
-                return;
-            }
-        };
 
-        // cycle next port if there's one available
-        let curr_obj = &objects[curr_obj_idx];
-        if let (Some(curr), Some(next)) = (curr_obj.active_port.as_ref(), curr_obj.next_port(dir)) {
-            if curr != next {
-                return self.set_object_port(what, curr_obj.index, &next.name, f);
+        let (next_obj, next_prt) = match cycle_target(objects, &curr_obj_name, dir) {
+            Some(CycleTarget::Port { obj, port }) => {
+                return self.set_object_port(what, obj.index, &port.name, f);
             }
-        }
-
-        // get the next object (that isn't a source monitor)
-        let next_obj = match dir.cycle(curr_obj_idx, objects, |o| !o.is_source_monitor) {
-            Some(obj) => obj,
+            Some(CycleTarget::Object { obj, port }) => (obj, port),
             None => return,
         };
 
-        // if there aren't any other objects to cycle to, then we're done
-        if curr_obj.index == next_obj.index {
-            return;
-        }
-
         // cycle next object
         let next_obj_name = next_obj.name.clone();
-        let next_prt = next_obj.first_port();
         let next_prt_name = match next_prt {
             Some(port) => port.name.clone(),
             None => {
@@ -204,7 +496,7 @@ impl RcCell<PulseState> {
 
         // if the object we're moving to already has the right port set, just set that object as
         // the new default
-        if next_obj.active_port.as_ref() == next_prt {
+        if next_obj.active_port.as_ref().map(|p| &p.name) == next_prt.map(|p| &p.name) {
             return self.set_default(what, next_obj_name.clone(), move |success| {
                 if !success {
                     log::warn!("failed to set default to {next_obj_name} while cycling");
@@ -298,19 +590,86 @@ impl RcCell<PulseState> {
         F: FnMut(bool) + 'static,
     {
         log::trace!("set_volume_{what} {vol}");
-        if let Some(p) = match what {
-            Object::Sink => self.default_sink().map(|mut p| {
-                self.set_volume_sink(p.index, self.update_volume(&mut p.volume, vol), f);
-                p
-            }),
-            Object::Source => self.default_source().map(|mut p| {
-                self.set_volume_source(p.index, self.update_volume(&mut p.volume, vol), f);
-                p
-            }),
-        } {
-            // send notification
-            let _ = self.tx.send(p.notify_volume_mute());
-            self.play_volume_sample_if_enabled(what);
+        let Some(mut p) = self.controlled(what) else {
+            return;
+        };
+
+        let from = p.volume;
+        // if this object is already ramping, carry on from where it was going so that quick
+        // successive changes (e.g. scrolling) add up
+        if let Some(ramp) = self.volume_ramp.take() {
+            if ramp.what == what && ramp.index == p.index && !ramp.task.is_finished() {
+                ramp.task.abort();
+                p.volume = ramp.target;
+            }
+        }
+
+        self.update_volume(&mut p.volume, vol);
+        if self.smooth {
+            self.ramp_volume(what, p.index, from, p.volume, f);
+        } else {
+            self.set_object_volume(what, p.index, &p.volume, f);
+        }
+
+        // send notification
+        let _ = self.tx.send(p.notify_volume_mute());
+        self.play_volume_sample_if_enabled(what);
+    }
+
+    fn set_object_volume<F>(&self, what: Object, index: u32, cv: &ChannelVolumes, f: F)
+    where
+        F: FnMut(bool) + 'static,
+    {
+        match what {
+            Object::Sink => self.set_volume_sink(index, cv, f),
+            Object::Source => self.set_volume_source(index, cv, f),
+        }
+    }
+
+    /// Set the volume over a few steps spaced out with a timer, so the server isn't flooded with
+    /// requests. Only the last step calls `f`.
+    fn ramp_volume<F>(
+        &mut self,
+        what: Object,
+        index: u32,
+        from: ChannelVolumes,
+        to: ChannelVolumes,
+        f: F,
+    ) where
+        F: FnMut(bool) + 'static,
+    {
+        let mut steps = ramp(&from, &to, RAMP_STEPS);
+        // SAFETY(unwrap): there's always at least one step
+        let last = steps.pop().unwrap();
+
+        let state = self.clone();
+        let task = tokio::task::spawn_local(async move {
+            for cv in steps {
+                state.set_object_volume(what, index, &cv, |_| {});
+                tokio::time::sleep(RAMP_STEP_DELAY).await;
+            }
+
+            state.set_object_volume(what, index, &last, f);
+        });
+
+        self.volume_ramp = Some(VolumeRamp {
+            what,
+            index,
+            target: to,
+            task,
+        });
+    }
+
+    fn set_balance<F>(&mut self, what: Object, balance: i32, f: F)
+    where
+        F: FnMut(bool) + 'static,
+    {
+        log::trace!("set_balance_{what} {balance}");
+        let obj = self.controlled(what);
+
+        if let Some(obj) = obj {
+            let cv = balance::set_balance(&obj.volume, &obj.channel_map, balance);
+            self.set_object_volume(what, obj.index, &cv, f);
         }
     }
 
@@ -319,18 +678,14 @@ impl RcCell<PulseState> {
         F: FnMut(bool) + 'static,
     {
         log::trace!("set_mute_{what} {mute}");
-        if let Some(p) = match what {
-            Object::Sink => self.default_sink().map(|mut p| {
-                p.mute = mute;
-                self.set_mute_sink(p.index, p.mute, f);
-                p
-            }),
-            Object::Source => self.default_source().map(|mut p| {
-                p.mute = mute;
-                self.set_mute_source(p.index, p.mute, f);
-                p
-            }),
-        } {
+        if let Some(p) = self.controlled(what).map(|mut p| {
+            p.mute = mute;
+            match what {
+                Object::Sink => self.set_mute_sink(p.index, p.mute, f),
+                Object::Source => self.set_mute_source(p.index, p.mute, f),
+            }
+            p
+        }) {
             let _ = self.tx.send(p.notify_volume_mute());
             self.play_volume_sample_if_enabled(what);
         }
@@ -340,18 +695,14 @@ impl RcCell<PulseState> {
     where
         F: FnMut(bool) + 'static,
     {
-        if let Some(p) = match what {
-            Object::Sink => self.default_sink().map(|mut p| {
-                p.mute = !p.mute;
-                self.set_mute_sink(p.index, p.mute, f);
-                p
-            }),
-            Object::Source => self.default_source().map(|mut p| {
-                p.mute = !p.mute;
-                self.set_mute_source(p.index, p.mute, f);
-                p
-            }),
-        } {
+        if let Some(p) = self.controlled(what).map(|mut p| {
+            p.mute = !p.mute;
+            match what {
+                Object::Sink => self.set_mute_sink(p.index, p.mute, f),
+                Object::Source => self.set_mute_source(p.index, p.mute, f),
+            }
+            p
+        }) {
             let _ = self.tx.send(p.notify_volume_mute());
             self.play_volume_sample_if_enabled(what);
         }
@@ -376,7 +727,7 @@ impl RcCell<PulseState> {
     }
 
     fn update_item(&self) {
-        let (default_sink, default_source) = match (self.default_sink(), self.default_source()) {
+        let (default_sink, default_source) = match (self.display_sink(), self.default_source()) {
             (Some(sink), Some(source)) => (sink, source),
             _ => {
                 log::warn!("tried to update, but failed to find default source");
@@ -384,9 +735,25 @@ impl RcCell<PulseState> {
             }
         };
 
+        let show_balance = self.show_balance;
+        let warn_above = self.warn_above;
+        let sink_profile = self.active_profile_label(&default_sink);
+        let source_profile = self.active_profile_label(&default_source);
         let _ = self.tx.send(Command::UpdateItem(Box::new(move |theme| {
-            let sink_text = default_sink.format(Object::Sink, theme);
-            let source_text = default_source.format(Object::Source, theme);
+            let sink_text = default_sink.format(
+                Object::Sink,
+                theme,
+                show_balance,
+                warn_above,
+                sink_profile.as_deref(),
+            );
+            let source_text = default_source.format(
+                Object::Source,
+                theme,
+                show_balance,
+                warn_above,
+                source_profile.as_deref(),
+            );
 
             I3Item::new(format!(r#"{} {}"#, sink_text, source_text))
                 .short_text(sink_text)
@@ -405,7 +772,10 @@ impl RcCell<PulseState> {
                 state.subscribe_cb(&inspect, fac.unwrap(), op.unwrap(), idx);
             })));
 
-        let mask = InterestMaskSet::SERVER | InterestMaskSet::SINK | InterestMaskSet::SOURCE;
+        let mask = InterestMaskSet::SERVER
+            | InterestMaskSet::SINK
+            | InterestMaskSet::SOURCE
+            | InterestMaskSet::CARD;
         self.pa_ctx.subscribe(mask, |success| {
             if !success {
                 log::error!("subscribe failed");
@@ -456,7 +826,8 @@ impl RcCell<PulseState> {
 
         impl_handler!(
             (Sink, get_sink_info_by_index),
-            (Source, get_source_info_by_index)
+            (Source, get_source_info_by_index),
+            (Card, get_card_info_by_index)
         );
     }
 
@@ -494,6 +865,11 @@ impl RcCell<PulseState> {
             inner.add_source(item);
         });
 
+        let mut inner = self.clone();
+        inspect.get_card_info_list(move |item| {
+            inner.add_card(item);
+        });
+
         let mut inner = self.clone();
         inspect.get_server_info(move |info| {
             let update_if_needed = |me: &mut PulseState, what: Object, name: Rc<str>| {
@@ -517,14 +893,37 @@ impl RcCell<PulseState> {
                 update_if_needed(&mut inner, Object::Source, name.to_string().into())
             }
 
+            // the server info is requested last, so by now all the existing objects are known
+            inner.loaded = true;
             inner.update_item();
         });
     }
 
+    /// Disconnect and drop the stream used to upload the volume sample, if there is one.
+    /// NOTE: this must never be called from within one of the stream's own callbacks, since
+    /// clearing a callback while it's running causes a segmentation fault
+    /// see: https://github.com/jnqnfe/pulse-binding-rust/issues/56
+    fn teardown_sample_upload(&mut self) {
+        if let Some(mut stream) = self.sample_upload.take() {
+            // disconnect first so no more callbacks are made, this fails if the upload has already
+            // finished (the stream is terminated then) which is fine
+            if let Err(e) = stream.disconnect() {
+                log::trace!("failed to disconnect sample stream: {}", e);
+            }
+
+            // the write callback holds a reference to the stream, so it must be cleared before
+            // the stream can be dropped
+            stream.set_write_callback(None);
+        }
+    }
+
     async fn setup_volume_sample(&mut self, wav_path: impl AsRef<Path>) -> Result<()> {
         let (spec, audio_data) = audio::read_wav_file(wav_path.as_ref()).await?;
         let audio_data_len = audio_data.len();
 
+        // only one upload should ever be in progress, so clean up any previous one
+        self.teardown_sample_upload();
+
         // create stream
         let mut stream = match Stream::new(&mut self.pa_ctx, SAMPLE_NAME, &spec, None) {
             Some(stream) => RcCell::new(stream),
@@ -536,9 +935,15 @@ impl RcCell<PulseState> {
         let mut stream_ref = stream.clone();
         let mut bytes_written = 0;
 
-        // NOTE: calling `stream_ref.set_write_callback(None)` causes a segmentation fault
+        // NOTE: calling `stream_ref.set_write_callback(None)` from in here causes a segmentation
+        // fault, so instead the stream is torn down outside of the callback once it's finished
         // see: https://github.com/jnqnfe/pulse-binding-rust/issues/56
         stream.set_write_callback(Some(Box::new(move |len| {
+            // the server may ask for more data before the stream is torn down
+            if bytes_written >= audio_data_len {
+                return;
+            }
+
             if let Err(e) = stream_ref.write(&audio_data, None, 0, SeekMode::Relative) {
                 log::error!(
                     "failed to write to stream: {:?} - {:?}",
@@ -557,59 +962,39 @@ impl RcCell<PulseState> {
                     // the upload to the audio server has completed - we're ready to use the sample now
                     inner.increment_sound = true;
                 }
+
+                // the stream isn't needed anymore, but it can't be dropped from within its callback
+                let _ = inner.tx.send(Command::TeardownSampleUpload);
             }
         })));
 
         // connect the stream as an upload, which sends it to the audio server instead of playing it directly
         stream.connect_upload(audio_data_len)?;
+        self.sample_upload = Some(stream);
 
         Ok(())
     }
 }
 
+/// Tears down the sample upload when the item stops (or its task is aborted), otherwise the stream
+/// and the state referenced by its callback would never be dropped
+struct SampleUploadGuard(RcCell<PulseState>);
+
+impl Drop for SampleUploadGuard {
+    fn drop(&mut self) {
+        self.0.teardown_sample_upload();
+    }
+}
+
 #[async_trait(?Send)]
 impl BarItem for Pulse {
     async fn start(&self, mut ctx: Context) -> Result<crate::context::StopAction> {
         // setup pulse main loop
-        let (mut main_loop, pa_ctx) = {
-            let mut main_loop = TokioMain::new();
-
-            let app_name = env!("CARGO_PKG_NAME");
-            let mut props = Proplist::new().ok_or("Failed to create PulseAudio Proplist")?;
-            let _ = props.set_str(APPLICATION_NAME, app_name);
-            let _ = props.set_str(APPLICATION_PROCESS_ID, &process::id().to_string());
-
-            let mut pa_ctx = PAContext::new_with_proplist(&main_loop, app_name, &props)
-                .ok_or("Failed to create PulseAudio context")?;
-
-            pa_ctx.connect(self.server_name.as_deref(), FlagSet::NOFAIL, None)?;
-            match main_loop.wait_for_ready(&pa_ctx).await {
-                Ok(State::Ready) => {}
-                Ok(state) => bail!(
-                    "failed to connect: state={:?}, err={:?}",
-                    state,
-                    pa_ctx.errno().to_string()
-                ),
-                Err(_) => bail!("Pulse mainloop exited while waiting on context, not continuing"),
-            }
-
-            (main_loop, pa_ctx)
-        };
+        let (mut main_loop, pa_ctx) = self.connect().await?;
 
         // this is shared between all the async tasks
         let (tx, mut rx) = mpsc::unbounded_channel();
-        let mut inner = RcCell::new(PulseState {
-            tx,
-            increment: self.increment,
-            increment_sound: false,
-            max_volume: self.max_volume,
-
-            pa_ctx,
-            default_sink: "?".into(),
-            default_source: "?".into(),
-            sinks: vec![],
-            sources: vec![],
-        });
+        let mut inner = RcCell::new(PulseState::new(self, tx, pa_ctx));
 
         // subscribe to server changes
         let (exit_tx, mut exit_rx) = mpsc::unbounded_channel();
@@ -618,6 +1003,7 @@ impl BarItem for Pulse {
         inner.fetch_server_state();
 
         // if a sound file was given, then setup a sample
+        let _sample_upload = SampleUploadGuard(inner.clone());
         if let Some(ref path) = self.increment_sound {
             if let Err(e) = inner.setup_volume_sample(expand_path(path)?).await {
                 log::error!("failed to setup volume sample: {}", e);
@@ -636,7 +1022,7 @@ impl BarItem for Pulse {
         loop {
             tokio::select! {
                 // handle events
-                Some(event) = ctx.raw_event_rx().recv() => match event {
+                Some(event) = ctx.raw_event() => match event {
                     BarEvent::Custom { payload, responder } => inner.handle_custom_message(payload, responder),
                     BarEvent::Click(click) => match click.button {
                         // cycle source ports
@@ -715,19 +1101,23 @@ impl BarItem for Pulse {
                     }
                     Command::NotifyVolume { name, volume, mute } => {
                         if self.notify.should_notify(NotificationSetting::VolumeMute) {
-                            let _ = notifications.pulse_volume_mute(name, volume, mute).await;
+                            let _ = notifications.pulse_volume_mute(&self.notify_options, name, volume, mute).await;
                         }
                     }
                     Command::NotifyNewSourceSink { name, what } => {
                         if self.notify.should_notify(NotificationSetting::NewSourceSink) {
-                            let _ = notifications.pulse_new_source_sink(name, what).await;
+                            let _ = notifications.pulse_new_source_sink(&self.notify_options, name, what).await;
                         }
                     }
                     Command::NotifyDefaultsChange { name, what } => {
                         if self.notify.should_notify(NotificationSetting::DefaultsChange) {
-                            let _ = notifications.pulse_defaults_change(name, what).await;
+                            let _ = notifications.pulse_defaults_change(&self.notify_options, name, what).await;
                         }
                     }
+                    Command::NotifyUnplugged { port, what } => {
+                        notifications.pulse_port_unplugged(&self.notify_options, port, what).await;
+                    }
+                    Command::TeardownSampleUpload => inner.teardown_sample_upload(),
                 },
 
                 // handle pulse main loop exited
@@ -742,12 +1132,220 @@ impl BarItem for Pulse {
 
 #[cfg(test)]
 mod tests {
+    use libpulse_binding::def::DevicePortType;
     use libpulse_binding::error::Code;
 
+    use super::*;
+
+    #[test]
+    #[ignore = "requires a running PulseAudio server"]
+    fn sample_upload_teardown() {
+        let dir = crate::util::TestDir::new("sample");
+        let wav = dir.join("sample.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&wav, spec).unwrap();
+        for _ in 0..800 {
+            writer.write_sample(0_i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        crate::util::local_block_on(async {
+            let pulse = Pulse::default();
+            let (mut main_loop, pa_ctx) = pulse.connect().await.unwrap();
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let mut inner = RcCell::new(PulseState::new(&pulse, tx, pa_ctx));
+            tokio::task::spawn_local(async move { main_loop.run().await });
+
+            // tear down streams which are still uploading
+            for _ in 0..10 {
+                inner.setup_volume_sample(&wav).await.unwrap();
+                inner.teardown_sample_upload();
+                assert!(inner.sample_upload.is_none());
+            }
+
+            // and streams which finished uploading
+            for _ in 0..10 {
+                inner.setup_volume_sample(&wav).await.unwrap();
+                loop {
+                    if let Some(Command::TeardownSampleUpload) = rx.recv().await {
+                        break;
+                    }
+                }
+                inner.teardown_sample_upload();
+                assert!(inner.increment_sound);
+                assert!(inner.sample_upload.is_none());
+            }
+        })
+        .unwrap();
+    }
+
     #[test]
     fn check_code_cast() {
         use num_traits::ToPrimitive;
 
         assert_eq!(Code::ConnectionTerminated.to_i32().unwrap(), 11);
     }
+
+    fn port(name: &str, available: PortAvailable) -> Port {
+        Port {
+            name: name.into(),
+            description: name.into(),
+            available,
+            port_type: DevicePortType::Speaker,
+        }
+    }
+
+    fn sink(index: u32, name: &str, ports: &[Port], active: Option<usize>) -> InOut {
+        InOut {
+            index,
+            name: name.into(),
+            volume: ChannelVolumes::default(),
+            channel_map: Default::default(),
+            mute: false,
+            ports: ports.into(),
+            active_port: active.map(|idx| ports[idx].clone()),
+            is_source_monitor: false,
+            is_remote: false,
+            card: None,
+        }
+    }
+
+    #[test]
+    fn remove_sink() {
+        let mut sinks = vec![sink(0, "one", &[], None), sink(1, "two", &[], None)];
+
+        // only the removed sink is dropped
+        remove_by_index(&mut sinks, 0);
+        let names = sinks.iter().map(|s| s.name.to_string()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["two"]);
+
+        // and unknown indices are ignored
+        remove_by_index(&mut sinks, 7);
+        assert_eq!(sinks.len(), 1);
+    }
+
+    fn target_names(target: Option<CycleTarget>) -> Option<(String, Option<String>)> {
+        target.map(|target| match target {
+            CycleTarget::Port { obj, port } => (obj.name.to_string(), Some(port.name.to_string())),
+            CycleTarget::Object { obj, port } => {
+                (obj.name.to_string(), port.map(|p| p.name.to_string()))
+            }
+        })
+    }
+
+    #[test]
+    fn cycle_ports_then_objects() {
+        let ports = [
+            port("a", PortAvailable::Yes),
+            port("b", PortAvailable::Yes),
+        ];
+        let sinks = vec![
+            sink(0, "one", &ports, Some(0)),
+            sink(1, "two", &ports, Some(1)),
+        ];
+
+        assert_eq!(
+            target_names(cycle_target(&sinks, "one", Dir::Next)),
+            Some(("one".into(), Some("b".into())))
+        );
+        assert_eq!(
+            target_names(cycle_target(&sinks, "two", Dir::Next)),
+            Some(("one".into(), Some("a".into())))
+        );
+        assert_eq!(
+            target_names(cycle_target(&sinks, "two", Dir::Prev)),
+            Some(("two".into(), Some("a".into())))
+        );
+        assert_eq!(target_names(cycle_target(&sinks, "missing", Dir::Next)), None);
+    }
+
+    #[test]
+    fn unplug_detection() {
+        let plugged = [
+            port("speakers", PortAvailable::Unknown),
+            port("headphones", PortAvailable::Yes),
+        ];
+        let unplugged = [
+            port("speakers", PortAvailable::Unknown),
+            port("headphones", PortAvailable::No),
+        ];
+
+        let before = sink(0, "one", &plugged, Some(1));
+
+        // pulse switched to another port after headphones were unplugged
+        let after = sink(0, "one", &unplugged, Some(0));
+        assert_eq!(
+            after.active_port_unplugged(&before).map(|p| &*p.name),
+            Some("headphones")
+        );
+
+        // pulse kept the unplugged port active
+        let after = sink(0, "one", &unplugged, Some(1));
+        assert_eq!(
+            after.active_port_unplugged(&before).map(|p| &*p.name),
+            Some("headphones")
+        );
+
+        // no change in availability
+        let after = sink(0, "one", &plugged, Some(1));
+        assert!(after.active_port_unplugged(&before).is_none());
+
+        // a port that wasn't active was unplugged
+        let before = sink(0, "one", &plugged, Some(0));
+        let after = sink(0, "one", &unplugged, Some(0));
+        assert!(after.active_port_unplugged(&before).is_none());
+
+        // it was already unplugged
+        let before = sink(0, "one", &unplugged, Some(1));
+        let after = sink(0, "one", &unplugged, Some(0));
+        assert!(after.active_port_unplugged(&before).is_none());
+    }
+
+    #[test]
+    fn cycle_position_survives_server_updates() {
+        let ports = [
+            port("a", PortAvailable::Yes),
+            port("b", PortAvailable::Unknown),
+            port("c", PortAvailable::Yes),
+        ];
+        let mut sinks = vec![
+            sink(0, "one", &ports, Some(0)),
+            sink(1, "two", &ports, Some(0)),
+        ];
+
+        // first cycle moves to the next port
+        assert_eq!(
+            target_names(cycle_target(&sinks, "one", Dir::Next)),
+            Some(("one".into(), Some("b".into())))
+        );
+
+        // the server then reports the new active port, but the availability of the port has
+        // changed in the meantime, so it no longer matches the port in the list exactly
+        let updated_ports = [
+            port("a", PortAvailable::Yes),
+            port("b", PortAvailable::Yes),
+            port("c", PortAvailable::Yes),
+        ];
+        sinks[0] = sink(0, "one", &updated_ports, None);
+        sinks[0].active_port = Some(ports[1].clone());
+
+        // we should carry on from the active port rather than jumping back to the first one
+        assert_eq!(
+            target_names(cycle_target(&sinks, "one", Dir::Next)),
+            Some(("one".into(), Some("c".into())))
+        );
+
+        // a new sink appearing mid-cycle doesn't change our position either
+        sinks.insert(0, sink(2, "three", &updated_ports, Some(2)));
+        sinks[1].active_port = Some(updated_ports[2].clone());
+        assert_eq!(
+            target_names(cycle_target(&sinks, "one", Dir::Next)),
+            Some(("two".into(), Some("a".into())))
+        );
+    }
 }
@@ -0,0 +1,91 @@
+//! Smoothly ramping between two volumes, rather than jumping straight to the new one.
+//! Each step is a separate request to the server (which also sends a change event back) so there
+//! are only a few of them, spaced out over a short time.
+
+use std::time::Duration;
+
+use libpulse_binding::volume::{ChannelVolumes, Volume};
+
+/// How many volumes are set when ramping from one volume to another
+pub const RAMP_STEPS: u32 = 5;
+/// How long to wait between each step of a ramp
+pub const RAMP_STEP_DELAY: Duration = Duration::from_millis(20);
+
+/// The volumes to set (in order) to move from `from` to `to` in even steps, the last of which is
+/// always `to`. If the channels of each volume don't match, then `to` is the only step.
+pub fn ramp(from: &ChannelVolumes, to: &ChannelVolumes, steps: u32) -> Vec<ChannelVolumes> {
+    if steps <= 1 || from.len() != to.len() {
+        return vec![*to];
+    }
+
+    (1..=steps)
+        .map(|step| {
+            let mut cv = *to;
+            for (vol, (a, b)) in cv.get_mut().iter_mut().zip(from.get().iter().zip(to.get())) {
+                let (a, b) = (a.0 as i64, b.0 as i64);
+                *vol = Volume((a + (b - a) * step as i64 / steps as i64) as u32);
+            }
+
+            cv
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cv(volumes: &[u32]) -> ChannelVolumes {
+        let mut cv = ChannelVolumes::default();
+        cv.set(volumes.len() as u8, Volume::NORMAL);
+        for (vol, v) in cv.get_mut().iter_mut().zip(volumes) {
+            *vol = Volume(*v);
+        }
+
+        cv
+    }
+
+    fn volumes(steps: Vec<ChannelVolumes>) -> Vec<Vec<u32>> {
+        steps
+            .iter()
+            .map(|cv| cv.get().iter().map(|v| v.0).collect())
+            .collect()
+    }
+
+    #[test]
+    fn ramp_up() {
+        assert_eq!(
+            volumes(ramp(&cv(&[0, 0]), &cv(&[1000, 500]), 5)),
+            vec![
+                vec![200, 100],
+                vec![400, 200],
+                vec![600, 300],
+                vec![800, 400],
+                vec![1000, 500]
+            ]
+        );
+    }
+
+    #[test]
+    fn ramp_down() {
+        assert_eq!(
+            volumes(ramp(&cv(&[1000]), &cv(&[0]), 4)),
+            vec![vec![750], vec![500], vec![250], vec![0]]
+        );
+        // steps are rounded towards `from`, but the last one is always exact
+        assert_eq!(
+            volumes(ramp(&cv(&[100]), &cv(&[90]), 3)),
+            vec![vec![97], vec![94], vec![90]]
+        );
+    }
+
+    #[test]
+    fn ramp_single_step() {
+        let (from, to) = (cv(&[0, 0]), cv(&[1000, 1000]));
+        assert_eq!(volumes(ramp(&from, &to, 1)), vec![vec![1000, 1000]]);
+        assert_eq!(volumes(ramp(&from, &to, 0)), vec![vec![1000, 1000]]);
+
+        // the channels changed, so there's nothing to ramp from
+        assert_eq!(volumes(ramp(&cv(&[0]), &to, 5)), vec![vec![1000, 1000]]);
+    }
+}
@@ -3,15 +3,25 @@ use std::ops::Add;
 use std::rc::Rc;
 
 use clap::ValueEnum;
-use libpulse_binding::context::introspect::{SinkInfo, SinkPortInfo, SourceInfo, SourcePortInfo};
+use libpulse_binding::channelmap::Map as ChannelMap;
+use libpulse_binding::context::introspect::{
+    CardInfo,
+    CardProfileInfo,
+    SinkInfo,
+    SinkPortInfo,
+    SourceInfo,
+    SourcePortInfo,
+};
 use libpulse_binding::def::{DevicePortType, PortAvailable};
+use libpulse_binding::proplist::Proplist;
 use libpulse_binding::volume::{ChannelVolumes, Volume};
 use serde_derive::{Deserialize, Serialize};
 
+use super::balance::{format_balance, get_balance};
 use crate::i3::I3Item;
 use crate::theme::Theme;
 
-#[derive(Debug, Copy, Clone, ValueEnum)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
 pub enum Object {
     Source,
     Sink,
@@ -83,16 +93,93 @@ macro_rules! impl_port_from {
 impl_port_from!(SinkPortInfo<'a>);
 impl_port_from!(SourcePortInfo<'a>);
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: Rc<str>,
+    pub description: Rc<str>,
+    pub available: bool,
+}
+
+impl<'a> From<&'a CardProfileInfo<'a>> for Profile {
+    fn from(value: &'a CardProfileInfo<'a>) -> Self {
+        Profile {
+            name: value.name.as_deref().unwrap_or("").into(),
+            description: value.description.as_deref().unwrap_or("").into(),
+            available: value.available,
+        }
+    }
+}
+
+impl Profile {
+    /// A short label for bluetooth profiles, e.g.: `A2DP AAC` or `HSP/HFP`.
+    /// Any other profile names are returned as-is.
+    pub fn label(&self) -> String {
+        let name = self.name.to_lowercase();
+        if let Some(codec) = name.strip_prefix("a2dp-sink") {
+            return match codec.trim_start_matches('-') {
+                "" => "A2DP".into(),
+                codec => format!("A2DP {}", codec.to_uppercase()),
+            };
+        }
+
+        if name.starts_with("headset-head-unit") || name.contains("hsp") || name.contains("hfp") {
+            return "HSP/HFP".into();
+        }
+
+        self.name.to_string()
+    }
+}
+
+/// Information about a card, which is the device that provides sinks and sources
+#[derive(Debug, Clone)]
+pub struct Card {
+    pub index: u32,
+    pub name: Rc<str>,
+    pub profiles: Rc<[Profile]>,
+    pub active_profile: Option<Profile>,
+}
+
+impl<'a> From<&'a CardInfo<'a>> for Card {
+    fn from(value: &'a CardInfo<'a>) -> Self {
+        Card {
+            index: value.index,
+            name: value.name.as_deref().unwrap_or("").into(),
+            profiles: value.profiles.iter().map(Profile::from).collect(),
+            active_profile: value
+                .active_profile
+                .as_ref()
+                .map(|p| Profile::from(p.as_ref())),
+        }
+    }
+}
+
 /// Information about a `Sink` or a `Source` (input or output)
 #[derive(Debug, Clone)]
 pub struct InOut {
     pub index: u32,
     pub name: Rc<str>,
     pub volume: ChannelVolumes,
+    pub channel_map: ChannelMap,
     pub mute: bool,
     pub ports: Rc<[Port]>,
     pub active_port: Option<Port>,
     pub is_source_monitor: bool,
+    /// Whether this is on another machine, such as a tunnel to a network sink
+    pub is_remote: bool,
+    /// Index of the card this belongs to, if any
+    pub card: Option<u32>,
+}
+
+/// Tunnel and network sinks (and sources) don't always have a `Network` port, so instead they're
+/// detected by the module that created them, or the properties those modules set.
+fn is_remote(driver: Option<&str>, props: &Proplist) -> bool {
+    const REMOTE_DRIVERS: &[&str] = &["tunnel", "raop", "roc", "rtp"];
+    const REMOTE_PROPS: &[&str] = &["tunnel.", "raop.", "roc.", "rtp."];
+
+    driver.map_or(false, |d| REMOTE_DRIVERS.iter().any(|r| d.contains(r)))
+        || props
+            .iter()
+            .any(|key| REMOTE_PROPS.iter().any(|p| key.starts_with(p)))
 }
 
 impl<'a> From<&'a SinkInfo<'a>> for InOut {
@@ -101,10 +188,13 @@ impl<'a> From<&'a SinkInfo<'a>> for InOut {
             index: value.index,
             name: value.name.as_deref().unwrap_or("").into(),
             volume: value.volume,
+            channel_map: value.channel_map,
             mute: value.mute,
             ports: value.ports.iter().map(Port::from).collect(),
             active_port: value.active_port.as_ref().map(|p| Port::from(p.as_ref())),
             is_source_monitor: false,
+            is_remote: is_remote(value.driver.as_deref(), &value.proplist),
+            card: value.card,
         }
     }
 }
@@ -115,10 +205,13 @@ impl<'a> From<&'a SourceInfo<'a>> for InOut {
             index: value.index,
             name: value.name.as_deref().unwrap_or("").into(),
             volume: value.volume,
+            channel_map: value.channel_map,
             mute: value.mute,
             ports: value.ports.iter().map(Port::from).collect(),
             active_port: value.active_port.as_ref().map(|p| Port::from(p.as_ref())),
             is_source_monitor: value.monitor_of_sink.is_some(),
+            is_remote: is_remote(value.driver.as_deref(), &value.proplist),
+            card: value.card,
         }
     }
 }
@@ -129,11 +222,26 @@ impl InOut {
         (self.volume.max().0 * 100 + normal / 2) / normal
     }
 
+    /// The stereo balance, `None` if there aren't both left and right channels
+    pub fn balance(&self) -> Option<i32> {
+        get_balance(&self.volume, &self.channel_map)
+    }
+
+    pub fn is_bluetooth(&self) -> bool {
+        self.active_port
+            .as_ref()
+            .map_or(false, |p| p.port_type == DevicePortType::Bluetooth)
+    }
+
     pub fn port_symbol(&self) -> Option<&str> {
         if self.is_source_monitor {
             return Some("󱡫 ");
         }
 
+        if self.is_remote {
+            return Some("󰛳 ");
+        }
+
         match &self.active_port {
             Some(port) => match port.port_type {
                 DevicePortType::Aux => Some("󱡬 "),
@@ -154,9 +262,11 @@ impl InOut {
         }
     }
 
+    /// The position of the active port is always derived from what the server reports, and ports
+    /// are matched by name since other fields (such as availability) may change between updates.
     fn current_port_idx(&self) -> usize {
         self.active_port.as_ref().map_or(0, |active| {
-            match self.ports.iter().position(|p| p == active) {
+            match self.ports.iter().position(|p| p.name == active.name) {
                 Some(idx) => idx,
                 None => {
                     log::warn!(
@@ -170,6 +280,21 @@ impl InOut {
         })
     }
 
+    /// If the port that was active in `previous` has since become unavailable (e.g., headphones
+    /// were unplugged) then it's returned. Pulse usually switches to another port when this
+    /// happens, so the port is looked up in the current list of ports rather than the active one.
+    pub fn active_port_unplugged(&self, previous: &InOut) -> Option<&Port> {
+        let prev_active = previous.active_port.as_ref()?;
+        if !prev_active.available() {
+            return None;
+        }
+
+        self.ports
+            .iter()
+            .find(|p| p.name == prev_active.name)
+            .filter(|p| !p.available())
+    }
+
     pub fn first_port(&self) -> Option<&Port> {
         self.ports.iter().find(|p| p.available())
     }
@@ -200,9 +325,37 @@ impl InOut {
         }
     }
 
-    pub fn format(&self, what: Object, theme: &Theme) -> String {
+    /// `profile` is only shown for bluetooth devices, since it's not very useful otherwise.
+    /// The volume is coloured red if it's above `warn_above` (and not muted).
+    pub fn format(
+        &self,
+        what: Object,
+        theme: &Theme,
+        show_balance: bool,
+        warn_above: Option<u32>,
+        profile: Option<&str>,
+    ) -> String {
+        let balance = match self.balance() {
+            Some(balance) if show_balance && balance != 0 => {
+                format!(" {}", format_balance(balance))
+            }
+            _ => String::new(),
+        };
+        let profile = match profile {
+            Some(profile) if self.is_bluetooth() => format!(" {}", profile),
+            _ => String::new(),
+        };
+        let volume = match warn_above {
+            Some(limit) if !self.mute && self.volume_pct() > limit => format!(
+                r#"<span foreground="{}">{}%</span>"#,
+                theme.red.display_rgb(),
+                self.volume_pct()
+            ),
+            _ => format!("{}%", self.volume_pct()),
+        };
+
         format!(
-            r#"<span foreground="{}">{} {}%</span>"#,
+            r#"<span foreground="{}">{} {}{}{}</span>"#,
             (if self.mute { theme.dim } else { theme.fg }).display_rgb(),
             self.port_symbol().unwrap_or(match (what, self.mute) {
                 (Object::Sink, false) => "",
@@ -210,7 +363,9 @@ impl InOut {
                 (Object::Source, false) => "󰍬",
                 (Object::Source, true) => "󰍭",
             }),
-            self.volume_pct(),
+            volume,
+            balance,
+            profile,
         )
     }
 }
@@ -230,6 +385,12 @@ pub enum Command {
         name: Rc<str>,
         what: Rc<str>,
     },
+    NotifyUnplugged {
+        port: Rc<str>,
+        what: Rc<str>,
+    },
+    /// The volume sample has finished uploading, and its stream can be dropped
+    TeardownSampleUpload,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -257,6 +418,19 @@ impl NotificationSetting {
     }
 }
 
+/// What to do when the active port of a sink or source is unplugged
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnplugAction {
+    /// Do nothing (the default)
+    #[default]
+    None,
+    /// Send a notification
+    Notify,
+    /// Mute the sink or source
+    Mute,
+}
+
 #[derive(Debug, Copy, Clone, ValueEnum)]
 pub enum Dir {
     Prev,
@@ -332,10 +506,13 @@ mod tests {
                 index: $index,
                 name: $name.into(),
                 volume: ChannelVolumes::default(),
+                channel_map: ChannelMap::default(),
                 mute: false,
                 ports: $ports.clone().into(),
                 active_port: $ports.get($active).cloned(),
                 is_source_monitor: false,
+                is_remote: false,
+                card: None,
             }
         };
     }
@@ -382,6 +559,111 @@ mod tests {
         assert_eq!(obj.next_port(Dir::Prev), Some(&ports[0]));
     }
 
+    /**
+     * Profile tests
+     */
+
+    #[test]
+    fn profile_labels() {
+        let label = |name: &str| {
+            Profile {
+                name: name.into(),
+                description: "".into(),
+                available: true,
+            }
+            .label()
+        };
+
+        assert_eq!(label("a2dp-sink"), "A2DP");
+        assert_eq!(label("a2dp-sink-aac"), "A2DP AAC");
+        assert_eq!(label("a2dp-sink-sbc_xq"), "A2DP SBC_XQ");
+        assert_eq!(label("headset-head-unit"), "HSP/HFP");
+        assert_eq!(label("headset-head-unit-msbc"), "HSP/HFP");
+        assert_eq!(label("handsfree_head_unit"), "handsfree_head_unit");
+        assert_eq!(label("output:analog-stereo"), "output:analog-stereo");
+    }
+
+    #[test]
+    fn profile_only_shown_for_bluetooth() {
+        let theme = Theme::default();
+        let ports = vec![
+            port!("a", PortAvailable::Yes, DevicePortType::Speaker),
+            port!("b", PortAvailable::Yes, DevicePortType::Bluetooth),
+        ];
+
+        let obj = obj!(0, "one", ports, active = 0);
+        assert!(!obj
+            .format(Object::Sink, &theme, false, None, Some("A2DP"))
+            .contains("A2DP"));
+
+        let obj = obj!(0, "one", ports, active = 1);
+        assert!(obj
+            .format(Object::Sink, &theme, false, None, Some("A2DP"))
+            .contains(" A2DP<"));
+    }
+
+    #[test]
+    fn volume_warning() {
+        let theme = Theme::default();
+        let red = format!(r#"<span foreground="{}">"#, theme.red.display_rgb());
+        let ports = vec![port!("a", PortAvailable::Yes, DevicePortType::Speaker)];
+        let with_volume = |pct: u32| {
+            let mut obj = obj!(0, "one", ports, active = 0);
+            obj.volume.set(2, Volume(Volume::NORMAL.0 * pct / 100));
+            assert_eq!(obj.volume_pct(), pct);
+            obj
+        };
+
+        // at the threshold isn't a warning
+        let text = with_volume(100).format(Object::Sink, &theme, false, Some(100), None);
+        assert!(!text.contains(&red));
+        assert!(text.contains(" 100%<"));
+
+        // but above it is
+        let text = with_volume(110).format(Object::Sink, &theme, false, Some(100), None);
+        assert!(text.contains(&format!("{}110%</span>", red)));
+
+        // not when muted, or when there's no threshold
+        let mut obj = with_volume(110);
+        obj.mute = true;
+        assert!(!obj.format(Object::Sink, &theme, false, Some(100), None).contains(&red));
+        let text = with_volume(150).format(Object::Sink, &theme, false, None, None);
+        assert!(!text.contains(&red));
+    }
+
+    #[test]
+    fn remote_detection() {
+        let props = |keys: &[&str]| {
+            let mut props = Proplist::new().unwrap();
+            for key in keys {
+                props.set_str(key, "value").unwrap();
+            }
+            props
+        };
+
+        assert!(!is_remote(Some("module-alsa-card.c"), &props(&["device.class"])));
+        assert!(!is_remote(None, &props(&[])));
+        assert!(is_remote(Some("module-tunnel-sink-new.c"), &props(&[])));
+        assert!(is_remote(Some("module-raop-sink.c"), &props(&[])));
+        assert!(is_remote(None, &props(&["device.class", "tunnel.remote.server"])));
+    }
+
+    #[test]
+    fn remote_symbol() {
+        let theme = Theme::default();
+        let ports = vec![port!("a", PortAvailable::Yes, DevicePortType::Speaker)];
+
+        let mut obj = obj!(0, "one", ports, active = 0);
+        assert_eq!(obj.port_symbol(), None);
+
+        // remote sinks show the network symbol even if their port doesn't say so
+        obj.is_remote = true;
+        assert_eq!(obj.port_symbol(), Some("󰛳 "));
+        assert!(obj
+            .format(Object::Sink, &theme, false, None, None)
+            .contains("󰛳  0%"));
+    }
+
     /**
      * Dir tests
      */
@@ -0,0 +1,202 @@
+use std::future::Future;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_derive::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::context::{BarItem, Context, StopAction};
+use crate::error::Result;
+use crate::i3::I3Item;
+use crate::util::output_with_timeout;
+
+/// Part of a raw item's text, commands are written as `{{command}}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment<'a> {
+    Text(&'a str),
+    Command(&'a str),
+}
+
+/// Splits text into plain text and command substitutions.
+/// An unterminated `{{` is treated as plain text.
+fn parse_template(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let len = match rest[start + 2..].find("}}") {
+            Some(len) => len,
+            None => break,
+        };
+
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+        segments.push(Segment::Command(rest[start + 2..start + 2 + len].trim()));
+        rest = &rest[start + 2 + len + 2..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    segments
+}
+
+/// Replace each command substitution in the segments with the output of `run`
+async fn render<'a, F, Fut>(segments: &[Segment<'a>], run: F) -> String
+where
+    F: Fn(&'a str) -> Fut,
+    Fut: Future<Output = String>,
+{
+    let mut text = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(s) => text.push_str(s),
+            Segment::Command(command) => text.push_str(&run(command).await),
+        }
+    }
+
+    text
+}
+
+/// Runs a command substitution, its output is trimmed of any trailing newlines. Commands which take
+/// longer than `limit` are killed, so a command that hangs can't stop the item from updating.
+async fn run_command(command: &str, limit: Duration) -> String {
+    let output = output_with_timeout(Command::new("sh").arg("-c").arg(command), Some(limit)).await;
+    let output = output.and_then(|output| match output {
+        Some(output) => output.into_result(),
+        None => bail!("timed out after {:?}", limit),
+    });
+
+    match output {
+        Ok(stdout) => stdout.trim_end_matches('\n').to_string(),
+        Err(e) => {
+            log::warn!("command substitution failed: {}: {}", command, e);
+            "?".into()
+        }
+    }
+}
+
+/// A static item, which is displayed as is. Its `full_text` may contain command substitutions
+/// (e.g.: `{{uname -r}}`) which are replaced by the command's output.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Raw {
+    #[serde(flatten)]
+    item: I3Item,
+    /// How often to re-run any command substitutions, if unset they're run once and then again on
+    /// any event
+    #[serde(
+        default,
+        with = "crate::human_time::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    interval: Option<Duration>,
+    /// Command substitutions which take longer than this are killed, and shown as `?`
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    timeout: Option<Duration>,
+}
+
+impl Raw {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+}
+
+impl From<I3Item> for Raw {
+    fn from(item: I3Item) -> Self {
+        Raw {
+            item,
+            interval: None,
+            timeout: None,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl BarItem for Raw {
+    async fn start(&self, mut ctx: Context) -> Result<StopAction> {
+        let segments = parse_template(&self.item.full_text);
+
+        // plain items are only displayed once
+        if !segments.iter().any(|s| matches!(s, Segment::Command(_))) {
+            return self.item.start(ctx).await;
+        }
+
+        let limit = self.timeout.unwrap_or(Self::DEFAULT_TIMEOUT);
+        loop {
+            let text = render(&segments, |command| run_command(command, limit)).await;
+            ctx.update_item(self.item.clone().full_text(text)).await?;
+            ctx.wait_for_event(self.interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::local_block_on;
+
+    #[test]
+    fn template_segments() {
+        use Segment::*;
+
+        assert_eq!(parse_template("plain"), vec![Text("plain")]);
+        assert_eq!(parse_template(""), vec![]);
+        assert_eq!(
+            parse_template("kernel {{ uname -r }} up {{uptime}}"),
+            vec![
+                Text("kernel "),
+                Command("uname -r"),
+                Text(" up "),
+                Command("uptime")
+            ]
+        );
+        assert_eq!(parse_template("{{a}}{{b}}"), vec![Command("a"), Command("b")]);
+        // unterminated substitutions are left as is
+        assert_eq!(
+            parse_template("{{a}} {{b"),
+            vec![Command("a"), Text(" {{b")]
+        );
+    }
+
+    #[test]
+    fn render_substitutions() {
+        let segments = parse_template("<{{ echo one }}|{{two}}>");
+        let mock = |command: &str| {
+            let output = command.to_uppercase();
+            async move { output }
+        };
+
+        let (text, _) = local_block_on(render(&segments, mock)).unwrap();
+        assert_eq!(text, "<ECHO ONE|TWO>");
+    }
+
+    #[test]
+    fn run_commands() {
+        let limit = Raw::DEFAULT_TIMEOUT;
+        let (text, _) = local_block_on(run_command("printf 'a\\nb\\n\\n'", limit)).unwrap();
+        assert_eq!(text, "a\nb");
+
+        let (text, _) = local_block_on(run_command("exit 1", limit)).unwrap();
+        assert_eq!(text, "?");
+    }
+
+    #[test]
+    fn hung_commands_are_killed() {
+        // a distinctive command, so it can be found in the process list
+        let sleep = "sleep 27.1828";
+        let command = format!("{} & wait", sleep);
+        let (text, _) =
+            local_block_on(run_command(&command, Duration::from_millis(200))).unwrap();
+        assert_eq!(text, "?");
+
+        // NOTE: processes which have exited but haven't been reaped have an empty command line
+        let running = std::fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|entry| std::fs::read(entry.ok()?.path().join("cmdline")).ok())
+            .any(|cmdline| cmdline == format!("{}\0", sleep.replace(' ', "\0")).as_bytes());
+        assert!(!running, "command started by the substitution is still running");
+    }
+}
@@ -1,13 +1,18 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use hex_color::HexColor;
 use serde_derive::{Deserialize, Serialize};
 use tokio::process::Command;
+#[cfg(feature = "inotify")]
+use tokio::time::timeout;
 
 use crate::context::{BarEvent, BarItem, Context, StopAction};
 use crate::error::Result;
 use crate::i3::{I3Item, I3Markup};
+use crate::util::{expand_path, output_with_timeout};
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -16,6 +21,25 @@ pub enum ScriptFormat {
     #[default]
     Simple,
     Json,
+    /// Like `Simple`, but a leading `#rrggbb ` sets the item's colour
+    Prefixed,
+}
+
+/// Splits an optional leading `#rrggbb` colour from the rest of the output.
+/// The colour must be followed by whitespace (or be the entire output) to count as a prefix.
+fn parse_colour_prefix(output: &str) -> (Option<HexColor>, &str) {
+    let (token, rest) = match output.split_once(char::is_whitespace) {
+        Some((token, rest)) => (token, rest),
+        None => (output, ""),
+    };
+
+    match token.len() == 7 && token.starts_with('#') {
+        true => match HexColor::parse_rgb(token) {
+            Ok(color) => (Some(color), rest),
+            Err(_) => (None, output),
+        },
+        false => (None, output),
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -23,23 +47,103 @@ pub struct Script {
     pub command: String,
     #[serde(default)]
     pub output: ScriptFormat,
-    #[serde(default, with = "humantime_serde")]
+    /// How often to re-run the script, if unset (or `"never"`) it's only re-run on events
+    #[serde(default, with = "crate::human_time::option::unclamped")]
     interval: Option<Duration>,
     #[serde(default)]
     pub markup: I3Markup,
+    /// If the script takes longer than this to run, it's killed and an error is displayed
+    #[serde(default, with = "humantime_serde")]
+    timeout: Option<Duration>,
+    /// Re-run the script when this file or directory changes (requires the `inotify` feature)
+    #[serde(default)]
+    watch: Option<PathBuf>,
+}
+
+/// Watches a file or directory for changes, so the script can be re-run instead of polling
+#[cfg(feature = "inotify")]
+struct FileWatcher {
+    stream: inotify::EventStream<[u8; 1024]>,
+}
+
+#[cfg(feature = "inotify")]
+impl FileWatcher {
+    /// How long to wait for things to settle after a change, so a burst of writes is one change
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    fn new(path: &std::path::Path) -> Result<FileWatcher> {
+        use inotify::{Inotify, WatchMask};
+
+        let inotify = Inotify::init()?;
+        inotify.watches().add(
+            path,
+            WatchMask::MODIFY
+                | WatchMask::CLOSE_WRITE
+                | WatchMask::CREATE
+                | WatchMask::DELETE
+                | WatchMask::MOVED_FROM
+                | WatchMask::MOVED_TO
+                | WatchMask::ATTRIB,
+        )?;
+
+        Ok(FileWatcher {
+            stream: inotify.into_event_stream([0; 1024])?,
+        })
+    }
+
+    async fn next_event(&mut self) -> Result<()> {
+        use futures::StreamExt;
+
+        match self.stream.next().await {
+            Some(event) => {
+                event?;
+                Ok(())
+            }
+            None => bail!("inotify event stream ended"),
+        }
+    }
+
+    /// Resolves after a change, once there have been no further changes for `DEBOUNCE`
+    async fn wait_for_change(&mut self) -> Result<()> {
+        self.next_event().await?;
+        loop {
+            match timeout(Self::DEBOUNCE, self.next_event()).await {
+                Ok(result) => result?,
+                Err(_) => return Ok(()),
+            }
+        }
+    }
 }
 
 impl Script {
-    // returns stdout
-    async fn run(&self, env: &HashMap<&str, String>) -> Result<String> {
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(&self.command)
-            .envs(env)
-            .output()
-            .await?;
-
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    // returns stdout, or `None` if the script timed out
+    async fn run(&self, env: &HashMap<&str, String>) -> Result<Option<String>> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&self.command).envs(env);
+
+        let output = match (output_with_timeout(&mut command, self.timeout).await?, self.timeout) {
+            (Some(output), _) => output,
+            (None, duration) => {
+                log::warn!(
+                    "script timed out after {:?}, killed: {}",
+                    duration.unwrap_or_default(),
+                    self.command
+                );
+                return Ok(None);
+            }
+        };
+
+        // the output is still displayed, but stderr is logged to help with debugging
+        if !output.status.success() {
+            log::warn!(
+                "script exited with {}: {} (stderr: {})",
+                output.status,
+                self.command,
+                output.stderr
+            );
+        }
+
+        Ok(Some(output.stdout))
     }
 }
 
@@ -52,6 +156,9 @@ impl BarItem for Script {
             BarEvent::Signal => {
                 env.insert("I3_SIGNAL", "true".to_string());
             }
+            BarEvent::Visibility(visible) => {
+                env.insert("I3_VISIBLE", visible.to_string());
+            }
             BarEvent::Click(c) => {
                 env.remove("I3_SIGNAL");
                 if let Some(name) = c.name {
@@ -80,23 +187,74 @@ impl BarItem for Script {
             _ => {}
         };
 
+        #[cfg(feature = "inotify")]
+        let mut watcher = match &self.watch {
+            Some(path) => match FileWatcher::new(&expand_path(path)?) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    log::error!(
+                        "failed to watch {}, falling back to polling: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
+        #[cfg(not(feature = "inotify"))]
+        if let Some(path) = &self.watch {
+            log::warn!(
+                "can't watch {} since i3stat wasn't built with the `inotify` feature, falling back to polling",
+                expand_path(path)?.display()
+            );
+        }
+
         loop {
             // Initial run has no click environment variables
-            let stdout = self.run(&script_env).await?;
-            let mut item = match self.output {
-                ScriptFormat::Simple => I3Item::new(stdout),
-                ScriptFormat::Json => match serde_json::from_str(&stdout) {
-                    Ok(item) => item,
-                    Err(e) => {
-                        log::error!("failed to parse script json output: {}", e);
-                        I3Item::new("ERROR").background_color(ctx.config.theme.red)
-                    }
-                },
+            let item = match self.run(&script_env).await? {
+                Some(stdout) => match self.output {
+                    ScriptFormat::Simple => I3Item::new(stdout),
+                    ScriptFormat::Prefixed => match parse_colour_prefix(&stdout) {
+                        (Some(color), text) => I3Item::new(text).color(color),
+                        (None, text) => I3Item::new(text),
+                    },
+                    ScriptFormat::Json => match serde_json::from_str(&stdout) {
+                        Ok(item) => item,
+                        Err(e) => {
+                            log::error!("failed to parse script json output: {}", e);
+                            I3Item::new("ERROR").background_color(ctx.config.theme.red)
+                        }
+                    },
+                }
+                .markup(self.markup),
+                None => I3Item::new("TIMEOUT").background_color(ctx.config.theme.red),
             };
-            item = item.markup(self.markup);
 
             ctx.update_item(item).await?;
 
+            #[cfg(feature = "inotify")]
+            if let Some(inner) = watcher.as_mut() {
+                let result = tokio::select! {
+                    result = inner.wait_for_change() => result,
+                    // the interval (if any) and events still re-run the script
+                    event = ctx.wait_for_event(self.interval) => {
+                        if let Some(event) = event {
+                            handle_event(event, &mut script_env);
+                        }
+                        Ok(())
+                    }
+                };
+
+                if let Err(e) = result {
+                    log::error!("failed to watch for changes, falling back to polling: {}", e);
+                    watcher = None;
+                }
+
+                continue;
+            }
+
             match self.interval {
                 // if an interval is set, then re-run the script on that interval
                 Some(interval) => {
@@ -116,3 +274,81 @@ impl BarItem for Script {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::local_block_on;
+
+    #[test]
+    fn colour_prefix() {
+        let red = Some(HexColor::rgb(255, 0, 0));
+        assert_eq!(parse_colour_prefix("#ff0000 hello"), (red, "hello"));
+        assert_eq!(parse_colour_prefix("#FF0000 hello world"), (red, "hello world"));
+        assert_eq!(parse_colour_prefix("#ff0000"), (red, ""));
+    }
+
+    #[test]
+    fn no_colour_prefix() {
+        assert_eq!(parse_colour_prefix("hello"), (None, "hello"));
+        assert_eq!(parse_colour_prefix(""), (None, ""));
+        // not followed by whitespace
+        assert_eq!(parse_colour_prefix("#ff0000hello"), (None, "#ff0000hello"));
+        // not a valid colour
+        assert_eq!(parse_colour_prefix("#gg0000 hello"), (None, "#gg0000 hello"));
+        // short form colours aren't supported, since they look too much like normal text
+        assert_eq!(parse_colour_prefix("#f00 hello"), (None, "#f00 hello"));
+        assert_eq!(parse_colour_prefix("#1 in the charts"), (None, "#1 in the charts"));
+    }
+
+    #[test]
+    fn timeout_kills_process_group() {
+        // a distinctive command, so it can be found in the process list
+        let sleep = "sleep 31.4159";
+        let script = Script {
+            command: format!("{} & wait", sleep),
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+
+        let (output, _) = local_block_on(script.run(&HashMap::new())).unwrap();
+        assert_eq!(output.unwrap(), None);
+
+        // NOTE: processes which have exited but haven't been reaped have an empty command line
+        let running = std::fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|entry| std::fs::read(entry.ok()?.path().join("cmdline")).ok())
+            .any(|cmdline| cmdline == format!("{}\0", sleep.replace(' ', "\0")).as_bytes());
+        assert!(!running, "command started by the script is still running");
+    }
+
+    #[cfg(feature = "inotify")]
+    #[test]
+    fn debounced_watch() {
+        let dir = crate::util::TestDir::new("script-watch");
+
+        let (changes, _) = local_block_on(async {
+            let mut watcher = FileWatcher::new(&dir).unwrap();
+
+            // a burst of writes
+            let file = dir.join("file");
+            for i in 0..5 {
+                std::fs::write(&file, i.to_string()).unwrap();
+            }
+
+            // only results in a single change
+            let mut changes = 0;
+            while timeout(Duration::from_secs(1), watcher.wait_for_change())
+                .await
+                .is_ok()
+            {
+                changes += 1;
+            }
+
+            changes
+        })
+        .unwrap();
+
+        assert_eq!(changes, 1);
+    }
+}
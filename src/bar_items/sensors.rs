@@ -1,50 +1,294 @@
 use crate::error::Result;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use async_trait::async_trait;
 use hex_color::HexColor;
 use serde_derive::{Deserialize, Serialize};
 use sysinfo::Components;
-use tokio::time::sleep;
+use tokio::fs;
+use tokio::process::Command;
 
 use crate::context::{BarItem, Context, StopAction};
 use crate::i3::{I3Item, I3Markup};
 use crate::theme::Theme;
 use crate::util::format::{float, FloatFormat};
+use crate::util::{expand_path, output_bounded, Hysteresis, Paginator};
+
+/// The kind of reading a sensor provides.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorKind {
+    /// hwmon's `temp*_input` files, in millidegrees Celsius
+    #[default]
+    Temperature,
+    /// hwmon's `in*_input` files, in millivolts
+    Voltage,
+    /// hwmon's `curr*_input` files, in milliamps
+    Current,
+}
+
+impl SensorKind {
+    fn default_unit(&self) -> &'static str {
+        match self {
+            SensorKind::Temperature => "°C",
+            SensorKind::Voltage => "V",
+            SensorKind::Current => "A",
+        }
+    }
+
+    /// The first hwmon input file for this kind
+    fn first_input(&self) -> &'static str {
+        match self {
+            SensorKind::Temperature => "temp1_input",
+            SensorKind::Voltage => "in0_input",
+            SensorKind::Current => "curr1_input",
+        }
+    }
+}
+
+/// Devices which can be discovered automatically, rather than configuring an `input`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorDevice {
+    /// The first GPU with a hwmon driver (see `GPU_HWMON_NAMES`)
+    Gpu,
+}
+
+/// The `name` of hwmon devices provided by GPU drivers
+const GPU_HWMON_NAMES: &[&str] = &["amdgpu", "radeon", "nouveau", "i915", "xe"];
+
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+
+/// Find the input file of the first hwmon device under `root` whose `name` is one of `names`.
+/// Devices are searched in order (`hwmon0`, `hwmon1`, ...) so the result is stable.
+fn find_hwmon_input(root: &Path, names: &[&str], kind: SensorKind) -> Option<PathBuf> {
+    let mut dirs = std::fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect::<Vec<_>>();
+    dirs.sort();
+
+    dirs.into_iter().find_map(|dir| {
+        let name = std::fs::read_to_string(dir.join("name")).ok()?;
+        let input = dir.join(kind.first_input());
+        (names.contains(&name.trim()) && input.exists()).then_some(input)
+    })
+}
+
+const THERMAL_ROOT: &str = "/sys/class/thermal";
+
+/// Find the `temp` file of the first thermal zone under `root` whose `type` is `zone_type`. Zones
+/// are searched in order (`thermal_zone0`, `thermal_zone1`, ...) so the result is stable.
+fn find_thermal_zone_input(root: &Path, zone_type: &str) -> Option<PathBuf> {
+    let mut dirs = std::fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with("thermal_zone"))
+        })
+        .collect::<Vec<_>>();
+    dirs.sort();
+
+    dirs.into_iter().find_map(|dir| {
+        let name = std::fs::read_to_string(dir.join("type")).ok()?;
+        let input = dir.join("temp");
+        (name.trim() == zone_type && input.exists()).then_some(input)
+    })
+}
+
+/// Where a sensor's value is read from
+enum Source {
+    Input(PathBuf),
+    Component(String),
+    Command(String),
+}
+
+/// A sensor shown on its own page, when `label` is a list
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SensorPage {
+    label: String,
+    #[serde(default)]
+    kind: Option<SensorKind>,
+    /// If none of `component`, `input`, `thermal_zone`, `device` or `command` are set, the item's
+    /// are used
+    #[serde(default)]
+    component: Option<String>,
+    #[serde(default)]
+    input: Option<PathBuf>,
+    #[serde(default)]
+    thermal_zone: Option<String>,
+    #[serde(default)]
+    device: Option<SensorDevice>,
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    scale: Option<f64>,
+    #[serde(default)]
+    unit: Option<String>,
+}
+
+impl SensorPage {
+    fn has_source(&self) -> bool {
+        self.component.is_some()
+            || self.input.is_some()
+            || self.thermal_zone.is_some()
+            || self.device.is_some()
+            || self.command.is_some()
+    }
+}
+
+/// Either a label shown after the value, or a list of sensors to page between
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SensorLabel {
+    Single(String),
+    Pages(Vec<SensorPage>),
+}
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Sensors {
     #[serde(with = "crate::human_time")]
     interval: Duration,
+    /// Shown after the value. If it's a list of sensors, the item pages between them on scroll.
+    #[serde(default)]
+    label: Option<SensorLabel>,
+    /// The kind of sensor, defaults to temperature.
+    #[serde(default)]
+    kind: SensorKind,
+    /// Name of the temperature component (see `i3stat-sensors`).
+    #[serde(default)]
+    component: Option<String>,
+    /// Path to a hwmon input file to read instead of a component.
+    #[serde(default)]
+    input: Option<PathBuf>,
+    /// Read the temperature of the thermal zone (in `/sys/class/thermal`) with this `type`, e.g.
+    /// `x86_pkg_temp`. Useful on systems without hwmon, such as some SBCs and VMs.
+    #[serde(default)]
+    thermal_zone: Option<String>,
+    /// Find the hwmon input file of this device automatically.
+    #[serde(default)]
+    device: Option<SensorDevice>,
+    /// Command to run when there's no hwmon input (e.g., `nvidia-smi`), its output should be the
+    /// value itself (it's not scaled unless `scale` is set).
     #[serde(default)]
-    label: Option<String>,
-    component: String,
+    command: Option<String>,
+    /// The raw value read from `input` is divided by this. Defaults to 1000.
+    #[serde(default)]
+    scale: Option<f64>,
+    /// Unit displayed after the value. Defaults depend on `kind`.
+    #[serde(default)]
+    unit: Option<String>,
+    /// How far a temperature must cross a threshold before its colour changes. Defaults to 0.
+    #[serde(default)]
+    hysteresis: f64,
     #[serde(flatten)]
     float_fmt: FloatFormat,
 }
 
 impl Sensors {
-    fn get_icon(theme: &Theme, temp: u32) -> (&'static str, Option<HexColor>) {
-        match temp {
-            0..=59 => ("", None),
-            60..=69 => ("", Some(theme.yellow)),
-            70..=79 => ("", Some(theme.yellow)),
-            80..=89 => ("", Some(theme.orange)),
-            90..=u32::MAX => ("", Some(theme.red)),
+    const DEFAULT_SCALE: f64 = 1000.0;
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(SensorLabel::Pages(pages)) = &self.label {
+            if pages.is_empty() {
+                bail!("`label` must not be an empty list");
+            }
         }
+
+        Ok(())
     }
-}
 
-#[async_trait(?Send)]
-impl BarItem for Sensors {
-    async fn start(&self, ctx: Context) -> Result<StopAction> {
-        let mut components = Components::new_with_refreshed_list();
+    /// The sensors shown by this item, one per page. Each page is configured like a single sensor
+    /// item, with anything it doesn't set taken from the item itself.
+    fn pages(&self) -> Vec<Sensors> {
+        let pages = match &self.label {
+            Some(SensorLabel::Pages(pages)) => pages,
+            _ => return vec![self.clone()],
+        };
 
-        let label = self.label.as_deref().unwrap_or("");
-        loop {
-            let temp = {
+        pages
+            .iter()
+            .map(|page| {
+                let mut sensor = Sensors {
+                    label: Some(SensorLabel::Single(page.label.clone())),
+                    kind: page.kind.unwrap_or(self.kind),
+                    scale: page.scale.or(self.scale),
+                    unit: page.unit.clone().or_else(|| self.unit.clone()),
+                    ..self.clone()
+                };
+
+                if page.has_source() {
+                    sensor.component = page.component.clone();
+                    sensor.input = page.input.clone();
+                    sensor.thermal_zone = page.thermal_zone.clone();
+                    sensor.device = page.device;
+                    sensor.command = page.command.clone();
+                }
+
+                sensor
+            })
+            .collect()
+    }
+
+    fn label(&self) -> &str {
+        match &self.label {
+            Some(SensorLabel::Single(label)) => label,
+            _ => "",
+        }
+    }
+
+    /// Temperatures at which the icon and colour change
+    const TEMPERATURE_THRESHOLDS: [f64; 4] = [60.0, 70.0, 80.0, 90.0];
+
+    /// `level` is the number of `TEMPERATURE_THRESHOLDS` the value has reached
+    fn get_icon(&self, theme: &Theme, level: usize) -> (&'static str, Option<HexColor>) {
+        match self.kind {
+            SensorKind::Temperature => match level {
+                0 => ("", None),
+                1 => ("", Some(theme.yellow)),
+                2 => ("", Some(theme.yellow)),
+                3 => ("", Some(theme.orange)),
+                _ => ("", Some(theme.red)),
+            },
+            SensorKind::Voltage => ("󱐋", None),
+            SensorKind::Current => ("󰚥", None),
+        }
+    }
+
+    /// Returns the formatted value for the full and short text respectively.
+    fn format_value(&self, value: f64) -> (String, String) {
+        let unit = self.unit.as_deref().unwrap_or(self.kind.default_unit());
+        let value = float(value, &self.float_fmt);
+        match self.kind {
+            SensorKind::Temperature => (format!("{}{}", value, unit), format!("{}C", value)),
+            _ => (format!("{} {}", value, unit), format!("{}{}", value, unit)),
+        }
+    }
+
+    async fn read_input(&self, path: &Path) -> Result<f64> {
+        let raw = fs::read_to_string(path).await?.trim().parse::<f64>()?;
+        Ok(raw / self.scale.unwrap_or(Self::DEFAULT_SCALE))
+    }
+
+    async fn read_command(&self, command: &str) -> Result<f64> {
+        let output = output_bounded(Command::new("sh").arg("-c").arg(command)).await?;
+        let raw = match output.into_result() {
+            Ok(stdout) => stdout.parse::<f64>()?,
+            Err(e) => bail!("command --> {} <-- {}", command, e),
+        };
+        Ok(raw / self.scale.unwrap_or(1.0))
+    }
+
+    async fn read(&self, source: &Source, components: &mut Components) -> Result<f64> {
+        Ok(match source {
+            Source::Input(path) => self.read_input(path).await?,
+            Source::Command(command) => self.read_command(command).await?,
+            Source::Component(component) => {
                 let search = components.iter_mut().find_map(|c| {
-                    if c.label() == self.component {
+                    if c.label() == component {
                         c.refresh();
                         Some(c.temperature())
                     } else {
@@ -53,27 +297,216 @@ impl BarItem for Sensors {
                 });
 
                 match search {
-                    Some(temp) => temp,
-                    None => {
-                        break Err(
-                            format!("no component found with name: {}", self.component).into()
-                        )
-                    }
+                    Some(temp) => temp as f64,
+                    None => bail!("no component found with name: {}", component),
                 }
+            }
+        })
+    }
+
+    fn source(&self) -> Result<Source> {
+        if let Some(input) = &self.input {
+            return Ok(Source::Input(expand_path(input)?));
+        }
+
+        if let Some(zone_type) = &self.thermal_zone {
+            // thermal zones are in millidegrees Celsius, just like hwmon
+            return match find_thermal_zone_input(Path::new(THERMAL_ROOT), zone_type) {
+                Some(input) => Ok(Source::Input(input)),
+                None => bail!("no thermal zone found with type: {}", zone_type),
             };
+        }
 
-            let (icon, color) = Self::get_icon(&ctx.config.theme, temp as u32);
-            let temp = float(temp, &self.float_fmt);
-            let mut item = I3Item::new(format!("{} {}°C{}", icon, temp, label))
-                .short_text(format!("{}C", temp))
-                .markup(I3Markup::Pango);
+        if let Some(SensorDevice::Gpu) = self.device {
+            let input = find_hwmon_input(Path::new(HWMON_ROOT), GPU_HWMON_NAMES, self.kind);
+            return match (input, &self.command) {
+                (Some(input), _) => Ok(Source::Input(input)),
+                // e.g., NVIDIA's proprietary driver doesn't provide a hwmon device
+                (None, Some(command)) => Ok(Source::Command(command.clone())),
+                (None, None) => bail!("no gpu hwmon device found, try setting a `command`"),
+            };
+        }
+
+        match (&self.command, &self.component) {
+            (Some(command), _) => Ok(Source::Command(command.clone())),
+            (None, Some(component)) if self.kind == SensorKind::Temperature => {
+                Ok(Source::Component(component.clone()))
+            }
+            // only temperatures can be read from a `component`
+            _ if self.kind == SensorKind::Temperature => bail!(
+                "sensors item requires a `component`, `input`, `thermal_zone` or `command`"
+            ),
+            _ => bail!(
+                "sensors item requires an `input` or `command` for kind: {:?}",
+                self.kind
+            ),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl BarItem for Sensors {
+    async fn start(&self, mut ctx: Context) -> Result<StopAction> {
+        let pages = self.pages();
+        let sources = pages
+            .iter()
+            .map(Sensors::source)
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut components = Components::new_with_refreshed_list();
+
+        // each page changes colour independently
+        let mut levels = pages
+            .iter()
+            .map(|s| Hysteresis::new(Self::TEMPERATURE_THRESHOLDS, s.hysteresis))
+            .collect::<Vec<_>>();
+
+        let mut p = Paginator::new();
+        p.set_len(pages.len())?;
+        loop {
+            let idx = p.idx();
+            let sensor = &pages[idx];
+            let value = sensor.read(&sources[idx], &mut components).await?;
+
+            let theme = &ctx.config.theme;
+            let (icon, color) = sensor.get_icon(theme, levels[idx].level(value));
+            let (full, short) = sensor.format_value(value);
+            let mut item = I3Item::new(format!(
+                "{} {}{}{}",
+                icon,
+                full,
+                sensor.label(),
+                p.format(theme)
+            ))
+            .short_text(short)
+            .markup(I3Markup::Pango);
 
             if let Some(color) = color {
                 item = item.color(color);
             }
 
             ctx.update_item(item).await?;
-            sleep(self.interval).await;
+
+            // cycle through sensors
+            ctx.delay_with_event_handler(self.interval, |event| {
+                p.update(&event);
+                async {}
+            })
+            .await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TestDir;
+
+    #[test]
+    fn find_gpu_hwmon() {
+        let root = TestDir::new("hwmon");
+        let hwmon = |name: &str, idx: usize| {
+            let dir = root.join(format!("hwmon{}", idx));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("name"), format!("{}\n", name)).unwrap();
+            std::fs::write(dir.join("temp1_input"), "45000\n").unwrap();
+            dir
+        };
+
+        // no gpu
+        hwmon("coretemp", 0);
+        hwmon("nvme", 1);
+        let find = |kind| find_hwmon_input(&root, GPU_HWMON_NAMES, kind);
+        assert_eq!(find(SensorKind::Temperature), None);
+
+        // mocked amdgpu hwmon
+        let amdgpu = hwmon("amdgpu", 2);
+        assert_eq!(find(SensorKind::Temperature), Some(amdgpu.join("temp1_input")));
+        // the device doesn't have an input for this kind
+        assert_eq!(find(SensorKind::Voltage), None);
+    }
+
+    #[test]
+    fn find_thermal_zone() {
+        let root = TestDir::new("thermal");
+        let zone = |zone_type: &str, idx: usize| {
+            let dir = root.join(format!("thermal_zone{}", idx));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("type"), format!("{}\n", zone_type)).unwrap();
+            std::fs::write(dir.join("temp"), "52000\n").unwrap();
+            dir
+        };
+
+        zone("acpitz", 0);
+        let find = |zone_type| find_thermal_zone_input(&root, zone_type);
+        assert_eq!(find("x86_pkg_temp"), None);
+
+        // mocked zone, and cooling devices which also live here are ignored
+        let pkg = zone("x86_pkg_temp", 1);
+        std::fs::create_dir_all(root.join("cooling_device0")).unwrap();
+        std::fs::write(root.join("cooling_device0/type"), "x86_pkg_temp\n").unwrap();
+        assert_eq!(find("x86_pkg_temp"), Some(pkg.join("temp")));
+        assert_eq!(find("acpitz"), Some(root.join("thermal_zone0/temp")));
+    }
+
+    #[test]
+    fn missing_source() {
+        let err = Sensors::default().source().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "sensors item requires a `component`, `input`, `thermal_zone` or `command`"
+        );
+
+        let sensors = Sensors {
+            kind: SensorKind::Voltage,
+            component: Some("nct6775 in0".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            sensors.source().unwrap_err().to_string(),
+            "sensors item requires an `input` or `command` for kind: Voltage"
+        );
+    }
+
+    #[test]
+    fn label_forms() {
+        let sensors = serde_json::from_value::<Sensors>(serde_json::json!({
+            "interval": "1s",
+            "component": "coretemp Package id 0",
+            "label": " (cpu)",
+        }))
+        .unwrap();
+        let pages = sensors.pages();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].label(), " (cpu)");
+        sensors.validate().unwrap();
+
+        let sensors = serde_json::from_value::<Sensors>(serde_json::json!({
+            "interval": "1s",
+            "component": "coretemp Package id 0",
+            "unit": "C",
+            "label": [
+                { "label": " (cpu)" },
+                { "label": " (gpu)", "device": "gpu", "unit": "°C" },
+            ],
+        }))
+        .unwrap();
+        let pages = sensors.pages();
+        assert_eq!(pages.len(), 2);
+        // the first page uses the item's sensor
+        assert_eq!(pages[0].label(), " (cpu)");
+        assert_eq!(pages[0].component.as_deref(), Some("coretemp Package id 0"));
+        assert_eq!(pages[0].unit.as_deref(), Some("C"));
+        // the second has its own, which replaces the item's
+        assert_eq!(pages[1].label(), " (gpu)");
+        assert_eq!(pages[1].component, None);
+        assert_eq!(pages[1].device, Some(SensorDevice::Gpu));
+        assert_eq!(pages[1].unit.as_deref(), Some("°C"));
+
+        let sensors = Sensors {
+            label: Some(SensorLabel::Pages(vec![])),
+            ..Default::default()
+        };
+        assert!(sensors.validate().is_err());
+    }
+}
@@ -1,32 +1,237 @@
+use std::fmt::Write;
 use std::time::Duration;
 
 use async_trait::async_trait;
 use chrono::prelude::*;
 use serde_derive::{Deserialize, Serialize};
+use tokio::fs;
 
 use crate::context::{BarItem, Context, StopAction};
 use crate::error::Result;
 use crate::i3::{I3Item, I3Markup};
 
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeMode {
+    /// Display the current date or time
+    #[default]
+    Clock,
+    /// Display the time elapsed since `since`
+    Since,
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Time {
     #[serde(with = "crate::human_time")]
     interval: Duration,
-    format_long: String,
-    format_short: String,
+    /// Required when `mode` is `clock`
+    #[serde(default)]
+    format_long: Option<String>,
+    /// Required when `mode` is `clock`
+    #[serde(default)]
+    format_short: Option<String>,
+    #[serde(default)]
+    mode: TimeMode,
+    /// Used when `mode` is `since`: either `boot` or a timestamp
+    #[serde(default)]
+    since: Option<String>,
+    /// Locale used for month and weekday names, e.g. `de_DE`
+    #[serde(default)]
+    locale: Option<String>,
+}
+
+impl Time {
+    /// Check the formats are valid, since chrono only reports invalid formats when they're used
+    pub fn validate(&self) -> Result<()> {
+        if let TimeMode::Clock = self.mode {
+            if self.format_long.is_none() || self.format_short.is_none() {
+                bail!("time item requires `format_long` and `format_short` when mode is `clock`");
+            }
+        }
+
+        for format in [&self.format_long, &self.format_short].into_iter().flatten() {
+            validate_format(format)?;
+        }
+
+        if let (TimeMode::Since, Some(since)) = (self.mode, &self.since) {
+            Anchor::parse(since)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a locale such as `de_DE`, falling back to the default (English) names if it's unknown
+fn parse_locale(locale: &str) -> Option<Locale> {
+    match Locale::try_from(locale) {
+        Ok(locale) => Some(locale),
+        Err(_) => {
+            log::warn!("unknown locale {:?}, falling back to English", locale);
+            None
+        }
+    }
+}
+
+/// Format a date with the given format, using the locale's names if one is set
+fn format_date(now: &DateTime<Local>, format: &str, locale: Option<Locale>) -> String {
+    match locale {
+        Some(locale) => now.format_localized(format, locale).to_string(),
+        None => now.format(format).to_string(),
+    }
+}
+
+/// Format a known date with the given format, which fails if it contains an invalid specifier
+fn validate_format(format: &str) -> Result<()> {
+    // use a date with an offset (like the `DateTime<Local>` that's formatted), otherwise timezone
+    // specifiers such as `%z` fail to format
+    // SAFETY: this is a valid date and offset
+    let date = FixedOffset::east_opt(0)
+        .unwrap()
+        .with_ymd_and_hms(2000, 1, 1, 0, 0, 0)
+        .unwrap();
+
+    let mut s = String::new();
+    match write!(s, "{}", date.format(format)) {
+        Ok(()) => Ok(()),
+        Err(_) => bail!("invalid time format: {}", format),
+    }
+}
+
+enum Anchor {
+    Boot,
+    Timestamp(DateTime<Local>),
+}
+
+impl Anchor {
+    fn parse(s: &str) -> Result<Anchor> {
+        if s == "boot" {
+            return Ok(Anchor::Boot);
+        }
+
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Anchor::Timestamp(dt.with_timezone(&Local)));
+        }
+
+        match NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+            .ok()
+            .and_then(|naive| naive.and_local_timezone(Local).single())
+        {
+            Some(dt) => Ok(Anchor::Timestamp(dt)),
+            None => bail!("failed to parse time anchor, expected `boot` or a timestamp: {}", s),
+        }
+    }
+
+    async fn elapsed(&self) -> Result<Duration> {
+        Ok(match self {
+            Anchor::Boot => {
+                let uptime = fs::read_to_string("/proc/uptime").await?;
+                let secs = uptime
+                    .split_whitespace()
+                    .next()
+                    .ok_or("failed to parse /proc/uptime")?
+                    .parse::<f64>()?;
+
+                Duration::from_secs_f64(secs)
+            }
+            Anchor::Timestamp(dt) => (Local::now() - *dt).to_std().unwrap_or_default(),
+        })
+    }
 }
 
 #[async_trait(?Send)]
 impl BarItem for Time {
     async fn start(&self, mut ctx: Context) -> Result<StopAction> {
+        let anchor = match self.mode {
+            TimeMode::Clock => None,
+            TimeMode::Since => match self.since.as_deref() {
+                Some(since) => Some(Anchor::parse(since)?),
+                None => bail!("time item requires `since` when mode is `since`"),
+            },
+        };
+
+        let locale = self.locale.as_deref().and_then(parse_locale);
+
         loop {
-            let now = Local::now();
-            let item = I3Item::new(format!("󰥔 {}", now.format(&self.format_long)))
-                .short_text(now.format(&self.format_short).to_string())
-                .markup(I3Markup::Pango);
+            let item = match anchor {
+                None => {
+                    let now = Local::now();
+                    let format_long = self.format_long.as_deref().unwrap_or_default();
+                    let format_short = self.format_short.as_deref().unwrap_or_default();
+                    I3Item::new(format!("󰥔 {}", format_date(&now, format_long, locale)))
+                        .short_text(format_date(&now, format_short, locale))
+                }
+                Some(ref anchor) => {
+                    let elapsed = crate::human_time::format(anchor.elapsed().await?);
+                    I3Item::new(format!("󰔛 {}", elapsed)).short_text(elapsed)
+                }
+            }
+            .markup(I3Markup::Pango);
 
             ctx.update_item(item).await?;
             ctx.wait_for_event(Some(self.interval)).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_formats() {
+        validate_format("").unwrap();
+        validate_format("%Y-%m-%d %H:%M:%S").unwrap();
+        // iso week number, and day of the year
+        validate_format("W%V %G, day %j").unwrap();
+        validate_format("100%%").unwrap();
+        // timezones
+        validate_format("%H:%M %Z").unwrap();
+        validate_format("%H:%M %z").unwrap();
+        validate_format("%+").unwrap();
+    }
+
+    #[test]
+    fn invalid_formats() {
+        assert!(validate_format("%Q").is_err());
+        assert!(validate_format("%Y-%m-%").is_err());
+
+        let time = Time {
+            format_long: Some("%Y %!".into()),
+            format_short: Some("%H:%M".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            time.validate().unwrap_err().to_string(),
+            "invalid time format: %Y %!"
+        );
+    }
+
+    #[test]
+    fn required_formats() {
+        let clock = Time {
+            format_long: Some("%H:%M:%S".into()),
+            ..Default::default()
+        };
+        assert!(clock.validate().is_err());
+
+        let since = Time {
+            mode: TimeMode::Since,
+            since: Some("boot".into()),
+            ..Default::default()
+        };
+        since.validate().unwrap();
+    }
+
+    #[test]
+    fn localized_formats() {
+        // a saturday in october
+        let date = Local.with_ymd_and_hms(1985, 10, 26, 1, 35, 0).unwrap();
+        assert_eq!(format_date(&date, "%A %B", None), "Saturday October");
+        assert_eq!(
+            format_date(&date, "%A %B", parse_locale("de_DE")),
+            "Samstag Oktober"
+        );
+        // unknown locales use the default names
+        assert_eq!(parse_locale("xx_XX"), None);
+    }
+}
@@ -15,6 +15,10 @@ pub struct Cli {
     /// Path to the socket to use for ipc. Takes precedence over the same option in the config file.
     #[clap(long)]
     pub socket: Option<PathBuf>,
+    /// Print a JSON Schema of the configuration file and exit. This can be given to editors to
+    /// validate and autocomplete config files.
+    #[clap(long)]
+    pub print_schema: bool,
 }
 
 #[cfg(test)]
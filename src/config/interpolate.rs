@@ -0,0 +1,167 @@
+use serde_json::Value;
+
+use crate::error::Result;
+
+/// Expand environment variables in every string of a raw config (not its keys), see `interpolate`.
+pub fn interpolate_env(value: &mut Value) -> Result<()> {
+    interpolate_value(value, &|name| std::env::var(name).ok())
+}
+
+fn interpolate_value(value: &mut Value, lookup: &impl Fn(&str) -> Option<String>) -> Result<()> {
+    match value {
+        Value::String(s) => *s = interpolate(s, lookup)?,
+        Value::Array(values) => {
+            for value in values {
+                interpolate_value(value, lookup)?;
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                interpolate_value(value, lookup)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Expand variables in a string, using `lookup` to find their values:
+/// * `$NAME` and `${NAME}` fail if the variable isn't set
+/// * `${NAME:-default}` uses the default (which may be empty) if the variable isn't set
+/// * `$$` is a literal `$`, e.g. for variables a script should expand itself
+///
+/// Templates (e.g. `{{ echo $HOME }}` in raw items) are left alone, since they're run by a shell.
+fn interpolate(s: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(idx) = rest.find(['$', '{']) {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        if rest.starts_with("{{") {
+            let end = rest.find("}}").map_or(rest.len(), |end| end + 2);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        if let Some(after) = rest.strip_prefix('{') {
+            out.push('{');
+            rest = after;
+            continue;
+        }
+
+        // skip the `$`
+        rest = &rest[1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = match after.find('}') {
+                Some(end) => end,
+                None => bail!("unterminated variable in: {}", s),
+            };
+
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+
+            match (lookup(name), default) {
+                (Some(value), _) => out.push_str(&value),
+                (None, Some(default)) => out.push_str(default),
+                (None, None) => bail!("environment variable is not set: {}", name),
+            }
+
+            rest = &after[end + 1..];
+        } else {
+            let end = rest.find(|c| !is_name(c)).unwrap_or(rest.len());
+            let name = &rest[..end];
+            if name.is_empty() {
+                // a lone `$`
+                out.push('$');
+                continue;
+            }
+
+            match lookup(name) {
+                Some(value) => out.push_str(&value),
+                None => bail!("environment variable is not set: {}", name),
+            }
+
+            rest = &rest[end..];
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/user".into()),
+            "EMPTY" => Some("".into()),
+            _ => None,
+        }
+    }
+
+    fn expand(s: &str) -> Result<String> {
+        interpolate(s, &lookup)
+    }
+
+    #[test]
+    fn set_variables() {
+        assert_eq!(expand("no variables").unwrap(), "no variables");
+        assert_eq!(expand("$HOME/file").unwrap(), "/home/user/file");
+        assert_eq!(expand("${HOME}file").unwrap(), "/home/userfile");
+        assert_eq!(expand("${HOME:-/tmp}").unwrap(), "/home/user");
+        assert_eq!(expand("[$EMPTY]").unwrap(), "[]");
+    }
+
+    #[test]
+    fn unset_variables() {
+        assert_eq!(
+            expand("$MISSING/file").unwrap_err().to_string(),
+            "environment variable is not set: MISSING"
+        );
+        assert!(expand("${MISSING}").is_err());
+        assert_eq!(expand("${MISSING:-/tmp}/file").unwrap(), "/tmp/file");
+        assert_eq!(expand("[${MISSING:-}]").unwrap(), "[]");
+        assert!(expand("${HOME").is_err());
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(expand("echo $$I3_SIGNAL").unwrap(), "echo $I3_SIGNAL");
+        assert_eq!(expand("100$ {or} $").unwrap(), "100$ {or} $");
+        // templates are left for the shell
+        assert_eq!(
+            expand("$HOME {{ echo $MISSING }} {{").unwrap(),
+            "/home/user {{ echo $MISSING }} {{"
+        );
+    }
+
+    #[test]
+    fn only_strings_are_expanded() {
+        let mut value = json!({
+            "$HOME": "$HOME",
+            "items": [{ "path": "${HOME}/a", "n": 1, "on": true }]
+        });
+        interpolate_value(&mut value, &lookup).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "$HOME": "/home/user",
+                "items": [{ "path": "/home/user/a", "n": 1, "on": true }]
+            })
+        );
+    }
+}
@@ -1,12 +1,15 @@
 use std::cell::OnceCell;
 use std::collections::HashSet;
+use std::time::Duration;
 
 use serde_derive::{Deserialize, Serialize};
 use strum::EnumIter;
 
 use crate::bar_items::*;
 use crate::context::BarItem;
-use crate::i3::{I3Item, I3Modifier};
+use crate::error::Result;
+use crate::i3::{I3Button, I3Modifier};
+use crate::signals::validate_item_signal;
 
 /// Custom item action.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +41,82 @@ pub struct Actions {
     pub middle_click: Option<ActionWrapper>,
     #[serde(default)]
     pub right_click: Option<ActionWrapper>,
+    #[serde(default)]
+    pub scroll_up: Option<ActionWrapper>,
+    #[serde(default)]
+    pub scroll_down: Option<ActionWrapper>,
+    #[serde(default)]
+    pub scroll_left: Option<ActionWrapper>,
+    #[serde(default)]
+    pub scroll_right: Option<ActionWrapper>,
+}
+
+impl Actions {
+    /// Returns the actions configured for the given button, if any
+    pub fn for_button(&self, button: I3Button) -> Option<&ActionWrapper> {
+        match button {
+            I3Button::Left => self.left_click.as_ref(),
+            I3Button::Middle => self.middle_click.as_ref(),
+            I3Button::Right => self.right_click.as_ref(),
+            I3Button::ScrollUp => self.scroll_up.as_ref(),
+            I3Button::ScrollDown => self.scroll_down.as_ref(),
+            I3Button::ScrollLeft => self.scroll_left.as_ref(),
+            I3Button::ScrollRight => self.scroll_right.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// How an item is restarted when it stops unexpectedly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Retry {
+    /// How many times the item is restarted before giving up
+    pub max_retries: u32,
+    /// Also restart the item if it exits with an error, rather than only when it asks to be
+    pub errors: bool,
+    /// How long to wait before the first restart, this doubles after each restart
+    #[serde(with = "humantime_serde")]
+    pub backoff: Duration,
+    /// The longest time to wait before restarting
+    #[serde(with = "humantime_serde")]
+    pub max_backoff: Duration,
+    /// If the item ran for longer than this before stopping, its retries are reset
+    #[serde(with = "humantime_serde")]
+    pub reset_after: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            max_retries: 3,
+            errors: false,
+            backoff: Duration::ZERO,
+            max_backoff: Duration::from_secs(60),
+            reset_after: Duration::from_secs(60 * 5),
+        }
+    }
+}
+
+impl Retry {
+    /// How long to wait before the restart, given how many retries there have already been
+    pub fn delay(&self, retries: u32) -> Duration {
+        self.backoff
+            .checked_mul(2_u32.saturating_pow(retries))
+            .unwrap_or(Duration::MAX)
+            .min(self.max_backoff)
+    }
+}
+
+/// When an item's `on_click_open` command is run, relative to the item handling the click.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClickOpenOrder {
+    /// Run with the item's value as it was when clicked, then the item handles the click
+    #[default]
+    Before,
+    /// Run once the item has handled the click and updated, with its new value
+    After,
 }
 
 /// Configuration that's common to every item.
@@ -54,16 +133,45 @@ pub struct Common {
     pub separator: Option<bool>,
     /// Optionally configure actions for each item
     pub actions: Option<Actions>,
+    /// Left clicking with these modifiers shows the item's detail in place of its text
+    pub detail_modifiers: Option<HashSet<I3Modifier>>,
+    /// Ignore clicks which are the same as the last handled click, if they're within this time
+    #[serde(default, with = "humantime_serde")]
+    pub click_debounce: Option<Duration>,
+    /// Run this command when the item is left clicked (without modifiers), with the item's
+    /// `full_text` in `I3STAT_VALUE`. Unlike `actions`, the item still handles the click.
+    pub on_click_open: Option<String>,
+    /// Whether `on_click_open` is run before or after the item handles the click
+    #[serde(default)]
+    pub on_click_open_order: ClickOpenOrder,
+    /// Override the log level for this item, its logs use the target `i3stat::item::<name>`
+    pub log_level: Option<log::LevelFilter>,
+    /// If the item hasn't updated within this time, it's marked as stale (if `staleness` is on).
+    /// Defaults to a multiple of the item's `interval`, if it has one.
+    #[serde(default, with = "humantime_serde")]
+    pub stale_after: Option<Duration>,
+    /// Truncate the item's `full_text` to this many characters (including a trailing ellipsis).
+    /// Pango markup isn't counted, and is never cut.
+    pub max_width: Option<usize>,
+    /// Colour each character of the item's text with a different colour from the theme
+    pub rainbow: Option<bool>,
+    /// How the item is restarted if it stops unexpectedly
+    #[serde(default)]
+    pub retry: Retry,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, EnumIter)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum ItemInner {
-    Raw(I3Item),
+    Raw(Raw),
     Battery(Battery),
     Cpu(Cpu),
+    DirSize(DirSize),
     Disk(Disk),
     Dunst(Dunst),
+    Group(Group),
+    #[cfg(feature = "http")]
+    Http(Http),
     Kbd(Kbd),
     Krb(Krb),
     Light(Light),
@@ -85,8 +193,12 @@ impl ItemInner {
             ItemInner::Raw(_) => "raw",
             ItemInner::Battery(_) => "battery",
             ItemInner::Cpu(_) => "cpu",
+            ItemInner::DirSize(_) => "dir_size",
             ItemInner::Disk(_) => "disk",
             ItemInner::Dunst(_) => "dunst",
+            ItemInner::Group(_) => "group",
+            #[cfg(feature = "http")]
+            ItemInner::Http(_) => "http",
             ItemInner::Kbd(_) => "kbd",
             ItemInner::Krb(_) => "krb",
             ItemInner::Light(_) => "light",
@@ -114,13 +226,50 @@ pub struct Item {
 }
 
 impl Item {
+    /// How many intervals an item can miss before it's considered stale
+    const STALE_INTERVAL_FACTOR: u32 = 3;
+
+    /// How long this item can go without updating before it's considered stale.
+    /// Items without a configured `interval` (e.g., items which update on events) are never
+    /// considered stale unless `stale_after` is set.
+    pub fn stale_after(&self) -> Option<Duration> {
+        self.common.stale_after.or_else(|| {
+            // not every item has an interval, and those that do keep them private
+            let value = serde_json::to_value(&self.inner).ok()?;
+            let interval = value.get("interval")?.clone();
+            serde_json::from_value::<humantime_serde::Serde<Duration>>(interval)
+                .ok()
+                .map(|interval| interval.into_inner() * Self::STALE_INTERVAL_FACTOR)
+        })
+    }
+
+    /// Check any item specific configuration which can't be checked when deserialising
+    pub fn validate(&self) -> Result<()> {
+        if let Some(sig) = self.common.signal {
+            validate_item_signal(sig)?;
+        }
+
+        match &self.inner {
+            ItemInner::DirSize(dir_size) => dir_size.validate(),
+            ItemInner::Time(time) => time.validate(),
+            ItemInner::Group(group) => group.validate(),
+            ItemInner::Nic(nic) => nic.validate(),
+            ItemInner::Sensors(sensors) => sensors.validate(),
+            _ => Ok(()),
+        }
+    }
+
     pub fn to_bar_item(&self) -> Box<dyn BarItem> {
         match &self.inner {
             ItemInner::Raw(inner) => Box::new(inner.clone()),
             ItemInner::Battery(inner) => Box::new(inner.clone()),
             ItemInner::Cpu(inner) => Box::new(inner.clone()),
+            ItemInner::DirSize(inner) => Box::new(inner.clone()),
             ItemInner::Disk(inner) => Box::new(inner.clone()),
             ItemInner::Dunst(inner) => Box::new(inner.clone()),
+            ItemInner::Group(inner) => Box::new(inner.clone()),
+            #[cfg(feature = "http")]
+            ItemInner::Http(inner) => Box::new(inner.clone()),
             ItemInner::Kbd(inner) => Box::new(inner.clone()),
             ItemInner::Krb(inner) => Box::new(inner.clone()),
             ItemInner::Light(inner) => Box::new(inner.clone()),
@@ -148,13 +297,15 @@ mod tests {
     use strum::IntoEnumIterator;
 
     use super::*;
+    use crate::i3::I3Item;
+    use crate::signals::max_item_signal;
 
     // only used in tests, in production code items are only created via deserialisation
     impl Item {
         pub fn new(common: Common, item: I3Item) -> Item {
             Item {
                 common,
-                inner: ItemInner::Raw(item),
+                inner: ItemInner::Raw(item.into()),
                 name: OnceCell::new(),
             }
         }
@@ -178,4 +329,102 @@ mod tests {
             assert_tag(&variant);
         }
     }
+
+    #[test]
+    fn log_level() {
+        let item = serde_json::from_value::<Item>(json!({
+            "type": "raw",
+            "full_text": "",
+            "log_level": "debug",
+        }))
+        .unwrap();
+        assert_eq!(item.common.log_level, Some(log::LevelFilter::Debug));
+    }
+
+    #[test]
+    fn stale_after() {
+        let item = |value| serde_json::from_value::<Item>(value).unwrap();
+
+        // derived from the item's interval
+        let script = item(json!({ "type": "script", "command": "", "interval": "10s" }));
+        assert_eq!(script.stale_after(), Some(Duration::from_secs(30)));
+
+        // explicitly configured
+        let script = item(json!({
+            "type": "script",
+            "command": "",
+            "interval": "10s",
+            "stale_after": "1m"
+        }));
+        assert_eq!(script.stale_after(), Some(Duration::from_secs(60)));
+
+        // no interval
+        let raw = item(json!({ "type": "raw", "full_text": "" }));
+        assert_eq!(raw.stale_after(), None);
+
+        // an interval which never elapses
+        let nic = item(json!({ "type": "nic", "interval": "never" }));
+        assert_eq!(nic.stale_after(), None);
+    }
+
+    #[test]
+    fn retry() {
+        let item = |value| serde_json::from_value::<Item>(value).unwrap();
+
+        // defaults
+        let raw = item(json!({ "type": "raw", "full_text": "" }));
+        assert_eq!(raw.common.retry.max_retries, 3);
+        assert!(!raw.common.retry.errors);
+        assert_eq!(raw.common.retry.delay(0), Duration::ZERO);
+        assert_eq!(raw.common.retry.delay(2), Duration::ZERO);
+
+        // exponential backoff, up to a limit
+        let raw = item(json!({
+            "type": "raw",
+            "full_text": "",
+            "retry": { "backoff": "1s", "max_backoff": "10s" }
+        }));
+        let delays = (0..6).map(|n| raw.common.retry.delay(n).as_secs());
+        assert_eq!(delays.collect::<Vec<_>>(), [1, 2, 4, 8, 10, 10]);
+        assert_eq!(raw.common.retry.delay(u32::MAX), Duration::from_secs(10));
+        assert_eq!(raw.common.retry.reset_after, Duration::from_secs(60 * 5));
+    }
+
+    #[test]
+    fn validate_items() {
+        let item = |value| serde_json::from_value::<Item>(value).unwrap();
+
+        let time = |format: &str| {
+            item(json!({
+                "type": "time",
+                "interval": "1s",
+                "format_long": format,
+                "format_short": "%H:%M"
+            }))
+        };
+        time("%V %j").validate().unwrap();
+        assert!(time("%Q").validate().is_err());
+
+        // both formats are required in the default `clock` mode
+        let clock = item(json!({ "type": "time", "interval": "1s", "format_long": "%V %j" }));
+        assert!(clock.validate().is_err());
+
+        // items within groups are also validated
+        let group = item(json!({
+            "type": "group",
+            "items": [{
+                "type": "time",
+                "interval": "1s",
+                "format_long": "%V %j",
+                "format_short": "%H:%"
+            }]
+        }));
+        assert!(group.validate().is_err());
+
+        // signals must map to a realtime signal
+        let raw = |signal| item(json!({ "type": "raw", "full_text": "", "signal": signal }));
+        raw(0).validate().unwrap();
+        raw(max_item_signal()).validate().unwrap();
+        assert!(raw(max_item_signal() + 1).validate().is_err());
+    }
 }
@@ -1,24 +1,31 @@
+mod interpolate;
 pub mod item;
 mod parse;
+pub mod schema;
 
 use std::cell::OnceCell;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use indexmap::IndexMap;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::cli::Cli;
 use crate::config::item::Item;
 use crate::error::Result;
+use crate::i3::I3Markup;
 use crate::ipc::get_socket_path;
 use crate::theme::Theme;
-use crate::util::sort_by_indices;
+use crate::util::format::SizeUnits;
+use crate::util::{expand_path, merge_patch, sort_by_indices};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     /// Optional list of paths of other configuration files to include.
     /// The paths can be absolute or relative to the main configuration file's directory.
     /// Shell syntax is also expanded (see **wordexp(3)** for details).
+    /// Globs and directories are also supported, and are included in alphabetical order.
     #[serde(default)]
     include: Vec<String>,
 
@@ -26,6 +33,51 @@ pub struct AppConfig {
     #[serde(default)]
     pub theme: Theme,
 
+    /// The convention used when displaying byte sizes, either `binary` (e.g., `KiB`) or `decimal`
+    /// (e.g., `kB`). Defaults to `binary`.
+    #[serde(default)]
+    pub units: SizeUnits,
+
+    /// Default `separator` for every item. Items may override this with their own `separator`.
+    #[serde(default)]
+    pub separator: Option<bool>,
+
+    /// Default `separator_block_width` (in pixels) for every item.
+    #[serde(default)]
+    pub separator_block_width: Option<usize>,
+
+    /// Default `markup` for every item which doesn't set its own.
+    #[serde(default)]
+    pub markup: Option<I3Markup>,
+
+    /// Mark items which haven't updated in a while (see `Item::stale_after`) as stale.
+    #[serde(default)]
+    pub staleness: bool,
+
+    /// Also write each frame of the bar to this file (or FIFO), one JSON array per line. Files are
+    /// rewritten each time, so they only contain the latest frame.
+    pub output_file: Option<PathBuf>,
+
+    /// Don't print the bar until every item has sent its first update (or a short timeout passes),
+    /// so the bar doesn't flicker as items start.
+    #[serde(default)]
+    pub wait_for_all: bool,
+
+    /// Allow items to share the same name. Events sent to a name normally go to the first item with
+    /// that name, but they can be sent to all of them (e.g. `i3stat-ipc signal --all <name>`).
+    #[serde(default)]
+    pub allow_duplicate_names: bool,
+
+    /// Expand environment variables (e.g. `$HOME` or `${VAR:-default}`) in all string values of
+    /// the config when it's read. Use `$$` for a literal `$`.
+    #[serde(default)]
+    pub interpolate_env: bool,
+
+    /// Default configuration for each type of item, keyed by the item's `type`, e.g.:
+    /// `defaults.cpu.interval = "1s"`. Anything set on an item itself takes precedence.
+    #[serde(default)]
+    pub defaults: BTreeMap<String, Value>,
+
     /// List of the items for the bar
     pub items: Vec<Item>,
 
@@ -40,12 +92,22 @@ pub struct AppConfig {
     #[serde(rename = "socket")]
     socket: Option<PathBuf>,
 
+    /// Runtime only list of the config files which were read, in the order they were merged
+    #[serde(skip)]
+    sources: Vec<PathBuf>,
+
     /// Runtime only cache for index to name item mappings
     #[serde(skip)]
     idx_to_name: OnceCell<IndexMap<usize, String>>,
 }
 
 impl AppConfig {
+    /// The config files which were read (the main config file, then any includes) in the order
+    /// they were merged
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
     pub fn item_idx_to_name(&self) -> &IndexMap<usize, String> {
         self.idx_to_name.get_or_init(|| {
             let mut map = self
@@ -60,6 +122,28 @@ impl AppConfig {
         })
     }
 
+    /// The indices of all the items with the given name. Without `allow_duplicate_names` there is at
+    /// most one of these.
+    pub fn item_indices_with_name(&self, name: &str) -> Vec<usize> {
+        self.item_idx_to_name()
+            .iter()
+            .filter_map(|(idx, n)| if n == name { Some(*idx) } else { None })
+            .collect()
+    }
+
+    /// Find an item by its index, or by its name (the first item with that name). This is how the
+    /// `instance` of click events, signals and ipc messages are resolved to items.
+    pub fn resolve_item(&self, instance: &str) -> Result<usize> {
+        match instance.parse::<usize>() {
+            Ok(idx) if idx < self.items.len() => Ok(idx),
+            Ok(idx) => bail!("no item found with index: {}", idx),
+            Err(_) => match self.item_indices_with_name(instance).first() {
+                Some(idx) => Ok(*idx),
+                None => bail!("no item found with name: {}", instance),
+            },
+        }
+    }
+
     // NOTE: this workaround exists due to a limitation in serde
     // see: https://github.com/serde-rs/serde/issues/2249
     pub fn socket(&self) -> PathBuf {
@@ -67,6 +151,43 @@ impl AppConfig {
         self.socket.clone().unwrap()
     }
 
+    /// Merge the configured `defaults` into each item (and the items of any groups) in a raw config.
+    /// This is done before deserialising, so items may rely on defaults for required options.
+    /// The item's own config is merged on top as a JSON merge patch, so it takes precedence and an
+    /// item can unset a default by setting it to `null`.
+    fn apply_defaults(config: &mut Value) {
+        fn apply(items: &mut Value, defaults: &serde_json::Map<String, Value>) {
+            let Some(items) = items.as_array_mut() else {
+                return;
+            };
+
+            for item in items {
+                if let Some(nested) = item.get_mut("items") {
+                    apply(nested, defaults);
+                }
+
+                let default = item
+                    .get("type")
+                    .and_then(Value::as_str)
+                    .and_then(|ty| defaults.get(ty));
+                if let Some(default) = default {
+                    let mut merged = default.clone();
+                    merge_patch(&mut merged, item.take());
+                    *item = merged;
+                }
+            }
+        }
+
+        let defaults = match config.get("defaults") {
+            Some(Value::Object(defaults)) if !defaults.is_empty() => defaults.clone(),
+            _ => return,
+        };
+
+        if let Some(items) = config.get_mut("items") {
+            apply(items, &defaults);
+        }
+    }
+
     /// Sort the items by reading the index defined in the configuration.
     fn sort(items: &mut [Item]) {
         let len = items.len();
@@ -83,6 +204,7 @@ impl AppConfig {
     }
 
     /// Ensure configuration of item names have no duplicates.
+    /// This is skipped if `allow_duplicate_names` is set, see `AppConfig::item_indices_with_name`.
     fn validate_names(items: &[Item]) -> Result<()> {
         for (i, a) in items.iter().enumerate().rev() {
             for (j, b) in items.iter().enumerate() {
@@ -112,22 +234,72 @@ impl AppConfig {
         // see: https://github.com/serde-rs/serde/issues/2249
         cfg.socket = Some(match args.socket {
             Some(socket_path) => socket_path,
-            None => get_socket_path(cfg.socket.as_ref())?,
+            None => get_socket_path(cfg.socket.map(expand_path).transpose()?.as_ref())?,
         });
 
-        // config validation
-        {
-            // sort items as defined in the configuration
-            Self::sort(&mut cfg.items);
+        // sort items as defined in the configuration
+        Self::sort(&mut cfg.items);
+        cfg.theme.resolve();
+
+        cfg.validate()?;
+
+        Ok(cfg)
+    }
 
-            // check no duplicate names
-            Self::validate_names(&cfg.items)?;
+    fn validate(&self) -> Result<()> {
+        // check no duplicate names, unless they've been opted into
+        if !self.allow_duplicate_names {
+            Self::validate_names(&self.items)?;
+        }
 
-            // check no empty powerline config
-            cfg.theme.validate()?;
+        // check item specific configuration
+        for (idx, item) in self.items.iter().enumerate() {
+            if let Err(e) = item.validate() {
+                bail!("item[{}] ({}) is invalid: {}", idx, item.name(), e);
+            }
         }
 
-        Ok(cfg)
+        // check no empty powerline config
+        self.theme.validate()?;
+
+        Ok(())
+    }
+
+    /// Apply a JSON merge patch (https://datatracker.ietf.org/doc/html/rfc7386) to the config,
+    /// returning the new config only if it's valid.
+    /// Since each item's task is tied to its index, items can't be added, removed or renamed, and
+    /// `disable` can't be changed.
+    pub fn patch(&self, patch: Value) -> Result<AppConfig> {
+        let mut value = serde_json::to_value(self)?;
+        merge_patch(&mut value, patch);
+
+        let mut new = serde_json::from_value::<AppConfig>(value)?;
+        new.theme.resolve();
+        new.sources = self.sources.clone();
+        if new.items.len() != self.items.len() {
+            bail!("items can't be added or removed at runtime");
+        }
+        if new.item_idx_to_name() != self.item_idx_to_name() {
+            bail!("items can't be renamed at runtime");
+        }
+        if new.disable != self.disable {
+            bail!("disabled items can't be changed at runtime");
+        }
+
+        new.validate()?;
+        Ok(new)
+    }
+
+    /// Returns the indices of the items which are configured differently in the other config
+    pub fn changed_items(&self, other: &AppConfig) -> Vec<usize> {
+        let to_value = |item: &Item| serde_json::to_value(item).ok();
+        self.items
+            .iter()
+            .zip(&other.items)
+            .enumerate()
+            .filter(|(_, (a, b))| to_value(a) != to_value(b))
+            .map(|(idx, _)| idx)
+            .collect()
     }
 }
 
@@ -186,6 +358,122 @@ mod tests {
         };
     }
 
+    fn config(items: Value) -> AppConfig {
+        serde_json::from_value(serde_json::json!({ "socket": "/tmp/i3stat.sock", "items": items }))
+            .unwrap()
+    }
+
+    #[test]
+    fn allow_duplicate_names() {
+        let mut cfg = config(serde_json::json!([
+            { "type": "raw", "full_text": "", "name": "a" },
+            { "type": "raw", "full_text": "", "name": "b" },
+            { "type": "raw", "full_text": "", "name": "a" },
+        ]));
+        assert!(cfg.validate().is_err());
+
+        cfg.allow_duplicate_names = true;
+        cfg.validate().unwrap();
+        assert_eq!(cfg.item_indices_with_name("a"), vec![0, 2]);
+        assert_eq!(cfg.item_indices_with_name("b"), vec![1]);
+        assert_eq!(cfg.item_indices_with_name("c"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn resolve_items() {
+        let cfg = config(serde_json::json!([
+            { "type": "raw", "full_text": "", "name": "a" },
+            { "type": "raw", "full_text": "", "name": "b" },
+        ]));
+        assert_eq!(cfg.resolve_item("1").unwrap(), 1);
+        assert_eq!(cfg.resolve_item("a").unwrap(), 0);
+        assert_eq!(
+            cfg.resolve_item("2").unwrap_err().to_string(),
+            "no item found with index: 2"
+        );
+        assert_eq!(
+            cfg.resolve_item("c").unwrap_err().to_string(),
+            "no item found with name: c"
+        );
+    }
+
+    #[test]
+    fn item_defaults() {
+        let mut value = serde_json::json!({
+            "socket": "/tmp/i3stat.sock",
+            "defaults": {
+                "cpu": { "interval": "1s", "sparkline": 5 },
+                "raw": { "full_text": "default" }
+            },
+            "items": [
+                { "type": "cpu" },
+                { "type": "cpu", "interval": "5s", "sparkline": null, "name": "other" },
+                { "type": "mem", "interval": "3s" },
+                { "type": "group", "items": [{ "type": "raw" }] }
+            ]
+        });
+        AppConfig::apply_defaults(&mut value);
+        let cfg = serde_json::from_value::<AppConfig>(value).unwrap();
+
+        let items = serde_json::to_value(&cfg.items).unwrap();
+        // inherited
+        assert_eq!(items[0]["interval"], "1s");
+        assert_eq!(items[0]["sparkline"], 5);
+        // overridden, or unset
+        assert_eq!(items[1]["interval"], "5s");
+        assert_eq!(items[1]["sparkline"], Value::Null);
+        // other types are untouched
+        assert_eq!(items[2]["interval"], "3s");
+        // items in groups also get defaults
+        assert_eq!(items[3]["items"][0]["full_text"], "default");
+    }
+
+    #[test]
+    fn patch_round_trip() {
+        let cfg = config(serde_json::json!([
+            { "type": "raw", "full_text": "a" },
+            { "type": "raw", "full_text": "b", "name": "b" },
+        ]));
+
+        let patched = cfg
+            .patch(serde_json::json!({
+                "theme": { "fg": "#123456" },
+                "items": [
+                    { "type": "raw", "full_text": "a" },
+                    { "type": "raw", "full_text": "c", "name": "b" },
+                ]
+            }))
+            .unwrap();
+        assert_eq!(patched.theme.fg.display_rgb().to_string(), "#123456");
+        // values not in the patch are kept
+        assert_eq!(patched.theme.bg, cfg.theme.bg);
+        assert_eq!(patched.socket(), cfg.socket());
+        assert_eq!(cfg.changed_items(&patched), [1]);
+
+        // an empty patch changes nothing
+        let patched = cfg.patch(serde_json::json!({})).unwrap();
+        assert!(cfg.changed_items(&patched).is_empty());
+    }
+
+    #[test]
+    fn patch_invalid() {
+        let cfg = config(serde_json::json!([{ "type": "raw", "full_text": "a", "name": "a" }]));
+
+        // fails to deserialise
+        assert!(cfg
+            .patch(serde_json::json!({ "theme": { "fg": "red" } }))
+            .is_err());
+        // fails validation
+        assert!(cfg
+            .patch(serde_json::json!({ "theme": { "powerline": [] } }))
+            .is_err());
+        // changes the items
+        assert!(cfg.patch(serde_json::json!({ "items": [] })).is_err());
+        let renamed = serde_json::json!({ "items": [{ "type": "raw", "full_text": "a" }] });
+        assert!(cfg.patch(renamed).is_err());
+        assert!(cfg.patch(serde_json::json!({ "disable": [0] })).is_err());
+    }
+
     #[test]
     fn sort_does_nothing() {
         let mut items = [item!("a"), item!("b"), item!("c")];
@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 
 use figment::error::Kind;
@@ -8,29 +9,94 @@ use figment::Figment;
 use wordexp::{wordexp, Wordexp};
 
 use crate::cli::Cli;
+use crate::config::interpolate::interpolate_env;
 use crate::config::AppConfig;
 use crate::error::Result;
 
+/// File extensions of config files that can be read
+const SUPPORTED_EXTENSIONS: &[&str] = &["toml", "json", "yaml", "yml"];
+
+/// Simple glob matching, supports `*` (any number of characters) and `?` (any one character)
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// List the files in `dir` for which `f` returns true, sorted so includes are deterministic
+fn list_dir(dir: &Path, f: impl Fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && f(&path) {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
 fn expand_include_path(s: impl AsRef<str>, cfg_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
     let cfg_dir = cfg_dir.as_ref();
     // perform expansion, see: man 3 wordexp
-    wordexp(s.as_ref(), Wordexp::new(0), 0)?
-        .map(|path| -> Result<_> {
-            // convert expansion to path
-            let path = PathBuf::from(path);
-            if path.is_absolute() {
-                // if it's already absolute, keep it
-                Ok(path)
-            } else {
-                // if it's not absolute, assume relative to `cfg_dir` and attempt to resolve
-                let joined = cfg_dir.join(path);
-                match joined.canonicalize() {
-                    Ok(p) => Ok(p),
-                    Err(e) => bail!("failed to resolve {}: {}", joined.display(), e),
-                }
-            }
-        })
-        .collect::<Result<_>>()
+    let mut paths = vec![];
+    for path in wordexp(s.as_ref(), Wordexp::new(0), 0)? {
+        // convert expansion to path
+        let path = PathBuf::from(path);
+        // if it's not absolute, assume relative to `cfg_dir`
+        let is_absolute = path.is_absolute();
+        let path = if is_absolute {
+            path
+        } else {
+            cfg_dir.join(path)
+        };
+
+        // `wordexp` already expands globs, but only relative to the current directory - so expand
+        // any globs in the file name that remain relative to the config directory
+        let file_name = path.file_name().map(OsStr::as_bytes).unwrap_or_default();
+        if file_name.iter().any(|b| matches!(b, b'*' | b'?')) {
+            // SAFETY: we checked it has a file name above, which means it also has a parent
+            let parent = path.parent().unwrap();
+            let parent = match parent.canonicalize() {
+                Ok(p) => p,
+                Err(e) => bail!("failed to resolve {}: {}", parent.display(), e),
+            };
+
+            paths.extend(list_dir(&parent, |p| {
+                p.file_name()
+                    .map_or(false, |name| glob_match(file_name, name.as_bytes()))
+            })?);
+
+            continue;
+        }
+
+        // if it's already absolute, keep it - otherwise attempt to resolve it
+        let path = match path.canonicalize() {
+            Ok(p) if !is_absolute => p,
+            Err(e) if !is_absolute => bail!("failed to resolve {}: {}", path.display(), e),
+            _ => path,
+        };
+
+        // include all supported files within a directory
+        if path.is_dir() {
+            paths.extend(list_dir(&path, |p| {
+                p.extension()
+                    .and_then(OsStr::to_str)
+                    .map_or(false, |ext| SUPPORTED_EXTENSIONS.contains(&ext))
+            })?);
+        } else {
+            paths.push(path);
+        }
+    }
+
+    Ok(paths)
 }
 
 pub fn parse(args: &Cli) -> Result<AppConfig> {
@@ -45,6 +111,13 @@ pub fn parse(args: &Cli) -> Result<AppConfig> {
         .parent()
         .ok_or("failed to find config dir")?;
 
+    // the config files that exist, in the order they're merged
+    let mut sources = SUPPORTED_EXTENSIONS
+        .iter()
+        .map(|ext| cfg_file.with_extension(ext))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+
     // main configuration file
     let mut figment = Figment::new()
         .merge(Toml::file(cfg_file.with_extension("toml")))
@@ -95,11 +168,122 @@ pub fn parse(args: &Cli) -> Result<AppConfig> {
                 }
 
                 log::trace!("read config file: {}", include.display());
+                if include.is_file() && !seen.contains(&include) {
+                    sources.push(include.clone());
+                }
                 seen.insert(include);
             }
         }
     };
 
-    let app_config = figment.extract::<AppConfig>()?;
+    // defaults need to be merged into the items, and variables expanded, before they're deserialised
+    let interpolate = match figment.extract_inner::<bool>("interpolate_env") {
+        Ok(interpolate) => interpolate,
+        // off if "interpolate_env" wasn't specified at all
+        Err(e) if matches!(e.kind, Kind::MissingField(_)) => false,
+        // some other error occurred, e.g. it's not a boolean
+        Err(e) => bail!(e),
+    };
+    if figment.contains("defaults") || interpolate {
+        let mut value = figment.extract::<serde_json::Value>()?;
+        AppConfig::apply_defaults(&mut value);
+        if interpolate {
+            interpolate_env(&mut value)?;
+        }
+
+        let mut app_config = serde_json::from_value::<AppConfig>(value)?;
+        app_config.sources = sources;
+        return Ok(app_config);
+    }
+
+    let mut app_config = figment.extract::<AppConfig>()?;
+    app_config.sources = sources;
     Ok(app_config)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::util::TestDir;
+
+    /// Creates a fresh directory for a test, containing the given (empty) files
+    fn setup(name: &str, files: &[&str]) -> TestDir {
+        let dir = TestDir::new(name);
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match(b"*.toml", b"a.toml"));
+        assert!(glob_match(b"*", b"a.toml"));
+        assert!(glob_match(b"?.toml", b"a.toml"));
+        assert!(glob_match(b"a*b*c", b"a__b__c"));
+        assert!(!glob_match(b"*.toml", b"a.json"));
+        assert!(!glob_match(b"?.toml", b"ab.toml"));
+    }
+
+    #[test]
+    fn include_file() {
+        let dir = setup("include_file", &["a.toml"]);
+        assert_eq!(
+            expand_include_path("a.toml", &dir).unwrap(),
+            vec![dir.join("a.toml")]
+        );
+        assert!(expand_include_path("missing.toml", &dir).is_err());
+    }
+
+    #[test]
+    fn include_glob() {
+        let dir = setup(
+            "include_glob",
+            &[
+                "items/c.toml",
+                "items/a.toml",
+                "items/b.toml",
+                "items/d.json",
+                "other.toml",
+            ],
+        );
+        assert_eq!(
+            expand_include_path("items/*.toml", &dir).unwrap(),
+            vec![
+                dir.join("items/a.toml"),
+                dir.join("items/b.toml"),
+                dir.join("items/c.toml"),
+            ]
+        );
+        assert_eq!(
+            expand_include_path("items/*.yaml", &dir).unwrap(),
+            Vec::<PathBuf>::new()
+        );
+    }
+
+    #[test]
+    fn include_dir() {
+        let dir = setup(
+            "include_dir",
+            &[
+                "items/b.yml",
+                "items/a.toml",
+                "items/c.json",
+                "items/notes.txt",
+            ],
+        );
+        assert_eq!(
+            expand_include_path("items", &dir).unwrap(),
+            vec![
+                dir.join("items/a.toml"),
+                dir.join("items/b.yml"),
+                dir.join("items/c.json"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! A JSON Schema of the configuration, so editors can validate and autocomplete config files.
+//!
+//! Rather than describing every option by hand, the schema is inferred from the defaults of the
+//! config and of each item type. This means every option is listed with its type and default, but
+//! options without a default (i.e., those which are unset) accept any value.
+//!
+//! Some things can't be inferred from a default (such as which values an enum accepts), so these are
+//! found by deserialising the defaults with a placeholder value in place of the option.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use strum::IntoEnumIterator;
+
+use crate::config::item::{Common, Item, ItemInner};
+use crate::config::AppConfig;
+use crate::error::Result;
+
+const SCHEMA_DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Used in place of an option's value when probing what the option accepts
+const PROBE: &str = "i3stat-schema-probe";
+
+/// Deserialises a modified copy of a default value, to find out what it accepts
+struct Prober {
+    root: Value,
+    deserialise: fn(Value) -> serde_json::Result<()>,
+}
+
+impl Prober {
+    fn new<T: DeserializeOwned>(root: Value) -> Prober {
+        Prober {
+            root,
+            deserialise: |value| serde_json::from_value::<T>(value).map(|_| ()),
+        }
+    }
+
+    /// Deserialise the default value, with the option at `path` replaced with `value`
+    fn with(&self, path: &[&str], value: Value) -> serde_json::Result<()> {
+        let mut root = self.root.clone();
+        let target = path.iter().fold(&mut root, |target, key| &mut target[*key]);
+        *target = value;
+        (self.deserialise)(root)
+    }
+
+    /// Unit enums are serialised as strings, so their variants can't be inferred from a default.
+    /// Instead, they're read from the error when the option isn't set to one of them.
+    fn variants(&self, path: &[&str]) -> Option<Vec<Value>> {
+        if path.is_empty() {
+            return None;
+        }
+
+        let err = self.with(path, PROBE.into()).err()?.to_string();
+        let (_, expected) = err.split_once(&format!("unknown variant `{}`, expected ", PROBE))?;
+        Some(
+            expected
+                .split('`')
+                .skip(1)
+                .step_by(2)
+                .map(Value::from)
+                .collect(),
+        )
+    }
+}
+
+/// Infer a schema from a default value
+fn infer(value: &Value, path: &[&str], prober: &Prober) -> Value {
+    let mut schema = match value {
+        // unset options could be anything
+        Value::Null => json!({}),
+        Value::Bool(_) => json!({ "type": "boolean" }),
+        Value::Number(n) if n.is_f64() => json!({ "type": "number" }),
+        Value::Number(_) => json!({ "type": "integer" }),
+        Value::String(_) => json!({ "type": "string" }),
+        Value::Array(values) => match values.first() {
+            // NOTE: elements aren't probed, since there may not be any in the default
+            Some(first) => json!({ "type": "array", "items": infer(first, &[], prober) }),
+            None => json!({ "type": "array" }),
+        },
+        Value::Object(map) => {
+            return json!({ "type": "object", "properties": properties(map, path, prober) })
+        }
+    };
+
+    if let Some(variants) = prober.variants(path) {
+        schema["enum"] = variants.into();
+    }
+    if !value.is_null() {
+        schema["default"] = value.clone();
+    }
+
+    schema
+}
+
+fn properties(map: &Map<String, Value>, path: &[&str], prober: &Prober) -> Map<String, Value> {
+    map.iter()
+        .map(|(k, v)| {
+            let path = path.iter().copied().chain([k.as_str()]).collect::<Vec<_>>();
+            (k.clone(), infer(v, &path, prober))
+        })
+        .collect()
+}
+
+/// Whether options which aren't known are kept (e.g. in a flattened map) rather than ignored
+fn keeps_unknown<T: DeserializeOwned + Serialize>(root: &Value) -> bool {
+    let mut root = root.clone();
+    root[PROBE] = true.into();
+    serde_json::from_value::<T>(root)
+        .and_then(serde_json::to_value)
+        .map_or(true, |value| value.get(PROBE).is_some())
+}
+
+/// Each item is an object with a `type`, as well as the options common to all items
+fn item_schema() -> Result<Value> {
+    let common = serde_json::to_value(Common::default())?;
+    let common = common.as_object().cloned().unwrap_or_default();
+
+    let mut variants = vec![];
+    for item in ItemInner::iter() {
+        // prefer the defaults used when deserialising, falling back to `Default` for items which
+        // have required options
+        let item =
+            serde_json::from_value::<ItemInner>(json!({ "type": item.tag() })).unwrap_or(item);
+
+        let mut defaults = common.clone();
+        if let Value::Object(map) = serde_json::to_value(&item)? {
+            defaults.extend(map);
+        }
+        defaults.remove("type");
+
+        let mut root = Value::Object(defaults.clone());
+        root["type"] = item.tag().into();
+        let additional = keeps_unknown::<Item>(&root);
+        let prober = Prober::new::<Item>(root);
+
+        let mut props = properties(&defaults, &[], &prober);
+        props.insert("type".into(), json!({ "const": item.tag() }));
+
+        variants.push(json!({
+            "title": item.tag(),
+            "type": "object",
+            "properties": props,
+            "required": ["type"],
+            // unknown options are ignored, so they're most likely a typo
+            "additionalProperties": additional,
+        }));
+    }
+
+    Ok(json!({ "oneOf": variants }))
+}
+
+/// The JSON Schema of the whole configuration file
+pub fn schema() -> Result<Value> {
+    // this is the smallest valid config, so everything else is set to its default
+    let config = serde_json::from_value::<AppConfig>(json!({ "items": [] }))?;
+    let config = serde_json::to_value(&config)?;
+    let prober = Prober::new::<AppConfig>(config.clone());
+    let mut props = match &config {
+        Value::Object(map) => properties(map, &[], &prober),
+        _ => Map::new(),
+    };
+    props.insert(
+        "items".into(),
+        json!({ "type": "array", "items": item_schema()? }),
+    );
+
+    Ok(json!({
+        "$schema": SCHEMA_DRAFT,
+        "title": "i3stat",
+        "type": "object",
+        "properties": props,
+        "required": ["items"],
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_is_valid_json() {
+        let schema = schema().unwrap().to_string();
+        let schema = serde_json::from_str::<Value>(&schema).unwrap();
+        assert_eq!(schema["$schema"], SCHEMA_DRAFT);
+        assert_eq!(schema["properties"]["staleness"]["type"], "boolean");
+        assert_eq!(schema["properties"]["theme"]["type"], "object");
+
+        let types = schema["properties"]["items"]["items"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variant| variant["properties"]["type"]["const"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        for tag in ["raw", "cpu", "nic", "pulse", "script", "time"] {
+            assert!(types.contains(&tag), "missing item type: {}", tag);
+        }
+    }
+
+    #[test]
+    fn item_options() {
+        let schema = item_schema().unwrap();
+        let nic = schema["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|variant| variant["title"] == "nic")
+            .unwrap();
+
+        // item specific options, with their defaults
+        assert_eq!(
+            nic["properties"]["scroll_width"],
+            json!({ "type": "integer", "default": 20 })
+        );
+        // and the options common to every item
+        assert_eq!(nic["properties"]["retry"]["type"], "object");
+        assert_eq!(nic["properties"]["name"], json!({}));
+    }
+
+    fn variant(tag: &str) -> Value {
+        item_schema().unwrap()["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|variant| variant["title"] == tag)
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn enum_options() {
+        assert_eq!(
+            variant("cpu")["properties"]["freq_mode"]["enum"],
+            json!(["average", "max"])
+        );
+
+        let schema = schema().unwrap();
+        assert_eq!(
+            schema["properties"]["units"],
+            json!({ "type": "string", "enum": ["binary", "decimal"], "default": "binary" })
+        );
+    }
+
+    #[test]
+    fn unknown_options() {
+        // options which would be ignored are rejected
+        assert_eq!(variant("nic")["additionalProperties"], false);
+        // but raw items pass them on to the bar
+        assert_eq!(variant("raw")["additionalProperties"], true);
+    }
+}
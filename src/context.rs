@@ -1,18 +1,23 @@
-use std::time::Duration;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::panic::AssertUnwindSafe;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use clap::builder::StyledStr;
-use futures::Future;
-use serde_json::Value;
+use futures::{Future, FutureExt};
+use serde_json::{json, Value};
 use sysinfo::System;
 use tokio::sync::mpsc::error::SendError;
-use tokio::sync::{mpsc, oneshot};
-use tokio::time::sleep;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{self, sleep_until};
 
+use crate::config::item::{ClickOpenOrder, Common};
 use crate::config::AppConfig;
 use crate::error::Result;
 use crate::i3::bar_item::I3Item;
-use crate::i3::I3ClickEvent;
+use crate::i3::{I3Button, I3ClickEvent, I3Markup, I3Modifier};
+use crate::util::exec::exec_with_value;
 use crate::util::RcCell;
 
 #[derive(Debug)]
@@ -25,24 +30,152 @@ pub enum CustomResponse {
 pub enum BarEvent {
     Click(I3ClickEvent),
     Signal,
+    /// The bar was hidden (`false`) or shown again (`true`) by i3
+    Visibility(bool),
     Custom {
         payload: Vec<String>,
         responder: oneshot::Sender<CustomResponse>,
     },
 }
 
+/// A clock shared by all items, so animations (e.g. blinking) stay in sync across the bar.
+/// It's only a point in time, so items which don't animate don't pay for it.
+#[derive(Debug, Copy, Clone)]
+pub struct AnimationClock {
+    epoch: time::Instant,
+}
+
+impl AnimationClock {
+    fn new() -> AnimationClock {
+        AnimationClock {
+            epoch: time::Instant::now(),
+        }
+    }
+
+    /// Time elapsed since the bar started
+    pub fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    /// How many whole `period`s have elapsed since the bar started, e.g. an item which blinks every
+    /// second can check if this is odd or even
+    pub fn tick(&self, period: Duration) -> u64 {
+        (self.now().as_nanos() / period.as_nanos().max(1)) as u64
+    }
+
+    /// Wait until the next tick of `period`
+    pub async fn wait_for_tick(&self, period: Duration) {
+        let next = self.tick(period) + 1;
+        sleep_until(self.epoch + period * next as u32).await;
+    }
+}
+
 #[derive(Debug)]
 pub struct SharedState {
     pub sys: System,
+    pub clock: AnimationClock,
+    /// Whether i3 is showing the bar, see `Dispatcher::visibility`
+    pub visible: watch::Receiver<bool>,
+    #[cfg(feature = "http")]
+    http: std::cell::OnceCell<reqwest::Client>,
 }
 
 impl SharedState {
-    pub fn new() -> RcCell<SharedState> {
+    pub fn new(visible: watch::Receiver<bool>) -> RcCell<SharedState> {
         RcCell::new(SharedState {
             // this loads nothing, it's up to each item to load what it needs
             sys: System::new(),
+            clock: AnimationClock::new(),
+            visible,
+            #[cfg(feature = "http")]
+            http: std::cell::OnceCell::new(),
         })
     }
+
+    /// A HTTP client shared between all items, so connection pools are reused.
+    /// This is created the first time it's requested.
+    #[cfg(feature = "http")]
+    pub fn http_client(&self) -> reqwest::Client {
+        self.http
+            .get_or_init(|| {
+                reqwest::Client::builder()
+                    .user_agent(concat!(
+                        env!("CARGO_PKG_NAME"),
+                        "/",
+                        env!("CARGO_PKG_VERSION")
+                    ))
+                    .build()
+                    // SAFETY: this only fails if the TLS backend can't be initialised
+                    .expect("failed to create http client")
+            })
+            .clone()
+    }
+}
+
+/// Runtime metrics about the bar, these are queryable via ipc.
+#[derive(Debug)]
+pub struct RuntimeStats {
+    started: Instant,
+    restarts: Vec<usize>,
+}
+
+impl RuntimeStats {
+    pub fn new(item_count: usize) -> RcCell<RuntimeStats> {
+        RcCell::new(RuntimeStats {
+            started: Instant::now(),
+            restarts: vec![0; item_count],
+        })
+    }
+
+    /// Record that the item at the given index was restarted
+    pub fn item_restarted(&mut self, idx: usize) {
+        self.restarts[idx] += 1;
+    }
+
+    pub fn to_value(&self, config: &AppConfig) -> Value {
+        json!({
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "items": config
+                .item_idx_to_name()
+                .iter()
+                .map(|(idx, name)| {
+                    (
+                        idx.to_string(),
+                        json!({ "name": name, "restarts": self.restarts[*idx] }),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>(),
+        })
+    }
+}
+
+/// Remembers the last click that was handled, so the same click repeated too soon can be ignored
+#[derive(Debug)]
+struct ClickDebounce {
+    window: Duration,
+    last: Option<(I3Button, HashSet<I3Modifier>, time::Instant)>,
+}
+
+impl ClickDebounce {
+    fn new(window: Duration) -> ClickDebounce {
+        ClickDebounce { window, last: None }
+    }
+
+    /// Returns `true` if `click` should be ignored. Ignored clicks aren't remembered, so holding
+    /// down a button can't stop clicks from getting through forever.
+    fn is_bounce(&mut self, click: &I3ClickEvent, at: time::Instant) -> bool {
+        if let Some((button, modifiers, last_at)) = &self.last {
+            if *button == click.button
+                && *modifiers == click.modifiers
+                && at.saturating_duration_since(*last_at) < self.window
+            {
+                return true;
+            }
+        }
+
+        self.last = Some((click.button, click.modifiers.clone(), at));
+        false
+    }
 }
 
 #[derive(Debug)]
@@ -52,22 +185,53 @@ pub struct Context {
     tx_item: mpsc::Sender<(I3Item, usize)>,
     rx_event: mpsc::Receiver<BarEvent>,
     index: usize,
+    /// Whether i3 is showing the bar, items don't refresh on their interval while it's hidden
+    visible: watch::Receiver<bool>,
+    /// Clicks with these modifiers toggle showing the item's detail
+    detail_modifiers: Option<HashSet<I3Modifier>>,
+    /// If set, the item's detail is being shown until this time
+    detail_until: Cell<Option<time::Instant>>,
+    /// The last item that was sent, so it can be sent again when showing or hiding its detail
+    last_item: RefCell<Option<I3Item>>,
+    /// If set, repeated clicks are ignored
+    click_debounce: RefCell<Option<ClickDebounce>>,
+    /// Command which is run when the item is left clicked, see `Common::on_click_open`
+    click_open: Option<(String, ClickOpenOrder)>,
+    /// Set when `click_open` should be run the next time the item updates
+    click_open_pending: Cell<bool>,
 }
 
 impl Context {
+    /// How long an item's detail is shown for after clicking it
+    const DETAIL_DURATION: Duration = Duration::from_secs(5);
+
     pub fn new(
         config: RcCell<AppConfig>,
         state: RcCell<SharedState>,
         tx_item: mpsc::Sender<(I3Item, usize)>,
         rx_event: mpsc::Receiver<BarEvent>,
         index: usize,
+        common: &Common,
     ) -> Context {
+        let click_open = common
+            .on_click_open
+            .clone()
+            .map(|command| (command, common.on_click_open_order));
+
+        let visible = state.visible.clone();
         Context {
             config,
             state,
             tx_item,
             rx_event,
             index,
+            visible,
+            detail_modifiers: common.detail_modifiers.clone(),
+            detail_until: Cell::new(None),
+            last_item: RefCell::new(None),
+            click_debounce: RefCell::new(common.click_debounce.map(ClickDebounce::new)),
+            click_open,
+            click_open_pending: Cell::new(false),
         }
     }
 
@@ -75,17 +239,156 @@ impl Context {
         &self,
         item: I3Item,
     ) -> std::result::Result<(), SendError<(I3Item, usize)>> {
-        self.tx_item.send((item, self.index)).await?;
+        let to_send = match self.detail_until.get() {
+            Some(_) => Self::swap_detail(&item),
+            None => item.clone(),
+        };
+
+        // the item has handled the click, so now it can be opened with the item's new value
+        if self.click_open_pending.take() {
+            if let Some((command, _)) = &self.click_open {
+                self.open(command, &item);
+            }
+        }
+
+        *self.last_item.borrow_mut() = Some(item);
+        self.tx_item.send((to_send, self.index)).await?;
         Ok(())
     }
 
+    /// Replace the item's text with its detail, if it has any
+    fn swap_detail(item: &I3Item) -> I3Item {
+        match item.get_detail() {
+            Some(detail) => item.clone().full_text(detail).short_text(detail),
+            None => item.clone(),
+        }
+    }
+
+    /// Show or hide the item's detail, and send the last item again so the change is visible
+    async fn set_detail_shown(&self, shown: bool) {
+        self.detail_until
+            .set(shown.then(|| time::Instant::now() + Self::DETAIL_DURATION));
+
+        let last_item = self.last_item.borrow().clone();
+        if let Some(item) = last_item {
+            if let Err(e) = self.update_item(item).await {
+                log::warn!("failed to toggle detail of item[{}]: {}", self.index, e);
+            }
+        }
+    }
+
+    /// Returns `true` if the event was handled here (or ignored), and shouldn't be passed on to the
+    /// item
+    async fn intercept_event(&self, event: &BarEvent) -> bool {
+        if self.intercept_click(event) {
+            return true;
+        }
+
+        self.handle_detail_click(event).await
+    }
+
+    /// Handling shared by every item, no matter how it reads its events. Returns `true` if the
+    /// event should be ignored.
+    fn intercept_click(&self, event: &BarEvent) -> bool {
+        if self.is_bounce(event) {
+            return true;
+        }
+
+        if let BarEvent::Click(click) = event {
+            self.handle_click_open(click);
+        }
+
+        false
+    }
+
+    /// Returns `true` if the event is a click which should be ignored, see `Common::click_debounce`
+    fn is_bounce(&self, event: &BarEvent) -> bool {
+        if let (BarEvent::Click(click), Some(debounce)) =
+            (event, self.click_debounce.borrow_mut().as_mut())
+        {
+            if debounce.is_bounce(click, time::Instant::now()) {
+                log::debug!(
+                    "item[{}] ignored repeated click: {:?}",
+                    self.index,
+                    click.button
+                );
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Run `on_click_open` for plain left clicks, either now or once the item has updated
+    fn handle_click_open(&self, click: &I3ClickEvent) {
+        let Some((command, order)) = &self.click_open else {
+            return;
+        };
+
+        if click.button != I3Button::Left || !click.modifiers.is_empty() {
+            return;
+        }
+
+        match order {
+            ClickOpenOrder::Before => {
+                if let Some(item) = self.last_item.borrow().as_ref() {
+                    self.open(command, item);
+                }
+            }
+            ClickOpenOrder::After => self.click_open_pending.set(true),
+        }
+    }
+
+    /// Run an `on_click_open` command with the item's value
+    fn open(&self, command: &str, item: &I3Item) {
+        // the bar's default markup is only applied to items after they're sent
+        let markup = item.get_markup().or(self.config.markup.as_ref());
+        exec_with_value(command, item, matches!(markup, Some(I3Markup::Pango)));
+    }
+
+    /// Returns `true` if the event was a click to toggle the item's detail, in which case it's
+    /// handled here and isn't passed on to the item
+    async fn handle_detail_click(&self, event: &BarEvent) -> bool {
+        match (event, &self.detail_modifiers) {
+            (BarEvent::Click(click), Some(modifiers))
+                if click.button == I3Button::Left && &click.modifiers == modifiers =>
+            {
+                self.set_detail_shown(self.detail_until.get().is_none())
+                    .await;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Wait for the next event, or until `delay` has elapsed (in which case `None` is returned).
+    /// If `delay` is `None` this only returns once there's an event, so items without an interval
+    /// (e.g. `interval = "never"`) can safely call this in a loop.
     pub async fn wait_for_event(&mut self, delay: Option<Duration>) -> Option<BarEvent> {
-        match delay {
-            None => self.rx_event.recv().await,
-            Some(delay) => tokio::select! {
+        let deadline = delay.map(|delay| time::Instant::now() + delay);
+        loop {
+            let event = tokio::select! {
                 event = self.rx_event.recv() => event,
-                _ = sleep(delay) => None
-            },
+                _ = sleep_until_visible(deadline, self.visible.clone()) => return None,
+                _ = sleep_until_some(self.detail_until.get()) => {
+                    self.set_detail_shown(false).await;
+                    continue;
+                }
+            };
+
+            match event {
+                Some(event) => {
+                    if !self.intercept_event(&event).await {
+                        return Some(event);
+                    }
+                }
+                // no more events will be sent, so only wait for the delay (if any) rather than
+                // returning straight away: otherwise callers without a delay would spin
+                None => {
+                    sleep_until_visible(deadline, self.visible.clone()).await;
+                    return None;
+                }
+            }
         }
     }
 
@@ -94,22 +397,74 @@ impl Context {
         F: FnMut(BarEvent) -> R,
         R: Future<Output = ()>,
     {
-        tokio::select! {
-            Some(event) = self.rx_event.recv() => {
-                closure(event).await;
-                while let Ok(event) = self.rx_event.try_recv() {
-                    closure(event).await
+        let deadline = time::Instant::now() + duration;
+        loop {
+            tokio::select! {
+                Some(event) = self.rx_event.recv() => {
+                    if self.intercept_event(&event).await {
+                        continue;
+                    }
+
+                    closure(event).await;
+                    while let Ok(event) = self.rx_event.try_recv() {
+                        if !self.intercept_event(&event).await {
+                            closure(event).await
+                        }
+                    }
+
+                    break;
                 }
+                _ = sleep_until_visible(Some(deadline), self.visible.clone()) => break,
+                _ = sleep_until_some(self.detail_until.get()) => self.set_detail_shown(false).await,
+            }
+        }
+    }
+
+    /// The clock shared between all items, see `AnimationClock`
+    pub fn clock(&self) -> AnimationClock {
+        self.state.clock
+    }
+
+    /// Time elapsed since the bar started, see `AnimationClock::now`
+    pub fn now(&self) -> Duration {
+        self.state.clock.now()
+    }
+
+    /// How many whole `period`s have elapsed since the bar started, see `AnimationClock::tick`
+    pub fn tick(&self, period: Duration) -> u64 {
+        self.state.clock.tick(period)
+    }
+
+    /// Wait for the next event, for items which handle all their events themselves. Unlike
+    /// `wait_for_event`, the only event that's intercepted is a repeated click (if the item has
+    /// `click_debounce` set), although clicks still run `on_click_open`. This is cancel safe, so it
+    /// can be used in `tokio::select!`.
+    pub async fn raw_event(&mut self) -> Option<BarEvent> {
+        loop {
+            let event = self.rx_event.recv().await?;
+            if !self.intercept_click(&event) {
+                return Some(event);
             }
-            _ = sleep(duration) => {}
         }
     }
+}
 
-    pub fn raw_event_rx(&mut self) -> &mut mpsc::Receiver<BarEvent> {
-        &mut self.rx_event
+/// Sleep until the given time, or forever if there isn't one
+async fn sleep_until_some(deadline: Option<time::Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => futures::future::pending().await,
     }
 }
 
+/// Sleep until the given time (or forever if there isn't one), and then for as long as the bar is
+/// hidden: there's no point refreshing items that can't be seen, they're refreshed once it's shown
+async fn sleep_until_visible(deadline: Option<time::Instant>, mut visible: watch::Receiver<bool>) {
+    sleep_until_some(deadline).await;
+    // this only fails if the bar has gone away, in which case there's nothing to wait for
+    let _ = visible.wait_for(|visible| *visible).await;
+}
+
 #[derive(Debug, Default, Copy, Clone)]
 pub enum StopAction {
     /// The task finished, and the item will stay in the bar
@@ -125,3 +480,135 @@ pub enum StopAction {
 pub trait BarItem: Send {
     async fn start(&self, ctx: Context) -> Result<StopAction>;
 }
+
+/// Runs an item's future, turning a panic into an error so it's shown and retried just like any
+/// other failure, rather than the item silently freezing in the bar.
+/// NOTE: this only works when panics unwind, so the release profile mustn't use `panic = "abort"`
+pub async fn catch_item_panic<F>(fut: F) -> Result<StopAction>
+where
+    F: Future<Output = Result<StopAction>>,
+{
+    match AssertUnwindSafe(fut).catch_unwind().await {
+        Ok(result) => result,
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+
+            bail!("item panicked: {}", msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animation_ticks() {
+        let clock = AnimationClock {
+            epoch: time::Instant::now() - Duration::from_millis(2500),
+        };
+        assert_eq!(clock.tick(Duration::from_secs(1)), 2);
+        assert_eq!(clock.tick(Duration::from_millis(500)), 5);
+        assert!(clock.now() >= Duration::from_millis(2500));
+        // shouldn't panic
+        assert!(clock.tick(Duration::ZERO) > 0);
+    }
+
+    #[test]
+    fn animation_clocks_agree() {
+        let clock = AnimationClock::new();
+        let copy = clock;
+        assert_eq!(
+            clock.tick(Duration::from_secs(1)),
+            copy.tick(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn click_debounce() {
+        let click = |button: I3Button, modifiers: &[I3Modifier]| I3ClickEvent {
+            button,
+            modifiers: modifiers.iter().copied().collect(),
+            ..Default::default()
+        };
+        let left = click(I3Button::Left, &[]);
+        let ms = |ms: u64| Duration::from_millis(ms);
+
+        let start = time::Instant::now();
+        let mut debounce = ClickDebounce::new(ms(300));
+        assert!(!debounce.is_bounce(&left, start));
+        assert!(debounce.is_bounce(&left, start + ms(100)));
+        // ignored clicks don't extend the window
+        assert!(debounce.is_bounce(&left, start + ms(299)));
+        assert!(!debounce.is_bounce(&left, start + ms(300)));
+
+        // different clicks aren't ignored
+        let start = start + ms(1000);
+        assert!(!debounce.is_bounce(&left, start));
+        assert!(!debounce.is_bounce(&click(I3Button::Right, &[]), start + ms(10)));
+        assert!(!debounce.is_bounce(
+            &click(I3Button::Right, &[I3Modifier::Shift]),
+            start + ms(20)
+        ));
+        assert!(debounce.is_bounce(
+            &click(I3Button::Right, &[I3Modifier::Shift]),
+            start + ms(30)
+        ));
+    }
+
+    #[test]
+    fn paused_while_hidden() {
+        let (tx, rx) = watch::channel(false);
+        crate::util::local_block_on(async {
+            let deadline = Some(time::Instant::now());
+            let paused = sleep_until_visible(deadline, rx.clone());
+            tokio::pin!(paused);
+
+            // the deadline has passed, but the bar is hidden
+            let wait = Duration::from_millis(50);
+            assert!(time::timeout(wait, &mut paused).await.is_err());
+
+            // and it resumes once it's shown again
+            tx.send_replace(true);
+            assert!(time::timeout(wait, &mut paused).await.is_ok());
+            assert!(time::timeout(wait, sleep_until_visible(deadline, rx))
+                .await
+                .is_ok());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn item_panics() {
+        async fn panics() -> Result<StopAction> {
+            panic!("oh no")
+        }
+
+        async fn panics_with(n: usize) -> Result<StopAction> {
+            panic!("{} went wrong", n)
+        }
+
+        async fn finishes() -> Result<StopAction> {
+            Ok(StopAction::Remove)
+        }
+
+        crate::util::local_block_on(async {
+            let result = catch_item_panic(panics()).await;
+            assert_eq!(result.unwrap_err().to_string(), "item panicked: oh no");
+
+            let result = catch_item_panic(panics_with(42)).await;
+            assert_eq!(
+                result.unwrap_err().to_string(),
+                "item panicked: 42 went wrong"
+            );
+
+            let result = catch_item_panic(finishes()).await;
+            assert!(matches!(result, Ok(StopAction::Remove)));
+        })
+        .unwrap();
+    }
+}
@@ -1,5 +1,7 @@
 pub mod dunst;
+pub mod network_manager;
 pub mod notifications;
+pub mod upower;
 
 use tokio::sync::OnceCell;
 use zbus::Connection;
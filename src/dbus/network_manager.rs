@@ -0,0 +1,49 @@
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{proxy, CacheProperties};
+
+#[proxy(
+    default_path = "/org/freedesktop/NetworkManager",
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager",
+    gen_blocking = false
+)]
+trait NetworkManager {
+    #[zbus(property)]
+    fn active_connections(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.NetworkManager",
+    interface = "org.freedesktop.NetworkManager.Connection.Active",
+    gen_blocking = false
+)]
+trait ActiveConnection {
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "Type")]
+    fn connection_type(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn vpn(&self) -> zbus::Result<bool>;
+}
+
+impl NetworkManagerProxy<'_> {
+    /// Returns the name of the first active VPN connection, if there is one.
+    /// WireGuard connections aren't marked as a VPN by NetworkManager, so they're checked too.
+    pub async fn active_vpn(&self) -> zbus::Result<Option<String>> {
+        for path in self.active_connections().await? {
+            let active = ActiveConnectionProxy::builder(self.inner().connection())
+                .path(path)?
+                .cache_properties(CacheProperties::No)
+                .build()
+                .await?;
+
+            if active.vpn().await? || active.connection_type().await? == "wireguard" {
+                return Ok(Some(active.id().await?));
+            }
+        }
+
+        Ok(None)
+    }
+}
@@ -2,8 +2,9 @@
 //! See: https://specifications.freedesktop.org/notification-spec/notification-spec-latest.html
 
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use tokio::sync::OnceCell;
+use serde_derive::{Deserialize, Serialize};
 use zbus::proxy;
 use zbus::zvariant::Value;
 
@@ -30,7 +31,8 @@ trait Notifications {
     ) -> zbus::Result<u32>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Urgency {
     Low = 0,
     Normal = 1,
@@ -43,6 +45,27 @@ impl<'a> From<Urgency> for Value<'a> {
     }
 }
 
+/// User configurable options for notifications sent by an item.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NotifyOptions {
+    /// Overrides the urgency of the notification
+    #[serde(default)]
+    pub urgency: Option<Urgency>,
+    /// An icon name (or path) to display with the notification
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+impl NotifyOptions {
+    fn urgency_or(&self, default: Urgency) -> Urgency {
+        self.urgency.unwrap_or(default)
+    }
+
+    fn icon(&self) -> &str {
+        self.icon.as_deref().unwrap_or("")
+    }
+}
+
 /// Easily create a hints notifications map.
 macro_rules! hints {
     () => {
@@ -60,9 +83,11 @@ macro_rules! hints {
     }};
 }
 
-static PULSE_DEFAULTS_ID: OnceCell<u32> = OnceCell::const_new();
-static PULSE_NOTIFICATION_ID: OnceCell<u32> = OnceCell::const_new();
-static BATTERY_NOTIFICATION_ID: OnceCell<u32> = OnceCell::const_new();
+// The last notification id sent for each category, passed as `replaces_id` so that repeated
+// notifications update the existing one rather than stacking. `0` means no notification yet.
+static PULSE_DEFAULTS_ID: AtomicU32 = AtomicU32::new(0);
+static PULSE_NOTIFICATION_ID: AtomicU32 = AtomicU32::new(0);
+static BATTERY_NOTIFICATION_ID: AtomicU32 = AtomicU32::new(0);
 
 impl<'a> NotificationsProxy<'a> {
     const APP_NAME: &'static str = "i3stat";
@@ -72,6 +97,7 @@ impl<'a> NotificationsProxy<'a> {
     async fn notify(
         &self,
         id: Option<u32>,
+        icon: &str,
         hints: Hints,
         summary: impl AsRef<str>,
         body: impl AsRef<str>,
@@ -81,7 +107,7 @@ impl<'a> NotificationsProxy<'a> {
             .notify_full(
                 Self::APP_NAME,
                 id.unwrap_or(0),
-                "",
+                icon,
                 summary.as_ref(),
                 body.as_ref(),
                 &[],
@@ -100,31 +126,43 @@ impl<'a> NotificationsProxy<'a> {
 
     async fn notify_id(
         &self,
-        once_cell: &OnceCell<u32>,
+        last_id: &AtomicU32,
+        icon: &str,
         hints: Hints,
         summary: impl AsRef<str>,
         body: impl AsRef<str>,
         timeout: i32,
     ) {
-        let cached_id = once_cell.get().cloned();
-        if let Some(id) = self.notify(cached_id, hints, summary, body, timeout).await {
-            match cached_id {
-                Some(_) => { /* do nothing, id already saved */ }
-                None => {
-                    let _ = once_cell.set(id);
-                }
-            }
+        let cached_id = match last_id.load(Ordering::Relaxed) {
+            0 => None,
+            id => Some(id),
+        };
+
+        // always save the returned id, since the server may hand out a new one (e.g., if the
+        // previous notification was already closed)
+        if let Some(id) = self
+            .notify(cached_id, icon, hints, summary, body, timeout)
+            .await
+        {
+            last_id.store(id, Ordering::Relaxed);
         }
     }
 
     // impl ----------------------------------------------------------------------------------------
 
-    pub async fn pulse_volume_mute(&self, name: impl AsRef<str>, pct: u32, mute: bool) {
+    pub async fn pulse_volume_mute(
+        &self,
+        opts: &NotifyOptions,
+        name: impl AsRef<str>,
+        pct: u32,
+        mute: bool,
+    ) {
         self.notify_id(
             &PULSE_NOTIFICATION_ID,
+            opts.icon(),
             hints! {
                 "value" => pct,
-                "urgency" => Urgency::Low,
+                "urgency" => opts.urgency_or(Urgency::Low),
             },
             name,
             format!("{}{}%", if mute { " " } else { " " }, pct),
@@ -133,10 +171,16 @@ impl<'a> NotificationsProxy<'a> {
         .await;
     }
 
-    pub async fn pulse_new_source_sink(&self, name: impl AsRef<str>, what: impl AsRef<str>) {
+    pub async fn pulse_new_source_sink(
+        &self,
+        opts: &NotifyOptions,
+        name: impl AsRef<str>,
+        what: impl AsRef<str>,
+    ) {
         self.notify(
             None,
-            hints! { "urgency" => Urgency::Low },
+            opts.icon(),
+            hints! { "urgency" => opts.urgency_or(Urgency::Low) },
             format!("New {} added", what.as_ref()),
             name,
             2_000,
@@ -144,10 +188,16 @@ impl<'a> NotificationsProxy<'a> {
         .await;
     }
 
-    pub async fn pulse_defaults_change(&self, name: impl AsRef<str>, what: impl AsRef<str>) {
+    pub async fn pulse_defaults_change(
+        &self,
+        opts: &NotifyOptions,
+        name: impl AsRef<str>,
+        what: impl AsRef<str>,
+    ) {
         self.notify_id(
             &PULSE_DEFAULTS_ID,
-            hints! { "urgency" => Urgency::Low },
+            opts.icon(),
+            hints! { "urgency" => opts.urgency_or(Urgency::Low) },
             format!("Default {}", what.as_ref()),
             name,
             2_000,
@@ -155,9 +205,27 @@ impl<'a> NotificationsProxy<'a> {
         .await;
     }
 
+    pub async fn pulse_port_unplugged(
+        &self,
+        opts: &NotifyOptions,
+        port: impl AsRef<str>,
+        what: impl AsRef<str>,
+    ) {
+        self.notify(
+            None,
+            opts.icon(),
+            hints! { "urgency" => opts.urgency_or(Urgency::Normal) },
+            format!("{} unplugged", port.as_ref()),
+            format!("The active {} port was unplugged", what.as_ref()),
+            2_000,
+        )
+        .await;
+    }
+
     pub async fn ac_adapter(&self, plugged_in: bool) {
         self.notify(
             None,
+            "",
             hints! { "urgency" => Urgency::Low },
             "AC Adapter",
             if plugged_in {
@@ -174,6 +242,7 @@ impl<'a> NotificationsProxy<'a> {
     pub async fn battery_critical(&self, pct: u8) {
         self.notify_id(
             &BATTERY_NOTIFICATION_ID,
+            "",
             hints! { "urgency" => Urgency::Critical },
             "Critical Battery Warning!",
             format!("Remaining: {}%", pct),
@@ -183,9 +252,22 @@ impl<'a> NotificationsProxy<'a> {
         .await;
     }
 
+    /// Sent once when the battery's `critical_action` is triggered
+    pub async fn battery_critical_action(&self, pct: u8) {
+        self.notify(
+            None,
+            "",
+            hints! { "urgency" => Urgency::Critical },
+            "Battery Critically Low",
+            format!("Remaining: {}%, plug in a charger now", pct),
+            10_000,
+        )
+        .await;
+    }
+
     /// Use to disable a previously sent critical battery notification
     pub async fn battery_critical_off(&self) {
-        self.notify_id(&BATTERY_NOTIFICATION_ID, hints! {}, "", "", 1)
+        self.notify_id(&BATTERY_NOTIFICATION_ID, "", hints! {}, "", "", 1)
             .await;
     }
 }
@@ -0,0 +1,43 @@
+use zbus::proxy;
+use zbus::zvariant::OwnedObjectPath;
+
+#[proxy(
+    default_path = "/org/freedesktop/UPower",
+    default_service = "org.freedesktop.UPower",
+    interface = "org.freedesktop.UPower",
+    gen_blocking = false
+)]
+trait UPower {
+    fn enumerate_devices(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.UPower",
+    interface = "org.freedesktop.UPower.Device",
+    gen_blocking = false
+)]
+trait UPowerDevice {
+    #[zbus(property)]
+    fn native_path(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn model(&self) -> zbus::Result<String>;
+
+    /// See: https://upower.freedesktop.org/docs/Device.html#Device:Type
+    #[zbus(property, name = "Type")]
+    fn device_type(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+
+    /// See: https://upower.freedesktop.org/docs/Device.html#Device:State
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<u32>;
+
+    /// Rate of discharge or charge in watts
+    #[zbus(property)]
+    fn energy_rate(&self) -> zbus::Result<f64>;
+}
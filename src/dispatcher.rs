@@ -1,14 +1,38 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
 use futures::future::join_all;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
+use tokio_util::sync::CancellationToken;
 
 use crate::context::BarEvent;
 use crate::error::Result;
 
+/// Signals sent to an item within this window of each other are coalesced into one
+const SIGNAL_COALESCE_WINDOW: Duration = Duration::from_millis(100);
+
+/// Tracks the signals sent to an item, so bursts of them can be coalesced
+#[derive(Debug, Default)]
+struct SignalState {
+    /// When a signal was last delivered to the item
+    last_sent: Cell<Option<Instant>>,
+    /// Whether a signal is waiting to be delivered at the end of the current window
+    pending: Cell<bool>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Dispatcher {
     bar_senders: Vec<Option<Sender<BarEvent>>>,
     bar_updater: Sender<()>,
+    /// Cancelled to make the item at the same index restart
+    restart_tokens: Vec<CancellationToken>,
+    signals: Vec<Rc<SignalState>>,
+    /// Whether i3 is showing the bar, items and the bar pause while it's hidden
+    visible: Rc<watch::Sender<bool>>,
 }
 
 impl Dispatcher {
@@ -16,9 +40,17 @@ impl Dispatcher {
         Dispatcher {
             bar_senders: vec![None; capacity],
             bar_updater,
+            restart_tokens: (0..capacity).map(|_| CancellationToken::new()).collect(),
+            signals: (0..capacity).map(|_| Rc::default()).collect(),
+            visible: Rc::new(watch::channel(true).0),
         }
     }
 
+    /// Returns a receiver which tracks whether i3 is showing the bar
+    pub fn visibility(&self) -> watch::Receiver<bool> {
+        self.visible.subscribe()
+    }
+
     pub fn remove(&mut self, idx: usize) {
         self.bar_senders[idx] = None;
     }
@@ -27,6 +59,22 @@ impl Dispatcher {
         self.bar_senders[idx] = Some(tx);
     }
 
+    /// Returns a new token which is cancelled when the item at the given index should restart
+    pub fn restart_token(&mut self, idx: usize) -> CancellationToken {
+        self.restart_tokens[idx] = CancellationToken::new();
+        self.restart_tokens[idx].clone()
+    }
+
+    /// Restart the item at the given index, e.g.: after its configuration has changed
+    pub fn restart(&self, idx: usize) -> Result<()> {
+        match self.restart_tokens.get(idx) {
+            Some(token) => token.cancel(),
+            None => bail!("no item found with index: {}", idx),
+        }
+
+        Ok(())
+    }
+
     /// Tell the bar to manually emit an update
     pub async fn manual_bar_update(&self) -> Result<()> {
         self.bar_updater.send(()).await?;
@@ -35,11 +83,21 @@ impl Dispatcher {
 
     /// Send `BarEvent::Signal` to all bar items
     pub async fn signal_all(&self) -> Result<()> {
+        self.send_all(|| BarEvent::Signal).await
+    }
+
+    /// Pause or resume the bar, and send `BarEvent::Visibility` to all bar items
+    pub async fn visibility_all(&self, visible: bool) -> Result<()> {
+        self.visible.send_replace(visible);
+        self.send_all(|| BarEvent::Visibility(visible)).await
+    }
+
+    async fn send_all(&self, event: impl Fn() -> BarEvent) -> Result<()> {
         join_all(
             self.bar_senders
                 .iter()
                 .enumerate()
-                .filter_map(|(i, o)| o.as_ref().map(|_| self.send_bar_event(i, BarEvent::Signal))),
+                .filter_map(|(i, o)| o.as_ref().map(|_| self.send_bar_event(i, event()))),
         )
         .await
         .into_iter()
@@ -51,8 +109,63 @@ impl Dispatcher {
         Ok(())
     }
 
-    /// Send the given `BarEvent` to the item at the given index
+    /// Send the given `BarEvent` to each of the items at the given indices, e.g.: all items sharing
+    /// a name. The event is sent to every item even if some fail, and the first failure is returned.
+    pub async fn send_bar_event_all(
+        &self,
+        idxs: &[usize],
+        event: impl Fn() -> BarEvent,
+    ) -> Result<()> {
+        join_all(idxs.iter().map(|idx| self.send_bar_event(*idx, event())))
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Send the given `BarEvent` to the item at the given index.
+    /// Signals are coalesced: if the item was signalled within the last `SIGNAL_COALESCE_WINDOW`,
+    /// then a single signal is delivered at the end of the window instead. Other events are always
+    /// sent straight away.
     pub async fn send_bar_event(&self, idx: usize, ev: BarEvent) -> Result<()> {
+        match (&ev, self.signals.get(idx)) {
+            (BarEvent::Signal, Some(state)) => self.send_signal(idx, state.clone()).await,
+            _ => self.deliver(idx, ev).await,
+        }
+    }
+
+    async fn send_signal(&self, idx: usize, state: Rc<SignalState>) -> Result<()> {
+        let now = Instant::now();
+        match state.last_sent.get() {
+            Some(last) if now < last + SIGNAL_COALESCE_WINDOW => {
+                // there's already a signal waiting to be sent, so this one is merged with it
+                if state.pending.replace(true) {
+                    return Ok(());
+                }
+
+                let tx = match self.bar_senders.get(idx) {
+                    Some(Some(tx)) => tx.clone(),
+                    _ => bail!("item no longer receiving events, index: {}", idx),
+                };
+
+                tokio::task::spawn_local(async move {
+                    sleep_until(last + SIGNAL_COALESCE_WINDOW).await;
+                    state.pending.set(false);
+                    state.last_sent.set(Some(Instant::now()));
+                    if let Err(e) = tx.try_send(BarEvent::Signal) {
+                        log::warn!("failed to send signal to item[{}]: {}", idx, e);
+                    }
+                });
+
+                Ok(())
+            }
+            _ => {
+                state.last_sent.set(Some(now));
+                self.deliver(idx, BarEvent::Signal).await
+            }
+        }
+    }
+
+    async fn deliver(&self, idx: usize, ev: BarEvent) -> Result<()> {
         match self.bar_senders.get(idx) {
             Some(Some(tx)) => {
                 // if the channel fills up (the bar never reads click events), since this is a bounded channel
@@ -78,3 +191,100 @@ impl Dispatcher {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::i3::I3ClickEvent;
+    use crate::util::local_block_on;
+
+    fn dispatcher() -> (Dispatcher, mpsc::Receiver<BarEvent>) {
+        let (update_tx, _) = mpsc::channel(1);
+        let (event_tx, event_rx) = mpsc::channel(32);
+        let mut dispatcher = Dispatcher::new(update_tx, 1);
+        dispatcher.set(0, event_tx);
+        (dispatcher, event_rx)
+    }
+
+    fn drain(rx: &mut mpsc::Receiver<BarEvent>) -> Vec<BarEvent> {
+        let mut events = vec![];
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn coalesces_signal_bursts() {
+        let (dispatcher, mut rx) = dispatcher();
+        local_block_on(async {
+            for _ in 0..20 {
+                dispatcher
+                    .send_bar_event(0, BarEvent::Signal)
+                    .await
+                    .unwrap();
+            }
+
+            // the first signal is delivered straight away
+            assert_eq!(drain(&mut rx).len(), 1);
+
+            // and the rest are merged into one at the end of the window
+            tokio::time::sleep(SIGNAL_COALESCE_WINDOW * 2).await;
+            let events = drain(&mut rx);
+            assert_eq!(events.len(), 1);
+            assert!(matches!(events[0], BarEvent::Signal));
+
+            // once the window has passed, signals are delivered straight away again
+            tokio::time::sleep(SIGNAL_COALESCE_WINDOW * 2).await;
+            dispatcher
+                .send_bar_event(0, BarEvent::Signal)
+                .await
+                .unwrap();
+            assert_eq!(drain(&mut rx).len(), 1);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn clicks_are_not_coalesced() {
+        let (dispatcher, mut rx) = dispatcher();
+        local_block_on(async {
+            for _ in 0..5 {
+                let click = BarEvent::Click(I3ClickEvent::default());
+                dispatcher.send_bar_event(0, click).await.unwrap();
+            }
+
+            assert_eq!(drain(&mut rx).len(), 5);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn sends_to_all_given_items() {
+        let (update_tx, _) = mpsc::channel(1);
+        let mut dispatcher = Dispatcher::new(update_tx, 3);
+        let mut receivers = (0..3)
+            .map(|idx| {
+                let (tx, rx) = mpsc::channel(32);
+                dispatcher.set(idx, tx);
+                rx
+            })
+            .collect::<Vec<_>>();
+
+        local_block_on(async {
+            let click = || BarEvent::Click(I3ClickEvent::default());
+            dispatcher.send_bar_event_all(&[0, 2], click).await.unwrap();
+
+            let counts = receivers.iter_mut().map(|rx| drain(rx).len());
+            assert_eq!(counts.collect::<Vec<_>>(), vec![1, 0, 1]);
+
+            // the other items still receive the event if one of them fails
+            dispatcher.remove(0);
+            assert!(dispatcher.send_bar_event_all(&[0, 2], click).await.is_err());
+            assert_eq!(drain(&mut receivers[2]).len(), 1);
+        })
+        .unwrap();
+    }
+}
@@ -18,6 +18,31 @@ where
     Ok(validate(duration))
 }
 
+/// Format a duration in a compact human readable form, e.g.: `1d 2h 3m 4s`.
+/// Leading units which are zero are omitted.
+pub fn format(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let parts = [
+        (secs / 86_400, "d"),
+        (secs / 3_600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ];
+
+    let s = parts
+        .iter()
+        .skip_while(|(n, _)| *n == 0)
+        .map(|(n, unit)| format!("{}{}", n, unit))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if s.is_empty() {
+        "0s".into()
+    } else {
+        s
+    }
+}
+
 fn validate(duration: Duration) -> Duration {
     if duration.as_secs() == 0 {
         log::warn!(
@@ -29,3 +54,18 @@ fn validate(duration: Duration) -> Duration {
         duration
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_duration() {
+        assert_eq!(format(Duration::from_secs(0)), "0s");
+        assert_eq!(format(Duration::from_millis(999)), "0s");
+        assert_eq!(format(Duration::from_secs(42)), "42s");
+        assert_eq!(format(Duration::from_secs(60)), "1m 0s");
+        assert_eq!(format(Duration::from_secs(3_661)), "1h 1m 1s");
+        assert_eq!(format(Duration::from_secs(90_000)), "1d 1h 0m 0s");
+    }
+}
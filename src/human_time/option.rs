@@ -1,19 +1,102 @@
 //! We use `humantime_serde` for intervals defined in the configuration file, but we want to disallow
 //! any interval that's too low. So we hook into it here to override any intervals.
+//!
+//! Optional intervals may also be set to `"never"` (or left unset), which means the item only
+//! updates in response to events.
 
 use std::time::Duration;
 
 pub use humantime_serde::option::serialize;
 use humantime_serde::Serde;
+use serde::de::IntoDeserializer;
 use serde::{Deserialize, Deserializer};
 
 use super::validate;
 
+/// Sentinel value for an interval that never elapses
+pub const NEVER: &str = "never";
+
 pub fn deserialize<'a, D>(d: D) -> Result<Option<Duration>, D::Error>
 where
-    Serde<Duration>: Deserialize<'a>,
     D: Deserializer<'a>,
 {
-    let got: Option<Serde<Duration>> = Deserialize::deserialize(d)?;
-    Ok(got.map(|d| validate(Serde::into_inner(d))))
+    Ok(unclamped::deserialize(d)?.map(validate))
+}
+
+/// The same as the parent module, but intervals under a second are allowed. Only use this for items
+/// which are expected to run quickly, e.g. scripts.
+pub mod unclamped {
+    pub use super::serialize;
+    use super::*;
+
+    pub fn deserialize<'a, D>(d: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'a>,
+    {
+        let got: Option<String> = Deserialize::deserialize(d)?;
+        match got.as_deref().map(str::trim) {
+            None => Ok(None),
+            Some(s) if s.eq_ignore_ascii_case(NEVER) => Ok(None),
+            Some(s) => {
+                let duration = Serde::<Duration>::deserialize(s.into_deserializer())?;
+                Ok(Some(duration.into_inner()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_derive::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Interval {
+        #[serde(default, with = "super")]
+        interval: Option<Duration>,
+    }
+
+    fn parse(value: serde_json::Value) -> serde_json::Result<Option<Duration>> {
+        serde_json::from_value::<Interval>(value).map(|i| i.interval)
+    }
+
+    #[test]
+    fn never() {
+        assert_eq!(parse(json!({ "interval": "never" })).unwrap(), None);
+        assert_eq!(parse(json!({ "interval": "Never" })).unwrap(), None);
+        assert_eq!(parse(json!({ "interval": null })).unwrap(), None);
+        assert_eq!(parse(json!({})).unwrap(), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(
+            parse(json!({ "interval": "5s" })).unwrap(),
+            Some(Duration::from_secs(5))
+        );
+        // too low intervals are still clamped
+        assert_eq!(
+            parse(json!({ "interval": "0s" })).unwrap(),
+            Some(Duration::from_secs(1))
+        );
+        assert!(parse(json!({ "interval": "sometimes" })).is_err());
+    }
+
+    #[test]
+    fn unclamped() {
+        #[derive(Debug, Deserialize)]
+        struct Unclamped {
+            #[serde(default, with = "super::unclamped")]
+            interval: Option<Duration>,
+        }
+
+        let parse = |value| serde_json::from_value::<Unclamped>(value).map(|i| i.interval);
+        assert_eq!(
+            parse(json!({ "interval": "100ms" })).unwrap(),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(parse(json!({ "interval": "never" })).unwrap(), None);
+    }
 }
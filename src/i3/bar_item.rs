@@ -35,6 +35,24 @@ impl I3Markup {
     }
 }
 
+/// Escape text so it can be interpolated into pango markup. Only use this on the dynamic parts of
+/// the text (e.g. names from the system or network), not on an item's own markup.
+pub fn pango_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
 #[serde(untagged, rename_all = "lowercase")]
 pub enum I3MinWidth {
@@ -101,6 +119,10 @@ pub struct I3Item {
     #[serde(skip_serializing_if = "I3Markup::is_none")]
     markup: Option<I3Markup>,
 
+    /// Extra detail about the item, this is never sent to i3
+    #[serde(skip_serializing)]
+    detail: Option<String>,
+
     #[serde(flatten, skip_serializing_if = "HashMap::is_empty")]
     additional_data: HashMap<String, Value>,
 }
@@ -167,6 +189,7 @@ impl I3Item {
             separator: None,
             separator_block_width_px: None,
             markup: None,
+            detail: None,
             additional_data: HashMap::new(),
         }
     }
@@ -235,6 +258,11 @@ impl I3Item {
     impl_get_set!((separator, bool));
     impl_get_set!((separator_block_width_px, usize));
     impl_get_set!((markup, I3Markup));
+    impl_get_set!(
+        /// Set extra detail for the item. This isn't displayed normally, but is returned via ipc
+        /// and can be shown in place of `full_text` by clicking the item, see `Context`.
+        (detail, Option<String>)
+    );
 }
 
 #[async_trait(?Send)]
@@ -250,9 +278,20 @@ mod tests {
     use hex_color::HexColor;
     use serde_json::json;
 
-    use super::I3Item;
+    use super::{pango_escape, I3Item};
     use crate::i3::{I3Align, I3Markup, I3MinWidth};
 
+    #[test]
+    fn escape_pango_text() {
+        assert_eq!(pango_escape("plain text"), "plain text");
+        assert_eq!(
+            pango_escape(r#"<b>"Tom" & 'Jerry'</b>"#),
+            "&lt;b&gt;&quot;Tom&quot; &amp; &apos;Jerry&apos;&lt;/b&gt;"
+        );
+        // already escaped text is escaped again
+        assert_eq!(pango_escape("&amp;"), "&amp;amp;");
+    }
+
     #[test]
     fn as_env_map() {
         let item = I3Item::new("full_text")
@@ -272,7 +311,8 @@ mod tests {
             .separator_block_width_px(6)
             .separator(true)
             .short_text("short_text")
-            .urgent(false);
+            .urgent(false)
+            .detail("detail");
 
         assert_eq!(
             serde_json::to_value(item.as_env_map().unwrap()).unwrap(),
@@ -298,4 +338,13 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn detail_is_not_serialized() {
+        let item = I3Item::new("full_text").detail("detail");
+        assert_eq!(
+            serde_json::to_value(&item).unwrap(),
+            json!({ "full_text": "full_text" })
+        );
+    }
 }
@@ -1,5 +1,7 @@
 use serde_derive::Serialize;
 
+use crate::signals::{BAR_CONT_SIGNAL, BAR_STOP_SIGNAL};
+
 #[derive(Debug, Serialize)]
 pub struct I3BarHeader {
     version: usize,
@@ -15,8 +17,11 @@ impl Default for I3BarHeader {
     fn default() -> Self {
         I3BarHeader {
             version: 1,
-            stop_signal: None,
-            cont_signal: None,
+            // use our own signals rather than the default SIGSTOP/SIGCONT, so that we're not actually
+            // stopped when the bar is hidden: instead items are notified of the visibility change,
+            // and the bar stops refreshing items and printing updates until it's shown again
+            stop_signal: Some(BAR_STOP_SIGNAL as usize),
+            cont_signal: Some(BAR_CONT_SIGNAL as usize),
             click_events: Some(true),
         }
     }
@@ -4,12 +4,11 @@ use tokio::io::{stdin, AsyncBufReadExt, BufReader};
 
 use super::{I3ClickEvent, I3Item};
 use crate::bar::Bar;
-use crate::config::item::{Action, ActionWrapper, Actions};
+use crate::config::item::{Action, ActionWrapper};
 use crate::config::AppConfig;
 use crate::context::BarEvent;
 use crate::dispatcher::Dispatcher;
 use crate::error::Result;
-use crate::i3::I3Button;
 use crate::util::exec::exec;
 use crate::util::RcCell;
 
@@ -41,16 +40,12 @@ pub async fn handle_click_events(
         log::trace!("i3 click: {}", &line);
         let click = serde_json::from_str::<I3ClickEvent>(&line)?;
 
-        // parse bar item index from the "instance" property
+        // find the bar item from the "instance" property
         let idx = match click.instance.as_ref() {
-            Some(inst) => match inst.parse::<usize>() {
+            Some(inst) => match config.resolve_item(inst) {
                 Ok(i) => i,
                 Err(e) => {
-                    log::warn!(
-                        "failed to parse click 'instance' property: {}, error: {}",
-                        inst,
-                        e
-                    );
+                    log::warn!("failed to route click event: {}", e);
                     continue;
                 }
             },
@@ -64,26 +59,8 @@ pub async fn handle_click_events(
         };
 
         // handle any custom actions
-        if let Some(Actions {
-            left_click,
-            middle_click,
-            right_click,
-        }) = &config.items[idx].common.actions
-        {
-            let did_action = match click.button {
-                I3Button::Left => handle_actions(left_click.as_ref(), &click, &bar[idx]),
-                I3Button::Middle => handle_actions(middle_click.as_ref(), &click, &bar[idx]),
-                I3Button::Right => handle_actions(right_click.as_ref(), &click, &bar[idx]),
-                _ => false,
-            };
-
-            if did_action {
-                log::debug!(
-                    "not forwarding click event to item {} because custom action was run",
-                    idx
-                );
-                continue;
-            }
+        if run_custom_actions(&config, &bar, idx, &click) {
+            continue;
         }
 
         // send click event to the bar item
@@ -94,6 +71,29 @@ pub async fn handle_click_events(
     }
 }
 
+/// Runs any custom actions configured for the item at `idx` which match the click event.
+/// Returns `true` if an action was run, in which case the click shouldn't be forwarded to the item.
+pub fn run_custom_actions(config: &AppConfig, bar: &Bar, idx: usize, click: &I3ClickEvent) -> bool {
+    let actions = match config
+        .items
+        .get(idx)
+        .and_then(|i| i.common.actions.as_ref())
+    {
+        Some(actions) => actions,
+        None => return false,
+    };
+
+    let did_action = handle_actions(actions.for_button(click.button), click, &bar[idx]);
+    if did_action {
+        log::debug!(
+            "not forwarding click event to item {} because custom action was run",
+            idx
+        );
+    }
+
+    did_action
+}
+
 fn handle_actions(actions: Option<&ActionWrapper>, click: &I3ClickEvent, item: &I3Item) -> bool {
     let mut did_action = false;
     let actions = match actions {
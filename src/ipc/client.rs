@@ -1,24 +1,46 @@
 use std::io::ErrorKind;
 
+use serde_json::{json, Value};
 use tokio::net::UnixStream;
 use tokio::sync::oneshot;
 
+use crate::config::AppConfig;
 use crate::context::{BarEvent, CustomResponse};
 use crate::error::Result;
-use crate::ipc::protocol::{IpcBarEvent, IpcMessage, IpcReply, IpcResult, IPC_HEADER_LEN};
+use crate::i3::ipc::run_custom_actions;
+use crate::ipc::protocol::{
+    decode_ipc_header,
+    IpcBarEvent,
+    IpcErrorCode,
+    IpcMessage,
+    IpcReply,
+    IpcResult,
+    IPC_HEADER_LEN,
+    IPC_MAX_LEN,
+    IPC_VERSION,
+};
 use crate::ipc::server::send_ipc_response;
 use crate::ipc::IpcContext;
+use crate::logger::recent_logs;
+use crate::signals::{signal_map, validate_item_signal};
 use crate::theme::Theme;
 
 pub async fn handle_ipc_client(stream: UnixStream, ctx: IpcContext) -> Result<()> {
-    // first read the length header of the IPC message
+    // first read the version and length header of the IPC message
     let mut buf = [0; IPC_HEADER_LEN];
     loop {
         stream.readable().await?;
         match stream.try_read(&mut buf) {
             Ok(0) => break,
             Ok(IPC_HEADER_LEN) => {
-                let len = u64::from_le_bytes(buf);
+                let (version, len) = decode_ipc_header(&buf);
+                // check the header before reading the body, so a bad client can't make the bar
+                // read (or allocate) anything more
+                if let Some(reply) = check_ipc_header(version, len) {
+                    send_ipc_response(&stream, &reply).await?;
+                    break;
+                }
+
                 handle_ipc_request(&stream, ctx, len as usize).await?;
                 break;
             }
@@ -38,13 +60,40 @@ pub async fn handle_ipc_client(stream: UnixStream, ctx: IpcContext) -> Result<()
     Ok(())
 }
 
+/// Returns a failure to reply with if the client's message shouldn't be read
+fn check_ipc_header(version: u32, len: u64) -> Option<IpcReply> {
+    // reject clients speaking another version, rather than failing to deserialise their message
+    let (code, detail) = if version != IPC_VERSION {
+        (
+            IpcErrorCode::VersionMismatch,
+            format!(
+                "ipc protocol version mismatch, client sent {} but bar expects {}",
+                version, IPC_VERSION
+            ),
+        )
+    } else if len > IPC_MAX_LEN {
+        (
+            IpcErrorCode::MessageTooLarge,
+            format!(
+                "ipc message too large, client sent {} bytes but the most allowed is {}",
+                len, IPC_MAX_LEN
+            ),
+        )
+    } else {
+        return None;
+    };
+
+    log::warn!("{}", detail);
+    Some(IpcReply::Result(IpcResult::failure(code, detail)))
+}
+
 async fn handle_ipc_request(stream: &UnixStream, mut ctx: IpcContext, len: usize) -> Result<()> {
     // read ipc message entirely
     let mut buf = vec![0; len];
     let mut idx = 0;
     loop {
         stream.readable().await?;
-        match stream.try_read(&mut buf) {
+        match stream.try_read(&mut buf[idx..]) {
             Ok(0) => {
                 bail!(
                     "unexpected end of ipc stream, read {} bytes, expected: {}",
@@ -68,7 +117,7 @@ async fn handle_ipc_request(stream: &UnixStream, mut ctx: IpcContext, len: usize
     let msg = serde_json::from_slice::<IpcMessage>(&buf)?;
     match msg {
         IpcMessage::Shutdown => {
-            send_ipc_response(stream, &IpcReply::Result(IpcResult::Success(None))).await?;
+            send_ipc_response(stream, &IpcReply::Result(IpcResult::success())).await?;
             ctx.token.cancel();
         }
         IpcMessage::GetBar => {
@@ -82,6 +131,29 @@ async fn handle_ipc_request(stream: &UnixStream, mut ctx: IpcContext, len: usize
             let info = serde_json::to_value(ctx.config.item_idx_to_name())?;
             send_ipc_response(stream, &IpcReply::Value(info)).await?;
         }
+        IpcMessage::BarLayout => {
+            let names = ctx.config.item_idx_to_name();
+            let layout = ctx
+                .bar
+                .layout()
+                .into_iter()
+                .map(|(idx, visible)| {
+                    json!({
+                        "index": idx,
+                        "name": names[&idx],
+                        "visible": visible,
+                    })
+                })
+                .collect::<Vec<_>>();
+            send_ipc_response(stream, &IpcReply::Value(Value::Array(layout))).await?;
+        }
+        IpcMessage::Stats => {
+            let stats = ctx.stats.to_value(&ctx.config);
+            send_ipc_response(stream, &IpcReply::Value(stats)).await?;
+        }
+        IpcMessage::Logs { lines } => {
+            send_ipc_response(stream, &IpcReply::Value(json!(recent_logs(lines)))).await?;
+        }
         IpcMessage::GetConfig => {
             send_ipc_response(
                 stream,
@@ -89,6 +161,10 @@ async fn handle_ipc_request(stream: &UnixStream, mut ctx: IpcContext, len: usize
             )
             .await?;
         }
+        IpcMessage::ConfigSources => {
+            let sources = json!(ctx.config.sources());
+            send_ipc_response(stream, &IpcReply::Value(sources)).await?;
+        }
         IpcMessage::GetTheme => {
             send_ipc_response(
                 stream,
@@ -97,83 +173,276 @@ async fn handle_ipc_request(stream: &UnixStream, mut ctx: IpcContext, len: usize
             .await?;
         }
         IpcMessage::SetTheme(json) => {
-            let reply = match serde_json::from_value::<Theme>(json) {
+            let reply = match parse_theme(json) {
                 Ok(new) => {
                     ctx.config.theme = new;
-                    IpcReply::Result(IpcResult::Success(None))
+                    IpcReply::Result(IpcResult::success())
+                }
+                Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::InvalidTheme, e)),
+            };
+            send_ipc_response(stream, &reply).await?;
+            ctx.dispatcher.manual_bar_update().await?;
+        }
+        IpcMessage::ValidateTheme(json) => {
+            let reply = match parse_theme(json) {
+                Ok(_) => IpcReply::Result(IpcResult::success()),
+                Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::InvalidTheme, e)),
+            };
+            send_ipc_response(stream, &reply).await?;
+        }
+        IpcMessage::SetItemOrder(order) => {
+            let reply = match resolve_item_order(&ctx.config, &order) {
+                Ok(order) => match ctx.bar.set_order(order) {
+                    Ok(()) => IpcReply::Result(IpcResult::success()),
+                    Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::InvalidOrder, e)),
+                },
+                Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::ItemNotFound, e)),
+            };
+            send_ipc_response(stream, &reply).await?;
+            ctx.dispatcher.manual_bar_update().await?;
+        }
+        IpcMessage::PatchConfig(patch) => {
+            let reply = match ctx.config.patch(patch) {
+                Ok(new) => {
+                    let changed = ctx.config.changed_items(&new);
+                    *ctx.config = new;
+                    for idx in changed {
+                        ctx.dispatcher.restart(idx)?;
+                    }
+
+                    IpcReply::Result(IpcResult::success())
                 }
-                Err(e) => IpcReply::Result(IpcResult::Failure(e.to_string())),
+                Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::InvalidConfig, e)),
             };
             send_ipc_response(stream, &reply).await?;
             ctx.dispatcher.manual_bar_update().await?;
         }
+        IpcMessage::Wait {
+            target,
+            contains,
+            timeout,
+        } => {
+            let reply = match ctx.config.resolve_item(&target) {
+                Ok(idx) => {
+                    // subscribe before checking, so no updates are missed in between
+                    let mut updates = ctx.bar.subscribe();
+                    let matched = async {
+                        loop {
+                            if ctx.bar[idx].full_text.contains(&contains) {
+                                break ctx.bar[idx].clone();
+                            }
+
+                            // the bar lives as long as the program, so this should never fail
+                            if updates.changed().await.is_err() {
+                                futures::future::pending::<()>().await;
+                            }
+                        }
+                    };
+
+                    let item = match timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, matched).await.ok(),
+                        None => Some(matched.await),
+                    };
+
+                    match item {
+                        Some(item) => IpcReply::Value(serde_json::to_value(item)?),
+                        None => IpcReply::Result(IpcResult::failure(
+                            IpcErrorCode::Timeout,
+                            format!("item didn't contain {:?} in time", contains),
+                        )),
+                    }
+                }
+                Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::ItemNotFound, e)),
+            };
+            send_ipc_response(stream, &reply).await?;
+        }
+        IpcMessage::GetSignals => {
+            let names = ctx.config.item_idx_to_name();
+            let signals = signal_map(&ctx.config)
+                .into_iter()
+                .map(|(sig, indices)| {
+                    let items = indices
+                        .into_iter()
+                        .map(|idx| json!({ "index": idx, "name": names[&idx] }))
+                        .collect::<Vec<_>>();
+                    (sig.to_string(), Value::Array(items))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            send_ipc_response(stream, &IpcReply::Value(Value::Object(signals))).await?;
+        }
+        IpcMessage::SetSignal { instance, signal } => {
+            let reply = match ctx.config.resolve_item(&instance) {
+                Ok(idx) => match signal.map(validate_item_signal).transpose() {
+                    // signals are routed using the config, so this takes effect immediately
+                    Ok(_) => {
+                        ctx.config.items[idx].common.signal = signal;
+                        IpcReply::Result(IpcResult::success())
+                    }
+                    Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::InvalidSignal, e)),
+                },
+                Err(e) => IpcReply::Result(IpcResult::failure(IpcErrorCode::ItemNotFound, e)),
+            };
+            send_ipc_response(stream, &reply).await?;
+        }
         IpcMessage::RefreshAll => {
             ctx.dispatcher.signal_all().await?;
-            send_ipc_response(stream, &IpcReply::Result(IpcResult::Success(None))).await?;
+            send_ipc_response(stream, &IpcReply::Result(IpcResult::success())).await?;
+        }
+        IpcMessage::RestartItem { instance } => {
+            let reply = match ctx.config.resolve_item(&instance) {
+                Ok(idx) if ctx.config.disable.contains(&idx) => IpcResult::failure(
+                    IpcErrorCode::ItemDisabled,
+                    format!(
+                        "item {} ({}) can't be restarted since it's disabled in the config",
+                        idx,
+                        ctx.config.items[idx].name()
+                    ),
+                ),
+                // the item's task restarts it the same way as when its config changes
+                Ok(idx) => match ctx.dispatcher.restart(idx) {
+                    Ok(()) => IpcResult::success(),
+                    Err(e) => IpcResult::failure(IpcErrorCode::EventFailed, e),
+                },
+                Err(e) => IpcResult::failure(IpcErrorCode::ItemNotFound, e),
+            };
+            send_ipc_response(stream, &IpcReply::Result(reply)).await?;
         }
         IpcMessage::BarEvent { instance, event } => {
-            // NOTE: special considerations here for `instance`: if it's a number, then it maps to the item at the index
-            // otherwise, it's interpreted as a name and the first item with that name is chosen
-            let instance = match instance.parse::<usize>() {
-                // ipc message contained an index
+            // NOTE: if `instance` is a number, then it maps to the item at the index, otherwise
+            // it's interpreted as a name and the first item with that name is chosen
+            let instance = match ctx.config.resolve_item(&instance) {
                 Ok(idx) => idx,
                 Err(e) => {
-                    match ctx
-                        .config
-                        .item_idx_to_name()
-                        .iter()
-                        .find_map(
-                            |(idx, name)| {
-                                if instance == *name {
-                                    Some(idx)
-                                } else {
-                                    None
-                                }
-                            },
-                        ) {
-                        // ipc message contained a tag
-                        Some(idx) => *idx,
-                        // error
-                        None => {
-                            let err = format!("failed to parse ipc instance property: {}", e);
-                            log::warn!("{}", err);
-                            send_ipc_response(stream, &IpcReply::Result(IpcResult::Failure(err)))
-                                .await?;
-
-                            return Ok(());
-                        }
-                    }
+                    log::warn!("{}", e);
+                    let reply = IpcResult::failure(IpcErrorCode::ItemNotFound, e);
+                    send_ipc_response(stream, &IpcReply::Result(reply)).await?;
+                    return Ok(());
                 }
             };
 
             let (event, rx) = match event {
                 IpcBarEvent::Signal => (BarEvent::Signal, None),
-                IpcBarEvent::Click(click) => (BarEvent::Click(click), None),
-                IpcBarEvent::Custom(payload) => {
+                IpcBarEvent::Click(click) => {
+                    // custom actions take precedence, just like clicks coming from i3
+                    if run_custom_actions(&ctx.config, &ctx.bar, instance, &click) {
+                        send_ipc_response(stream, &IpcReply::Result(IpcResult::success())).await?;
+                        return Ok(());
+                    }
+
+                    (BarEvent::Click(click), None)
+                }
+                IpcBarEvent::Custom { args, plain } => {
                     let (responder, receiver) = oneshot::channel();
-                    (BarEvent::Custom { payload, responder }, Some(receiver))
+                    let event = BarEvent::Custom {
+                        payload: args,
+                        responder,
+                    };
+                    (event, Some((receiver, plain)))
                 }
             };
 
             let reply = match ctx.dispatcher.send_bar_event(instance, event).await {
                 Ok(()) => match rx {
-                    Some(rx) => match rx.await {
-                        Ok(CustomResponse::Help(help)) => IpcReply::Help(help.ansi().to_string()),
+                    Some((rx, plain)) => match rx.await {
+                        Ok(CustomResponse::Help(help)) => IpcReply::Help(match plain {
+                            true => help.to_string(),
+                            false => help.ansi().to_string(),
+                        }),
                         Ok(CustomResponse::Json(value)) => IpcReply::Value(value),
-                        Err(_) => IpcReply::Result(IpcResult::Failure(
-                            "bar item not listening for response".into(),
+                        Err(_) => IpcReply::Result(IpcResult::failure(
+                            IpcErrorCode::NotListening,
+                            "bar item not listening for response",
                         )),
                     },
-                    None => IpcReply::Result(IpcResult::Success(None)),
+                    None => IpcReply::Result(IpcResult::success()),
                 },
                 Err(e) => {
                     log::warn!("{}", e);
-                    IpcReply::Result(IpcResult::Failure(e.to_string()))
+                    IpcReply::Result(IpcResult::failure(IpcErrorCode::EventFailed, e))
                 }
             };
             send_ipc_response(stream, &reply).await?;
         }
+        IpcMessage::BarEventAll { name, event } => {
+            let mut targets = ctx.config.item_indices_with_name(&name);
+            let reply = match event {
+                _ if targets.is_empty() => IpcResult::failure(
+                    IpcErrorCode::ItemNotFound,
+                    format!("no items found with name: {}", name),
+                ),
+                IpcBarEvent::Custom { .. } => IpcResult::failure(
+                    IpcErrorCode::EventFailed,
+                    "custom events can only be sent to a single item",
+                ),
+                event => {
+                    // custom actions take precedence, just like clicks coming from i3
+                    if let IpcBarEvent::Click(ref click) = event {
+                        targets
+                            .retain(|idx| !run_custom_actions(&ctx.config, &ctx.bar, *idx, click));
+                    }
+
+                    let bar_event = || match &event {
+                        IpcBarEvent::Click(click) => BarEvent::Click(click.clone()),
+                        _ => BarEvent::Signal,
+                    };
+
+                    match ctx.dispatcher.send_bar_event_all(&targets, bar_event).await {
+                        Ok(()) => IpcResult::success(),
+                        Err(e) => {
+                            log::warn!("{}", e);
+                            IpcResult::failure(IpcErrorCode::EventFailed, e)
+                        }
+                    }
+                }
+            };
+            send_ipc_response(stream, &IpcReply::Result(reply)).await?;
+        }
     }
 
     Ok(())
 }
+
+/// Parse and validate a theme, the same checks are done when reading the config
+fn parse_theme(json: Value) -> Result<Theme> {
+    let mut theme = serde_json::from_value::<Theme>(json)?;
+    theme.resolve();
+    theme.validate()?;
+    Ok(theme)
+}
+
+/// Resolve a list of item indices or names into item indices.
+fn resolve_item_order(config: &AppConfig, order: &[Value]) -> Result<Vec<usize>> {
+    order
+        .iter()
+        .map(|value| match value {
+            Value::Number(n) => config.resolve_item(&n.to_string()),
+            Value::String(s) => config.resolve_item(s),
+            _ => bail!("failed to find item: {}", value),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse_valid_theme() {
+        let theme = parse_theme(json!({ "bg": "#000000", "fg": "#FFFFFF" })).unwrap();
+        assert_eq!(theme.bg, hex_color::HexColor::BLACK);
+
+        // the default theme should always be valid
+        assert!(parse_theme(serde_json::to_value(Theme::default()).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn parse_invalid_theme() {
+        // not a colour
+        assert!(parse_theme(json!({ "bg": "not a colour" })).is_err());
+        // fails validation
+        let powerline = json!([{ "fg": "#FFFFFF", "bg": "#000000" }]);
+        assert!(parse_theme(json!({ "powerline": powerline })).is_err());
+    }
+}
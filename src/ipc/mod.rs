@@ -10,6 +10,7 @@ use tokio_util::sync::CancellationToken;
 pub use self::server::{create_ipc_socket, handle_ipc_events};
 use crate::bar::Bar;
 use crate::config::AppConfig;
+use crate::context::RuntimeStats;
 use crate::dispatcher::Dispatcher;
 use crate::error::Result;
 use crate::util::RcCell;
@@ -20,6 +21,7 @@ pub struct IpcContext {
     token: CancellationToken,
     config: RcCell<AppConfig>,
     dispatcher: RcCell<Dispatcher>,
+    stats: RcCell<RuntimeStats>,
 }
 
 impl IpcContext {
@@ -28,12 +30,14 @@ impl IpcContext {
         token: CancellationToken,
         config: RcCell<AppConfig>,
         dispatcher: RcCell<Dispatcher>,
+        stats: RcCell<RuntimeStats>,
     ) -> IpcContext {
         IpcContext {
             bar,
             token,
             config,
             dispatcher,
+            stats,
         }
     }
 }
@@ -1,3 +1,6 @@
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_derive::Deserialize;
 use serde_json::Value;
@@ -5,29 +8,84 @@ use serde_json::Value;
 use crate::error::Result;
 use crate::i3::I3ClickEvent;
 
-pub const IPC_HEADER_LEN: usize = std::mem::size_of::<u64>();
+/// Version of the IPC protocol, bump this whenever messages change in an incompatible way
+pub const IPC_VERSION: u32 = 2;
+
+/// Header is a u32 of the protocol version, followed by a u64 of the body's length
+pub const IPC_HEADER_LEN: usize = std::mem::size_of::<u32>() + std::mem::size_of::<u64>();
+
+/// The largest body the bar accepts, this is plenty for even the biggest configs
+pub const IPC_MAX_LEN: u64 = 16 * 1024 * 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum IpcBarEvent {
     Click(I3ClickEvent),
     Signal,
-    Custom(Vec<String>),
+    Custom {
+        args: Vec<String>,
+        /// Reply with plain text help rather than ANSI, e.g. when it's not printed to a terminal
+        #[serde(default)]
+        plain: bool,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum IpcMessage {
     Info,
+    /// The items in the order they're displayed in the bar
+    BarLayout,
+    Stats,
     RefreshAll,
+    /// Stop an item and start it again from scratch, resetting its retries
+    RestartItem {
+        /// The index or name of the item
+        instance: String,
+    },
     GetBar,
     GetConfig,
+    /// The config files which were read, in the order they were merged
+    ConfigSources,
     GetTheme,
     SetTheme(Value),
+    /// Check a theme is valid, without applying it
+    ValidateTheme(Value),
+    /// Items (by index or name) to display first, the rest follow in their current order
+    SetItemOrder(Vec<Value>),
+    /// Apply a JSON merge patch to the config, restarting any items whose config changed
+    PatchConfig(Value),
+    /// The last `lines` lines that were logged, oldest first
+    Logs {
+        lines: usize,
+    },
     BarEvent {
         instance: String,
         event: IpcBarEvent,
     },
+    /// Send an event to every item with the given name, rather than just the first one.
+    /// Custom events aren't supported, since each item would reply to them.
+    BarEventAll {
+        name: String,
+        event: IpcBarEvent,
+    },
+    /// Which items are listening for each signal
+    GetSignals,
+    /// Change (or remove, if `None`) the signal an item listens for
+    SetSignal {
+        /// The index or name of the item
+        instance: String,
+        signal: Option<u32>,
+    },
+    /// Wait until an item's text contains `contains`, replying with the item once it does
+    Wait {
+        /// The index or name of the item
+        target: String,
+        contains: String,
+        /// If set, stop waiting after this long and reply with a `timeout` failure
+        #[serde(default, with = "humantime_serde")]
+        timeout: Option<Duration>,
+    },
     Shutdown,
 }
 
@@ -41,21 +99,111 @@ pub enum IpcReply {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-#[serde(rename_all = "snake_case", tag = "type", content = "detail")]
+#[serde(rename_all = "snake_case", tag = "type")]
 pub enum IpcResult {
-    Success(Option<String>),
-    Failure(String),
+    Success {
+        detail: Option<String>,
+    },
+    Failure {
+        code: IpcErrorCode,
+        /// Human readable description of the error
+        detail: String,
+    },
+}
+
+impl IpcResult {
+    pub fn success() -> IpcResult {
+        IpcResult::Success { detail: None }
+    }
+
+    pub fn failure(code: IpcErrorCode, detail: impl ToString) -> IpcResult {
+        IpcResult::Failure {
+            code,
+            detail: detail.to_string(),
+        }
+    }
+}
+
+/// Machine readable codes for failures, so scripts don't need to match on messages
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IpcErrorCode {
+    /// No item matched the given index or name
+    ItemNotFound,
+    /// The item was disabled by the config, so it isn't running
+    ItemDisabled,
+    /// The theme sent couldn't be parsed
+    InvalidTheme,
+    /// The item order sent wasn't valid
+    InvalidOrder,
+    /// The config patch sent couldn't be applied
+    InvalidConfig,
+    /// The item didn't respond to a custom event
+    NotListening,
+    /// The event couldn't be sent to the item
+    EventFailed,
+    /// The client and the bar speak different versions of the IPC protocol
+    VersionMismatch,
+    /// The condition being waited on wasn't met in time
+    Timeout,
+    /// The signal isn't within the range of realtime signals
+    InvalidSignal,
+    /// The message's body was longer than `IPC_MAX_LEN`
+    MessageTooLarge,
 }
 
 pub fn encode_ipc_msg<T: Serialize>(t: T) -> Result<Vec<u8>> {
     let msg = serde_json::to_vec(&t)?;
-    // header is a u64 of length
-    let mut payload = (msg.len() as u64).to_le_bytes().to_vec();
+    // header is a u32 of the protocol version
+    let mut payload = IPC_VERSION.to_le_bytes().to_vec();
+    // then a u64 of length
+    payload.extend((msg.len() as u64).to_le_bytes());
     // followed by bytes of the body encoded as json
     payload.extend(msg);
     Ok(payload)
 }
 
+/// Returns the protocol version and body length from an IPC header
+pub fn decode_ipc_header(header: &[u8; IPC_HEADER_LEN]) -> (u32, u64) {
+    let (version, len) = header.split_at(std::mem::size_of::<u32>());
+    (
+        u32::from_le_bytes(version.try_into().unwrap()),
+        u64::from_le_bytes(len.try_into().unwrap()),
+    )
+}
+
+/// Decode a whole IPC message, failing with a clear error if the sender's protocol version differs
+pub fn decode_ipc_msg<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let header = match bytes.get(..IPC_HEADER_LEN) {
+        Some(header) => header.try_into().unwrap(),
+        None => bail!(
+            "ipc message too short, read {} bytes, expected at least {}",
+            bytes.len(),
+            IPC_HEADER_LEN
+        ),
+    };
+
+    let (version, len) = decode_ipc_header(header);
+    if version != IPC_VERSION {
+        bail!(
+            "ipc protocol version mismatch, got {} but expected {}",
+            version,
+            IPC_VERSION
+        );
+    }
+
+    let body = &bytes[IPC_HEADER_LEN..];
+    if body.len() as u64 != len {
+        bail!(
+            "ipc message length mismatch, read {} bytes, expected {}",
+            body.len(),
+            len
+        );
+    }
+
+    Ok(serde_json::from_slice(body)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,16 +213,106 @@ mod tests {
         let bytes = encode_ipc_msg::<IpcMessage>(IpcMessage::Info).unwrap();
         let header = &bytes[0..IPC_HEADER_LEN];
         let body = &bytes[IPC_HEADER_LEN..];
-        assert_eq!(header, 6_u64.to_le_bytes());
+        assert_eq!(header[0..4], IPC_VERSION.to_le_bytes());
+        assert_eq!(header[4..], 6_u64.to_le_bytes());
         assert_eq!(body, br#""info""#);
     }
 
     #[test]
     fn test_encode_ipc_reply() {
-        let bytes = encode_ipc_msg::<IpcReply>(IpcReply::Result(IpcResult::Success(None))).unwrap();
+        let bytes = encode_ipc_msg::<IpcReply>(IpcReply::Result(IpcResult::success())).unwrap();
         let header = &bytes[0..IPC_HEADER_LEN];
         let body = &bytes[IPC_HEADER_LEN..];
-        assert_eq!(header, 43_u64.to_le_bytes());
+        assert_eq!(header[0..4], IPC_VERSION.to_le_bytes());
+        assert_eq!(header[4..], 43_u64.to_le_bytes());
         assert_eq!(body, br#"{"result":{"type":"success","detail":null}}"#);
     }
+
+    #[test]
+    fn test_decode_ipc_header() {
+        let bytes = encode_ipc_msg::<IpcMessage>(IpcMessage::Info).unwrap();
+        let header = bytes[0..IPC_HEADER_LEN].try_into().unwrap();
+        assert_eq!(decode_ipc_header(header), (IPC_VERSION, 6));
+    }
+
+    #[test]
+    fn test_decode_matching_version() {
+        let bytes = encode_ipc_msg::<IpcMessage>(IpcMessage::Stats).unwrap();
+        let msg = decode_ipc_msg::<IpcMessage>(&bytes).unwrap();
+        assert!(matches!(msg, IpcMessage::Stats));
+    }
+
+    #[test]
+    fn test_decode_mismatched_version() {
+        let mut bytes = encode_ipc_msg::<IpcMessage>(IpcMessage::Stats).unwrap();
+        bytes[0..4].copy_from_slice(&(IPC_VERSION + 1).to_le_bytes());
+        let err = decode_ipc_msg::<IpcMessage>(&bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "ipc protocol version mismatch, got {} but expected {}",
+                IPC_VERSION + 1,
+                IPC_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn test_decode_short_message() {
+        let err = decode_ipc_msg::<IpcMessage>(&[0; 4]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "ipc message too short, read 4 bytes, expected at least {}",
+                IPC_HEADER_LEN
+            )
+        );
+    }
+
+    #[test]
+    fn test_wait_timeout_under_a_second() {
+        let bytes = encode_ipc_msg(IpcMessage::Wait {
+            target: "0".into(),
+            contains: "foo".into(),
+            timeout: Some(Duration::from_millis(200)),
+        })
+        .unwrap();
+
+        // short timeouts aren't raised to a second like item intervals are
+        match decode_ipc_msg::<IpcMessage>(&bytes).unwrap() {
+            IpcMessage::Wait { timeout, .. } => {
+                assert_eq!(timeout, Some(Duration::from_millis(200)))
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_failure_shape() {
+        let value = serde_json::to_value(IpcReply::Result(IpcResult::failure(
+            IpcErrorCode::ItemNotFound,
+            "no item found",
+        )))
+        .unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "result": {
+                    "type": "failure",
+                    "code": "item_not_found",
+                    "detail": "no item found"
+                }
+            })
+        );
+
+        // and it should round trip
+        let reply = serde_json::from_value::<IpcReply>(value).unwrap();
+        assert_eq!(
+            reply,
+            IpcReply::Result(IpcResult::failure(
+                IpcErrorCode::ItemNotFound,
+                "no item found"
+            ))
+        );
+    }
 }
@@ -14,6 +14,7 @@ pub mod error;
 pub mod human_time;
 pub mod i3;
 pub mod ipc;
+pub mod logger;
 pub mod signals;
 pub mod theme;
 pub mod util;
@@ -0,0 +1,241 @@
+//! Wraps the usual `env_logger` so each item's logs can be filtered individually.
+//!
+//! Any of i3stat's own logs emitted while an item is running are re-targeted to
+//! `i3stat::item::<name>` (where `<name>` is the item's name, which defaults to its type). This means
+//! `RUST_LOG` can be used to filter logs per item, e.g.: `RUST_LOG=warn,i3stat::item::nic=debug`.
+//! Logs from dependencies keep their own targets, so they can still be filtered as usual.
+//! Each item may also set a `log_level` in its config, which overrides `RUST_LOG` for that item.
+//!
+//! The most recent logs are also kept in memory, so they can be fetched over IPC.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::config::AppConfig;
+use crate::error::Result;
+
+tokio::task_local! {
+    /// The log target of the item that's currently running
+    static ITEM_TARGET: String;
+}
+
+/// Per item log level overrides, keyed by log target. Set once the config has been read.
+static ITEM_LEVELS: OnceLock<HashMap<String, LevelFilter>> = OnceLock::new();
+
+/// How many log lines are kept in memory
+const LOG_BUFFER_LEN: usize = 1000;
+
+/// The most recent log lines, see `recent_logs`
+static LOG_BUFFER: Mutex<LogBuffer> = Mutex::new(LogBuffer::new(LOG_BUFFER_LEN));
+
+/// A bounded buffer of log lines, once full the oldest lines are dropped
+struct LogBuffer {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    const fn new(capacity: usize) -> LogBuffer {
+        LogBuffer {
+            lines: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// The last `n` lines, oldest first
+    fn last(&self, n: usize) -> Vec<String> {
+        let skip = self.lines.len().saturating_sub(n);
+        self.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Returns the last `n` log lines which were emitted, oldest first
+pub fn recent_logs(n: usize) -> Vec<String> {
+    match LOG_BUFFER.lock() {
+        Ok(buffer) => buffer.last(n),
+        Err(poisoned) => poisoned.into_inner().last(n),
+    }
+}
+
+fn buffer_record(record: &Record) {
+    let line = format!(
+        "{} {:<5} {} > {}",
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+        record.level(),
+        record.target(),
+        record.args()
+    );
+
+    match LOG_BUFFER.lock() {
+        Ok(mut buffer) => buffer.push(line),
+        Err(poisoned) => poisoned.into_inner().push(line),
+    }
+}
+
+/// The log target used for all logs emitted by an item
+pub fn item_log_target(name: impl AsRef<str>) -> String {
+    format!("{}::item::{}", env!("CARGO_CRATE_NAME"), name.as_ref())
+}
+
+/// Whether the log target is from this crate (rather than a dependency), only these are re-targeted
+fn is_own_target(target: &str) -> bool {
+    let name = env!("CARGO_CRATE_NAME");
+    target
+        .strip_prefix(name)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// Run the given future with its logs re-targeted to the item's log target
+pub async fn with_item_target<F: Future>(target: String, f: F) -> F::Output {
+    ITEM_TARGET.scope(target, f).await
+}
+
+struct ItemLogger {
+    /// Filtered by `RUST_LOG`
+    filtered: Box<dyn Log>,
+    /// Not filtered at all, used for items which have overridden their log level
+    unfiltered: Box<dyn Log>,
+}
+
+impl ItemLogger {
+    fn override_for(target: &str) -> Option<LevelFilter> {
+        ITEM_LEVELS
+            .get()
+            .and_then(|levels| levels.get(target))
+            .copied()
+    }
+}
+
+impl Log for ItemLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        match Self::override_for(metadata.target()) {
+            Some(level) => metadata.level() <= level,
+            None => self.filtered.enabled(metadata),
+        }
+    }
+
+    fn log(&self, record: &Record) {
+        if !is_own_target(record.target()) {
+            return self.log_record(record);
+        }
+
+        // NOTE: this fails outside of a tokio task, or when not running within an item's task
+        match ITEM_TARGET.try_with(|target| target.clone()) {
+            Ok(target) => self.log_record(&record.to_builder().target(&target).build()),
+            Err(_) => self.log_record(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.filtered.flush();
+    }
+}
+
+impl ItemLogger {
+    fn log_record(&self, record: &Record) {
+        match Self::override_for(record.target()) {
+            Some(level) if record.level() <= level => {
+                buffer_record(record);
+                self.unfiltered.log(record);
+            }
+            Some(_) => {}
+            None if self.filtered.enabled(record.metadata()) => {
+                buffer_record(record);
+                self.filtered.log(record);
+            }
+            None => {}
+        }
+    }
+}
+
+/// Setup the global logger, this should be called as early as possible
+pub fn init() -> Result<()> {
+    let filtered = pretty_env_logger::formatted_timed_builder()
+        .parse_default_env()
+        .build();
+    let unfiltered = pretty_env_logger::formatted_timed_builder()
+        .filter_level(LevelFilter::Trace)
+        .build();
+
+    let max_level = filtered.filter();
+    log::set_boxed_logger(Box::new(ItemLogger {
+        filtered: Box::new(filtered),
+        unfiltered: Box::new(unfiltered),
+    }))?;
+    log::set_max_level(max_level);
+
+    Ok(())
+}
+
+/// Apply any log level overrides from the config, this can only be done once
+pub fn set_item_levels(config: &AppConfig) {
+    let levels = config
+        .items
+        .iter()
+        .filter_map(|item| {
+            item.common
+                .log_level
+                .map(|level| (item_log_target(item.name()), level))
+        })
+        .collect::<HashMap<_, _>>();
+
+    // make sure the logging macros don't skip logs from items which have increased their level
+    if let Some(max) = levels.values().max() {
+        log::set_max_level(log::max_level().max(*max));
+    }
+
+    if ITEM_LEVELS.set(levels).is_err() {
+        log::warn!("item log levels were already set, ignoring");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_target() {
+        assert_eq!(item_log_target("nic"), "i3stat::item::nic");
+        assert_eq!(item_log_target("my item"), "i3stat::item::my item");
+    }
+
+    #[test]
+    fn own_targets() {
+        assert!(is_own_target("i3stat"));
+        assert!(is_own_target("i3stat::bar_items::nic"));
+        assert!(!is_own_target("i3stat_other"));
+        assert!(!is_own_target("reqwest::connect"));
+        assert!(!is_own_target("zbus"));
+    }
+
+    #[test]
+    fn log_buffer_is_bounded() {
+        let mut buffer = LogBuffer::new(3);
+        assert_eq!(buffer.last(10), Vec::<String>::new());
+
+        for i in 0..5 {
+            buffer.push(i.to_string());
+        }
+        assert_eq!(buffer.last(10), vec!["2", "3", "4"]);
+        assert_eq!(buffer.last(2), vec!["3", "4"]);
+        assert_eq!(buffer.last(0), Vec::<String>::new());
+
+        let mut buffer = LogBuffer::new(0);
+        buffer.push("ignored".into());
+        assert_eq!(buffer.last(1), Vec::<String>::new());
+    }
+}
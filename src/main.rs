@@ -1,22 +1,37 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+#[cfg(debug_assertions)]
+use std::env;
+#[cfg(debug_assertions)]
+use std::future::Future;
 use std::process;
+use std::time::Duration;
 
 use clap::Parser;
 use i3stat::bar::Bar;
 use i3stat::cli::Cli;
+use i3stat::config::schema::schema;
 use i3stat::config::AppConfig;
-use i3stat::context::{Context, SharedState, StopAction};
+use i3stat::context::{catch_item_panic, Context, RuntimeStats, SharedState, StopAction};
 use i3stat::dispatcher::Dispatcher;
 use i3stat::error::Result;
 use i3stat::i3::header::I3BarHeader;
 use i3stat::i3::ipc::handle_click_events;
-use i3stat::i3::I3Item;
+use i3stat::i3::{I3Item, I3Markup};
 use i3stat::ipc::{create_ipc_socket, handle_ipc_events, IpcContext};
+use i3stat::logger::{self, item_log_target, with_item_target};
 use i3stat::signals::handle_signals;
-use i3stat::util::{local_block_on, RcCell, UrgentTimer};
+use i3stat::theme::rainbow;
+use i3stat::util::format::truncate;
+use i3stat::util::{local_block_on, OutputFile, RcCell, UrgentTimer};
 use tokio::sync::mpsc::{self, Receiver};
-use tokio::time::Instant;
+use tokio::sync::watch;
+use tokio::time::{sleep, Instant};
 use tokio_util::sync::CancellationToken;
 
+/// How long to wait for every item to send its first update when `wait_for_all` is set
+const WAIT_FOR_ALL_TIMEOUT: Duration = Duration::from_secs(2);
+
 enum RuntimeStopReason {
     Shutdown,
 }
@@ -32,9 +47,13 @@ fn main() {
 }
 
 fn start_runtime() -> Result<RuntimeStopReason> {
-    pretty_env_logger::try_init_timed()?;
+    logger::init()?;
 
     let args = Cli::parse();
+    if args.print_schema {
+        println!("{}", serde_json::to_string_pretty(&schema()?)?);
+        return Ok(RuntimeStopReason::Shutdown);
+    }
 
     let (result, runtime) = local_block_on(async_main(args))?;
 
@@ -51,15 +70,19 @@ fn start_runtime() -> Result<RuntimeStopReason> {
 
 async fn async_main(args: Cli) -> Result<RuntimeStopReason> {
     let config = RcCell::new(AppConfig::read(args).await?);
+    logger::set_item_levels(&config);
 
     // create socket first, so it's ready before anything is written to stdout
     let socket = create_ipc_socket(&config).await?;
 
+    // runtime metrics, shared between the item tasks and ipc
+    let stats = RuntimeStats::new(config.items.len());
+
     // create i3 bar and spawn tasks for each bar item
-    let (bar, dispatcher) = setup_i3_bar(&config)?;
+    let (bar, dispatcher) = setup_i3_bar(&config, &stats)?;
 
     // handle incoming signals
-    let signal_handle = handle_signals(config.clone(), dispatcher.clone())?;
+    let signal_handle = handle_signals(config.clone(), dispatcher.clone(), bar.clone())?;
 
     // used to handle app shutdown
     let token = CancellationToken::new();
@@ -70,6 +93,7 @@ async fn async_main(args: Cli) -> Result<RuntimeStopReason> {
         token.clone(),
         config.clone(),
         dispatcher.clone(),
+        stats,
     );
 
     // handle our inputs: i3's IPC and our own IPC
@@ -84,12 +108,12 @@ async fn async_main(args: Cli) -> Result<RuntimeStopReason> {
     result
 }
 
-fn setup_i3_bar(config: &RcCell<AppConfig>) -> Result<(RcCell<Bar>, RcCell<Dispatcher>)> {
+fn setup_i3_bar(
+    config: &RcCell<AppConfig>,
+    stats: &RcCell<RuntimeStats>,
+) -> Result<(RcCell<Bar>, RcCell<Dispatcher>)> {
     let item_count = config.items.len();
 
-    // shared state
-    let state = SharedState::new();
-
     // A list of items which represents the i3 bar
     let bar = RcCell::new(Bar::new(item_count));
 
@@ -97,101 +121,182 @@ fn setup_i3_bar(config: &RcCell<AppConfig>) -> Result<(RcCell<Bar>, RcCell<Dispa
     let (update_tx, update_rx) = mpsc::channel(1);
     let dispatcher = RcCell::new(Dispatcher::new(update_tx, item_count));
 
+    // shared state
+    let state = SharedState::new(dispatcher.visibility());
+
     // Used by items to send updates back to the bar
     let (item_tx, item_rx) = mpsc::channel(item_count + 1);
 
     // Iterate config and create bar items
-    for (idx, item) in config.items.iter().enumerate() {
+    for idx in 0..item_count {
         if config.disable.contains(&idx) {
             log::info!("not creating item {idx} since it was disabled by config");
             continue;
         }
 
-        let bar_item = item.to_bar_item();
-
-        // all cheaply cloneable (smart pointers, senders, etc)
-        let mut bar = bar.clone();
-        let state = state.clone();
-        let config = config.clone();
-        let item_tx = item_tx.clone();
-        let mut dispatcher = dispatcher.clone();
-
-        tokio::task::spawn_local(async move {
-            let mut retries = 0;
-            let mut last_start;
-            loop {
-                last_start = Instant::now();
-                let (event_tx, event_rx) = mpsc::channel(32);
-                dispatcher.set(idx, event_tx);
-
-                let ctx = Context::new(
-                    config.clone(),
-                    state.clone(),
-                    item_tx.clone(),
-                    event_rx,
-                    idx,
-                );
-
-                let fut = bar_item.start(ctx);
-                match fut.await {
-                    Ok(StopAction::Restart) => {
-                        // reset retries if no retries have occurred in the last 5 minutes
-                        if last_start.elapsed().as_secs() > 60 * 5 {
-                            retries = 0;
-                        }
+        spawn_item(
+            idx,
+            config.clone(),
+            state.clone(),
+            dispatcher.clone(),
+            bar.clone(),
+            item_tx.clone(),
+            stats.clone(),
+        );
+    }
 
-                        // restart if we haven't exceeded limit
-                        if retries < 3 {
-                            log::warn!("item[{}] requested restart...", idx);
-                            retries += 1;
-                            continue;
-                        }
+    // setup listener for handling item updates and printing the bar to STDOUT
+    handle_item_updates(
+        config.clone(),
+        item_rx,
+        update_rx,
+        dispatcher.visibility(),
+        bar.clone(),
+    )?;
 
-                        // we exceeded the limit, so error out
-                        log::error!("item[{}] stopped, exceeded max retries", idx);
-                        let theme = config.theme.clone();
-                        bar[idx] = I3Item::new("MAX RETRIES")
-                            .color(theme.bg)
-                            .background_color(theme.red);
+    Ok((bar, dispatcher))
+}
 
-                        break;
-                    }
-                    // since this item has terminated, remove its entry from the bar
-                    action @ Ok(StopAction::Complete) | action @ Ok(StopAction::Remove) => {
-                        log::info!("item[{}] finished running", idx);
-                        dispatcher.remove(idx);
-
-                        // Remove this item if requested
-                        if matches!(action, Ok(StopAction::Remove)) {
-                            // NOTE: wait for all tasks in queue so any remaining item updates are flushed and processed
-                            // before we set it for the last time here
-                            tokio::task::yield_now().await;
-                            // replace with an empty item
-                            bar[idx] = I3Item::empty();
-                        }
+/// Run the item at the given index in its own task. The item is restarted if it asks to be (or
+/// fails, if it's configured to), and whenever it's restarted via the dispatcher.
+fn spawn_item(
+    idx: usize,
+    config: RcCell<AppConfig>,
+    state: RcCell<SharedState>,
+    mut dispatcher: RcCell<Dispatcher>,
+    mut bar: RcCell<Bar>,
+    item_tx: mpsc::Sender<(I3Item, usize)>,
+    mut stats: RcCell<RuntimeStats>,
+) {
+    tokio::task::spawn_local(async move {
+        let mut retries = 0;
+        let mut last_start;
+        loop {
+            last_start = Instant::now();
+            let (event_tx, event_rx) = mpsc::channel(32);
+            dispatcher.set(idx, event_tx);
+            let restart = dispatcher.restart_token(idx);
+
+            // (re)create the item each time, since its config may have changed
+            let bar_item = config.items[idx].to_bar_item();
+
+            let ctx = Context::new(
+                config.clone(),
+                state.clone(),
+                item_tx.clone(),
+                event_rx,
+                idx,
+                &config.items[idx].common,
+            );
+
+            let start = bar_item.start(ctx);
+            #[cfg(debug_assertions)]
+            let start = panic_if_requested(config.items[idx].name().clone(), start);
+
+            // all logs emitted while the item is running use the item's log target
+            let target = item_log_target(config.items[idx].name());
+            // a panicking item is treated as an error, so it's shown in the bar and retried
+            let fut = catch_item_panic(with_item_target(target, start));
+            let result = tokio::select! {
+                result = fut => result,
+                () = restart.cancelled() => {
+                    log::info!(
+                        "item[{}] restarting since its config changed or it was restarted",
+                        idx
+                    );
+                    retries = 0;
+                    continue;
+                }
+            };
+
+            if let Err(e) = &result {
+                log::error!("item[{}] exited with error: {}", idx, e);
+            }
+
+            // items are restarted if they request it, or if they fail and are configured to
+            let retry = config.items[idx].common.retry.clone();
+            let should_retry = match &result {
+                Ok(StopAction::Restart) => true,
+                Err(_) => retry.errors,
+                _ => false,
+            };
+
+            if should_retry {
+                // reset retries if the item ran for a while before stopping
+                if last_start.elapsed() > retry.reset_after {
+                    retries = 0;
+                }
 
-                        break;
+                // restart if we haven't exceeded limit
+                if retries < retry.max_retries {
+                    let delay = retry.delay(retries);
+                    log::warn!("item[{}] restarting in {:?}...", idx, delay);
+                    retries += 1;
+                    stats.item_restarted(idx);
+
+                    tokio::select! {
+                        () = sleep(delay) => {}
+                        () = restart.cancelled() => retries = 0,
                     }
-                    // unexpected error, log and display an error block
-                    Err(e) => {
-                        log::error!("item[{}] exited with error: {}", idx, e);
-                        // replace with an error item
-                        let theme = config.theme.clone();
-                        bar[idx] = I3Item::new(format!("ERROR({})", config.items[idx].name()))
-                            .color(theme.bg)
-                            .background_color(theme.red)
-                            .instance(idx.to_string());
-                        break;
+                    continue;
+                }
+
+                log::error!("item[{}] stopped, exceeded max retries", idx);
+            }
+
+            match result {
+                Ok(StopAction::Restart) => {
+                    // we exceeded the limit, so error out
+                    let theme = &config.theme;
+                    bar[idx] = I3Item::new("MAX RETRIES")
+                        .color(theme.bg)
+                        .background_color(theme.red);
+                }
+                // since this item has terminated, remove its entry from the bar
+                action @ Ok(StopAction::Complete) | action @ Ok(StopAction::Remove) => {
+                    log::info!("item[{}] finished running", idx);
+                    dispatcher.remove(idx);
+
+                    // Remove this item if requested
+                    if matches!(action, Ok(StopAction::Remove)) {
+                        // NOTE: wait for all tasks in queue so any remaining item updates are flushed and processed
+                        // before we set it for the last time here
+                        tokio::task::yield_now().await;
+                        // replace with an empty item
+                        bar[idx] = I3Item::empty();
                     }
                 }
+                // unexpected error, display an error block
+                Err(_) => {
+                    // replace with an error item
+                    let theme = &config.theme;
+                    bar[idx] = I3Item::new(format!("ERROR({})", config.items[idx].name()))
+                        .color(theme.bg)
+                        .background_color(theme.red)
+                        .instance(idx.to_string());
+                }
             }
-        });
-    }
 
-    // setup listener for handling item updates and printing the bar to STDOUT
-    handle_item_updates(config.clone(), item_rx, update_rx, bar.clone())?;
+            // the item has stopped, but it's started again if its config changes or it's restarted
+            restart.cancelled().await;
+            log::info!(
+                "item[{}] restarting since its config changed or it was restarted",
+                idx
+            );
+            retries = 0;
+        }
+    });
+}
 
-    Ok((bar, dispatcher))
+/// Panics instead of starting the item if it's named in `I3STAT_TEST_PANIC`, so the tests can check
+/// how panicking items are handled. This is only in debug builds.
+#[cfg(debug_assertions)]
+async fn panic_if_requested<F: Future>(name: String, start: F) -> F::Output {
+    if env::var("I3STAT_TEST_PANIC").map_or(false, |target| target == name) {
+        panic!("{} was asked to panic", name);
+    }
+
+    start.await
 }
 
 // task to manage updating the bar and printing it as JSON
@@ -199,6 +304,7 @@ fn handle_item_updates(
     config: RcCell<AppConfig>,
     mut item_rx: Receiver<(I3Item, usize)>,
     mut update_rx: Receiver<()>,
+    mut visible: watch::Receiver<bool>,
     mut bar: RcCell<Bar>,
 ) -> Result<()> {
     // output first parts of the i3 bar protocol - the header
@@ -207,11 +313,38 @@ fn handle_item_updates(
     println!("[");
 
     tokio::task::spawn_local(async move {
-        let item_names = config.item_idx_to_name();
-        let mut urgent_timer = UrgentTimer::new();
+        // NOTE: cloned since the config may be replaced at runtime (see `IpcMessage::PatchConfig`)
+        let item_names = config.item_idx_to_name().clone();
+        let mut urgent_timer = UrgentTimer::new(config.theme.urgent_flash_interval);
+        let mut output_file = config.output_file.clone().map(OutputFile::new);
+
+        // track when each item last sent an update, so stale items can be marked
+        let stale_after = config
+            .items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| match config.disable.contains(&idx) {
+                true => None,
+                false => item.stale_after(),
+            })
+            .collect::<Vec<_>>();
+        let mut last_updates = vec![Instant::now(); config.items.len()];
+        let mut stale_check = tokio::time::interval(Duration::from_secs(1));
+
+        // the items which haven't sent their first update yet, if the bar should wait for them
+        let mut pending = config.wait_for_all.then(|| {
+            (0..config.items.len())
+                .filter(|idx| !config.disable.contains(idx))
+                .collect::<HashSet<_>>()
+        });
+        let pending_timeout = sleep(WAIT_FOR_ALL_TIMEOUT);
+        tokio::pin!(pending_timeout);
+
         loop {
-            // enable urgent timer if any item is urgent
-            urgent_timer.toggle(bar.any_urgent());
+            // enable urgent timer if any item is urgent (the theme may change, so refresh it too)
+            urgent_timer.set_interval(config.theme.urgent_flash_interval);
+            // (there's no need to flash while the bar is hidden)
+            urgent_timer.toggle(config.theme.urgent_flash && bar.any_urgent() && *visible.borrow());
 
             tokio::select! {
                 // the urgent timer triggered, so update the timer and start it again
@@ -219,20 +352,75 @@ fn handle_item_updates(
                 () = urgent_timer.wait() => urgent_timer.reset(),
                 // a manual update was requested
                 Some(()) = update_rx.recv() => {}
+                // the bar was shown or hidden, when it's shown it's printed with any updates which
+                // were received while it was hidden
+                Ok(()) = visible.changed() => {}
+                // stop waiting for items which are taking too long to start
+                () = &mut pending_timeout, if pending.is_some() => {
+                    log::warn!(
+                        "items {:?} didn't update within {:?}, printing the bar anyway",
+                        pending.take().unwrap_or_default(),
+                        WAIT_FOR_ALL_TIMEOUT
+                    );
+                }
+                // periodically check if any items have stopped updating
+                _ = stale_check.tick(), if config.staleness => {
+                    let mut changed = false;
+                    for (idx, stale_after) in stale_after.iter().enumerate() {
+                        if let Some(stale_after) = stale_after {
+                            let is_stale = last_updates[idx].elapsed() > *stale_after;
+                            changed |= bar.set_stale(idx, is_stale);
+                        }
+                    }
+
+                    if !changed {
+                        continue;
+                    }
+                }
                 // an item is requesting an update, update the bar state
                 Some((i3_item, idx)) = item_rx.recv() => {
+                    last_updates[idx] = Instant::now();
+                    let was_stale = bar.set_stale(idx, false);
+                    let was_pending = pending.as_mut().map_or(false, |p| p.remove(&idx));
+
                     let mut i3_item = i3_item
                         // the name of the item
                         .name(item_names[idx].clone())
                         // always override the bar item's `instance`, since we track that ourselves
                         .instance(idx.to_string());
 
+                    // apply any global defaults the item hasn't set itself
+                    if let (None, Some(separator)) = (i3_item.get_separator(), config.separator) {
+                        i3_item = i3_item.separator(separator);
+                    }
+                    if let (None, Some(width)) = (
+                        i3_item.get_separator_block_width_px(),
+                        config.separator_block_width,
+                    ) {
+                        i3_item = i3_item.separator_block_width_px(width);
+                    }
+                    if let (None, Some(markup)) = (i3_item.get_markup(), config.markup) {
+                        i3_item = i3_item.markup(markup);
+                    }
+
                     if let Some(separator) = config.items[idx].common.separator {
                         i3_item = i3_item.separator(separator);
                     }
 
+                    if let Some(max_width) = config.items[idx].common.max_width {
+                        let pango = matches!(i3_item.get_markup(), Some(I3Markup::Pango));
+                        i3_item.full_text = truncate(&i3_item.full_text, max_width, pango);
+                    }
+
+                    if config.items[idx].common.rainbow == Some(true) {
+                        let pango = matches!(i3_item.get_markup(), Some(I3Markup::Pango));
+                        let colors = config.theme.rainbow_colors();
+                        i3_item.full_text = rainbow(&i3_item.full_text, &colors, pango);
+                        i3_item = i3_item.markup(I3Markup::Pango);
+                    }
+
                     // don't bother doing anything if the item hasn't changed
-                    if bar[idx] == i3_item {
+                    if bar[idx] == i3_item && !was_stale && !was_pending {
                         log::trace!("not updating item {} because it hasn't changed", idx);
                         continue;
                     }
@@ -242,17 +430,40 @@ fn handle_item_updates(
                 }
             }
 
-            // style urgent colours differently based on the urgent_timer's status
-            let mut theme = config.theme.clone();
-            if urgent_timer.swapped() {
-                theme.urgent_bg = config.theme.urgent_fg;
-                theme.urgent_fg = config.theme.urgent_bg;
+            // don't print anything until every item has sent its first update
+            if let Some(items) = &pending {
+                if !items.is_empty() {
+                    continue;
+                }
+
+                pending = None;
+            }
+
+            // don't print anything while i3 has hidden the bar, the items are still updated so the
+            // latest state is printed when it's shown again
+            if !*visible.borrow_and_update() {
+                continue;
             }
 
+            // style urgent colours differently based on the urgent_timer's status, only copying the
+            // theme when it's flashed since this runs for every update
+            let theme = if urgent_timer.swapped() {
+                Cow::Owned(config.theme.flashed())
+            } else {
+                Cow::Borrowed(&config.theme)
+            };
+
             // print bar to STDOUT for i3
             match bar.to_json(&theme) {
                 // make sure to include the trailing comma `,` as part of the protocol
-                Ok(json) => println!("{},", json),
+                Ok(json) => {
+                    println!("{},", json);
+                    if let Some(output_file) = output_file.as_mut() {
+                        output_file.write_frame(&json);
+                    }
+
+                    bar.notify_updated();
+                }
                 // on any serialisation error, emit an error that will be drawn to the status bar
                 Err(e) => {
                     log::error!("failed to serialise bar to json: {}", e);
@@ -1,52 +1,112 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use libc::{SIGRTMAX, SIGRTMIN, SIGTERM};
+use libc::{SIGRTMAX, SIGRTMIN, SIGTERM, SIGUSR1, SIGUSR2};
+use serde_json::json;
 use signal_hook_tokio::{Handle, Signals};
 
+use crate::bar::Bar;
 use crate::config::AppConfig;
 use crate::context::BarEvent;
 use crate::dispatcher::Dispatcher;
 use crate::error::Result;
 use crate::util::RcCell;
 
+/// Sent by i3 when the bar is hidden (see `stop_signal` in the i3bar protocol header)
+pub const BAR_STOP_SIGNAL: i32 = SIGUSR1;
+/// Sent by i3 when the bar is visible again (see `cont_signal` in the i3bar protocol header)
+pub const BAR_CONT_SIGNAL: i32 = SIGUSR2;
+/// Dumps the current bar and config to a file, useful for debugging. This is the last realtime
+/// signal, since the standard signals already have other uses (e.g. `SIGQUIT` from a terminal).
+pub fn dump_signal() -> i32 {
+    SIGRTMAX()
+}
+
+/// Item signals are offsets from `SIGRTMIN`, this is the largest one which is valid (the last
+/// realtime signal is reserved for `dump_signal`)
+pub fn max_item_signal() -> u32 {
+    (dump_signal() - 1 - SIGRTMIN()) as u32
+}
+
+/// Check an item's signal maps to a realtime signal
+pub fn validate_item_signal(sig: u32) -> Result<()> {
+    let max = max_item_signal();
+    if sig > max {
+        bail!(
+            "Invalid signal: {}. Valid signals range from 0 up to {} inclusive",
+            sig,
+            max
+        );
+    }
+
+    Ok(())
+}
+
+/// The indices of the items listening for each signal. This is read from the config each time a
+/// signal arrives, so any changes made at runtime (e.g. via ipc) take effect straight away.
+pub fn signal_map(config: &AppConfig) -> BTreeMap<u32, Vec<usize>> {
+    let mut map: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+    for (idx, item) in config.items.iter().enumerate() {
+        if let Some(sig) = item.common.signal {
+            map.entry(sig).or_default().push(idx);
+        }
+    }
+
+    map
+}
+
+/// Write the current bar and config to a file in `$XDG_RUNTIME_DIR` (or the temp dir if that's not
+/// set), and return its path.
+async fn dump_state(config: &AppConfig, bar: &mut Bar) -> Result<PathBuf> {
+    let dir = env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = dir.join(format!(
+        "i3stat-dump-{}-{}.json",
+        std::process::id(),
+        timestamp
+    ));
+
+    // same as the `get-bar` and `get-config` ipc messages
+    let dump = json!({
+        "bar": bar.to_value(&config.theme)?,
+        "config": serde_json::to_value(config)?,
+    });
+
+    tokio::fs::write(&path, serde_json::to_vec_pretty(&dump)?).await?;
+    Ok(path)
+}
+
 // NOTE: the `signal_hook` crate isn't designed to be used with realtime signals, because
 // they may be lost due to its internal buffering, etc. For our use case, I think this is
 // fine as is, but if not, we may have to use `signal_hook_register` to do it ourselves.
 // See: https://docs.rs/signal-hook/latest/signal_hook/index.html#limitations
-pub fn handle_signals(config: RcCell<AppConfig>, dispatcher: RcCell<Dispatcher>) -> Result<Handle> {
+pub fn handle_signals(
+    config: RcCell<AppConfig>,
+    dispatcher: RcCell<Dispatcher>,
+    mut bar: RcCell<Bar>,
+) -> Result<Handle> {
     let min = SIGRTMIN();
     let max = SIGRTMAX();
     let realtime_signals = min..=max;
+    let dump_signal = dump_signal();
 
-    let mut sig_to_indices: HashMap<i32, Vec<usize>> = HashMap::new();
-    for (idx, item) in config.items.iter().enumerate() {
-        if let Some(sig) = item.common.signal {
-            // signals are passed in from 0..(SIGRTMAX - SIGRTMIN)
-            let translated_sig = min + sig as i32;
-            // make sure all signals are valid
-            if !realtime_signals.contains(&translated_sig) {
-                bail!(
-                    "Invalid signal: {}. Valid signals range from 0 up to {} inclusive",
-                    sig,
-                    max - min
-                );
-            }
-
-            log::debug!(
-                "mapping signal {} ({}) to item: {} ({})",
-                sig,
-                translated_sig,
-                idx,
-                item.name()
-            );
-            sig_to_indices
-                .entry(translated_sig)
-                .and_modify(|v| v.push(idx))
-                .or_insert_with(|| vec![idx]);
-        }
+    // NOTE: signals are validated along with the rest of the config
+    for (sig, indices) in signal_map(&config) {
+        log::debug!(
+            "mapping signal {} ({}) to items: {:?}",
+            sig,
+            min + sig as i32,
+            indices
+        );
     }
 
-    let mut signals = Signals::new(realtime_signals.chain([SIGTERM]))?;
+    // NOTE: `dump_signal` is one of the realtime signals
+    let mut signals =
+        Signals::new(realtime_signals.chain([SIGTERM, BAR_STOP_SIGNAL, BAR_CONT_SIGNAL]))?;
     let handle = signals.handle();
     let socket_path = config.socket();
     tokio::task::spawn_local(async move {
@@ -60,10 +120,25 @@ pub fn handle_signals(config: RcCell<AppConfig>, dispatcher: RcCell<Dispatcher>)
                     let _ = std::fs::remove_file(&socket_path);
                     std::process::exit(0);
                 }
+                // i3 hid or showed the bar, let all items know
+                Some(signal @ (BAR_STOP_SIGNAL | BAR_CONT_SIGNAL)) => {
+                    let visible = signal == BAR_CONT_SIGNAL;
+                    log::debug!("bar visibility changed, visible: {}", visible);
+                    if let Err(e) = dispatcher.visibility_all(visible).await {
+                        log::warn!("failed to send visibility event: {}", e);
+                    }
+                }
+                // write the current state to a file, doesn't affect anything else
+                Some(signal) if signal == dump_signal => {
+                    match dump_state(&config, &mut bar).await {
+                        Ok(path) => log::info!("dumped bar state to: {}", path.display()),
+                        Err(e) => log::error!("failed to dump bar state: {}", e),
+                    }
+                }
                 // any other signal will be a realtime signal
                 Some(signal) => {
                     // find all items which are listening for this signal
-                    match sig_to_indices.get(&signal) {
+                    match signal_map(&config).get(&((signal - min) as u32)) {
                         // send signal event to all items
                         Some(indices) => {
                             for idx in indices {
@@ -92,3 +167,19 @@ pub fn handle_signals(config: RcCell<AppConfig>, dispatcher: RcCell<Dispatcher>)
 
     Ok(handle)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn item_signals_exclude_dump_signal() {
+        let max = max_item_signal();
+        assert_eq!(SIGRTMIN() + max as i32 + 1, dump_signal());
+
+        assert!(validate_item_signal(0).is_ok());
+        assert!(validate_item_signal(max).is_ok());
+        // this would be the dump signal
+        assert!(validate_item_signal(max + 1).is_err());
+    }
+}
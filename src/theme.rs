@@ -1,7 +1,11 @@
+use std::time::Duration;
+
 use hex_color::HexColor;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::error::Result;
+use crate::i3::pango_escape;
+use crate::util::format::{tokenise, Token};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ColorPair {
@@ -15,6 +19,35 @@ impl ColorPair {
     }
 }
 
+/// Relative luminance of a colour, see: https://www.w3.org/TR/WCAG21/#dfn-relative-luminance
+fn luminance(color: HexColor) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * channel(color.r) + 0.7152 * channel(color.g) + 0.0722 * channel(color.b)
+}
+
+/// Mix `pct` percent of `fg` into `bg`, e.g. `0` is `bg` and `100` is `fg`
+pub fn blend(fg: HexColor, bg: HexColor, pct: u8) -> HexColor {
+    let pct = pct.min(100) as f64 / 100.0;
+    let mix = |fg: u8, bg: u8| (bg as f64 + (fg as f64 - bg as f64) * pct).round() as u8;
+    HexColor::rgb(mix(fg.r, bg.r), mix(fg.g, bg.g), mix(fg.b, bg.b))
+}
+
+/// Contrast ratio between two colours, ranges from 1 (none) to 21 (black on white)
+/// See: https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+pub fn contrast_ratio(a: HexColor, b: HexColor) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PowerlineSeparator {
     value: String,
@@ -23,22 +56,71 @@ pub struct PowerlineSeparator {
 }
 
 impl PowerlineSeparator {
-    pub fn to_span(&self) -> String {
+    /// Pairs of powerline glyphs which point in opposite directions, as `(right, left)`
+    const MIRRORED_GLYPHS: &'static [(char, char)] = &[
+        ('\u{e0b0}', '\u{e0b2}'),
+        ('\u{e0b1}', '\u{e0b3}'),
+        ('\u{e0b4}', '\u{e0b6}'),
+        ('\u{e0b5}', '\u{e0b7}'),
+        ('\u{e0b8}', '\u{e0ba}'),
+        ('\u{e0b9}', '\u{e0bb}'),
+        ('\u{e0bc}', '\u{e0be}'),
+        ('\u{e0bd}', '\u{e0bf}'),
+    ];
+
+    /// The separator is configured for the default direction (right to left), when rendering
+    /// left to right any known powerline glyphs are swapped with their mirrored counterparts
+    fn glyphs(&self, direction: PowerlineDirection) -> String {
+        match direction {
+            PowerlineDirection::RightToLeft => self.value.clone(),
+            PowerlineDirection::LeftToRight => self
+                .value
+                .chars()
+                .map(|c| {
+                    Self::MIRRORED_GLYPHS
+                        .iter()
+                        .find_map(|&(r, l)| (c == r).then_some(l).or((c == l).then_some(r)))
+                        .unwrap_or(c)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_span(&self, direction: PowerlineDirection) -> String {
+        let value = self.glyphs(direction);
         match self.scale {
-            None => self.value.clone(),
-            Some(pct) => format!(r#"<span size="{}%">{}</span>"#, pct, self.value),
+            None => value,
+            Some(pct) => format!(r#"<span size="{}%">{}</span>"#, pct, value),
         }
     }
 }
 
+/// Which way the powerline flows, this decides which side of each item its separator is on
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerlineDirection {
+    /// Separators are placed before (to the left of) each item, pointing left
+    #[default]
+    RightToLeft,
+    /// Separators are placed after (to the right of) each item, pointing right
+    LeftToRight,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Theme {
     #[serde(default = "Theme::default_bg")]
     pub bg: HexColor,
     #[serde(default = "Theme::default_fg")]
     pub fg: HexColor,
-    #[serde(default = "Theme::default_dim")]
+    /// Used for inactive or disabled states, this is set from the config by `Theme::resolve`.
+    #[serde(skip, default = "Theme::default_dim")]
     pub dim: HexColor,
+    /// The configured `dim` colour, which takes precedence over `dim_pct`
+    #[serde(rename = "dim", default, skip_serializing_if = "Option::is_none")]
+    dim_config: Option<HexColor>,
+    /// If `dim` isn't set, it's made by blending this percentage of `fg` into `bg`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dim_pct: Option<u8>,
 
     #[serde(default = "Theme::default_red")]
     pub red: HexColor,
@@ -59,6 +141,21 @@ pub struct Theme {
     /// The background for an urgent item. Defaults to `theme.red`.
     #[serde(default = "Theme::default_red")]
     pub urgent_bg: HexColor,
+    /// Whether urgent items should flash between two colour states.
+    #[serde(default = "Theme::default_urgent_flash")]
+    pub urgent_flash: bool,
+    /// How long each of the flash states is displayed for.
+    #[serde(
+        default = "Theme::default_urgent_flash_interval",
+        with = "humantime_serde"
+    )]
+    pub urgent_flash_interval: Duration,
+    /// The foreground of an urgent item while flashing. Defaults to `theme.urgent_bg`.
+    #[serde(default)]
+    pub urgent_flash_fg: Option<HexColor>,
+    /// The background of an urgent item while flashing. Defaults to `theme.urgent_fg`.
+    #[serde(default)]
+    pub urgent_flash_bg: Option<HexColor>,
 
     #[serde(default = "Theme::default_powerline")]
     pub powerline: Vec<ColorPair>,
@@ -66,6 +163,11 @@ pub struct Theme {
     pub powerline_enable: bool,
     #[serde(default = "Theme::default_powerline_separator")]
     pub powerline_separator: PowerlineSeparator,
+    #[serde(default)]
+    pub powerline_direction: PowerlineDirection,
+    /// Whether to render a separator at the end of the powerline, which blends into the bar
+    #[serde(default = "Theme::default_powerline_caps")]
+    pub powerline_caps: bool,
 }
 
 impl Default for Theme {
@@ -74,6 +176,8 @@ impl Default for Theme {
             bg: Self::default_bg(),
             fg: Self::default_fg(),
             dim: Self::default_dim(),
+            dim_config: None,
+            dim_pct: None,
 
             red: Self::default_red(),
             orange: Self::default_orange(),
@@ -84,23 +188,106 @@ impl Default for Theme {
 
             urgent_fg: Self::default_bg(),
             urgent_bg: Self::default_red(),
+            urgent_flash: Self::default_urgent_flash(),
+            urgent_flash_interval: Self::default_urgent_flash_interval(),
+            urgent_flash_fg: None,
+            urgent_flash_bg: None,
 
             powerline: Self::default_powerline(),
             powerline_enable: false,
             powerline_separator: Self::default_powerline_separator(),
+            powerline_direction: PowerlineDirection::default(),
+            powerline_caps: Self::default_powerline_caps(),
+        }
+    }
+}
+
+/// Colour each grapheme of the text, cycling through `colors`. Graphemes are used (rather than
+/// bytes or chars) so multibyte glyphs aren't split. If `pango` is set then any existing tags are
+/// kept, otherwise the text is escaped. The result is always pango markup.
+pub fn rainbow(text: &str, colors: &[HexColor], pango: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut colors = colors.iter().cycle();
+    for token in tokenise(text, pango) {
+        match token {
+            Token::Tag(tag) => result.push_str(tag),
+            Token::Visible(s) => {
+                let s = if pango { s.into() } else { pango_escape(s) };
+                match colors.next() {
+                    Some(fg) => result.push_str(&format!(
+                        r#"<span foreground="{}">{}</span>"#,
+                        fg.display_rgb(),
+                        s
+                    )),
+                    None => result.push_str(&s),
+                }
+            }
         }
     }
+
+    result
 }
 
 impl Theme {
+    /// The colours used by items with `rainbow` set
+    pub fn rainbow_colors(&self) -> [HexColor; 6] {
+        [
+            self.red,
+            self.orange,
+            self.yellow,
+            self.green,
+            self.blue,
+            self.purple,
+        ]
+    }
+
+    /// Set the colours which are derived from others, this must be done after the theme is parsed
+    pub fn resolve(&mut self) {
+        self.dim = match (self.dim_config, self.dim_pct) {
+            (Some(dim), _) => dim,
+            (None, Some(pct)) => blend(self.fg, self.bg, pct),
+            (None, None) => Self::default_dim(),
+        };
+    }
+
     pub fn validate(&self) -> Result<()> {
+        if self.dim_pct.map_or(false, |pct| pct > 100) {
+            bail!("theme.dim_pct must be between 0 and 100");
+        }
+
         if self.powerline.len() <= 1 {
             bail!("theme.powerline must contain at least two values");
         }
 
+        if self.urgent_flash && self.urgent_flash_interval.is_zero() {
+            bail!("theme.urgent_flash_interval must be greater than zero");
+        }
+
+        // only warn here, since there may be valid reasons for unusual colour choices
+        let ratio = contrast_ratio(self.fg, self.bg);
+        if ratio < Self::MIN_CONTRAST_RATIO {
+            log::warn!(
+                "theme.fg ({}) and theme.bg ({}) have a very low contrast ratio of {:.2}, text may be hard to read",
+                self.fg.display_rgb(),
+                self.bg.display_rgb(),
+                ratio
+            );
+        }
+
         Ok(())
     }
 
+    /// Returns a copy of this theme with the urgent colours replaced by the flashing colours
+    pub fn flashed(&self) -> Theme {
+        let mut theme = self.clone();
+        theme.urgent_fg = self.urgent_flash_fg.unwrap_or(self.urgent_bg);
+        theme.urgent_bg = self.urgent_flash_bg.unwrap_or(self.urgent_fg);
+        theme
+    }
+
+    /// Anything below this is likely to be unreadable
+    const MIN_CONTRAST_RATIO: f64 = 1.5;
+
     const DEFAULT_POWERLINE: &'static [ColorPair] = &[
         ColorPair::new(HexColor::rgb(216, 222, 233), HexColor::rgb(46, 52, 64)),
         ColorPair::new(HexColor::rgb(229, 233, 240), HexColor::rgb(59, 66, 82)),
@@ -144,10 +331,22 @@ impl Theme {
         HexColor::rgb(180, 142, 173)
     }
 
+    const fn default_urgent_flash() -> bool {
+        true
+    }
+
+    const fn default_urgent_flash_interval() -> Duration {
+        Duration::from_secs(1)
+    }
+
     fn default_powerline() -> Vec<ColorPair> {
         Self::DEFAULT_POWERLINE.to_vec()
     }
 
+    const fn default_powerline_caps() -> bool {
+        true
+    }
+
     fn default_powerline_separator() -> PowerlineSeparator {
         PowerlineSeparator {
             value: "".into(),
@@ -155,3 +354,176 @@ impl Theme {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use super::*;
+
+    #[test]
+    fn contrast_black_white() {
+        let black = HexColor::rgb(0, 0, 0);
+        let white = HexColor::rgb(255, 255, 255);
+        let ratio = contrast_ratio(black, white);
+        assert!((ratio - 21.0).abs() < 0.01);
+        assert!(ratio >= Theme::MIN_CONTRAST_RATIO);
+        // order shouldn't matter
+        assert_eq!(ratio, contrast_ratio(white, black));
+    }
+
+    #[test]
+    fn contrast_similar_grays() {
+        let ratio = contrast_ratio(HexColor::rgb(128, 128, 128), HexColor::rgb(132, 132, 132));
+        assert!(ratio < Theme::MIN_CONTRAST_RATIO);
+    }
+
+    #[test]
+    fn contrast_default_theme() {
+        let theme = Theme::default();
+        assert!(contrast_ratio(theme.fg, theme.bg) >= Theme::MIN_CONTRAST_RATIO);
+    }
+
+    #[test]
+    fn blend_colours() {
+        let black = HexColor::rgb(0, 0, 0);
+        let white = HexColor::rgb(255, 255, 255);
+        assert_eq!(blend(white, black, 0), black);
+        assert_eq!(blend(white, black, 100), white);
+        assert_eq!(blend(white, black, 50), HexColor::rgb(128, 128, 128));
+        assert_eq!(blend(black, white, 25), HexColor::rgb(191, 191, 191));
+
+        // channels are blended independently, in either direction
+        let fg = HexColor::rgb(216, 222, 233);
+        let bg = HexColor::rgb(46, 52, 64);
+        assert_eq!(blend(fg, bg, 20), HexColor::rgb(80, 86, 98));
+    }
+
+    #[test]
+    fn resolve_dim() {
+        let parse = |json: serde_json::Value| {
+            let mut theme = serde_json::from_value::<Theme>(json).unwrap();
+            theme.resolve();
+            theme
+        };
+
+        let black = HexColor::rgb(0, 0, 0);
+        let white = HexColor::rgb(255, 255, 255);
+        let theme = parse(serde_json::json!({ "fg": "#ffffff", "bg": "#000000", "dim_pct": 40 }));
+        assert_eq!(theme.dim, blend(white, black, 40));
+
+        // an explicit dim takes precedence
+        let theme = parse(serde_json::json!({ "dim": "#010203", "dim_pct": 40 }));
+        assert_eq!(theme.dim, HexColor::rgb(1, 2, 3));
+
+        // neither is the default
+        assert_eq!(parse(serde_json::json!({})).dim, Theme::default_dim());
+
+        // and the configured value is what's serialised, so it survives a round trip
+        let value = serde_json::to_value(parse(serde_json::json!({ "dim_pct": 40 }))).unwrap();
+        assert!(value.get("dim").is_none());
+        assert_eq!(value.get("dim_pct").unwrap(), 40);
+    }
+
+    #[test]
+    fn powerline_separator_direction() {
+        let sep = PowerlineSeparator {
+            value: "\u{e0b2}".into(),
+            scale: None,
+        };
+        assert_eq!(sep.to_span(PowerlineDirection::RightToLeft), "\u{e0b2}");
+        assert_eq!(sep.to_span(PowerlineDirection::LeftToRight), "\u{e0b0}");
+
+        // mirroring works both ways, and anything unknown is left as is
+        let sep = PowerlineSeparator {
+            value: "\u{e0bc}|".into(),
+            scale: Some(115),
+        };
+        assert_eq!(
+            sep.to_span(PowerlineDirection::LeftToRight),
+            "<span size=\"115%\">\u{e0be}|</span>"
+        );
+    }
+
+    #[test]
+    fn flashed_swaps_urgent_by_default() {
+        let theme = Theme::default();
+        let flashed = theme.flashed();
+        assert_eq!(flashed.urgent_fg, theme.urgent_bg);
+        assert_eq!(flashed.urgent_bg, theme.urgent_fg);
+    }
+
+    #[test]
+    fn flashed_uses_configured_colours() {
+        let theme = Theme {
+            urgent_flash_fg: Some(HexColor::rgb(1, 2, 3)),
+            urgent_flash_bg: Some(HexColor::rgb(4, 5, 6)),
+            ..Theme::default()
+        };
+        let flashed = theme.flashed();
+        assert_eq!(flashed.urgent_fg, HexColor::rgb(1, 2, 3));
+        assert_eq!(flashed.urgent_bg, HexColor::rgb(4, 5, 6));
+    }
+
+    #[test]
+    fn zero_flash_interval_is_invalid() {
+        let theme = Theme {
+            urgent_flash_interval: Duration::ZERO,
+            ..Theme::default()
+        };
+        assert!(theme.validate().is_err());
+        let theme = Theme {
+            urgent_flash: false,
+            ..theme
+        };
+        assert!(theme.validate().is_ok());
+    }
+
+    #[test]
+    fn rainbow_spans_each_grapheme() {
+        let colors = Theme::default().rainbow_colors();
+        // a combining accent, a multibyte glyph and an emoji with a skin tone modifier
+        let text = "ne\u{301}t 󰋊 👋🏽";
+        let markup = rainbow(text, &colors, false);
+
+        let graphemes = text.graphemes(true).count();
+        assert_eq!(markup.matches("<span ").count(), graphemes);
+        assert_eq!(markup.matches("</span>").count(), graphemes);
+        assert!(markup.contains(">e\u{301}</span>"));
+        assert!(markup.contains(r#">👋🏽</span>"#));
+
+        // the palette is cycled
+        assert!(markup.starts_with(&format!(
+            r#"<span foreground="{}">n</span><span foreground="{}">e"#,
+            colors[0].display_rgb(),
+            colors[1].display_rgb()
+        )));
+    }
+
+    #[test]
+    fn rainbow_markup() {
+        let colors = [HexColor::RED];
+        let red = HexColor::RED.display_rgb();
+
+        // plain text is escaped
+        assert_eq!(
+            rainbow("<&", &colors, false),
+            format!(
+                r#"<span foreground="{0}">&lt;</span><span foreground="{0}">&amp;</span>"#,
+                red
+            )
+        );
+
+        // existing tags are kept, and entities aren't split
+        assert_eq!(
+            rainbow("<b>a</b>&amp;", &colors, true),
+            format!(
+                r#"<b><span foreground="{0}">a</span></b><span foreground="{0}">&amp;</span>"#,
+                red
+            )
+        );
+
+        // no colours, no spans
+        assert_eq!(rainbow("a<b", &[], false), "a&lt;b");
+    }
+}
@@ -1,73 +1,74 @@
-use std::iter::Peekable;
-
 use strum::IntoEnumIterator;
 
 use crate::error::Result;
 
-/// A safe wrapper for an iterator that cycles endlessly.
-pub struct EnumCycle<T: IntoEnumIterator> {
-    inner: Peekable<Box<dyn Iterator<Item = T>>>,
+/// Cycle through the variants of an enum, wrapping around at either end.
+///
+/// This is implemented for any enum which derives `strum::EnumIter`, so items can cycle between
+/// their modes without tracking indices themselves.
+pub trait EnumCycle: Sized {
+    /// Returns the variant after this one, wrapping around to the first variant.
+    fn next(&self) -> Self;
+    /// Returns the variant before this one, wrapping around to the last variant.
+    fn prev(&self) -> Self;
 }
 
-impl<T: IntoEnumIterator + Clone + 'static> Iterator for EnumCycle<T> {
-    type Item = T;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        // SAFETY: `self.inner` is a cycling iterator that has at least one variant
-        self.inner.next()
+/// Returns all the variants of the enum.
+/// Returns an error if the enum doesn't have at least one variant.
+fn variants<T: IntoEnumIterator>() -> Result<Vec<T>> {
+    let all = T::iter().collect::<Vec<T>>();
+    if all.is_empty() {
+        bail!("enum to cycle must contain at least one variant!");
     }
-}
 
-impl<T: IntoEnumIterator + Clone + 'static> EnumCycle<T> {
-    /// Creates a new `EnumCycle` for the given type.
-    /// Returns an error if the enum doesn't have at least one variant.
-    pub fn new() -> Result<EnumCycle<T>> {
-        let all = T::iter().collect::<Vec<T>>();
-        if all.is_empty() {
-            bail!("enum to cycle must contain at least one variant!");
-        }
+    Ok(all)
+}
 
-        Ok(EnumCycle {
-            inner: (Box::new(all.into_iter().cycle()) as Box<dyn Iterator<Item = T>>).peekable(),
-        })
+impl<T: IntoEnumIterator + PartialEq + Clone> EnumCycle for T {
+    fn next(&self) -> Self {
+        // SAFETY: `self` is a variant, so there's at least one
+        let all = variants::<T>().unwrap();
+        let idx = all.iter().position(|v| v == self).unwrap_or(0);
+        all[(idx + 1) % all.len()].clone()
     }
 
-    pub fn current(&mut self) -> &T {
-        // SAFETY: `self.inner` is a cycling iterator that has at least one variant
-        self.inner.peek().unwrap()
+    fn prev(&self) -> Self {
+        // SAFETY: `self` is a variant, so there's at least one
+        let all = variants::<T>().unwrap();
+        let idx = all.iter().position(|v| v == self).unwrap_or(0);
+        all[(idx + all.len() - 1) % all.len()].clone()
     }
 }
 
-impl<T: IntoEnumIterator + PartialEq + Clone + 'static> EnumCycle<T> {
-    /// Creates a new `EnumCycle` for the given type, starting at the given variant.
-    /// Returns an error if the enum doesn't have at least one variant.
-    pub fn new_at(start: T) -> Result<EnumCycle<T>> {
-        let mut me = Self::new()?;
-        while *me.current() != start {
-            me.next();
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(me)
+    #[derive(Debug, Clone, PartialEq, strum::EnumIter)]
+    enum Sample {
+        A,
+        B,
+        C,
     }
-}
 
-impl<T: IntoEnumIterator + PartialEq + Default + Clone + 'static> EnumCycle<T> {
-    /// Creates a new `EnumCycle` for the given type, starting at the default variant.
-    /// Returns an error if the enum doesn't have at least one variant.
-    pub fn new_at_default() -> Result<EnumCycle<T>> {
-        let start = T::default();
-        let mut me = Self::new()?;
-        while *me.current() != start {
-            me.next();
-        }
+    #[derive(Debug, Clone, PartialEq, strum::EnumIter)]
+    enum Single {
+        Only,
+    }
 
-        Ok(me)
+    #[test]
+    fn next_wraps_around() {
+        assert_eq!(Sample::A.next(), Sample::B);
+        assert_eq!(Sample::B.next(), Sample::C);
+        assert_eq!(Sample::C.next(), Sample::A);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn prev_wraps_around() {
+        assert_eq!(Sample::A.prev(), Sample::C);
+        assert_eq!(Sample::C.prev(), Sample::B);
+        assert_eq!(Sample::B.prev(), Sample::A);
+    }
 
     #[test]
     fn empty_iterator() {
@@ -75,7 +76,7 @@ mod tests {
         enum Empty {}
 
         // should not allow an empty iterator
-        match EnumCycle::<Empty>::new() {
+        match variants::<Empty>() {
             Ok(_) => panic!("should not be Ok"),
             Err(e) => assert_eq!(
                 e.to_string(),
@@ -83,4 +84,10 @@ mod tests {
             ),
         }
     }
+
+    #[test]
+    fn single_variant() {
+        assert_eq!(Single::Only.next(), Single::Only);
+        assert_eq!(Single::Only.prev(), Single::Only);
+    }
 }
@@ -1,32 +1,193 @@
-use std::process::Command;
-use std::thread;
+use std::collections::HashMap;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
 
+use futures::try_join;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command as AsyncCommand};
+use tokio::time::timeout;
+
+use crate::error::Result;
 use crate::i3::I3Item;
+use crate::util::format::strip_markup;
+
+/// The most bytes kept from each of a command's stdout and stderr, anything more is discarded
+pub const MAX_OUTPUT_LEN: usize = 64 * 1024;
 
 /// Used when bar items need to run an external command. It won't block, and also
 /// won't return any error: it shouldn't crash the app if the child process fails
 /// in any way (just like i3 handles commands).
 ///
-/// Unfortunately there's no `Command::try_wait` equivalent on `tokio::process::Command`,
-/// so this spawns a separate thread for each command, in case the command blocks or waits.
+/// The command runs in its own task, and its output is bounded (see `output_bounded`) so a
+/// chatty command can't use up all the memory.
 pub fn exec(cmd: impl AsRef<str>, item: &I3Item) {
-    let cmd = cmd.as_ref().to_owned();
+    spawn_exec(cmd.as_ref().to_owned(), item.as_env_map().unwrap());
+}
+
+/// Like `exec`, but the item's `full_text` is also available as `I3STAT_VALUE`. If the text is
+/// pango markup, then the markup is removed so only the text that's shown is passed on.
+pub fn exec_with_value(cmd: impl AsRef<str>, item: &I3Item, pango: bool) {
+    let value = match pango {
+        true => strip_markup(item.get_full_text()),
+        false => item.get_full_text().clone(),
+    };
+
+    let mut env_map = item.as_env_map().unwrap();
+    env_map.insert("I3STAT_VALUE".into(), value);
+    spawn_exec(cmd.as_ref().to_owned(), env_map);
+}
+
+fn spawn_exec(cmd: String, env_map: HashMap<String, String>) {
     log::debug!("exec: command --> {} <--", &cmd);
 
-    let env_map = item.as_env_map().unwrap();
-    thread::spawn(move || {
-        let output = Command::new("sh")
+    tokio::spawn(async move {
+        let mut command = AsyncCommand::new("sh");
+        // stdin is where i3 sends click events, so don't let the command read from it
+        command
             .arg("-c")
             .arg(&cmd)
             .envs(env_map)
-            .output();
-        match output {
+            .stdin(Stdio::null());
+
+        match output_bounded(&mut command).await {
             Ok(output) => {
-                if !output.status.success() {
-                    log::warn!("exit: command --> {} <-- {}", cmd, output.status);
+                if let Err(e) = output.into_result() {
+                    log::warn!("exit: command --> {} <-- {}", cmd, e);
                 }
             }
             Err(e) => log::error!("fail: command --> {} <-- {}", cmd, e),
         }
     });
 }
+
+/// The output of a command run with `output_bounded` or `wait_with_bounded_output`
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Returns stdout if the command succeeded, otherwise an error which includes stderr
+    pub fn into_result(self) -> Result<String> {
+        if !self.status.success() {
+            match self.stderr.is_empty() {
+                true => bail!("command failed with {}", self.status),
+                false => bail!("command failed with {}: {}", self.status, self.stderr),
+            }
+        }
+
+        Ok(self.stdout)
+    }
+}
+
+/// Reads everything from the reader, but only keeps the first `MAX_OUTPUT_LEN` bytes.
+/// The rest is still read, so the child process doesn't block on a full pipe.
+async fn read_bounded(reader: Option<impl AsyncRead + Unpin>) -> Result<String> {
+    let mut buf = vec![];
+    if let Some(mut reader) = reader {
+        let mut chunk = [0; 4096];
+        loop {
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+
+            let keep = n.min(MAX_OUTPUT_LEN - buf.len());
+            buf.extend_from_slice(&chunk[..keep]);
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&buf).trim().to_string())
+}
+
+/// Wait for the child to exit, collecting its (bounded) stdout and stderr if they were piped
+pub async fn wait_with_bounded_output(child: &mut Child) -> Result<CommandOutput> {
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let (stdout, stderr) = try_join!(read_bounded(stdout), read_bounded(stderr))?;
+    let status = child.wait().await?;
+
+    Ok(CommandOutput {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Run the command to completion, collecting its (bounded) stdout and stderr
+pub async fn output_bounded(command: &mut AsyncCommand) -> Result<CommandOutput> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    wait_with_bounded_output(&mut child).await
+}
+
+/// Like `output_bounded`, but if a `limit` is given and the command takes longer than that to run,
+/// it's killed and `None` is returned. The command runs in its own process group, so anything it
+/// starts is killed along with it.
+pub async fn output_with_timeout(
+    command: &mut AsyncCommand,
+    limit: Option<Duration>,
+) -> Result<Option<CommandOutput>> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .process_group(0)
+        .spawn()?;
+
+    let output = wait_with_bounded_output(&mut child);
+    let Some(limit) = limit else {
+        return Ok(Some(output.await?));
+    };
+
+    match timeout(limit, output).await {
+        Ok(result) => Ok(Some(result?)),
+        Err(_) => {
+            // kill the whole process group rather than only `sh`, otherwise any commands it
+            // started are left running
+            if let Some(pid) = child.id() {
+                // SAFETY: this only sends a signal, the group is the one created for the command
+                unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+            }
+            // this also waits for the child, so it doesn't become a zombie
+            child.kill().await?;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::local_block_on;
+
+    fn sh(script: &str) -> CommandOutput {
+        let (output, _) =
+            local_block_on(output_bounded(AsyncCommand::new("sh").arg("-c").arg(script))).unwrap();
+        output.unwrap()
+    }
+
+    #[test]
+    fn stderr_on_failure() {
+        let output = sh("echo out; echo oh no >&2; exit 3");
+        assert_eq!(output.stdout, "out");
+        assert_eq!(output.stderr, "oh no");
+
+        let err = output.into_result().unwrap_err();
+        assert_eq!(err.to_string(), "command failed with exit status: 3: oh no");
+
+        assert_eq!(sh("echo out").into_result().unwrap(), "out");
+    }
+
+    #[test]
+    fn bounded_output() {
+        let output = sh("head -c 100000 /dev/zero | tr '\\0' a");
+        assert_eq!(output.stdout.len(), MAX_OUTPUT_LEN);
+        assert!(output.status.success());
+    }
+}
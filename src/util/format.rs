@@ -1,5 +1,6 @@
 use num_traits::Float;
 use serde_derive::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::theme::Theme;
 
@@ -19,6 +20,61 @@ pub fn fraction(theme: &Theme, num: usize, den: usize) -> String {
     )
 }
 
+/// The convention used when displaying byte sizes.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeUnits {
+    /// Powers of 1024 with IEC suffixes, e.g., `KiB`, `MiB`
+    #[default]
+    Binary,
+    /// Powers of 1000 with SI suffixes, e.g., `kB`, `MB`
+    Decimal,
+}
+
+impl SizeUnits {
+    const BINARY_PREFIXES: [&'static str; 6] = ["Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+    const DECIMAL_PREFIXES: [&'static str; 6] = ["k", "M", "G", "T", "P", "E"];
+
+    pub fn base(&self) -> u64 {
+        match self {
+            SizeUnits::Binary => 1024,
+            SizeUnits::Decimal => 1000,
+        }
+    }
+
+    fn prefixes(&self) -> &'static [&'static str] {
+        match self {
+            SizeUnits::Binary => &Self::BINARY_PREFIXES,
+            SizeUnits::Decimal => &Self::DECIMAL_PREFIXES,
+        }
+    }
+}
+
+/// Format a size with the given units and suffix (e.g., `B` for bytes or `bits`).
+pub fn size(n: u64, units: SizeUnits, suffix: &str) -> String {
+    let base = units.base();
+    if n < base {
+        return format!("{} {}", n, suffix);
+    }
+
+    let prefixes = units.prefixes();
+    let mut value = n as f64;
+    let mut idx = 0;
+    // compare against the rounded value, so it moves to the next unit rather than showing `1024.0`
+    let rounded = |value: f64| (value * 10.0).round() / 10.0;
+    while rounded(value) >= base as f64 && idx < prefixes.len() {
+        value /= base as f64;
+        idx += 1;
+    }
+
+    format!("{:.1} {}{}", value, prefixes[idx - 1], suffix)
+}
+
+/// Format a number of bytes with the given units.
+pub fn bytes(n: u64, units: SizeUnits) -> String {
+    size(n, units, "B")
+}
+
 /// Common, re-usable options for formatting floats.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FloatFormat {
@@ -75,10 +131,275 @@ pub fn float<F: Float>(n: F, fmt: &FloatFormat) -> String {
     }
 }
 
+/// How a percentage is displayed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PercentStyle {
+    /// Only the number, e.g. `60%`
+    #[default]
+    Number,
+    /// Only a progress bar, e.g. `[███░░]`
+    Bar,
+    /// The number followed by a progress bar
+    Both,
+}
+
+/// Common, re-usable options for displaying percentages.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PercentFormat {
+    /// Whether to show the number, a progress bar, or both.
+    #[serde(default)]
+    style: PercentStyle,
+    /// How many glyphs wide the progress bar is. Defaults to 5.
+    bar_width: Option<usize>,
+}
+
+impl PercentFormat {
+    const DEFAULT_BAR_WIDTH: usize = 5;
+
+    /// Display `text` (the item's usual text) and/or a progress bar of `pct`. The bar is part of
+    /// the item's text, so it's coloured the same way as the item.
+    pub fn format(&self, text: &str, pct: f64) -> String {
+        let bar = || progress_bar(pct, self.bar_width.unwrap_or(Self::DEFAULT_BAR_WIDTH));
+        match self.style {
+            PercentStyle::Number => text.into(),
+            PercentStyle::Bar => bar(),
+            PercentStyle::Both => format!("{} {}", text, bar()),
+        }
+    }
+}
+
+/// Draw a percentage as a progress bar `width` glyphs wide, e.g. `[███░░]`.
+/// Values outside of 0-100 are clamped.
+pub fn progress_bar(pct: f64, width: usize) -> String {
+    // NOTE: `NaN` is cast to `0`
+    let filled = (pct.clamp(0.0, 100.0) / 100.0 * width as f64).round() as usize;
+    let filled = filled.min(width);
+    format!("[{}{}]", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+/// Glyphs used to draw a sparkline, from lowest to highest
+const SPARKLINE_GLYPHS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Returns the sparkline glyph for a percentage, values outside of 0-100 are clamped
+pub fn sparkline_glyph(pct: f32) -> char {
+    let len = SPARKLINE_GLYPHS.len();
+    // NOTE: `NaN` is cast to `0`
+    let idx = (pct.clamp(0.0, 100.0) / 100.0 * len as f32) as usize;
+    SPARKLINE_GLYPHS[idx.min(len - 1)]
+}
+
+/// A piece of (possibly pango) text
+pub(crate) enum Token<'a> {
+    /// A pango tag, e.g.: `<span foreground="red">` or `</span>`
+    Tag(&'a str),
+    /// A grapheme, or a pango entity, e.g.: `&amp;`
+    Visible(&'a str),
+}
+
+pub(crate) fn tokenise(text: &str, pango: bool) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut graphemes = text.grapheme_indices(true).peekable();
+    while let Some((start, g)) = graphemes.next() {
+        let end_of = |c: char| text[start..].find(c).map(|n| start + n + 1);
+        let end = match (pango, g) {
+            (true, "<") => end_of('>'),
+            (true, "&") => end_of(';'),
+            _ => None,
+        };
+
+        match end {
+            Some(end) => {
+                // skip the rest of the tag or entity
+                while graphemes.next_if(|(idx, _)| *idx < end).is_some() {}
+                let s = &text[start..end];
+                tokens.push(if g == "<" { Token::Tag(s) } else { Token::Visible(s) });
+            }
+            None => tokens.push(Token::Visible(g)),
+        }
+    }
+
+    tokens
+}
+
+/// Truncate the text so it's at most `max_width` graphemes wide (including the trailing ellipsis).
+/// If `pango` is set, tags are never cut and are always kept so the markup stays balanced.
+pub fn truncate(text: &str, max_width: usize, pango: bool) -> String {
+    let tokens = tokenise(text, pango);
+    let width = tokens
+        .iter()
+        .filter(|t| matches!(t, Token::Visible(_)))
+        .count();
+    if width <= max_width {
+        return text.into();
+    }
+
+    let keep = max_width.saturating_sub(1);
+    let mut result = String::with_capacity(text.len());
+    let mut visible = 0;
+    let mut ellipsis = false;
+    for token in tokens {
+        match token {
+            Token::Tag(tag) => result.push_str(tag),
+            Token::Visible(s) if visible < keep => {
+                result.push_str(s);
+                visible += 1;
+            }
+            // the first character that doesn't fit is replaced with the ellipsis
+            Token::Visible(_) if !ellipsis => {
+                result.push('…');
+                ellipsis = true;
+            }
+            Token::Visible(_) => {}
+        }
+    }
+
+    result
+}
+
+/// The text that's shown for the given pango markup, i.e.: without any tags, and with entities
+/// replaced by the characters they stand for
+pub fn strip_markup(text: &str) -> String {
+    tokenise(text, true)
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Tag(_) => None,
+            Token::Visible(s) => Some(unescape_entity(s)),
+        })
+        .collect()
+}
+
+fn unescape_entity(s: &str) -> std::borrow::Cow<'_, str> {
+    let Some(name) = s.strip_prefix('&').and_then(|s| s.strip_suffix(';')) else {
+        return s.into();
+    };
+
+    let c = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+            None => name
+                .strip_prefix('#')
+                .and_then(|dec| dec.parse().ok())
+                .and_then(char::from_u32),
+        },
+    };
+
+    // unknown entities are left as they are
+    c.map_or_else(|| s.into(), |c| c.to_string().into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_sparkline_glyph() {
+        assert_eq!(sparkline_glyph(0.0), '▁');
+        assert_eq!(sparkline_glyph(7.0), '▁');
+        assert_eq!(sparkline_glyph(50.0), '▅');
+        assert_eq!(sparkline_glyph(93.0), '█');
+        assert_eq!(sparkline_glyph(100.0), '█');
+
+        // out of range values are clamped
+        assert_eq!(sparkline_glyph(-10.0), '▁');
+        assert_eq!(sparkline_glyph(150.0), '█');
+        assert_eq!(sparkline_glyph(f32::NAN), '▁');
+    }
+
+    #[test]
+    fn progress_bars() {
+        assert_eq!(progress_bar(0.0, 5), "[░░░░░]");
+        assert_eq!(progress_bar(50.0, 4), "[██░░]");
+        assert_eq!(progress_bar(100.0, 5), "[█████]");
+        // widths which don't divide evenly are rounded
+        assert_eq!(progress_bar(50.0, 5), "[███░░]");
+        assert_eq!(progress_bar(50.0, 3), "[██░]");
+        assert_eq!(progress_bar(33.0, 3), "[█░░]");
+        assert_eq!(progress_bar(10.0, 0), "[]");
+
+        // out of range values are clamped
+        assert_eq!(progress_bar(-10.0, 4), "[░░░░]");
+        assert_eq!(progress_bar(150.0, 4), "[████]");
+        assert_eq!(progress_bar(f64::NAN, 4), "[░░░░]");
+    }
+
+    #[test]
+    fn percent_styles() {
+        let fmt = |style, bar_width| PercentFormat { style, bar_width };
+        assert_eq!(fmt(PercentStyle::Number, None).format("60%", 60.0), "60%");
+        assert_eq!(fmt(PercentStyle::Bar, None).format("60%", 60.0), "[███░░]");
+        assert_eq!(fmt(PercentStyle::Both, Some(2)).format("60%", 60.0), "60% [█░]");
+    }
+
+    #[test]
+    fn truncate_plain() {
+        assert_eq!(truncate("hello world", 20, false), "hello world");
+        assert_eq!(truncate("hello world", 11, false), "hello world");
+        assert_eq!(truncate("hello world", 6, false), "hello…");
+        assert_eq!(truncate("hello world", 1, false), "…");
+        assert_eq!(truncate("hello world", 0, false), "…");
+        // graphemes aren't split
+        assert_eq!(truncate("ne\u{301}twork", 3, false), "ne\u{301}…");
+        // markup is only respected when using pango
+        assert_eq!(truncate("<b>bold</b>", 4, false), "<b>…");
+    }
+
+    #[test]
+    fn truncate_pango() {
+        let text = r#"<span foreground="red">hello</span> world"#;
+        assert_eq!(truncate(text, 11, true), text);
+        assert_eq!(truncate(text, 8, true), r#"<span foreground="red">hello</span> w…"#);
+        assert_eq!(truncate(text, 3, true), r#"<span foreground="red">he…</span>"#);
+        assert_eq!(truncate("<b>a</b><i>bcd</i>", 2, true), "<b>a</b><i>…</i>");
+
+        // entities count as a single character, and aren't cut
+        assert_eq!(truncate("a &amp; b", 5, true), "a &amp; b");
+        assert_eq!(truncate("a &amp; b", 4, true), "a &amp;…");
+        assert_eq!(truncate("a &amp; b", 3, true), "a …");
+
+        // unterminated tags are treated as text
+        assert_eq!(truncate("a < b", 4, true), "a <…");
+    }
+    #[test]
+    fn bytes_binary() {
+        let units = SizeUnits::Binary;
+        assert_eq!(bytes(0, units), "0 B");
+        assert_eq!(bytes(1023, units), "1023 B");
+        assert_eq!(bytes(1024, units), "1.0 KiB");
+        assert_eq!(bytes(1536, units), "1.5 KiB");
+        assert_eq!(bytes(1024 * 1024 - 1, units), "1.0 MiB");
+        assert_eq!(bytes(1024 * 1000, units), "1000.0 KiB");
+        assert_eq!(bytes(1024 * 1024, units), "1.0 MiB");
+        assert_eq!(bytes(1024 * 1024 * 1024, units), "1.0 GiB");
+        assert_eq!(bytes(u64::MAX, units), "16.0 EiB");
+    }
+
+    #[test]
+    fn bytes_decimal() {
+        let units = SizeUnits::Decimal;
+        assert_eq!(bytes(0, units), "0 B");
+        assert_eq!(bytes(999, units), "999 B");
+        assert_eq!(bytes(1000, units), "1.0 kB");
+        assert_eq!(bytes(1024, units), "1.0 kB");
+        assert_eq!(bytes(1_500, units), "1.5 kB");
+        assert_eq!(bytes(999_949, units), "999.9 kB");
+        assert_eq!(bytes(999_999, units), "1.0 MB");
+        assert_eq!(bytes(1_000_000, units), "1.0 MB");
+        assert_eq!(bytes(1_000_000_000, units), "1.0 GB");
+        assert_eq!(bytes(u64::MAX, units), "18.4 EB");
+    }
+
+    #[test]
+    fn size_suffix() {
+        assert_eq!(size(999, SizeUnits::Decimal, "bits"), "999 bits");
+        assert_eq!(size(8_000, SizeUnits::Decimal, "bits"), "8.0 kbits");
+    }
+
     #[test]
     fn test_num_digits() {
         assert_eq!(num_digits(0.0), 1);
@@ -174,4 +495,13 @@ mod tests {
         assert_eq!(float(100.4, &fmt), "--100.400");
         assert_eq!(float(99999.999, &fmt), "99999.999");
     }
+
+    #[test]
+    fn strip_pango() {
+        assert_eq!(strip_markup("plain"), "plain");
+        assert_eq!(strip_markup(r#"<span foreground="red">hello</span> <b>world</b>"#), "hello world");
+        assert_eq!(strip_markup("a &amp; b &lt;c&gt; &#65;&#x42; &nope;"), "a & b <c> AB &nope;");
+        // unterminated tags are treated as text
+        assert_eq!(strip_markup("a < b"), "a < b");
+    }
 }
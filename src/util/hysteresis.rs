@@ -0,0 +1,96 @@
+/// Maps a value to a level (the number of thresholds it's above), but only changes level once the
+/// value has crossed a threshold by at least `margin`. This stops items from flickering between
+/// colours when a value hovers around a threshold.
+#[derive(Debug)]
+pub struct Hysteresis {
+    thresholds: Vec<f64>,
+    margin: f64,
+    level: Option<usize>,
+}
+
+impl Hysteresis {
+    /// `thresholds` should be sorted in ascending order
+    pub fn new(thresholds: impl Into<Vec<f64>>, margin: f64) -> Hysteresis {
+        Hysteresis {
+            thresholds: thresholds.into(),
+            margin: margin.abs(),
+            level: None,
+        }
+    }
+
+    /// Returns the level for the given value, between `0` and `thresholds.len()`
+    pub fn level(&mut self, value: f64) -> usize {
+        let count_below = |offset: f64| {
+            self.thresholds
+                .iter()
+                .filter(|t| value >= **t + offset)
+                .count()
+        };
+
+        let level = match self.level {
+            // the first value doesn't have anything to stick to
+            None => count_below(0.0),
+            Some(current) => {
+                let up = count_below(self.margin);
+                let down = count_below(-self.margin);
+                if up > current {
+                    up
+                } else if down < current {
+                    down
+                } else {
+                    current
+                }
+            }
+        };
+
+        self.level = Some(level);
+        level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_margin() {
+        let mut h = Hysteresis::new([60.0, 80.0], 0.0);
+        assert_eq!(h.level(10.0), 0);
+        assert_eq!(h.level(60.0), 1);
+        assert_eq!(h.level(59.9), 0);
+        assert_eq!(h.level(85.0), 2);
+        assert_eq!(h.level(0.0), 0);
+    }
+
+    #[test]
+    fn oscillating_values_are_stable() {
+        let mut h = Hysteresis::new([60.0, 80.0], 2.0);
+        assert_eq!(h.level(59.0), 0);
+
+        // hovering around the threshold doesn't change the level
+        for value in [60.0, 59.0, 61.0, 58.5, 61.9, 59.5] {
+            assert_eq!(h.level(value), 0, "value: {}", value);
+        }
+
+        // crossing it by the margin does
+        assert_eq!(h.level(62.0), 1);
+
+        // and now hovering around it stays on the new level
+        for value in [60.0, 59.0, 61.0, 58.1, 61.9, 59.5] {
+            assert_eq!(h.level(value), 1, "value: {}", value);
+        }
+
+        // until it drops below by the margin
+        assert_eq!(h.level(57.9), 0);
+    }
+
+    #[test]
+    fn large_jumps() {
+        let mut h = Hysteresis::new([60.0, 80.0], 2.0);
+        assert_eq!(h.level(50.0), 0);
+        assert_eq!(h.level(90.0), 2);
+        assert_eq!(h.level(79.0), 2);
+        assert_eq!(h.level(61.0), 1);
+        assert_eq!(h.level(20.0), 0);
+    }
+}
@@ -1,6 +1,13 @@
-use_and_export!(cell, enum_cycle, exec, format, net, netlink, paginator, path, urgent, vec);
+use_and_export!(
+    cell, enum_cycle, exec, format, hysteresis, json, net, netlink, output, paginator, path,
+    urgent, vec
+);
 
+#[cfg(test)]
+mod test_dir;
 use futures::Future;
+#[cfg(test)]
+pub use test_dir::TestDir;
 use tokio::runtime::{Builder, Runtime};
 use tokio::task::LocalSet;
 
@@ -35,9 +35,11 @@ impl TryFrom<&str> for InterfaceKind {
 /// This type is in the format of `interface[:type]`, where `interface` is the interface name, and
 /// `type` is an optional part which is either `ipv4` or `ipv6`.
 ///
-/// If `interface` is an empty string, then all interfaces are matched, for example:
+/// If `interface` is an empty string, then all interfaces are matched, and a trailing `*` matches
+/// any interface starting with the rest of it, for example:
 /// - `vpn0:ipv4` will match ip4 addresses for the `vpn` interface
 /// - `:ipv6`     will match all interfaces which have an ip6 address
+/// - `wlan*`     will match all interfaces starting with `wlan`
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InterfaceFilter {
     name: String,
@@ -52,12 +54,17 @@ impl InterfaceFilter {
         }
     }
 
+    /// Whether the interface's name matches, regardless of its addresses
+    pub fn matches_name(&self, name: impl AsRef<str>) -> bool {
+        let name = name.as_ref();
+        match self.name.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => self.name.is_empty() || self.name == name,
+        }
+    }
+
     pub fn matches(&self, name: impl AsRef<str>, addr: &IpAddr) -> bool {
-        let name_match = if self.name.is_empty() {
-            true
-        } else {
-            self.name == name.as_ref()
-        };
+        let name_match = self.matches_name(name);
 
         match self.kind {
             None => name_match,
@@ -149,6 +156,24 @@ mod tests {
         assert_eq!(p(":v6"), F::new("", Some(InterfaceKind::V6)));
     }
 
+    #[test]
+    fn interface_filter_matches() {
+        use InterfaceFilter as F;
+
+        let v4 = IpAddr::from([127, 0, 0, 1]);
+        let v6 = IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(F::new("wlan0", None).matches("wlan0", &v4));
+        assert!(!F::new("wlan0", None).matches("wlan1", &v4));
+        assert!(F::new("", Some(InterfaceKind::V6)).matches("eth0", &v6));
+        assert!(!F::new("", Some(InterfaceKind::V6)).matches("eth0", &v4));
+
+        // a trailing `*` matches any suffix
+        assert!(F::new("wlan*", None).matches_name("wlan0"));
+        assert!(F::new("wlan*", None).matches_name("wlan"));
+        assert!(!F::new("wlan*", None).matches_name("eth0"));
+        assert!(F::new("*", None).matches_name("eth0"));
+    }
+
     #[test]
     fn interface_filter_ser() {
         let to_s = |i| serde_json::to_value(i).unwrap();
@@ -77,14 +77,15 @@ impl Interfaces {
         self.inner.get(&index)
     }
 
-    /// Get an address by its index (where index is `0..interfaces.len_addresses()`)
-    pub fn get_address_at(&self, address_index: usize) -> Option<(&NetlinkInterface, &IpAddr)> {
+    /// Get an address and its prefix length by its index (where index is
+    /// `0..interfaces.len_addresses()`)
+    pub fn get_address_at(&self, address_index: usize) -> Option<(&NetlinkInterface, &IpAddr, u8)> {
         self.inner
             .iter()
             .flat_map(|(_, int)| {
                 int.ip_addresses
                     .iter()
-                    .map(|addr| (int, addr))
+                    .map(|(addr, prefix_len)| (int, addr, *prefix_len))
                     .collect::<Vec<_>>()
             })
             .nth(address_index)
@@ -99,13 +100,29 @@ impl Interfaces {
         self.inner.retain(|_, interface| {
             interface
                 .ip_addresses
-                .retain(|addr| filters.iter().any(|f| f.matches(&interface.name, addr)));
+                .retain(|(addr, _)| filters.iter().any(|f| f.matches(&interface.name, addr)));
 
             !interface.ip_addresses.is_empty()
         });
 
         self
     }
+
+    /// Only keep interfaces which are up and have a carrier
+    pub fn connected_only(mut self) -> Interfaces {
+        self.inner.retain(|_, interface| interface.has_carrier);
+        self
+    }
+
+    /// Only keep globally routable addresses, see `is_global`
+    pub fn global_only(mut self) -> Interfaces {
+        self.inner.retain(|_, interface| {
+            interface.ip_addresses.retain(|(addr, _)| is_global(addr));
+            !interface.ip_addresses.is_empty()
+        });
+
+        self
+    }
 }
 
 impl From<InterfaceUpdate> for Interfaces {
@@ -144,7 +161,7 @@ async fn watch_net_updates(
                 log::trace!("found interface: {:?}", int);
 
                 // some address filtering
-                int.ip_addresses.retain(|addr| match addr {
+                int.ip_addresses.retain(|(addr, _)| match addr {
                     // get rid of loopback addresses
                     any if any.is_loopback() => false,
                     // get rid of link local addresses
@@ -168,3 +185,40 @@ async fn watch_net_updates(
 fn v6_is_unicast_link_local(ipv6: &Ipv6Addr) -> bool {
     (ipv6.segments()[0] & 0xffc0) == 0xfe80
 }
+
+/// Unique local addresses are the ipv6 equivalent of ipv4's private addresses.
+/// see: https://doc.rust-lang.org/nightly/std/net/struct.Ipv6Addr.html#method.is_unique_local
+fn v6_is_unique_local(ipv6: &Ipv6Addr) -> bool {
+    (ipv6.segments()[0] & 0xfe00) == 0xfc00
+}
+
+/// Whether the address is globally routable, rather than only within a local network.
+fn is_global(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(v4) => !(v4.is_private() || v4.is_loopback() || v4.is_link_local()),
+        IpAddr::V6(v6) => {
+            !(v6_is_unique_local(v6) || v6.is_loopback() || v6_is_unicast_link_local(v6))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_addresses() {
+        let global = |s: &str| is_global(&s.parse().unwrap());
+
+        assert!(global("1.1.1.1"));
+        assert!(!global("192.168.1.2"));
+        assert!(!global("10.0.0.1"));
+        assert!(!global("127.0.0.1"));
+        assert!(!global("169.254.0.1"));
+
+        assert!(global("2001:db8::1"));
+        assert!(!global("fd12:3456:789a::1"));
+        assert!(!global("fe80::1"));
+        assert!(!global("::1"));
+    }
+}
@@ -82,7 +82,29 @@ pub struct NetlinkInterface {
     // NOTE: `Arc` rather than `Rc` here because `Send` is needed by `tokio::sync::broadcast`
     pub name: Arc<str>,
     pub mac_address: Option<MacAddr>,
-    pub ip_addresses: IndexSet<IpAddr>,
+    /// Addresses of the interface, along with their prefix length
+    pub ip_addresses: IndexSet<(IpAddr, u8)>,
+    /// Whether the interface has been brought up (`IFF_UP`)
+    pub is_up: bool,
+    /// Whether the interface is up and has a carrier (`IFF_RUNNING`)
+    pub has_carrier: bool,
+    /// Maximum transmission unit of the interface, in bytes
+    pub mtu: Option<u32>,
+    /// Counters of the interface's traffic at the time it was fetched
+    pub stats: Option<LinkStats>,
+}
+
+/// The start of `struct rtnl_link_stats64` (see `linux/if_link.h`), the rest isn't used
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LinkStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
 }
 
 #[cfg(test)]
@@ -136,6 +136,8 @@ pub struct WirelessInfo {
     pub bssid: Option<MacAddr>,
     /// Signal strength of the connection; only set when connected to a wireless network
     pub signal: Option<SignalStrength>,
+    /// Packet and byte counters of the station; only set when connected to a wireless network
+    pub counters: Option<StationCounters>,
 }
 
 impl NetlinkInterface {
@@ -246,10 +248,10 @@ impl NetlinkInterface {
                 // NOTE: if we didn't get the ssid before, it's also returned in the `GetScan` response
                 // so pass it in here too just in case
                 let bssid = get_scan(socket, self.index, &mut ssid).await?;
-                let signal = match bssid.as_ref() {
+                let (signal, counters) = match bssid.as_ref() {
                     Some(bssid) => get_signal_strength(socket, self.index, bssid).await?,
                     // we can't fetch the signal strength without the bssid
-                    None => None,
+                    None => (None, None),
                 };
 
                 return Ok(Some(WirelessInfo {
@@ -259,6 +261,7 @@ impl NetlinkInterface {
                     ssid,
                     bssid,
                     signal,
+                    counters,
                 }));
             }
         }
@@ -280,6 +283,20 @@ pub struct SignalStrength {
     quality: std::cell::OnceCell<f32>,
 }
 
+/// Packet and byte counters of a station. Each of these may be missing if the driver doesn't
+/// report them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StationCounters {
+    /// Total bytes received from the station
+    pub rx_bytes: Option<u64>,
+    /// Total bytes transmitted to the station
+    pub tx_bytes: Option<u64>,
+    /// Total packets received from the station
+    pub rx_packets: Option<u32>,
+    /// Total packets transmitted to the station
+    pub tx_packets: Option<u32>,
+}
+
 impl SignalStrength {
     pub fn new(dbm: i8) -> SignalStrength {
         SignalStrength {
@@ -414,12 +431,12 @@ async fn get_scan(
     Ok(None)
 }
 
-/// Gets the signal strength of a wireless network connection
+/// Gets the signal strength (and counters) of a wireless network connection
 async fn get_signal_strength(
     socket: &NlRouter,
     index: i32,
     bssid: &MacAddr,
-) -> Result<Option<SignalStrength>> {
+) -> Result<(Option<SignalStrength>, Option<StationCounters>)> {
     let mut recv = genl80211_send(
         socket,
         Nl80211Command::GetStation,
@@ -438,6 +455,32 @@ async fn get_signal_strength(
                     if let Ok(station_info) = attr_handle
                         .get_nested_attributes::<Nl80211StationInfo>(Nl80211Attribute::StaInfo)
                     {
+                        // not all drivers report the 64-bit counters, so fallback to the 32-bit ones
+                        let counters = StationCounters {
+                            rx_bytes: counter(
+                                station_info
+                                    .get_attr_payload_as::<u64>(Nl80211StationInfo::RxBytes64)
+                                    .ok(),
+                                station_info
+                                    .get_attr_payload_as::<u32>(Nl80211StationInfo::RxBytes)
+                                    .ok(),
+                            ),
+                            tx_bytes: counter(
+                                station_info
+                                    .get_attr_payload_as::<u64>(Nl80211StationInfo::TxBytes64)
+                                    .ok(),
+                                station_info
+                                    .get_attr_payload_as::<u32>(Nl80211StationInfo::TxBytes)
+                                    .ok(),
+                            ),
+                            rx_packets: station_info
+                                .get_attr_payload_as::<u32>(Nl80211StationInfo::RxPackets)
+                                .ok(),
+                            tx_packets: station_info
+                                .get_attr_payload_as::<u32>(Nl80211StationInfo::TxPackets)
+                                .ok(),
+                        };
+
                         if let Ok(signal) =
                             station_info.get_attr_payload_as::<u8>(Nl80211StationInfo::Signal)
                         {
@@ -449,7 +492,7 @@ async fn get_signal_strength(
                                 signal_strength.dbm
                             );
 
-                            return Ok(Some(signal_strength));
+                            return Ok((Some(signal_strength), Some(counters)));
                         }
                     }
                 }
@@ -473,7 +516,12 @@ async fn get_signal_strength(
     }
 
     log::debug!("index {} bssid {} no signal strength found", index, bssid);
-    Ok(None)
+    Ok((None, None))
+}
+
+/// Prefer the 64-bit counter if it was reported, otherwise use the 32-bit one
+fn counter(counter_64: Option<u64>, counter_32: Option<u32>) -> Option<u64> {
+    counter_64.or(counter_32.map(u64::from))
 }
 
 #[cfg(test)]
@@ -500,4 +548,15 @@ mod tests {
         assert_eq!(100, quality(0));
         assert_eq!(100, quality(100));
     }
+
+    // station counter tests ---------------------------------------------------
+
+    #[test]
+    fn station_counter_fallback() {
+        assert_eq!(counter(None, None), None);
+        assert_eq!(counter(Some(u64::MAX), None), Some(u64::MAX));
+        assert_eq!(counter(None, Some(u32::MAX)), Some(u32::MAX as u64));
+        // the 64-bit counter is preferred since the 32-bit one may have wrapped
+        assert_eq!(counter(Some(1 << 33), Some(2)), Some(1 << 33));
+    }
 }
@@ -1,6 +1,7 @@
 //! Use rtnetlink (route netlink) for the following:
 //!     - fetching information about all current network interfaces
 //!     - be notified when ip addresses change
+//!     - be notified when links go up or down (carrier changes)
 //!
 //! Useful things when developing this:
 //!     - https://github.com/thom311/libnl/blob/main/src/nl-monitor.c
@@ -16,7 +17,7 @@ use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::rc::Rc;
 
 use indexmap::{IndexMap, IndexSet};
-use libc::{RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV6_IFADDR};
+use libc::{RTNLGRP_IPV4_IFADDR, RTNLGRP_IPV6_IFADDR, RTNLGRP_LINK};
 use neli::consts::nl::NlmF;
 use neli::consts::rtnl::{Arphrd, Ifa, Ifla, RtAddrFamily, RtScope, Rtm};
 use neli::consts::socket::NlFamily;
@@ -25,10 +26,11 @@ use neli::genl::Genlmsghdr;
 use neli::nl::{NlPayload, Nlmsghdr};
 use neli::router::asynchronous::{NlRouter, NlRouterReceiverHandle};
 use neli::rtnl::{Ifaddrmsg, IfaddrmsgBuilder, Ifinfomsg, IfinfomsgBuilder};
+use neli::types::Buffer;
 use neli::utils::Groups;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 
-use super::NetlinkInterface;
+use super::{LinkStats, NetlinkInterface};
 use crate::error::Result;
 
 pub type InterfaceUpdate = IndexMap<i32, NetlinkInterface>;
@@ -45,11 +47,12 @@ pub async fn netlink_ipaddr_listen(
     // https://docs.kernel.org/userspace-api/netlink/intro.html#strict-checking
     socket.enable_strict_checking(true)?;
 
-    // add multicast membership for ipv4 and ipv6 addr updates
+    // add multicast membership for ipv4 and ipv6 addr updates, and link updates
     socket
         .add_mcast_membership(Groups::new_groups(&[
             RTNLGRP_IPV4_IFADDR,
             RTNLGRP_IPV6_IFADDR,
+            RTNLGRP_LINK,
         ]))
         .unwrap();
 
@@ -100,7 +103,9 @@ async fn handle_netlink_route_messages(
 ) -> Result<Infallible> {
     // listen for multicast events
     loop {
-        match multicast.next().await as RtNext<Ifaddrmsg> {
+        // NOTE: we receive both `ifaddrmsg` and `ifinfomsg` messages here, and since they're only
+        // used as a trigger to fetch everything again, the payload is left unparsed
+        match multicast.next().await as RtNext<Buffer> {
             None => bail!("Unexpected end of netlink route stream"),
             // we got a multicast event
             Some(response) => {
@@ -116,7 +121,7 @@ async fn handle_netlink_route_messages(
                 // check we have a payload
                 match response.nl_payload() {
                     // parse payload and send event
-                    NlPayload::Payload(_ifaddrmsg) => {
+                    NlPayload::Payload(_msg) => {
                         // request all interfaces from netlink again - we request it each time because we get ifaddrmsg
                         // events even when the address is deleted (but we can't tell that is was deleted)
                         tx.send(get_all_interfaces(&socket).await?).await?
@@ -167,6 +172,8 @@ async fn get_all_interfaces(socket: &Rc<NlRouter>) -> Result<InterfaceUpdate> {
                 // handle to the attributes of this message
                 let attr_handle = ifinfomsg.rtattrs().get_attr_handle();
 
+                let (is_up, has_carrier) = parse_link_flags(ifinfomsg.ifi_flags().bits());
+
                 // extract interface name
                 let mut interface_info = NetlinkInterface {
                     index: *ifinfomsg.ifi_index(),
@@ -183,6 +190,10 @@ async fn get_all_interfaces(socket: &Rc<NlRouter>) -> Result<InterfaceUpdate> {
                     },
                     mac_address: None,
                     ip_addresses: IndexSet::new(),
+                    is_up,
+                    has_carrier,
+                    mtu: None,
+                    stats: None,
                 };
 
                 // extract mac address if set
@@ -194,6 +205,15 @@ async fn get_all_interfaces(socket: &Rc<NlRouter>) -> Result<InterfaceUpdate> {
                     }
                 }
 
+                // extract mtu and traffic counters, if the kernel sent them
+                let attr_bytes = |ifla: Ifla| {
+                    attr_handle
+                        .get_attr_payload_as_with_len_borrowed::<&[u8]>(ifla)
+                        .ok()
+                };
+                interface_info.mtu = attr_bytes(Ifla::Mtu).and_then(parse_mtu);
+                interface_info.stats = attr_bytes(Ifla::Stats64).and_then(parse_link_stats64);
+
                 interface_map.insert(*ifinfomsg.ifi_index(), interface_info);
             }
         }
@@ -231,30 +251,20 @@ async fn get_all_interfaces(socket: &Rc<NlRouter>) -> Result<InterfaceUpdate> {
                         Some(if_info) => {
                             // handle to the attributes of this message
                             let attr_handle = ifaddrmsg.rtattrs().get_attr_handle();
+                            let attr_bytes = |ifa: Ifa| {
+                                attr_handle
+                                    .get_attr_payload_as_with_len_borrowed::<&[u8]>(ifa)
+                                    .ok()
+                            };
 
-                            // extract address
-                            match ifaddrmsg.ifa_family() {
-                                RtAddrFamily::Inet => {
-                                    if let Ok(addr) =
-                                        attr_handle.get_attr_payload_as::<u32>(Ifa::Address)
-                                    {
-                                        if_info
-                                            .ip_addresses
-                                            .insert(IpAddr::V4(Ipv4Addr::from(u32::from_be(addr))));
-                                    }
-                                }
-                                RtAddrFamily::Inet6 => {
-                                    if let Ok(addr) =
-                                        attr_handle.get_attr_payload_as::<u128>(Ifa::Address)
-                                    {
-                                        if_info.ip_addresses.insert(IpAddr::V6(Ipv6Addr::from(
-                                            u128::from_be(addr),
-                                        )));
-                                    }
-                                }
-                                _ => {
-                                    continue;
-                                }
+                            // extract address and its prefix length
+                            if let Some(addr) = parse_ifaddr(
+                                ifaddrmsg.ifa_family(),
+                                *ifaddrmsg.ifa_prefixlen(),
+                                attr_bytes(Ifa::Local),
+                                attr_bytes(Ifa::Address),
+                            ) {
+                                if_info.ip_addresses.insert(addr);
                             }
                         }
                         None => {
@@ -272,3 +282,153 @@ async fn get_all_interfaces(socket: &Rc<NlRouter>) -> Result<InterfaceUpdate> {
 
     Ok(interface_map)
 }
+
+/// Parse the flags of an `ifinfomsg`, returning whether the link is up and has a carrier.
+/// `IFF_RUNNING` reflects the operational state of the link, which requires a carrier.
+fn parse_link_flags(flags: u32) -> (bool, bool) {
+    let is_up = flags & libc::IFF_UP as u32 != 0;
+    let has_carrier = is_up && flags & libc::IFF_RUNNING as u32 != 0;
+    (is_up, has_carrier)
+}
+
+/// Parse the `IFLA_MTU` attribute of an `ifinfomsg`, which is a `u32` in native byte order
+fn parse_mtu(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+/// Parse the `IFLA_STATS64` attribute of an `ifinfomsg`, which is a `struct rtnl_link_stats64` of
+/// `u64`s in native byte order. Newer kernels append fields, so only the length we need is checked.
+fn parse_link_stats64(bytes: &[u8]) -> Option<LinkStats> {
+    let mut fields = bytes
+        .chunks_exact(std::mem::size_of::<u64>())
+        // SAFETY: `chunks_exact` only returns chunks of the given size
+        .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()));
+
+    Some(LinkStats {
+        rx_packets: fields.next()?,
+        tx_packets: fields.next()?,
+        rx_bytes: fields.next()?,
+        tx_bytes: fields.next()?,
+        rx_errors: fields.next()?,
+        tx_errors: fields.next()?,
+        rx_dropped: fields.next()?,
+        tx_dropped: fields.next()?,
+    })
+}
+
+/// Parse an address and its prefix length from the attributes of an `ifaddrmsg`.
+/// `IFA_LOCAL` is preferred if present, since on point-to-point interfaces `IFA_ADDRESS` is the
+/// address of the other end of the link.
+fn parse_ifaddr(
+    family: &RtAddrFamily,
+    prefix_len: u8,
+    local: Option<&[u8]>,
+    address: Option<&[u8]>,
+) -> Option<(IpAddr, u8)> {
+    let bytes = local.or(address)?;
+    let (addr, max_prefix_len) = match family {
+        RtAddrFamily::Inet => (IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?)), 32),
+        RtAddrFamily::Inet6 => (IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?)), 128),
+        _ => return None,
+    };
+
+    if prefix_len > max_prefix_len {
+        log::warn!("invalid prefix length {} for address {}", prefix_len, addr);
+        return None;
+    }
+
+    Some((addr, prefix_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ifaddr_prefix() {
+        let v4 = [192, 168, 1, 2];
+        assert_eq!(
+            parse_ifaddr(&RtAddrFamily::Inet, 24, None, Some(&v4)),
+            Some((IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), 24))
+        );
+
+        let v6 = 0x2001_0db8_0000_f101_0000_0000_0000_0001u128.to_be_bytes();
+        assert_eq!(
+            parse_ifaddr(&RtAddrFamily::Inet6, 64, None, Some(&v6)),
+            Some((IpAddr::V6("2001:db8:0:f101::1".parse().unwrap()), 64))
+        );
+    }
+
+    #[test]
+    fn parse_ifaddr_prefers_local() {
+        let local = [10, 0, 0, 1];
+        let peer = [10, 0, 0, 2];
+        assert_eq!(
+            parse_ifaddr(&RtAddrFamily::Inet, 32, Some(&local), Some(&peer)),
+            Some((IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 32))
+        );
+    }
+
+    #[test]
+    fn parse_ifaddr_invalid() {
+        let v4 = [192, 168, 1, 2];
+        // prefix too long
+        assert_eq!(parse_ifaddr(&RtAddrFamily::Inet, 33, None, Some(&v4)), None);
+        // wrong length for the family
+        assert_eq!(parse_ifaddr(&RtAddrFamily::Inet6, 64, None, Some(&v4)), None);
+        // no address at all
+        assert_eq!(parse_ifaddr(&RtAddrFamily::Inet, 24, None, None), None);
+        // unsupported family
+        assert_eq!(parse_ifaddr(&RtAddrFamily::Unspecified, 24, None, Some(&v4)), None);
+    }
+
+    #[test]
+    fn mtu() {
+        assert_eq!(parse_mtu(&1500_u32.to_ne_bytes()), Some(1500));
+        assert_eq!(parse_mtu(&[0, 1]), None);
+    }
+
+    #[test]
+    fn link_stats64() {
+        // an `IFLA_STATS64` payload is 24 fields on recent kernels, only the first 8 are read
+        let mut bytes = [
+            1_234_u64, 567, 9_876_543, 123_456, 2, 1, 30, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0,
+        ]
+        .iter()
+        .flat_map(|n| n.to_ne_bytes())
+        .collect::<Vec<u8>>();
+
+        assert_eq!(
+            parse_link_stats64(&bytes),
+            Some(LinkStats {
+                rx_packets: 1_234,
+                tx_packets: 567,
+                rx_bytes: 9_876_543,
+                tx_bytes: 123_456,
+                rx_errors: 2,
+                tx_errors: 1,
+                rx_dropped: 30,
+                tx_dropped: 0,
+            })
+        );
+
+        // too short
+        bytes.truncate(7 * 8 + 4);
+        assert_eq!(parse_link_stats64(&bytes), None);
+    }
+
+    #[test]
+    fn link_flags() {
+        let up = libc::IFF_UP as u32;
+        let running = libc::IFF_RUNNING as u32;
+        let other = (libc::IFF_BROADCAST | libc::IFF_MULTICAST) as u32;
+
+        assert_eq!(parse_link_flags(0), (false, false));
+        assert_eq!(parse_link_flags(other), (false, false));
+        assert_eq!(parse_link_flags(up | other), (true, false));
+        assert_eq!(parse_link_flags(up | running | other), (true, true));
+        // running without up shouldn't happen, but it's not considered connected
+        assert_eq!(parse_link_flags(running), (false, false));
+    }
+}
@@ -0,0 +1,225 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Seek, SeekFrom, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+use crate::error::Result;
+use crate::util::expand_path;
+
+/// A file (or FIFO) which each frame of the bar is mirrored to, as a JSON array on its own line.
+/// Failing to write to it is never fatal, since the bar on stdout is what really matters.
+#[derive(Debug)]
+pub struct OutputFile {
+    path: PathBuf,
+    file: Option<File>,
+    /// Whether `file` is a FIFO, rather than a regular file
+    is_fifo: bool,
+    /// The rest of a frame which was only partly written to the FIFO
+    pending: Vec<u8>,
+    /// Only warn once about failures, until a frame is written again
+    warned: bool,
+}
+
+/// Like `Write::write`, but a full FIFO is treated as writing nothing rather than an error
+fn write_nonblocking(file: &mut File, buf: &[u8]) -> io::Result<usize> {
+    match file.write(buf) {
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(0),
+        result => result,
+    }
+}
+
+impl OutputFile {
+    pub fn new(path: PathBuf) -> OutputFile {
+        OutputFile {
+            path,
+            file: None,
+            is_fifo: false,
+            pending: vec![],
+            warned: false,
+        }
+    }
+
+    /// FIFOs are opened without blocking, so if nothing is reading from them (or the reader goes
+    /// away) frames are dropped until there's a reader again.
+    fn open(&mut self) -> Result<Option<File>> {
+        let path = expand_path(&self.path)?;
+        self.is_fifo = std::fs::metadata(&path).map_or(false, |m| m.file_type().is_fifo());
+        self.pending.clear();
+        if !self.is_fifo {
+            return Ok(Some(
+                OpenOptions::new().create(true).write(true).open(path)?,
+            ));
+        }
+
+        match OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+        {
+            Ok(file) => Ok(Some(file)),
+            // no one is reading from the FIFO
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Regular files only ever contain the latest frame
+    fn rewrite(file: &mut File, frame: &[u8]) -> io::Result<()> {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(frame)
+    }
+
+    /// Frames are never split up in a FIFO: if the reader isn't keeping up, then the rest of the
+    /// frame is written before the next one, and any frames in the meantime are dropped.
+    fn write_fifo(file: &mut File, pending: &mut Vec<u8>, frame: &[u8]) -> io::Result<()> {
+        if !pending.is_empty() {
+            let n = write_nonblocking(file, pending)?;
+            pending.drain(..n);
+            if !pending.is_empty() {
+                log::trace!("dropped frame, the reader isn't keeping up");
+                return Ok(());
+            }
+        }
+
+        let n = write_nonblocking(file, frame)?;
+        if n == 0 {
+            log::trace!("dropped frame, the reader isn't keeping up");
+        } else {
+            pending.extend_from_slice(&frame[n..]);
+        }
+
+        Ok(())
+    }
+
+    pub fn write_frame(&mut self, frame: &str) {
+        if self.file.is_none() {
+            match self.open() {
+                Ok(file) => self.file = file,
+                Err(e) => return self.warn(format!("failed to open: {}", e)),
+            }
+        }
+
+        let Some(file) = self.file.as_mut() else {
+            return;
+        };
+
+        let frame = format!("{}\n", frame);
+        let result = match self.is_fifo {
+            true => Self::write_fifo(file, &mut self.pending, frame.as_bytes()),
+            false => Self::rewrite(file, frame.as_bytes()),
+        };
+
+        match result {
+            Ok(()) => self.warned = false,
+            // the reader went away: drop this frame and try again next time
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                log::debug!("dropped frame for {}: {}", self.path.display(), e);
+                self.file = None;
+            }
+            Err(e) => {
+                self.file = None;
+                self.warn(format!("failed to write: {}", e));
+            }
+        }
+    }
+
+    fn warn(&mut self, msg: String) {
+        if !self.warned {
+            log::warn!("output_file {}: {}", self.path.display(), msg);
+            self.warned = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::util::TestDir;
+
+    #[test]
+    fn files_contain_the_latest_frame() {
+        let dir = TestDir::new("output-file");
+        let path = dir.join("output");
+        let mut output = OutputFile::new(path.clone());
+        output.write_frame(r#"[{"full_text":"first frame"}]"#);
+        output.write_frame(r#"[{"full_text":"two"}]"#);
+
+        let frame = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(frame, "[{\"full_text\":\"two\"}]\n");
+    }
+
+    #[test]
+    fn fifo_readers_can_come_and_go() {
+        let dir = TestDir::new("output-fifo");
+        let path = dir.join("output");
+        nix::unistd::mkfifo(&path, nix::sys::stat::Mode::S_IRWXU).unwrap();
+        let mut output = OutputFile::new(path.clone());
+
+        // no reader yet, so the frame is dropped
+        output.write_frame("[1]");
+        assert!(output.file.is_none());
+
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        output.write_frame("[2]");
+        let mut buf = [0; 64];
+        let len = reader.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"[2]\n");
+
+        // the reader went away, which shouldn't be an error
+        drop(reader);
+        output.write_frame("[3]");
+        assert!(output.file.is_none());
+        assert!(!output.warned);
+    }
+
+    #[test]
+    fn fifo_frames_are_not_split() {
+        let dir = TestDir::new("output-fifo-partial");
+        let path = dir.join("output");
+        nix::unistd::mkfifo(&path, nix::sys::stat::Mode::S_IRWXU).unwrap();
+        let mut reader = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap();
+        let mut output = OutputFile::new(path.clone());
+
+        // bigger than the pipe's buffer, so it can't be written all at once
+        let big = format!("[{}]", "1".repeat(1024 * 1024));
+        output.write_frame(&big);
+        assert!(!output.pending.is_empty());
+
+        // the reader hasn't read anything yet, so this is dropped
+        output.write_frame("[dropped]");
+
+        let mut read = vec![];
+        let mut buf = [0; 64 * 1024];
+        let mut read_available = |read: &mut Vec<u8>| loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => read.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => panic!("{}", e),
+            }
+        };
+
+        // once the reader catches up, the rest of the frame is written before the next one
+        while !output.pending.is_empty() {
+            read_available(&mut read);
+            output.write_frame("[next]");
+        }
+        read_available(&mut read);
+
+        let read = String::from_utf8(read).unwrap();
+        let lines = read.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], big);
+        assert!(lines[1..].iter().all(|line| *line == "[next]"));
+    }
+}
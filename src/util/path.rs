@@ -39,6 +39,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn expansion_matrix() {
+        let home = std::env::var("HOME").unwrap();
+        std::env::set_var("I3STAT_TEST_PATH_VAR", "/custom/dir");
+
+        for (input, expected) in [
+            ("/no/expansion/needed", "/no/expansion/needed".to_string()),
+            ("~", home.clone()),
+            ("~/file", format!("{}/file", home)),
+            ("$HOME/file", format!("{}/file", home)),
+            ("${HOME}/file", format!("{}/file", home)),
+            ("$I3STAT_TEST_PATH_VAR/file", "/custom/dir/file".to_string()),
+            ("${I3STAT_TEST_PATH_VAR}/file", "/custom/dir/file".to_string()),
+        ] {
+            assert_eq!(
+                expand_path(input).unwrap(),
+                PathBuf::from(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
     #[test]
     #[should_panic(expected = "expansion resulted in nothing")]
     fn passthrough_to_wordexp() {
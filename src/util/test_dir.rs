@@ -0,0 +1,45 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static UNIQUE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh temporary directory for a test, which is removed when dropped (even if the test fails).
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        let dir = std::env::temp_dir().join(format!(
+            "i3stat-{}-{}.{}",
+            name,
+            std::process::id(),
+            UNIQUE_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        // left over from a previous run that was killed
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TestDir(dir.canonicalize().unwrap())
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
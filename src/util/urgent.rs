@@ -1,7 +1,8 @@
 use std::time::{Duration, Instant};
 
-#[derive(Default)]
 pub struct UrgentTimer {
+    /// How long to wait before toggling between the two urgent states.
+    interval: Duration,
     /// If set, then the timer is active. Marks the start of the timer.
     started: Option<Instant>,
     /// Whether or not the urgent bg should be swapped with the urgent fg.
@@ -10,8 +11,16 @@ pub struct UrgentTimer {
 
 impl UrgentTimer {
     #[inline]
-    pub fn new() -> UrgentTimer {
-        UrgentTimer::default()
+    pub fn new(interval: Duration) -> UrgentTimer {
+        UrgentTimer {
+            interval,
+            started: None,
+            swapped: false,
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
     }
 
     pub fn swapped(&self) -> bool {
@@ -43,7 +52,7 @@ impl UrgentTimer {
     pub async fn wait(&self) {
         match self.started {
             Some(started) => {
-                if let Some(time_left) = Duration::from_secs(1).checked_sub(started.elapsed()) {
+                if let Some(time_left) = self.interval.checked_sub(started.elapsed()) {
                     tokio::time::sleep(time_left).await
                 }
             }
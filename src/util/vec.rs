@@ -1,3 +1,11 @@
+/// Complete a partial order of indices: the indices in `partial` come first, in the given order,
+/// followed by the rest of the indices in `base`, keeping their relative order from `base`.
+pub fn merge_partial_order(partial: &[usize], base: &[usize]) -> Vec<usize> {
+    let mut order = partial.to_vec();
+    order.extend(base.iter().filter(|idx| !partial.contains(idx)));
+    order
+}
+
 /// Sort a vec in place, by providing a list of desired indices.
 /// Thanks to: https://stackoverflow.com/a/69774341/5552584
 pub fn sort_by_indices<T>(slice: &mut [T], mut indices: Vec<usize>) {
@@ -117,6 +125,23 @@ mod tests {
         assert_eq!(data, &["a", "c", "d", "b"]);
     }
 
+    #[test]
+    fn partial_order() {
+        let base = vec![0, 1, 2, 3, 4];
+        assert_eq!(merge_partial_order(&[3, 1], &base), vec![3, 1, 0, 2, 4]);
+        assert_eq!(merge_partial_order(&[4], &base), vec![4, 0, 1, 2, 3]);
+
+        // the rest keep their order from the base, even if it isn't sorted
+        assert_eq!(merge_partial_order(&[2], &[4, 3, 2, 1, 0]), vec![2, 4, 3, 1, 0]);
+    }
+
+    #[test]
+    fn partial_order_all_or_none() {
+        let base = vec![2, 0, 1];
+        assert_eq!(merge_partial_order(&[], &base), base);
+        assert_eq!(merge_partial_order(&[1, 0, 2], &base), vec![1, 0, 2]);
+    }
+
     /// test a bunch of random datasets to ensure it works for all configurations
     #[test]
     fn monkey_test() {
@@ -156,6 +156,61 @@ screenshot! {
     }
 }
 
+screenshot! {
+    battery_power,
+    json!({
+        "type": "battery",
+        "interval": "1s",
+        "show_power": true,
+        "batteries": ["/sys/class/power_supply/BAT0"],
+    }),
+    {
+        // 11.1 V * 1.2 A = 13.3 W
+        from_current: {
+            files => {
+                "/sys/class/power_supply/BAT0/charge_now": "80",
+                "/sys/class/power_supply/BAT0/charge_full": "100",
+                "/sys/class/power_supply/BAT0/status": "Discharging",
+                "/sys/class/power_supply/BAT0/voltage_now": "11100000",
+                "/sys/class/power_supply/BAT0/current_now": "1200000",
+            };
+        },
+        from_power: {
+            files => {
+                "/sys/class/power_supply/BAT0/charge_now": "80",
+                "/sys/class/power_supply/BAT0/charge_full": "100",
+                "/sys/class/power_supply/BAT0/status": "Discharging",
+                "/sys/class/power_supply/BAT0/power_now": "27500000",
+            };
+        },
+    }
+}
+
+screenshot! {
+    battery_individual,
+    json!({
+        "type": "battery",
+        "interval": "1s",
+        "show_individual": true,
+        "batteries": [
+            "/sys/class/power_supply/BAT0",
+            "/sys/class/power_supply/BAT1"
+        ],
+    }),
+    {
+        one_charging: {
+            files => {
+                "/sys/class/power_supply/BAT0/charge_now": "80",
+                "/sys/class/power_supply/BAT0/charge_full": "100",
+                "/sys/class/power_supply/BAT0/status": "Discharging",
+                "/sys/class/power_supply/BAT1/charge_now": "30",
+                "/sys/class/power_supply/BAT1/charge_full": "100",
+                "/sys/class/power_supply/BAT1/status": "Charging",
+            };
+        },
+    }
+}
+
 // cpu -------------------------------------------------------------------------
 
 screenshot! {
@@ -184,6 +239,34 @@ screenshot! {
     }
 }
 
+screenshot! {
+    cpu_freq,
+    json!({
+        "type": "cpu",
+        "interval": "1s",
+        "show_freq": true,
+    }),
+    {
+        ghz: {
+            files => {
+                "/proc/stat": "cpu  1 0 0 1 0 0 0 0 0 0",
+                "/sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq": "2000000",
+                "/sys/devices/system/cpu/cpu1/cpufreq/scaling_cur_freq": "2800000",
+            };
+        },
+        mhz: {
+            files => {
+                "/proc/stat": "cpu  1 0 0 1 0 0 0 0 0 0",
+                "/sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq": "800000",
+            };
+        },
+        // no cpufreq (e.g., in a virtual machine) so the frequency is omitted
+        missing: {
+            files => { "/proc/stat": "cpu  1 0 0 1 0 0 0 0 0 0" };
+        },
+    }
+}
+
 // disk ------------------------------------------------------------------------
 
 // NOTE: this one is difficult to mock, since it first reads `/proc/mount` and then
@@ -261,6 +344,39 @@ screenshot! {
     }
 }
 
+screenshot! {
+    kbd_hide_inactive,
+    json!({
+        "type": "kbd",
+        "show": ["caps_lock", "num_lock", "scroll_lock"],
+        "labels": { "caps_lock": "CAPS", "num_lock": "NUM", "scroll_lock": "SCRL" },
+        "hide_inactive": true
+    }),
+    {
+        caps_on: {
+            files => {
+                "/sys/class/leds/input0::capslock/brightness": "1",
+                "/sys/class/leds/input0::numlock/brightness": "0",
+                "/sys/class/leds/input0::scrolllock/brightness": "0",
+            };
+        },
+        all_on: {
+            files => {
+                "/sys/class/leds/input0::capslock/brightness": "1",
+                "/sys/class/leds/input0::numlock/brightness": "1",
+                "/sys/class/leds/input0::scrolllock/brightness": "1",
+            };
+        },
+        all_off: {
+            files => {
+                "/sys/class/leds/input0::capslock/brightness": "0",
+                "/sys/class/leds/input0::numlock/brightness": "0",
+                "/sys/class/leds/input0::scrolllock/brightness": "0",
+            };
+        }
+    }
+}
+
 // krb -------------------------------------------------------------------------
 
 screenshot!(
@@ -506,6 +622,76 @@ screenshot!(
     }
 );
 
+screenshot!(
+    sensors_voltage,
+    json!({
+        "type": "sensors",
+        "interval": "1s",
+        "kind": "voltage",
+        "input": "/sys/class/hwmon/hwmon1/in0_input",
+        "precision": 1,
+        "pad_count": 0
+    }),
+    {
+        at_12v: {
+            files => {
+                "/sys/class/hwmon/hwmon1/name": "name",
+                "/sys/class/hwmon/hwmon1/in0_input": "12100",
+            };
+        }
+    }
+);
+
+screenshot!(
+    sensors_thermal_zone,
+    json!({
+        "type": "sensors",
+        "interval": "1s",
+        "thermal_zone": "x86_pkg_temp"
+    }),
+    {
+        at_65: {
+            files => {
+                "/sys/class/thermal/thermal_zone0/type": "acpitz",
+                "/sys/class/thermal/thermal_zone0/temp": "30000",
+                "/sys/class/thermal/thermal_zone1/type": "x86_pkg_temp",
+                "/sys/class/thermal/thermal_zone1/temp": "65000",
+            };
+        }
+    }
+);
+
+screenshot!(
+    sensors_pages,
+    json!({
+        "type": "sensors",
+        "interval": "1s",
+        "label": [
+            { "label": " (cpu)", "input": "/sys/class/hwmon/hwmon1/temp1_input" },
+            { "label": " (gpu)", "input": "/sys/class/hwmon/hwmon2/temp1_input" },
+        ]
+    }),
+    {
+        first: {
+            files => {
+                "/sys/class/hwmon/hwmon1/name": "coretemp",
+                "/sys/class/hwmon/hwmon1/temp1_input": "45000",
+                "/sys/class/hwmon/hwmon2/name": "amdgpu",
+                "/sys/class/hwmon/hwmon2/temp1_input": "85000",
+            };
+        },
+        second: {
+            files => {
+                "/sys/class/hwmon/hwmon1/name": "coretemp",
+                "/sys/class/hwmon/hwmon1/temp1_input": "45000",
+                "/sys/class/hwmon/hwmon2/name": "amdgpu",
+                "/sys/class/hwmon/hwmon2/temp1_input": "85000",
+            };
+            test_fn => |t: &X11Test| t.i3stat_ipc("click sensors scroll_up");
+        }
+    }
+);
+
 // time ------------------------------------------------------------------------
 
 screenshot!(
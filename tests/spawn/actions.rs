@@ -1,8 +1,12 @@
-use i3stat::i3::{I3Button, I3Modifier};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use i3stat::i3::{I3Button, I3ClickEvent, I3Modifier};
+use i3stat::ipc::protocol::{IpcBarEvent, IpcMessage};
 use serde_json::json;
 
 use crate::spawn::SpawnedProgram;
-use crate::util::{get_exe, Test};
+use crate::util::{get_exe, wait_for_file, Test};
 
 spawn_test!(
     actions,
@@ -17,7 +21,8 @@ spawn_test!(
             "right_click": [
                 { "modifiers": ["Control"], "command": "baz" },
                 { "modifiers": ["Shift"], "command": "foo" },
-            ]
+            ],
+            "scroll_up": "bar"
           }
         }
       ]
@@ -64,5 +69,149 @@ spawn_test!(
             i3stat.next_line_json().unwrap(),
             json!([{ "instance": "0", "name": "script", "full_text": "foo" }])
         );
+
+        i3stat.click("0", I3Button::ScrollUp, &[]);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "bar" }])
+        );
+
+        // actions also run for clicks sent via ipc
+        i3stat.send_ipc(IpcMessage::BarEvent {
+            instance: "0".into(),
+            event: IpcBarEvent::Click(I3ClickEvent {
+                button: I3Button::Left,
+                ..Default::default()
+            }),
+        });
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "foo" }])
+        );
+    }
+);
+
+/// Writes `I3STAT_VALUE` to `$OUT`, atomically so it's never read half written
+const WRITE_VALUE: &str = r#"printf %s "$I3STAT_VALUE" > "$OUT.tmp" && mv "$OUT.tmp" "$OUT""#;
+
+/// Where `WRITE_VALUE` writes to, unique to each test
+fn out_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("i3stat-{}-{}", name, std::process::id()))
+}
+
+fn set_out(test: &mut Test, name: &str) {
+    let out = out_path(name);
+    let _ = std::fs::remove_file(&out);
+    test.env.insert("OUT".into(), out.to_string_lossy().into());
+}
+
+fn read_out(name: &str) -> String {
+    let out = out_path(name);
+    wait_for_file(&out, Duration::from_secs(5));
+    let value = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    value
+}
+
+spawn_test!(
+    on_click_open,
+    json!({
+        "items": [
+            {
+                "type": "script",
+                "command": "echo -n button=${I3_BUTTON:-none}",
+                "on_click_open": WRITE_VALUE,
+            }
+        ]
+    }),
+    |test: &mut Test| set_out(test, "on_click_open"),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "button=none" }])
+        );
+
+        // the item still handles the click
+        i3stat.click("0", I3Button::Left, &[]);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "button=1" }])
+        );
+
+        // and the command was run with the value from before the click
+        assert_eq!(read_out("on_click_open"), "button=none");
+    }
+);
+
+spawn_test!(
+    on_click_open_after,
+    json!({
+        "items": [
+            {
+                "type": "script",
+                "command": "echo -n button=${I3_BUTTON:-none}",
+                "on_click_open": WRITE_VALUE,
+                "on_click_open_order": "after",
+            }
+        ]
+    }),
+    |test: &mut Test| set_out(test, "on_click_open_after"),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "button=none" }])
+        );
+
+        // clicks with modifiers don't open anything
+        i3stat.click("0", I3Button::Left, &[I3Modifier::Shift]);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "button=1" }])
+        );
+
+        i3stat.click("0", I3Button::Right, &[]);
+        i3stat.click("0", I3Button::Left, &[]);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "button=3" }])
+        );
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "button=1" }])
+        );
+
+        // the command was run with the value after the item handled the click
+        assert_eq!(read_out("on_click_open_after"), "button=1");
+    }
+);
+
+spawn_test!(
+    on_click_open_group,
+    json!({
+        "items": [
+            {
+                "type": "group",
+                "summary": "<b>G</b> &amp; co",
+                "on_click_open": WRITE_VALUE,
+                "items": [{ "type": "raw", "full_text": "a" }]
+            }
+        ]
+    }),
+    |test: &mut Test| set_out(test, "on_click_open_group"),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "<b>G</b> &amp; co", "markup": "pango", "color": "#D8DEE9" }])
+        );
+
+        // items which read their own events still run the command
+        i3stat.click("0", I3Button::Left, &[]);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "<b>G</b> &amp; co a", "markup": "pango", "color": "#D8DEE9" }])
+        );
+
+        // with the shown text, rather than the markup
+        assert_eq!(read_out("on_click_open_group"), "G & co");
     }
 );
@@ -1,8 +1,11 @@
+use std::time::Duration;
+
 use i3stat::i3::{I3Button, I3ClickEvent};
-use i3stat::ipc::protocol::{IpcBarEvent, IpcMessage};
+use i3stat::ipc::protocol::{encode_ipc_msg, IpcBarEvent, IpcMessage, IPC_MAX_LEN, IPC_VERSION};
 use serde_json::{json, Value};
 
 use crate::spawn::SpawnedProgram;
+use crate::util::Test;
 
 spawn_test!(
     shutdown,
@@ -18,6 +21,87 @@ spawn_test!(
     }
 );
 
+spawn_test!(
+    version_mismatch,
+    json!({ "items": [] }),
+    |mut i3stat: SpawnedProgram| {
+        let mut bytes = encode_ipc_msg(IpcMessage::Info).unwrap();
+        bytes[0..4].copy_from_slice(&(IPC_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            i3stat.send_ipc_raw(&bytes),
+            json!({
+                "result": {
+                    "type": "failure",
+                    "code": "version_mismatch",
+                    "detail": format!(
+                        "ipc protocol version mismatch, client sent {} but bar expects {}",
+                        IPC_VERSION + 1,
+                        IPC_VERSION
+                    )
+                }
+            })
+        );
+
+        // the bar should still be running and answering clients on the right version
+        assert_eq!(i3stat.send_ipc(IpcMessage::Info), json!({ "value": {} }));
+    }
+);
+
+spawn_test!(
+    message_too_large,
+    json!({ "items": [] }),
+    |mut i3stat: SpawnedProgram| {
+        let mut bytes = encode_ipc_msg(IpcMessage::Info).unwrap();
+        bytes[4..12].copy_from_slice(&(IPC_MAX_LEN + 1).to_le_bytes());
+        assert_eq!(
+            i3stat.send_ipc_raw(&bytes),
+            json!({
+                "result": {
+                    "type": "failure",
+                    "code": "message_too_large",
+                    "detail": format!(
+                        "ipc message too large, client sent {} bytes but the most allowed is {}",
+                        IPC_MAX_LEN + 1,
+                        IPC_MAX_LEN
+                    )
+                }
+            })
+        );
+
+        // the bar should still be running and answering other clients
+        assert_eq!(i3stat.send_ipc(IpcMessage::Info), json!({ "value": {} }));
+    }
+);
+
+spawn_test!(
+    wait,
+    json!({ "items": [{ "type": "raw", "full_text": "before", "name": "target" }] }),
+    |mut i3stat: SpawnedProgram| {
+        let wait = |contains: &str, timeout| IpcMessage::Wait {
+            target: "target".into(),
+            contains: contains.into(),
+            timeout: Some(Duration::from_millis(timeout)),
+        };
+
+        // already matches, so replies straight away
+        let reply = i3stat.send_ipc(wait("before", 5000));
+        assert_eq!(reply["value"]["full_text"], "before");
+
+        // never matches
+        let reply = i3stat.send_ipc(wait("after", 100));
+        assert_eq!(reply["result"]["code"], "timeout");
+
+        // unblocks once the item updates
+        let waiter = i3stat.send_ipc_in_background(wait("after", 5000));
+        std::thread::sleep(Duration::from_millis(200));
+        i3stat.send_ipc(IpcMessage::PatchConfig(json!({
+            "items": [{ "type": "raw", "full_text": "after", "name": "target" }]
+        })));
+        let reply = waiter.join().unwrap();
+        assert_eq!(reply["value"]["full_text"], "after");
+    }
+);
+
 spawn_test!(
     info,
     json!({
@@ -41,6 +125,83 @@ spawn_test!(
     }
 );
 
+spawn_test!(
+    stats,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "raw", "full_text": "1", "name": "custom_name" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        let stats = i3stat.send_ipc(IpcMessage::Stats);
+        assert!(stats["value"]["uptime_secs"].is_u64());
+        assert_eq!(
+            stats["value"]["items"],
+            json!({
+                "0": { "name": "raw", "restarts": 0 },
+                "1": { "name": "custom_name", "restarts": 0 },
+            })
+        );
+    }
+);
+
+spawn_test!(
+    set_item_order,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "raw", "full_text": "1" },
+            { "type": "raw", "full_text": "2", "name": "custom_name" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "raw", "full_text": "1" },
+                { "instance": "2", "name": "custom_name", "full_text": "2" },
+            ])
+        );
+
+        // invalid orders are rejected
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::SetItemOrder(vec![json!(0), json!(0), json!(1)])),
+            json!({ "result": { "code": "invalid_order", "detail": "item index 0 was specified more than once", "type": "failure" } })
+        );
+        assert_eq!(i3stat.next_line_json().unwrap()[0]["instance"], json!("0"));
+
+        // items can be referred to by index or by name, and keep their instance
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::SetItemOrder(vec![json!("custom_name"), json!(0), json!("1")])),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "2", "name": "custom_name", "full_text": "2" },
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "raw", "full_text": "1" },
+            ])
+        );
+
+        // partial orders move the given items to the front, and the rest keep their order
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::SetItemOrder(vec![json!(1)])),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "1", "name": "raw", "full_text": "1" },
+                { "instance": "2", "name": "custom_name", "full_text": "2" },
+                { "instance": "0", "name": "raw", "full_text": "0" },
+            ])
+        );
+    }
+);
+
 spawn_test!(
     refresh_all,
     json!({
@@ -79,6 +240,63 @@ spawn_test!(
     }
 );
 
+spawn_test!(
+    set_signal,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "0", "signal": 2 },
+            { "type": "script", "command": "echo -n signal: ${I3_SIGNAL:-false}", "output": "simple" }
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "script", "full_text": "signal: false" },
+            ])
+        );
+
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::GetSignals),
+            json!({ "value": { "2": [{ "index": 0, "name": "raw" }] } })
+        );
+
+        // signal must be a realtime signal
+        let reply = i3stat.send_ipc(IpcMessage::SetSignal {
+            instance: "script".into(),
+            signal: Some(u32::MAX),
+        });
+        assert_eq!(reply["result"]["code"], "invalid_signal");
+
+        // map the signal to the script at runtime
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::SetSignal {
+                instance: "script".into(),
+                signal: Some(2),
+            }),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::GetSignals),
+            json!({ "value": { "2": [{ "index": 0, "name": "raw" }, { "index": 1, "name": "script" }] } })
+        );
+
+        // the new mapping is used straight away
+        i3stat.send_signal(libc::SIGRTMIN() + 2);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "script", "full_text": "signal: true" },
+            ])
+        );
+
+        i3stat.send_shutdown();
+        assert_eq!(i3stat.next_line_json().unwrap(), json!(null));
+    }
+);
+
 spawn_test!(
     signal_item_index,
     json!({
@@ -264,6 +482,29 @@ spawn_test!(
     }
 );
 
+spawn_test!(
+    config_sources,
+    json!({
+        "include": ["extra.toml", "missing.toml"],
+        "items": [{ "type": "raw", "full_text": "raw" }]
+    }),
+    |test: &mut Test| {
+        std::fs::write(test.dir.join("extra.toml"), "separator = false").unwrap();
+    },
+    |mut i3stat: SpawnedProgram| {
+        let reply = i3stat.send_ipc(IpcMessage::ConfigSources);
+        let names = reply
+            .get("value")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|path| path.as_str().unwrap().rsplit('/').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["config.json", "extra.toml"]);
+    }
+);
+
 spawn_test!(
     get_theme,
     json!({ "items": [] }),
@@ -308,3 +549,367 @@ spawn_test!(
         );
     }
 );
+
+spawn_test!(
+    validate_theme,
+    json!({ "items": [] }),
+    |mut i3stat: SpawnedProgram| {
+        let mut reply = i3stat.send_ipc(IpcMessage::GetTheme);
+        let original = reply.as_object_mut().unwrap().remove("value").unwrap();
+
+        // a valid theme
+        let mut theme = original.clone();
+        *theme.pointer_mut("/powerline_enable").unwrap() = Value::Bool(true);
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::ValidateTheme(theme)),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+
+        // an invalid theme
+        let mut theme = original.clone();
+        *theme.pointer_mut("/bg").unwrap() = Value::String("not a colour".into());
+        let reply = i3stat.send_ipc(IpcMessage::ValidateTheme(theme));
+        assert_eq!(reply["result"]["type"], json!("failure"));
+        assert_eq!(reply["result"]["code"], json!("invalid_theme"));
+
+        // neither were applied
+        let reply = i3stat.send_ipc(IpcMessage::GetTheme);
+        assert_eq!(reply.get("value").unwrap(), &original);
+    }
+);
+
+spawn_test!(
+    bar_event_unknown_item,
+    json!({ "items": [{ "type": "raw", "full_text": "0" }] }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "raw", "full_text": "0" }])
+        );
+
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::BarEvent {
+                instance: "1".into(),
+                event: IpcBarEvent::Signal
+            }),
+            json!({ "result": { "code": "item_not_found", "detail": "no item found with index: 1", "type": "failure" } })
+        );
+
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::BarEvent {
+                instance: "missing".into(),
+                event: IpcBarEvent::Signal
+            })["result"]["code"],
+            json!("item_not_found")
+        );
+    }
+);
+
+spawn_test!(
+    get_bar_detail,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "text", "detail": "more detail" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        // detail is never sent to i3
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "raw", "full_text": "text" }])
+        );
+
+        // but it's available via ipc
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::GetBar),
+            json!({
+                "value": [
+                    {
+                        "instance": "0",
+                        "name": "raw",
+                        "full_text": "text",
+                        "detail": "more detail"
+                    }
+                ]
+            })
+        );
+    }
+);
+
+spawn_test!(
+    bar_layout,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "raw", "full_text": "" },
+            { "type": "raw", "full_text": "2", "name": "custom_name" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        i3stat.next_line_json().unwrap();
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::BarLayout),
+            json!({
+                "value": [
+                    { "index": 0, "name": "raw", "visible": true },
+                    { "index": 1, "name": "raw", "visible": false },
+                    { "index": 2, "name": "custom_name", "visible": true },
+                ]
+            })
+        );
+
+        // layout follows the order of the bar
+        i3stat.send_ipc(IpcMessage::SetItemOrder(vec![json!("custom_name"), json!(0), json!(1)]));
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::BarLayout),
+            json!({
+                "value": [
+                    { "index": 2, "name": "custom_name", "visible": true },
+                    { "index": 0, "name": "raw", "visible": true },
+                    { "index": 1, "name": "raw", "visible": false },
+                ]
+            })
+        );
+    }
+);
+
+spawn_test!(
+    patch_config,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "raw", "full_text": "1", "name": "b" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "b", "full_text": "1" },
+            ])
+        );
+
+        // changed items are restarted with their new config
+        let patch = json!({
+            "items": [
+                { "type": "raw", "full_text": "0" },
+                { "type": "raw", "full_text": "patched", "name": "b" },
+            ]
+        });
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::PatchConfig(patch)),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "b", "full_text": "patched" },
+            ])
+        );
+
+        // invalid patches aren't applied
+        let reply = i3stat.send_ipc(IpcMessage::PatchConfig(json!({ "items": [] })));
+        assert_eq!(reply["result"]["code"], json!("invalid_config"));
+        let reply = i3stat.send_ipc(IpcMessage::GetConfig);
+        assert_eq!(reply["value"]["items"][1]["full_text"], json!("patched"));
+    }
+);
+
+spawn_test!(
+    logs,
+    json!({ "items": [{ "type": "raw", "full_text": "0" }] }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::Logs { lines: 0 }),
+            json!({ "value": [] })
+        );
+
+        let logs = i3stat.send_ipc(IpcMessage::Logs { lines: 5 });
+        let lines = logs["value"].as_array().unwrap();
+        assert!(lines.len() <= 5);
+        assert!(lines.iter().all(Value::is_string));
+    }
+);
+
+spawn_test!(
+    cli_exit_status,
+    json!({ "items": [{ "type": "raw", "full_text": "0" }] }),
+    |i3stat: SpawnedProgram| {
+        // failures exit with a non-zero status
+        let output = i3stat.run_ipc_cli(&["signal", "does-not-exist"]);
+        assert_eq!(output.status.code(), Some(1));
+
+        // and with `--json` print the raw reply
+        let output = i3stat.run_ipc_cli(&["--json", "signal", "does-not-exist"]);
+        assert_eq!(output.status.code(), Some(1));
+        let reply = serde_json::from_slice::<Value>(&output.stdout).unwrap();
+        assert_eq!(reply["result"]["type"], json!("failure"));
+        assert_eq!(reply["result"]["code"], json!("item_not_found"));
+
+        // successes exit with a zero status
+        let output = i3stat.run_ipc_cli(&["--json", "get-bar"]);
+        assert_eq!(output.status.code(), Some(0));
+        let reply = serde_json::from_slice::<Value>(&output.stdout).unwrap();
+        assert!(reply["value"].is_array());
+    }
+);
+
+spawn_test!(
+    bar_event_all,
+    json!({
+        "allow_duplicate_names": true,
+        "items": [
+            { "type": "script", "command": "echo -n signal: ${I3_SIGNAL:-false}", "name": "dup" },
+            { "type": "raw", "full_text": "other" },
+            { "type": "script", "command": "echo -n signal: ${I3_SIGNAL:-false}", "name": "dup" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "dup", "full_text": "signal: false" },
+                { "instance": "1", "name": "raw", "full_text": "other" },
+                { "instance": "2", "name": "dup", "full_text": "signal: false" },
+            ])
+        );
+
+        // every item with the name is signalled
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::BarEventAll {
+                name: "dup".into(),
+                event: IpcBarEvent::Signal
+            }),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+        // one update per item
+        i3stat.next_line_json().unwrap();
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "dup", "full_text": "signal: true" },
+                { "instance": "1", "name": "raw", "full_text": "other" },
+                { "instance": "2", "name": "dup", "full_text": "signal: true" },
+            ])
+        );
+
+        // custom events can't be broadcast
+        let reply = i3stat.send_ipc(IpcMessage::BarEventAll {
+            name: "dup".into(),
+            event: IpcBarEvent::Custom {
+                args: vec![],
+                plain: false,
+            },
+        });
+        assert_eq!(reply["result"]["code"], json!("event_failed"));
+
+        // unknown names are reported
+        let reply = i3stat.send_ipc(IpcMessage::BarEventAll {
+            name: "missing".into(),
+            event: IpcBarEvent::Signal,
+        });
+        assert_eq!(reply["result"]["code"], json!("item_not_found"));
+    }
+);
+
+spawn_test!(
+    restart_item,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "script", "command": "count-runs", "name": "counter" },
+        ]
+    }),
+    |test: &mut Test| {
+        // prints how many times it has been run
+        test.add_bin(
+            "count-runs",
+            "#!/usr/bin/env bash\nf=\"$(dirname \"$0\")/runs\"; printf x >> \"$f\"; printf 'runs: %s' \"$(wc -c < \"$f\")\"",
+        )
+    },
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "counter", "full_text": "runs: 1" },
+            ])
+        );
+
+        // the item is started again
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::RestartItem { instance: "counter".into() }),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "counter", "full_text": "runs: 2" },
+            ])
+        );
+
+        // items which have finished running can be restarted too
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::RestartItem { instance: "0".into() }),
+            json!({ "result": { "detail": null, "type": "success" } })
+        );
+
+        let reply = i3stat.send_ipc(IpcMessage::RestartItem { instance: "missing".into() });
+        assert_eq!(reply["result"]["code"], json!("item_not_found"));
+    }
+);
+
+spawn_test!(
+    restart_disabled_item,
+    json!({
+        "disable": [1],
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "raw", "full_text": "1", "name": "off" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.send_ipc(IpcMessage::RestartItem { instance: "off".into() }),
+            json!({
+                "result": {
+                    "type": "failure",
+                    "code": "item_disabled",
+                    "detail": "item 1 (off) can't be restarted since it's disabled in the config"
+                }
+            })
+        );
+    }
+);
+
+spawn_test!(
+    custom_help_no_color,
+    json!({ "items": [{ "type": "light", "path": "$LIGHT_DIR" }] }),
+    |test: &mut Test| {
+        let dir = test.dir.join("backlight");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("max_brightness"), "100").unwrap();
+        std::fs::write(dir.join("brightness"), "50").unwrap();
+        test.env.insert("LIGHT_DIR".into(), dir.to_string_lossy().into());
+    },
+    |mut i3stat: SpawnedProgram| {
+        // without a command, the item replies with its help
+        let output = i3stat.run_ipc_cli(&["--no-color", "custom", "light"]);
+        let help = String::from_utf8(output.stdout).unwrap();
+        assert!(help.contains("Usage"), "unexpected help: {}", help);
+        assert!(!help.contains('\x1b'), "help contains escape codes: {:?}", help);
+
+        // which is the same as asking for plain help over ipc
+        let reply = i3stat.send_ipc(IpcMessage::BarEvent {
+            instance: "light".into(),
+            event: IpcBarEvent::Custom {
+                args: vec![],
+                plain: true,
+            },
+        });
+        assert_eq!(reply["help"].as_str().unwrap().trim_end(), help.trim_end());
+    }
+);
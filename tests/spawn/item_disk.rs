@@ -0,0 +1,50 @@
+use serde_json::json;
+
+use crate::spawn::SpawnedProgram;
+use crate::util::Test;
+
+fn setup(test: &mut Test, exit_code: u8) {
+    test.add_fake_file("/proc/mounts", "/dev/sda1 / ext4 rw 0 0");
+    test.add_bin(
+        "smartctl",
+        format!("#!/usr/bin/env bash\nexit {}", exit_code),
+    );
+}
+
+spawn_test!(
+    disk_healthy,
+    json!({
+        "items": [{
+            "type": "disk",
+            "interval": "1s",
+            "mounts": ["/"],
+            "aliases": [{ "path": "/", "name": "root" }],
+            "health_command": "smartctl -H $DISK_DEVICE"
+        }]
+    }),
+    |test: &mut Test| setup(test, 0),
+    |mut i3stat: SpawnedProgram| {
+        let line = i3stat.next_line_json().unwrap();
+        assert!(line[0]["full_text"].as_str().unwrap().starts_with("󰋊 root "));
+        assert_ne!(line[0]["color"], json!("#BF616A"));
+    }
+);
+
+spawn_test!(
+    disk_unhealthy,
+    json!({
+        "items": [{
+            "type": "disk",
+            "interval": "1s",
+            "mounts": ["/"],
+            "aliases": [{ "path": "/", "name": "root" }],
+            "health_command": "smartctl -H $DISK_DEVICE"
+        }]
+    }),
+    |test: &mut Test| setup(test, 1),
+    |mut i3stat: SpawnedProgram| {
+        let line = i3stat.next_line_json().unwrap();
+        assert!(line[0]["full_text"].as_str().unwrap().starts_with("󱁌 root "));
+        assert_eq!(line[0]["color"], json!("#BF616A"));
+    }
+);
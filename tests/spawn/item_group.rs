@@ -0,0 +1,104 @@
+use i3stat::i3::{I3Button, I3ClickEvent};
+use serde_json::json;
+
+use crate::spawn::SpawnedProgram;
+
+spawn_test!(
+    group_expand_and_forward_clicks,
+    json!({
+        "items": [
+            {
+                "type": "group",
+                "summary": "G",
+                "items": [
+                    { "type": "raw", "full_text": "a" },
+                    { "type": "script", "command": "echo -n ${I3_BUTTON:-x}" },
+                ]
+            }
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        // collapsed
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "G", "markup": "pango", "color": "#D8DEE9" }])
+        );
+
+        // clicking the summary expands the group
+        i3stat.click("0", I3Button::Left, &[]);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "G a x", "markup": "pango", "color": "#D8DEE9" }])
+        );
+
+        // clicking a child forwards the click to it
+        i3stat.click_raw(I3ClickEvent {
+            instance: Some("0".into()),
+            button: I3Button::Right,
+            relative_x: 99,
+            width: 100,
+            ..Default::default()
+        });
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "G a 3", "markup": "pango", "color": "#D8DEE9" }])
+        );
+
+        // clicking the summary again collapses it
+        i3stat.click_raw(I3ClickEvent {
+            instance: Some("0".into()),
+            button: I3Button::Left,
+            relative_x: 0,
+            width: 100,
+            ..Default::default()
+        });
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "G", "markup": "pango", "color": "#D8DEE9" }])
+        );
+    }
+);
+
+spawn_test!(
+    group_click_debounce,
+    json!({
+        "items": [
+            {
+                "type": "group",
+                "summary": "G",
+                "click_debounce": "1m",
+                "items": [
+                    { "type": "raw", "full_text": "a" },
+                    { "type": "script", "command": "echo -n ${I3_BUTTON:-x}" },
+                ]
+            }
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "G", "markup": "pango", "color": "#D8DEE9" }])
+        );
+
+        // the repeated click is ignored, so the group stays expanded
+        i3stat.click("0", I3Button::Left, &[]);
+        i3stat.click("0", I3Button::Left, &[]);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "G a x", "markup": "pango", "color": "#D8DEE9" }])
+        );
+
+        // other clicks still get through
+        i3stat.click_raw(I3ClickEvent {
+            instance: Some("0".into()),
+            button: I3Button::Right,
+            relative_x: 99,
+            width: 100,
+            ..Default::default()
+        });
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "group", "full_text": "G a 3", "markup": "pango", "color": "#D8DEE9" }])
+        );
+    }
+);
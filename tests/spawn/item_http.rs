@@ -0,0 +1,74 @@
+#![cfg(feature = "http")]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use serde_json::json;
+
+use crate::spawn::SpawnedProgram;
+
+/// Start a server which responds to every request with the given JSON, returns its url
+fn mock_server(body: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+
+            // read request headers
+            let mut reader = BufReader::new(&stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+
+    url
+}
+
+spawn_test!(
+    http_pointer,
+    json!({
+        "items": [{
+            "type": "http",
+            "url": mock_server(r#"{"current":{"temperature":21.5}}"#),
+            "pointer": "/current/temperature",
+            "format": "{}°C",
+            "interval": "1m"
+        }]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "http", "full_text": "21.5°C" }])
+        );
+    }
+);
+
+spawn_test!(
+    http_missing_pointer,
+    json!({
+        "items": [{
+            "type": "http",
+            "url": mock_server(r#"{"current":{}}"#),
+            "pointer": "/current/temperature",
+            "interval": "1m"
+        }]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "http", "full_text": "ERROR", "background": "#BF616A" }])
+        );
+    }
+);
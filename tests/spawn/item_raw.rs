@@ -22,3 +22,85 @@ spawn_test!(
         );
     }
 );
+
+spawn_test!(
+    raw_global_defaults,
+    json!({
+        "separator": false,
+        "separator_block_width": 12,
+        "markup": "pango",
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "raw", "full_text": "1", "separator_block_width": 3 },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                {
+                    "instance": "0",
+                    "full_text": "0",
+                    "name": "raw",
+                    "separator": false,
+                    "separator_block_width": 12,
+                    "markup": "pango"
+                },
+                {
+                    "instance": "1",
+                    "full_text": "1",
+                    "name": "raw",
+                    "separator": false,
+                    "separator_block_width": 3,
+                    "markup": "pango"
+                },
+            ])
+        );
+    }
+);
+
+spawn_test!(
+    max_width,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "hello world", "max_width": 6 },
+            {
+                "type": "raw",
+                "full_text": "<b>hello</b> world",
+                "markup": "pango",
+                "max_width": 4
+            },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "full_text": "hello…", "name": "raw" },
+                {
+                    "instance": "1",
+                    "full_text": "<b>hel…</b>",
+                    "name": "raw",
+                    "markup": "pango"
+                },
+            ])
+        );
+    }
+);
+
+spawn_test!(
+    raw_command_substitution,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "a {{ echo b }} c" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "full_text": "a b c", "name": "raw" },
+            ])
+        );
+    }
+);
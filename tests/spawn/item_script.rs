@@ -2,6 +2,7 @@ use i3stat::i3::I3Button;
 use serde_json::json;
 
 use crate::spawn::SpawnedProgram;
+use crate::util::Test;
 
 spawn_test!(
     script_simple,
@@ -78,3 +79,141 @@ spawn_test!(
         );
     }
 );
+
+spawn_test!(
+    script_visibility,
+    json!({
+        "items":[
+            {
+                "type": "script",
+                "command": "echo -n visible=${I3_VISIBLE:-unknown}",
+                "output": "simple",
+            }
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "visible=unknown" }])
+        );
+
+        // bar was hidden, nothing is printed while it's hidden
+        i3stat.send_signal(libc::SIGUSR1);
+        assert!(i3stat.next_line().is_err());
+
+        // bar was shown again, so the update received while it was hidden is printed, and it also
+        // triggers a refresh
+        i3stat.send_signal(libc::SIGUSR2);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "visible=false" }])
+        );
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "visible=true" }])
+        );
+    }
+);
+
+spawn_test!(
+    script_timeout,
+    json!({
+        "items":[
+            {
+                "type": "script",
+                "command": "sleep 10",
+                "timeout": "1s",
+            }
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                {
+                    "instance": "0",
+                    "name": "script",
+                    "full_text": "TIMEOUT",
+                    "background": "#BF616A",
+                }
+            ])
+        );
+    }
+);
+
+spawn_test!(
+    script_stale,
+    json!({
+        "staleness": true,
+        "items": [
+            {
+                "type": "script",
+                // only output once, then hang on any later runs
+                "command": "if [ -e \"$MARKER\" ]; then sleep 60; fi; touch \"$MARKER\"; echo ok",
+                "interval": "100ms",
+                "stale_after": "500ms",
+            }
+        ]
+    }),
+    |test: &mut Test| {
+        let marker = test.dir.join("marker");
+        test.env.insert("MARKER".into(), marker.to_string_lossy().into());
+    },
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "ok" }])
+        );
+
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                {
+                    "instance": "0",
+                    "name": "script",
+                    "full_text": "ok ⚠",
+                    "color": "#4C566A",
+                    "_stale": true
+                }
+            ])
+        );
+    }
+);
+
+spawn_test!(
+    script_signal_only,
+    json!({
+        "items":[
+            {
+                "type": "script",
+                "command": "echo -n \"$(count-runs) signal: ${I3_SIGNAL:-false}\"",
+                "interval": "never",
+                "signal": 1,
+            }
+        ]
+    }),
+    |test: &mut Test| {
+        // prints how many times it has been run
+        test.add_bin(
+            "count-runs",
+            "#!/usr/bin/env bash\nf=\"$(dirname \"$0\")/runs\"; printf x >> \"$f\"; printf 'runs: %s' \"$(wc -c < \"$f\")\"",
+        )
+    },
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "runs: 1 signal: false" }])
+        );
+
+        // it doesn't run again on its own, only once it's signalled
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        i3stat.send_signal(libc::SIGRTMIN() + 1);
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "script", "full_text": "runs: 2 signal: true" }])
+        );
+
+        i3stat.send_shutdown();
+        assert_eq!(i3stat.next_line_json().unwrap(), json!(null));
+    }
+);
@@ -46,3 +46,89 @@ spawn_test!(
         assert_eq!(i3stat.next_line_json().unwrap(), json!(null));
     }
 );
+
+spawn_test!(
+    time_since,
+    json!({
+        "items":[
+            {
+                "type": "time",
+                "interval": "1 s",
+                "mode": "since",
+                "since": "1985-10-26 00:00:00"
+            }
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                {
+                    "instance": "0",
+                    "name": "time",
+                    "full_text": "󰔛 1h 35m 0s",
+                    "short_text": "1h 35m 0s",
+                    "markup": "pango"
+                }
+            ])
+        );
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                {
+                    "instance": "0",
+                    "name": "time",
+                    "full_text": "󰔛 1h 35m 1s",
+                    "short_text": "1h 35m 1s",
+                    "markup": "pango"
+                }
+            ])
+        );
+        i3stat.send_shutdown();
+        assert_eq!(i3stat.next_line_json().unwrap(), json!(null));
+    }
+);
+
+spawn_test!(
+    time_locale,
+    json!({
+        "items":[
+            {
+                "type": "time",
+                "interval": "1 s",
+                "format_long": "%A",
+                "format_short": "%a",
+                "locale": "de_DE"
+            },
+            {
+                "type": "time",
+                "interval": "1 s",
+                "format_long": "%A",
+                "format_short": "%a",
+                "locale": "not_a_locale"
+            }
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                {
+                    "instance": "0",
+                    "name": "time",
+                    "full_text": "󰥔 Samstag",
+                    "short_text": "Sa",
+                    "markup": "pango"
+                },
+                {
+                    "instance": "1",
+                    "name": "time",
+                    "full_text": "󰥔 Saturday",
+                    "short_text": "Sat",
+                    "markup": "pango"
+                }
+            ])
+        );
+        i3stat.send_shutdown();
+    }
+);
@@ -3,18 +3,20 @@
 use std::io::{BufRead, BufReader, Read, Write};
 use std::marker::PhantomData;
 use std::os::unix::net::UnixStream;
-use std::path::PathBuf;
-use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, ChildStdout, Command, Output, Stdio};
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
 use i3stat::config::AppConfig;
 use i3stat::i3::{I3Button, I3ClickEvent, I3Modifier};
-use i3stat::ipc::protocol::{encode_ipc_msg, IpcMessage, IpcReply, IpcResult, IPC_HEADER_LEN};
+use i3stat::ipc::protocol::{decode_ipc_msg, encode_ipc_msg, IpcMessage, IpcReply, IpcResult};
 use serde_json::Value;
 use timeout_readwrite::{TimeoutReadExt, TimeoutReader};
 
 use crate::util::{
     get_current_exe,
+    get_exe,
     get_fakeroot_lib,
     get_faketime_lib,
     LogOnDropChild,
@@ -79,7 +81,7 @@ impl<'a> SpawnedProgram<'a> {
         // assert header
         assert_eq!(
             test.next_line().unwrap().as_deref(),
-            Some(r#"{"version":1,"click_events":true}"#)
+            Some(r#"{"version":1,"stop_signal":10,"cont_signal":12,"click_events":true}"#)
         );
         assert_eq!(test.next_line().unwrap().as_deref(), Some(r#"["#));
 
@@ -118,21 +120,43 @@ impl<'a> SpawnedProgram<'a> {
         })
     }
 
+    /// Send a signal to the running program
+    pub fn send_signal(&mut self, signal: i32) {
+        assert_eq!(unsafe { libc::kill(self.child.id() as i32, signal) }, 0);
+    }
+
     /// Send an IPC message to the running program
     pub fn send_ipc(&mut self, msg: IpcMessage) -> Value {
-        let mut stream = UnixStream::connect(&self.socket).unwrap();
-        stream.write_all(&encode_ipc_msg(msg).unwrap()).unwrap();
+        self.send_ipc_raw(&encode_ipc_msg(msg).unwrap())
+    }
+
+    /// Send already encoded bytes to the running program over IPC
+    pub fn send_ipc_raw(&mut self, bytes: &[u8]) -> Value {
+        send_ipc_to(&self.socket, bytes)
+    }
+
+    /// Send an IPC message to the running program from another thread, for messages which block
+    pub fn send_ipc_in_background(&self, msg: IpcMessage) -> JoinHandle<Value> {
+        let socket = self.socket.clone();
+        let bytes = encode_ipc_msg(msg).unwrap();
+        thread::spawn(move || send_ipc_to(&socket, &bytes))
+    }
 
-        let mut buf = vec![];
-        stream.read_to_end(&mut buf).unwrap();
-        serde_json::from_slice::<Value>(&buf[IPC_HEADER_LEN..]).unwrap()
+    /// Run `i3stat-ipc` against the running program with the given arguments
+    pub fn run_ipc_cli(&self, args: &[&str]) -> Output {
+        Command::new(get_exe("i3stat-ipc"))
+            .arg("--socket")
+            .arg(&self.socket)
+            .args(args)
+            .output()
+            .unwrap()
     }
 
     /// Send a shutdown request via IPC
     pub fn send_shutdown(&mut self) {
         let reply = self.send_ipc(IpcMessage::Shutdown);
         let reply = serde_json::from_value::<IpcReply>(reply).unwrap();
-        assert_eq!(reply, IpcReply::Result(IpcResult::Success(None)));
+        assert_eq!(reply, IpcReply::Result(IpcResult::success()));
     }
 
     /// Gets the current config for the program via IPC
@@ -155,13 +179,28 @@ impl<'a> SpawnedProgram<'a> {
     }
 
     /// A message is emitted per item, so wait for all items to have emitted something
+    /// With `wait_for_all` the bar does this itself, and only emits once they all have.
     fn wait_for_all_init(&mut self) {
-        for _ in 0..self.get_config().items.len().saturating_sub(1) {
+        let config = self.get_config();
+        if config.wait_for_all {
+            return;
+        }
+
+        for _ in 0..config.items.len().saturating_sub(1) {
             self.next_line_json().unwrap();
         }
     }
 }
 
+fn send_ipc_to(socket: &Path, bytes: &[u8]) -> Value {
+    let mut stream = UnixStream::connect(socket).unwrap();
+    stream.write_all(bytes).unwrap();
+
+    let mut buf = vec![];
+    stream.read_to_end(&mut buf).unwrap();
+    decode_ipc_msg::<Value>(&buf).unwrap()
+}
+
 macro_rules! spawn_test {
     ($name:ident, $config:expr, $test_fn:expr) => {
         spawn_test!($name, $config, |x| x, $test_fn);
@@ -0,0 +1,36 @@
+use serde_json::json;
+
+use crate::spawn::SpawnedProgram;
+use crate::util::Test;
+
+spawn_test!(
+    panicking_item_shows_error,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "fine" },
+            { "type": "raw", "full_text": "never shown", "name": "boom" },
+        ]
+    }),
+    |test: &mut Test| {
+        test.env.insert("I3STAT_TEST_PANIC".into(), "boom".into());
+    },
+    |mut i3stat: SpawnedProgram| {
+        // the panic is caught, and shown just like any other error
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "fine" },
+                {
+                    "instance": "1",
+                    "name": "boom",
+                    "full_text": "ERROR(boom)",
+                    "color": "#2E3440",
+                    "background": "#BF616A"
+                },
+            ])
+        );
+
+        // the rest of the bar keeps running
+        i3stat.send_shutdown();
+    }
+);
@@ -0,0 +1,41 @@
+use i3stat::ipc::protocol::IpcMessage;
+use serde_json::json;
+
+use crate::spawn::SpawnedProgram;
+use crate::util::Test;
+
+spawn_test!(
+    retry_errors_with_backoff,
+    json!({
+        "items": [{
+            "type": "sensors",
+            "interval": "1s",
+            "command": "flaky",
+            "retry": { "errors": true, "backoff": "10ms" }
+        }]
+    }),
+    |test: &mut Test| {
+        // fails twice, and then succeeds
+        test.add_bin(
+            "flaky",
+            r#"#!/usr/bin/env bash
+count="$(dirname "$0")/flaky_count"
+n="$(cat "$count" 2>/dev/null || echo 0)"
+echo "$((n + 1))" > "$count"
+if [ "$n" -lt 2 ]; then
+  echo "not ready yet" >&2
+  exit 1
+fi
+echo 42
+"#,
+        );
+    },
+    |mut i3stat: SpawnedProgram| {
+        let line = i3stat.next_line_json().unwrap();
+        let text = line[0]["full_text"].as_str().unwrap();
+        assert!(text.ends_with("42°C"), "unexpected text: {}", text);
+
+        let stats = i3stat.send_ipc(IpcMessage::Stats);
+        assert!(stats.to_string().contains("\"restarts\":2"), "{}", stats);
+    }
+);
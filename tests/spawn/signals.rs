@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, fs, thread};
+
+use serde_json::{json, Value};
+
+use crate::spawn::SpawnedProgram;
+use crate::util::Test;
+
+/// Where the dump files are written, unique to this test process
+fn dump_dir() -> PathBuf {
+    env::temp_dir().join(format!("i3stat-test-dump.{}", std::process::id()))
+}
+
+spawn_test!(
+    dump_state,
+    json!({
+        "items": [
+            { "type": "raw", "full_text": "text", "detail": "detail" },
+        ]
+    }),
+    |test: &mut Test| {
+        let dir = dump_dir();
+        fs::create_dir_all(&dir).unwrap();
+        test.env.insert("XDG_RUNTIME_DIR".into(), dir.to_string_lossy().into());
+    },
+    |mut i3stat: SpawnedProgram| {
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([{ "instance": "0", "name": "raw", "full_text": "text" }])
+        );
+
+        i3stat.send_signal(libc::SIGRTMAX());
+
+        // wait for the dump to be written
+        let dir = dump_dir();
+        let mut dump = None;
+        for _ in 0..20 {
+            let entry = fs::read_dir(&dir).unwrap().next();
+            if let Some(entry) = entry {
+                dump = Some(fs::read_to_string(entry.unwrap().path()).unwrap());
+                break;
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+        let dump = serde_json::from_str::<Value>(&dump.expect("no dump was written")).unwrap();
+        assert_eq!(
+            dump["bar"],
+            json!([{ "instance": "0", "name": "raw", "full_text": "text", "detail": "detail" }])
+        );
+        assert_eq!(dump["config"]["items"][0]["full_text"], json!("text"));
+
+        // the bar is otherwise unaffected
+        i3stat.send_shutdown();
+    }
+);
@@ -0,0 +1,56 @@
+use serde_json::json;
+
+use crate::spawn::SpawnedProgram;
+
+spawn_test!(
+    urgent_flash,
+    json!({
+        "theme": {
+            "urgent_fg": "#000001",
+            "urgent_bg": "#000002",
+            "urgent_flash_fg": "#000003",
+            "urgent_flash_bg": "#000004",
+            "urgent_flash_interval": "100ms",
+        },
+        "items": [
+            { "type": "raw", "full_text": "!", "urgent": true },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        let item = |fg: &str, bg: &str| {
+            json!([{
+                "instance": "0",
+                "name": "raw",
+                "full_text": "!",
+                "urgent": false,
+                "_urgent": true,
+                "color": fg,
+                "background": bg,
+            }])
+        };
+
+        assert_eq!(i3stat.next_line_json().unwrap(), item("#000001", "#000002"));
+        assert_eq!(i3stat.next_line_json().unwrap(), item("#000003", "#000004"));
+        assert_eq!(i3stat.next_line_json().unwrap(), item("#000001", "#000002"));
+    }
+);
+
+spawn_test!(
+    urgent_flash_disabled,
+    json!({
+        "theme": {
+            "urgent_fg": "#000001",
+            "urgent_bg": "#000002",
+            "urgent_flash": false,
+            "urgent_flash_interval": "100ms",
+        },
+        "items": [
+            { "type": "raw", "full_text": "!", "urgent": true },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        let line = i3stat.next_line_json().unwrap();
+        assert_eq!(line[0]["color"], json!("#000001"));
+        assert_eq!(line[0]["background"], json!("#000002"));
+    }
+);
@@ -0,0 +1,29 @@
+use serde_json::json;
+
+use crate::spawn::SpawnedProgram;
+
+spawn_test!(
+    wait_for_all,
+    json!({
+        "wait_for_all": true,
+        "items": [
+            { "type": "raw", "full_text": "0" },
+            { "type": "script", "command": "sleep 0.5; echo -n 1" },
+            { "type": "script", "command": "sleep 0.2; echo -n 2" },
+        ]
+    }),
+    |mut i3stat: SpawnedProgram| {
+        // the first frame contains every item, even though some took a while to start
+        assert_eq!(
+            i3stat.next_line_json().unwrap(),
+            json!([
+                { "instance": "0", "name": "raw", "full_text": "0" },
+                { "instance": "1", "name": "script", "full_text": "1" },
+                { "instance": "2", "name": "script", "full_text": "2" },
+            ])
+        );
+
+        i3stat.send_shutdown();
+        assert_eq!(i3stat.next_line_json().unwrap(), json!(null));
+    }
+);